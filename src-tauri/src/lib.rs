@@ -1,30 +1,110 @@
+mod redact;
+mod retry;
+
+#[cfg(test)]
+mod mock_server_tests;
+
 use chrono::{Local, TimeZone};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fs;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use notify::{EventKind, RecursiveMode, Watcher};
 use reqwest::{Client, Proxy};
+use retry::{
+    classify_response, error_chain_message, is_certificate_error, is_transient_error,
+    ResponseClass, RetryPolicy,
+};
 use serde::{Deserialize, Serialize};
-use tauri::menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem};
+use tauri::menu::{CheckMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu};
 use tauri::tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent};
-use tauri::{AppHandle, Emitter, Manager, Runtime};
+use tauri::{
+    AppHandle, Emitter, Manager, PhysicalPosition, Runtime, WebviewUrl, WebviewWindow,
+    WebviewWindowBuilder, WindowEvent,
+};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, ShortcutState};
 use tokio::process::{Child, Command};
+use tokio_util::sync::CancellationToken;
 
 static USAGE_BINDINGS_LOCK: Mutex<()> = Mutex::new(());
+static USAGE_HISTORY_LOCK: Mutex<()> = Mutex::new(());
+static USAGE_HISTORY_COMPACTED_AT_MS: AtomicU64 = AtomicU64::new(0);
+/// 预测用的近期用量历史（按账号，只保留回看时长内的记录），首次使用时从历史文件加载
+static USAGE_FORECAST_HISTORY: Mutex<Option<HashMap<String, Vec<UsageHistoryEntry>>>> =
+    Mutex::new(None);
+/// 各 ChatGPT 账号最近一次写入用量历史的 session 采样
+static SESSION_HISTORY_SAMPLES: Mutex<Option<HashMap<String, SessionHistorySample>>> =
+    Mutex::new(None);
 static LOGIN_CANCEL_REQUESTED: AtomicBool = AtomicBool::new(false);
 static AUTO_REFRESH_RUNNING: AtomicBool = AtomicBool::new(false);
-static LAST_AUTO_REFRESH_MS: Mutex<u64> = Mutex::new(0);
+static REFRESH_JOB: Mutex<Option<RefreshJobStatus>> = Mutex::new(None);
+static REFRESH_JOB_SEQ: AtomicU64 = AtomicU64::new(0);
+static REFRESH_JOB_CANCEL: Mutex<Option<CancellationToken>> = Mutex::new(None);
+static PENDING_REFRESH_REQUEST: Mutex<Option<PendingRefreshRequest>> = Mutex::new(None);
+static ACCOUNT_REFRESH_STATES: Mutex<Vec<AccountRefreshState>> = Mutex::new(Vec::new());
+static MANAGED_HTTP_CLIENT: Mutex<Option<ManagedHttpClient>> = Mutex::new(None);
+static ACCOUNT_PROXY_HTTP_CLIENTS: Mutex<VecDeque<ManagedHttpClient>> = Mutex::new(VecDeque::new());
+static DIAGNOSTIC_EVENTS: Mutex<VecDeque<DiagnosticEvent>> = Mutex::new(VecDeque::new());
+static SESSION_INDEX: Mutex<Option<SessionIndex>> = Mutex::new(None);
+/// 全量扫描在索引锁之外读取文件，用该锁避免多个线程同时扫描
+static SESSION_INDEX_SCAN_LOCK: Mutex<()> = Mutex::new(());
+static PROXY_CREDENTIALS_CACHE: Mutex<Option<Option<ProxyCredentials>>> = Mutex::new(None);
+/// 串行化 accounts.json 的写入：后端的读取-修改-写回在锁内完成，避免互相覆盖
+static ACCOUNTS_STORE_LOCK: Mutex<()> = Mutex::new(());
+static ACCOUNT_SESSION_KEYS_CACHE: Mutex<Option<HashMap<String, AccountSessionKey>>> =
+    Mutex::new(None);
+static SESSION_WATCHER_ACTIVE: AtomicBool = AtomicBool::new(false);
+static TRAY_POPOVER_HIDDEN_AT_MS: AtomicU64 = AtomicU64::new(0);
 const TRAY_ID: &str = "main-tray";
 const TRAY_MENU_OPEN_ID: &str = "tray-open";
 const TRAY_MENU_EXIT_ID: &str = "tray-exit";
+const TRAY_SETTING_ID_PREFIX: &str = "tray-setting:";
+const TRAY_AUTO_REFRESH_PRESETS: [u64; 5] = [0, 5, 15, 30, 60];
+const TRAY_POPOVER_LABEL: &str = "popover";
+const TRAY_POPOVER_WIDTH: f64 = 340.0;
+const TRAY_POPOVER_HEIGHT: f64 = 460.0;
+const TRAY_POPOVER_MARGIN: f64 = 8.0;
 const MIN_VALID_EPOCH_MS: i64 = 946684800000; // 2000-01-01T00:00:00Z
 const MAX_VALID_EPOCH_MS: i64 = 4102444800000; // 2100-01-01T00:00:00Z
 const DEFAULT_LOGIN_TIMEOUT_SECONDS: u64 = 180;
+const DEFAULT_REFRESH_CONCURRENCY: usize = 4;
+const MAX_REFRESH_CONCURRENCY: usize = 16;
+/// 缓存的账号级代理客户端数量上限
+const ACCOUNT_PROXY_HTTP_CLIENT_LIMIT: usize = 8;
+const DEFAULT_CONNECT_TIMEOUT_SECONDS: u64 = 10;
+const DEFAULT_READ_TIMEOUT_SECONDS: u64 = 30;
+const MAX_REQUEST_TIMEOUT_SECONDS: u64 = 300;
+const ACTIVE_ACCOUNT_REFRESH_DIVISOR: u64 = 3;
+const MIN_ACCOUNT_REFRESH_INTERVAL_MS: u64 = 60_000;
+const RESET_REFRESH_GRACE_MS: u64 = 30_000;
+const CIRCUIT_BREAKER_THRESHOLD: u32 = 2;
+const CIRCUIT_BREAKER_BASE_COOLDOWN_MS: u64 = 60_000;
+const CIRCUIT_BREAKER_MAX_COOLDOWN_MS: u64 = 60 * 60 * 1000;
+const BACKGROUND_REFRESH_TICK: Duration = Duration::from_secs(30);
+const SUSPEND_GAP_THRESHOLD_MS: u64 = 60_000;
+const NETWORK_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+const DEFAULT_API_BASE_URL: &str = "https://chatgpt.com/backend-api";
+const API_BASE_URL_ENV: &str = "CODEX_MANAGER_API_BASE_URL";
+const PROXY_KEYRING_SERVICE: &str = "com.codex-manager.app.proxy";
+const TLS_ERROR_STATUS: &str = "tls_error";
+const USAGE_SOURCE_WHAM: &str = "wham";
+const USAGE_SOURCE_SESSION: &str = "session";
+const MAX_DIAGNOSTIC_EVENTS: usize = 200;
+const DIAGNOSTIC_BODY_EXCERPT_CHARS: usize = 2048;
+const PROXY_MODE_ENV: &str = "env";
+const PROXY_MODE_DIRECT: &str = "direct";
+const ACCOUNT_PROXY_DIRECT: &str = "direct";
+const DEFAULT_USAGE_HISTORY_RETENTION_DAYS: u64 = 90;
+const USAGE_HISTORY_COMPACT_INTERVAL_MS: u64 = 6 * 60 * 60 * 1000;
+const FORECAST_LOOKBACK_MS: u64 = 6 * 60 * 60 * 1000;
+const FORECAST_MIN_SPAN_MS: u64 = 10 * 60 * 1000;
+/// 本地 session 采样写入用量历史的最短间隔（按账号计），窗口重置后的首个采样不受限制
+const SESSION_HISTORY_SAMPLE_INTERVAL_MS: u64 = 5 * 60 * 1000;
+const FORECAST_RESET_TOLERANCE_MS: i64 = 10 * 60 * 1000;
 
 #[cfg(windows)]
 const CREATE_NO_WINDOW: u32 = 0x08000000;
@@ -75,9 +155,12 @@ fn get_account_auth_path(account_id: &str) -> Result<PathBuf, String> {
 struct TrayLimitSummary {
     percent_left: f64,
     reset_time: String,
+    reset_time_ms: Option<i64>,
+    burn_rate_per_hour: Option<f64>,
+    projected_exhaustion_ms: Option<i64>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
 #[serde(rename_all = "camelCase")]
 struct TrayContextWindowSummary {
     percent_left: f64,
@@ -97,6 +180,11 @@ struct TrayUsageSummary {
     code_review_limit: Option<TrayLimitSummary>,
     last_updated: Option<String>,
     source_file: Option<String>,
+    next_retry_at_ms: Option<u64>,
+    details: Option<WhamUsageDetails>,
+    source: Option<String>,
+    source_age_ms: Option<u64>,
+    windows: Option<Vec<UsageWindow>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, Default)]
@@ -132,9 +220,14 @@ struct TrayStoredAccount {
     is_active: bool,
     created_at: String,
     updated_at: String,
+    group: Option<String>,
+    pinned: Option<bool>,
+    sort_index: Option<i64>,
+    /// 账号级代理：为空时跟随全局设置，"direct" 表示该账号直连
+    proxy_url: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 struct TrayAppConfig {
     auto_refresh_interval: Option<u64>,
@@ -146,6 +239,30 @@ struct TrayAppConfig {
     proxy_url: Option<String>,
     auto_restart_codex_on_switch: Option<bool>,
     skip_switch_restart_confirm: Option<bool>,
+    global_shortcuts: Option<TrayGlobalShortcuts>,
+    tray_sort_mode: Option<String>,
+    tray_hide_unavailable: Option<bool>,
+    tray_popover_enabled: Option<bool>,
+    refresh_concurrency: Option<u64>,
+    request_connect_timeout_seconds: Option<u64>,
+    request_read_timeout_seconds: Option<u64>,
+    usage_history_enabled: Option<bool>,
+    usage_history_retention_days: Option<u64>,
+    api_base_url: Option<String>,
+    proxy_mode: Option<String>,
+    proxy_no_proxy: Option<String>,
+    tls_ca_bundle_paths: Option<Vec<String>>,
+    tls_use_system_certs: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+struct TrayGlobalShortcuts {
+    next_account: Option<String>,
+    previous_account: Option<String>,
+    recommended_account: Option<String>,
+    open_window: Option<String>,
+    refresh_all: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -156,12 +273,21 @@ struct TrayAccountsStore {
     config: TrayAppConfig,
 }
 
+/// 托盘中各账号的详情菜单项，供倒计时定时器原地更新文字
+struct TrayDetailItems<R: Runtime>(Mutex<Vec<(String, MenuItem<R>)>>);
+
 #[derive(Debug, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct TrayAccountSwitchedPayload {
     account_id: String,
 }
 
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct TrayConfigChangedPayload {
+    config: TrayAppConfig,
+}
+
 #[derive(Debug, Serialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct BackgroundUsageRefreshedPayload {
@@ -169,6 +295,78 @@ struct BackgroundUsageRefreshedPayload {
     finished_at: String,
 }
 
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct UsageRefreshAccountPayload {
+    account_id: String,
+    phase: String,
+    code: Option<String>,
+    message: Option<String>,
+    summary: Option<TrayUsageSummary>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct UsageRefreshProgressPayload {
+    total: usize,
+    completed: usize,
+    succeeded: usize,
+    failed: usize,
+    in_flight: usize,
+    finished: bool,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct RefreshJobStatus {
+    job_id: u64,
+    trigger: String,
+    state: String,
+    total: usize,
+    completed: usize,
+    succeeded: usize,
+    failed: usize,
+    started_at_ms: u64,
+    finished_at_ms: Option<u64>,
+    error: Option<String>,
+}
+
+/// 已有任务运行时排队的手动刷新请求；账号列表为空（None）表示刷新全部
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PendingRefreshRequest {
+    job_id: u64,
+    account_ids: Option<Vec<String>>,
+}
+
+enum UsageRefreshWorkerMessage {
+    Started(String),
+    Finished(String, Box<UsageResult>),
+    Cancelled(String),
+}
+
+#[derive(Debug, Clone, Default)]
+struct AccountRefreshState {
+    account_id: String,
+    last_attempt_ms: u64,
+    consecutive_failures: u32,
+    cooldown_until_ms: Option<u64>,
+    last_error: Option<String>,
+    /// 需要用户处理的失败状态（expired / forbidden 等），冷却期间沿用该状态返回
+    attention_status: Option<String>,
+    last_attempt_at: Option<Instant>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+struct AccountRefreshPlan {
+    account_id: String,
+    next_refresh_at_ms: Option<u64>,
+    reason: String,
+    /// reason 为 attention 时导致长时间退避的失败状态
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+}
+
 /// 加载账号存储数据
 #[tauri::command]
 fn load_accounts_store() -> Result<String, String> {
@@ -184,8 +382,31 @@ fn load_accounts_store() -> Result<String, String> {
 /// 保存账号存储数据
 #[tauri::command]
 fn save_accounts_store(data: String) -> Result<(), String> {
+    let _guard = ACCOUNTS_STORE_LOCK
+        .lock()
+        .map_err(|_| "Accounts store lock poisoned".to_string())?;
+    write_accounts_store_unlocked(&data)
+}
+
+fn write_accounts_store_unlocked(data: &str) -> Result<(), String> {
     let path = get_accounts_store_path()?;
-    fs::write(&path, data).map_err(|e| e.to_string())
+    write_file_atomic(&path, data.as_bytes())?;
+    if let Ok(mut cache) = ACCOUNT_SESSION_KEYS_CACHE.lock() {
+        *cache = None;
+    }
+    Ok(())
+}
+
+/// 先写入同目录的临时文件再重命名，写入中途崩溃不会留下不完整的文件
+fn write_file_atomic(path: &Path, data: &[u8]) -> Result<(), String> {
+    let mut tmp_name = path
+        .file_name()
+        .ok_or_else(|| "Invalid file path".to_string())?
+        .to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    fs::write(&tmp_path, data).map_err(|e| e.to_string())?;
+    fs::rename(&tmp_path, path).map_err(|e| e.to_string())
 }
 
 fn load_accounts_store_data() -> Result<TrayAccountsStore, String> {
@@ -205,6 +426,20 @@ fn load_accounts_store_data() -> Result<TrayAccountsStore, String> {
                 proxy_url: Some("http://127.0.0.1:7890".to_string()),
                 auto_restart_codex_on_switch: Some(false),
                 skip_switch_restart_confirm: Some(false),
+                global_shortcuts: None,
+                tray_sort_mode: Some("manual".to_string()),
+                tray_hide_unavailable: Some(false),
+                tray_popover_enabled: Some(false),
+                refresh_concurrency: Some(DEFAULT_REFRESH_CONCURRENCY as u64),
+                request_connect_timeout_seconds: Some(DEFAULT_CONNECT_TIMEOUT_SECONDS),
+                request_read_timeout_seconds: Some(DEFAULT_READ_TIMEOUT_SECONDS),
+                usage_history_enabled: Some(true),
+                usage_history_retention_days: Some(DEFAULT_USAGE_HISTORY_RETENTION_DAYS),
+                api_base_url: None,
+                proxy_mode: Some(PROXY_MODE_ENV.to_string()),
+                proxy_no_proxy: None,
+                tls_ca_bundle_paths: None,
+                tls_use_system_certs: Some(false),
             },
         });
     }
@@ -213,9 +448,20 @@ fn load_accounts_store_data() -> Result<TrayAccountsStore, String> {
     serde_json::from_str(&content).map_err(|e| e.to_string())
 }
 
-fn save_accounts_store_data(store: &TrayAccountsStore) -> Result<(), String> {
-    let data = serde_json::to_string_pretty(store).map_err(|e| e.to_string())?;
-    save_accounts_store(data)
+/// 在存储锁内读取 accounts.json 并交给 `update` 修改；返回 true 时写回，并返回写回后的数据
+fn update_accounts_store_data(
+    update: impl FnOnce(&mut TrayAccountsStore) -> Result<bool, String>,
+) -> Result<Option<TrayAccountsStore>, String> {
+    let _guard = ACCOUNTS_STORE_LOCK
+        .lock()
+        .map_err(|_| "Accounts store lock poisoned".to_string())?;
+    let mut store = load_accounts_store_data()?;
+    if !update(&mut store)? {
+        return Ok(None);
+    }
+    let data = serde_json::to_string_pretty(&store).map_err(|e| e.to_string())?;
+    write_accounts_store_unlocked(&data)?;
+    Ok(Some(store))
 }
 
 fn persist_current_auth_to_matching_account() -> Result<(), String> {
@@ -256,6 +502,25 @@ fn normalize_tray_close_behavior(value: Option<&str>) -> &'static str {
     }
 }
 
+fn summarize_context_window(context_window: &ContextWindowUsage) -> TrayContextWindowSummary {
+    TrayContextWindowSummary {
+        percent_left: context_window.percent_left.round(),
+        used: format_token_count(context_window.used_tokens),
+        total: format_token_count(context_window.total_tokens),
+    }
+}
+
+/// 紧凑的 token 数（如 850、45.2K、1M）
+fn format_token_count(tokens: u64) -> String {
+    let (value, unit) = match tokens {
+        0..=999 => return tokens.to_string(),
+        1_000..=999_999 => (tokens as f64 / 1_000.0, "K"),
+        _ => (tokens as f64 / 1_000_000.0, "M"),
+    };
+    let text = format!("{:.1}", value);
+    format!("{}{}", text.strip_suffix(".0").unwrap_or(&text), unit)
+}
+
 fn format_tray_percent(limit: Option<&TrayLimitSummary>, label: &str) -> String {
     match limit {
         Some(limit) => format!("{} {:.0}%", label, limit.percent_left),
@@ -294,8 +559,26 @@ fn build_tray_usage_summary(result: &UsageResult) -> TrayUsageSummary {
             .usage
             .as_ref()
             .and_then(|usage| usage.source_file.clone()),
+        next_retry_at_ms: result.next_retry_at_ms,
+        details: result
+            .usage
+            .as_ref()
+            .and_then(|usage| usage.details.clone()),
+        source: result.usage.as_ref().and_then(|usage| usage.source.clone()),
+        source_age_ms: result.usage.as_ref().and_then(|usage| usage.age_ms),
+        windows: result
+            .usage
+            .as_ref()
+            .map(|usage| usage.windows.clone())
+            .filter(|windows| !windows.is_empty()),
     };
 
+    summary.context_window = result
+        .usage
+        .as_ref()
+        .and_then(|usage| usage.context_window.as_ref())
+        .map(summarize_context_window);
+
     if let Some(usage) = &result.usage {
         summary.last_updated = Some(usage.last_updated.clone());
 
@@ -306,6 +589,9 @@ fn build_tray_usage_summary(result: &UsageResult) -> TrayUsageSummary {
                 summary.five_hour_limit = Some(TrayLimitSummary {
                     percent_left: percent_left.round(),
                     reset_time,
+                    reset_time_ms: Some(reset_time_ms),
+                    burn_rate_per_hour: usage.five_hour_burn_rate_per_hour,
+                    projected_exhaustion_ms: usage.five_hour_projected_exhaustion_ms,
                 });
             }
         }
@@ -317,6 +603,9 @@ fn build_tray_usage_summary(result: &UsageResult) -> TrayUsageSummary {
                 summary.weekly_limit = Some(TrayLimitSummary {
                     percent_left: percent_left.round(),
                     reset_time,
+                    reset_time_ms: Some(reset_time_ms),
+                    burn_rate_per_hour: usage.weekly_burn_rate_per_hour,
+                    projected_exhaustion_ms: usage.weekly_projected_exhaustion_ms,
                 });
             }
         }
@@ -329,6 +618,9 @@ fn build_tray_usage_summary(result: &UsageResult) -> TrayUsageSummary {
                 summary.code_review_limit = Some(TrayLimitSummary {
                     percent_left: percent_left.round(),
                     reset_time,
+                    reset_time_ms: Some(reset_time_ms),
+                    burn_rate_per_hour: None,
+                    projected_exhaustion_ms: None,
                 });
             }
         }
@@ -376,14 +668,97 @@ fn build_tray_account_title(account: &TrayStoredAccount) -> String {
     }
 }
 
+/// 将剩余毫秒数格式化为紧凑的倒计时（如 1h12m、3d4h），不足一分钟按一分钟计
+fn format_reset_countdown(remaining_ms: i64) -> String {
+    let total_minutes = (remaining_ms.max(0) + 59_999) / 60_000;
+    let days = total_minutes / 1_440;
+    let hours = total_minutes % 1_440 / 60;
+    let minutes = total_minutes % 60;
+
+    if days > 0 {
+        format!("{}d{}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h{}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+fn format_tray_limit_with_countdown(limit: &TrayLimitSummary, label: &str, now_ms: i64) -> String {
+    let text = match limit.reset_time_ms {
+        Some(reset_time_ms) if reset_time_ms <= now_ms => format!("{} 已重置·待刷新", label),
+        Some(reset_time_ms) => format!(
+            "{} · {}后重置",
+            format_tray_percent(Some(limit), label),
+            format_reset_countdown(reset_time_ms - now_ms)
+        ),
+        None => format_tray_percent(Some(limit), label),
+    };
+
+    match limit.projected_exhaustion_ms {
+        Some(exhaustion_ms) if exhaustion_ms > now_ms => format!(
+            "{} · 约{}后耗尽",
+            text,
+            format_reset_countdown(exhaustion_ms - now_ms)
+        ),
+        _ => text,
+    }
+}
+
 fn build_tray_account_detail(account: &TrayStoredAccount) -> String {
+    let now_ms = i64::try_from(now_epoch_ms_u64()).unwrap_or(i64::MAX);
+    build_tray_account_detail_at(account, now_ms)
+}
+
+fn build_tray_account_detail_at(account: &TrayStoredAccount, now_ms: i64) -> String {
     let usage = account.usage_info.as_ref();
     let mut parts = Vec::new();
+    let retry_label = match usage.and_then(|current| current.status.as_deref()) {
+        Some("cooldown") => Some("冷却中"),
+        Some("rate_limited") => Some("限流中"),
+        _ => None,
+    };
+    if let (Some(retry_label), Some(next_retry_at_ms)) = (
+        retry_label,
+        usage
+            .and_then(|current| current.next_retry_at_ms)
+            .and_then(|next_retry_at_ms| i64::try_from(next_retry_at_ms).ok()),
+    ) {
+        if next_retry_at_ms > now_ms {
+            parts.push(format!(
+                "{}·{}后重试",
+                retry_label,
+                format_reset_countdown(next_retry_at_ms - now_ms)
+            ));
+        }
+    }
+    if usage.and_then(|current| current.source.as_deref()) == Some(USAGE_SOURCE_SESSION) {
+        let age = usage
+            .and_then(|current| current.source_age_ms)
+            .map(|age_ms| {
+                format!(
+                    "·{}前",
+                    format_reset_countdown(i64::try_from(age_ms).unwrap_or(i64::MAX))
+                )
+            })
+            .unwrap_or_default();
+        parts.push(format!("本地数据{}", age));
+    }
+    if usage
+        .and_then(|current| current.details.as_ref())
+        .is_some_and(|details| details.blocked)
+    {
+        parts.push("已受限".to_string());
+    }
     if let Some(five_hour_limit) = usage.and_then(|current| current.five_hour_limit.as_ref()) {
-        parts.push(format_tray_percent(Some(five_hour_limit), "5H"));
+        parts.push(format_tray_limit_with_countdown(
+            five_hour_limit,
+            "5H",
+            now_ms,
+        ));
     }
     if let Some(weekly_limit) = usage.and_then(|current| current.weekly_limit.as_ref()) {
-        parts.push(format_tray_percent(Some(weekly_limit), "周"));
+        parts.push(format_tray_limit_with_countdown(weekly_limit, "周", now_ms));
     }
     let code_review = format_tray_percent(
         usage.and_then(|current| current.code_review_limit.as_ref()),
@@ -392,10 +767,139 @@ fn build_tray_account_detail(account: &TrayStoredAccount) -> String {
     let expiry = format_tray_expiry(account.account_info.subscription_active_until.as_deref());
 
     parts.push(code_review);
+    // 5H / 周 / 审查之外的额度窗口（如每日额度、按模型计量的额外额度）
+    for window in usage
+        .and_then(|current| current.windows.as_ref())
+        .into_iter()
+        .flatten()
+        .filter(|window| !window.is_legacy_limit())
+    {
+        parts.push(format!("{} {}%", window.label, window.percent_left.round()));
+    }
+    // 上下文窗口只对应当前账号正在进行的会话
+    if let Some(context_window) = usage
+        .filter(|_| account.is_active)
+        .and_then(|current| current.context_window.as_ref())
+    {
+        parts.push(format!("上下文 {:.0}%", context_window.percent_left));
+    }
     parts.push(expiry);
     parts.join("  ")
 }
 
+fn normalize_tray_sort_mode(value: Option<&str>) -> &'static str {
+    match value.unwrap_or_default() {
+        "remaining" => "remaining",
+        "reset" => "reset",
+        "alphabetical" => "alphabetical",
+        _ => "manual",
+    }
+}
+
+fn is_tray_account_unavailable(account: &TrayStoredAccount) -> bool {
+    matches!(
+        account
+            .usage_info
+            .as_ref()
+            .and_then(|usage| usage.status.as_deref()),
+        Some("expired") | Some("forbidden")
+    )
+}
+
+fn tray_account_next_reset_ms(account: &TrayStoredAccount) -> Option<i64> {
+    let usage = account.usage_info.as_ref()?;
+    [usage.five_hour_limit.as_ref(), usage.weekly_limit.as_ref()]
+        .into_iter()
+        .flatten()
+        .filter_map(|limit| limit.reset_time_ms)
+        .min()
+}
+
+/// 比较可选值，缺失的值始终排在后面
+fn compare_present_first<T: PartialOrd>(
+    a: Option<T>,
+    b: Option<T>,
+    descending: bool,
+) -> std::cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let ordering = a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal);
+            if descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        }
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+}
+
+fn sort_tray_accounts<'a>(
+    accounts: &'a [TrayStoredAccount],
+    sort_mode: &str,
+) -> Vec<&'a TrayStoredAccount> {
+    let mut sorted: Vec<&TrayStoredAccount> = accounts.iter().collect();
+    sorted.sort_by(|a, b| {
+        let pinned = b.pinned.unwrap_or(false).cmp(&a.pinned.unwrap_or(false));
+        pinned.then_with(|| match sort_mode {
+            "remaining" => compare_present_first(
+                tray_account_quota_score(a),
+                tray_account_quota_score(b),
+                true,
+            ),
+            "reset" => compare_present_first(
+                tray_account_next_reset_ms(a),
+                tray_account_next_reset_ms(b),
+                false,
+            ),
+            "alphabetical" => build_tray_account_title(a)
+                .to_lowercase()
+                .cmp(&build_tray_account_title(b).to_lowercase()),
+            _ => compare_present_first(a.sort_index, b.sort_index, false),
+        })
+    });
+    sorted
+}
+
+#[derive(Debug, Default)]
+struct TrayAccountLayout<'a> {
+    top_level: Vec<&'a TrayStoredAccount>,
+    groups: Vec<(String, Vec<&'a TrayStoredAccount>)>,
+}
+
+/// 计算托盘中账号的展示结构：置顶与未分组账号在顶层，其余按分组放入子菜单
+fn layout_tray_accounts<'a>(
+    accounts: &'a [TrayStoredAccount],
+    config: &TrayAppConfig,
+) -> TrayAccountLayout<'a> {
+    let sort_mode = normalize_tray_sort_mode(config.tray_sort_mode.as_deref());
+    let hide_unavailable = config.tray_hide_unavailable.unwrap_or(false);
+    let mut layout = TrayAccountLayout::default();
+
+    for account in sort_tray_accounts(accounts, sort_mode) {
+        if hide_unavailable && !account.is_active && is_tray_account_unavailable(account) {
+            continue;
+        }
+
+        match normalize_tray_text(account.group.as_deref()) {
+            Some(group) if !account.pinned.unwrap_or(false) => {
+                if let Some((_, members)) =
+                    layout.groups.iter_mut().find(|(name, _)| *name == group)
+                {
+                    members.push(account);
+                } else {
+                    layout.groups.push((group, vec![account]));
+                }
+            }
+            _ => layout.top_level.push(account),
+        }
+    }
+
+    layout
+}
+
 fn show_main_window_internal<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
     let window = app
         .get_webview_window("main")
@@ -413,6 +917,107 @@ fn hide_to_tray_internal<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
     window.hide().map_err(|e| e.to_string())
 }
 
+/// 计算迷你面板位置：水平居中于点击点，靠近任务栏一侧弹出，并限制在显示器工作区内
+fn compute_popover_position(
+    anchor: (f64, f64),
+    size: (f64, f64),
+    work_area: (f64, f64, f64, f64),
+) -> (f64, f64) {
+    let (anchor_x, anchor_y) = anchor;
+    let (width, height) = size;
+    let (area_x, area_y, area_width, area_height) = work_area;
+    let max_x = (area_x + area_width - width).max(area_x);
+    let max_y = (area_y + area_height - height).max(area_y);
+
+    let x = (anchor_x - width / 2.0).clamp(area_x, max_x);
+    let y = if anchor_y >= area_y + area_height / 2.0 {
+        anchor_y - height - TRAY_POPOVER_MARGIN
+    } else {
+        anchor_y + TRAY_POPOVER_MARGIN
+    };
+
+    (x, y.clamp(area_y, max_y))
+}
+
+fn hide_tray_popover_internal<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    match app.get_webview_window(TRAY_POPOVER_LABEL) {
+        Some(window) => window.hide().map_err(|e| e.to_string()),
+        None => Ok(()),
+    }
+}
+
+fn get_or_create_tray_popover<R: Runtime>(app: &AppHandle<R>) -> Result<WebviewWindow<R>, String> {
+    if let Some(window) = app.get_webview_window(TRAY_POPOVER_LABEL) {
+        return Ok(window);
+    }
+
+    let window = WebviewWindowBuilder::new(app, TRAY_POPOVER_LABEL, WebviewUrl::default())
+        .title("Codex Manager")
+        .inner_size(TRAY_POPOVER_WIDTH, TRAY_POPOVER_HEIGHT)
+        .resizable(false)
+        .decorations(false)
+        .skip_taskbar(true)
+        .always_on_top(true)
+        .visible(false)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let popover = window.clone();
+    window.on_window_event(move |event| {
+        if let WindowEvent::Focused(false) = event {
+            TRAY_POPOVER_HIDDEN_AT_MS.store(now_epoch_ms_u64(), Ordering::SeqCst);
+            let _ = popover.hide();
+        }
+    });
+
+    Ok(window)
+}
+
+fn toggle_tray_popover<R: Runtime>(
+    app: &AppHandle<R>,
+    anchor: PhysicalPosition<f64>,
+) -> Result<(), String> {
+    let window = get_or_create_tray_popover(app)?;
+    if window.is_visible().unwrap_or(false) {
+        return window.hide().map_err(|e| e.to_string());
+    }
+
+    // 点击托盘图标会先让面板失焦隐藏，短时间内的再次点击视为关闭
+    let hidden_at = TRAY_POPOVER_HIDDEN_AT_MS.load(Ordering::SeqCst);
+    if now_epoch_ms_u64().saturating_sub(hidden_at) < 300 {
+        return Ok(());
+    }
+
+    if let Some(monitor) = app
+        .monitor_from_point(anchor.x, anchor.y)
+        .map_err(|e| e.to_string())?
+    {
+        let scale_factor = monitor.scale_factor();
+        let work_area = monitor.work_area();
+        let (x, y) = compute_popover_position(
+            (anchor.x, anchor.y),
+            (
+                TRAY_POPOVER_WIDTH * scale_factor,
+                TRAY_POPOVER_HEIGHT * scale_factor,
+            ),
+            (
+                f64::from(work_area.position.x),
+                f64::from(work_area.position.y),
+                f64::from(work_area.size.width),
+                f64::from(work_area.size.height),
+            ),
+        );
+        window
+            .set_position(PhysicalPosition::new(x, y))
+            .map_err(|e| e.to_string())?;
+    }
+
+    window.show().map_err(|e| e.to_string())?;
+    let _ = window.set_focus();
+    let _ = window.emit("tray-popover-shown", ());
+    Ok(())
+}
+
 fn switch_account_from_tray<R: Runtime>(
     app: &AppHandle<R>,
     account_id: &str,
@@ -422,24 +1027,24 @@ fn switch_account_from_tray<R: Runtime>(
     let auth_json = read_account_auth(account_id.to_string())?;
     write_codex_auth(auth_json)?;
 
-    let mut store = load_accounts_store_data()?;
-    let mut matched = false;
-    let now = now_epoch_ms_string();
+    update_accounts_store_data(|store| {
+        let mut matched = false;
+        let now = now_epoch_ms_string();
 
-    for account in store.accounts.iter_mut() {
-        let is_target = account.id == account_id;
-        if is_target {
-            matched = true;
-            account.updated_at = now.clone();
+        for account in store.accounts.iter_mut() {
+            let is_target = account.id == account_id;
+            if is_target {
+                matched = true;
+                account.updated_at = now.clone();
+            }
+            account.is_active = is_target;
         }
-        account.is_active = is_target;
-    }
-
-    if !matched {
-        return Err("\u{76ee}\u{6807}\u{8d26}\u{53f7}\u{4e0d}\u{5b58}\u{5728}".to_string());
-    }
 
-    save_accounts_store_data(&store)?;
+        if !matched {
+            return Err("\u{76ee}\u{6807}\u{8d26}\u{53f7}\u{4e0d}\u{5b58}\u{5728}".to_string());
+        }
+        Ok(true)
+    })?;
     let _ = refresh_tray_menu_internal(app);
 
     if let Some(window) = app.get_webview_window("main") {
@@ -454,2459 +1059,8332 @@ fn switch_account_from_tray<R: Runtime>(
     Ok(())
 }
 
-fn build_tray_menu<R: Runtime>(app: &AppHandle<R>) -> Result<Menu<R>, String> {
-    let store = load_accounts_store_data()?;
-    let menu = Menu::new(app).map_err(|e| e.to_string())?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GlobalShortcutAction {
+    NextAccount,
+    PreviousAccount,
+    RecommendedAccount,
+    OpenWindow,
+    RefreshAll,
+}
 
-    let open_item = MenuItem::with_id(app, TRAY_MENU_OPEN_ID, "打开主界面", true, None::<&str>)
-        .map_err(|e| e.to_string())?;
-    menu.append(&open_item).map_err(|e| e.to_string())?;
+fn collect_global_shortcut_bindings(
+    shortcuts: Option<&TrayGlobalShortcuts>,
+) -> Vec<(GlobalShortcutAction, String)> {
+    let Some(shortcuts) = shortcuts else {
+        return Vec::new();
+    };
 
-    let separator = PredefinedMenuItem::separator(app).map_err(|e| e.to_string())?;
-    menu.append(&separator).map_err(|e| e.to_string())?;
+    [
+        (
+            GlobalShortcutAction::NextAccount,
+            shortcuts.next_account.as_deref(),
+        ),
+        (
+            GlobalShortcutAction::PreviousAccount,
+            shortcuts.previous_account.as_deref(),
+        ),
+        (
+            GlobalShortcutAction::RecommendedAccount,
+            shortcuts.recommended_account.as_deref(),
+        ),
+        (
+            GlobalShortcutAction::OpenWindow,
+            shortcuts.open_window.as_deref(),
+        ),
+        (
+            GlobalShortcutAction::RefreshAll,
+            shortcuts.refresh_all.as_deref(),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(action, accelerator)| {
+        normalize_tray_text(accelerator).map(|accelerator| (action, accelerator))
+    })
+    .collect()
+}
 
-    if store.accounts.is_empty() {
-        let empty_item = MenuItem::with_id(
-            app,
-            "tray-empty",
-            "暂无账号，请先在主界面导入",
-            false,
-            None::<&str>,
-        )
-        .map_err(|e| e.to_string())?;
-        menu.append(&empty_item).map_err(|e| e.to_string())?;
-    } else {
-        for account in &store.accounts {
-            let account_item = CheckMenuItem::with_id(
-                app,
-                format!("account:{}", account.id),
-                build_tray_account_title(account),
-                true,
-                account.is_active,
-                None::<&str>,
-            )
-            .map_err(|e| e.to_string())?;
-            menu.append(&account_item).map_err(|e| e.to_string())?;
+fn cycle_account_index(len: usize, current: Option<usize>, forward: bool) -> Option<usize> {
+    if len == 0 {
+        return None;
+    }
 
-            let detail_item = MenuItem::with_id(
-                app,
-                format!("account-detail:{}", account.id),
-                build_tray_account_detail(account),
-                false,
-                None::<&str>,
-            )
-            .map_err(|e| e.to_string())?;
-            menu.append(&detail_item).map_err(|e| e.to_string())?;
+    Some(match (current, forward) {
+        (None, true) => 0,
+        (None, false) => len - 1,
+        (Some(index), true) => (index + 1) % len,
+        (Some(index), false) => (index + len - 1) % len,
+    })
+}
 
-            let account_separator =
-                PredefinedMenuItem::separator(app).map_err(|e| e.to_string())?;
-            menu.append(&account_separator).map_err(|e| e.to_string())?;
-        }
+/// 账号剩余额度评分：取各窗口剩余百分比的最小值，状态异常的账号不参与推荐
+fn tray_account_quota_score(account: &TrayStoredAccount) -> Option<f64> {
+    let usage = account.usage_info.as_ref()?;
+    if !matches!(usage.status.as_deref(), None | Some("ok")) {
+        return None;
     }
 
-    let close_behavior_item = MenuItem::with_id(
-        app,
-        "tray-close-behavior",
-        format!(
-            "关闭按钮：{}",
-            match normalize_tray_close_behavior(store.config.close_behavior.as_deref()) {
-                "exit" => "直接退出",
-                "tray" => "最小化到托盘",
-                _ => "每次询问",
-            }
-        ),
-        false,
-        None::<&str>,
-    )
-    .map_err(|e| e.to_string())?;
-    menu.append(&close_behavior_item)
-        .map_err(|e| e.to_string())?;
+    [usage.five_hour_limit.as_ref(), usage.weekly_limit.as_ref()]
+        .into_iter()
+        .flatten()
+        .map(|limit| limit.percent_left)
+        .reduce(f64::min)
+}
 
-    let exit_separator = PredefinedMenuItem::separator(app).map_err(|e| e.to_string())?;
-    menu.append(&exit_separator).map_err(|e| e.to_string())?;
+fn find_recommended_account_id(accounts: &[TrayStoredAccount]) -> Option<String> {
+    let mut best: Option<(&TrayStoredAccount, f64)> = None;
 
-    let exit_item = MenuItem::with_id(app, TRAY_MENU_EXIT_ID, "退出", true, None::<&str>)
-        .map_err(|e| e.to_string())?;
-    menu.append(&exit_item).map_err(|e| e.to_string())?;
+    for account in accounts {
+        let Some(score) = tray_account_quota_score(account) else {
+            continue;
+        };
+        let is_better = match best {
+            Some((_, best_score)) => score > best_score,
+            None => true,
+        };
+        if is_better {
+            best = Some((account, score));
+        }
+    }
 
-    Ok(menu)
+    best.map(|(account, _)| account.id.clone())
 }
 
-fn refresh_tray_menu_internal<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
-    let tray = app
-        .tray_by_id(TRAY_ID)
-        .ok_or_else(|| "托盘图标不存在".to_string())?;
-    let menu = build_tray_menu(app)?;
-    tray.set_menu(Some(menu)).map_err(|e| e.to_string())
+fn cycle_account_from_shortcut<R: Runtime>(
+    app: &AppHandle<R>,
+    forward: bool,
+) -> Result<(), String> {
+    let store = load_accounts_store_data()?;
+    let current = store.accounts.iter().position(|account| account.is_active);
+    let index = cycle_account_index(store.accounts.len(), current, forward)
+        .ok_or_else(|| "暂无可切换的账号".to_string())?;
+
+    if Some(index) == current {
+        return Ok(());
+    }
+
+    switch_account_from_tray(app, &store.accounts[index].id)
 }
 
-fn now_epoch_ms_u64() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|duration| duration.as_millis() as u64)
-        .unwrap_or_default()
+fn switch_to_recommended_account<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let store = load_accounts_store_data()?;
+    let account_id = find_recommended_account_id(&store.accounts)
+        .ok_or_else(|| "暂无可推荐的账号，请先刷新用量".to_string())?;
+
+    if store
+        .accounts
+        .iter()
+        .any(|account| account.id == account_id && account.is_active)
+    {
+        return Ok(());
+    }
+
+    switch_account_from_tray(app, &account_id)
 }
 
-fn should_run_background_auto_refresh(
-    interval_minutes: u64,
-    last_refresh_ms: u64,
-    current_ms: u64,
-) -> bool {
-    if interval_minutes == 0 {
-        return false;
+fn handle_global_shortcut_action<R: Runtime>(app: &AppHandle<R>, action: GlobalShortcutAction) {
+    let result = match action {
+        GlobalShortcutAction::NextAccount => cycle_account_from_shortcut(app, true),
+        GlobalShortcutAction::PreviousAccount => cycle_account_from_shortcut(app, false),
+        GlobalShortcutAction::RecommendedAccount => switch_to_recommended_account(app),
+        GlobalShortcutAction::OpenWindow => show_main_window_internal(app),
+        GlobalShortcutAction::RefreshAll => {
+            trigger_usage_refresh_now(app);
+            Ok(())
+        }
+    };
+
+    if let Err(error) = result {
+        log::warn!("全局快捷键执行失败 ({:?}): {}", action, error);
     }
+}
 
-    if last_refresh_ms == 0 {
-        return true;
+/// 按配置重新注册全局快捷键，单个快捷键失败不影响其余快捷键
+fn register_global_shortcuts<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let store = load_accounts_store_data()?;
+    let global_shortcut = app.global_shortcut();
+    global_shortcut
+        .unregister_all()
+        .map_err(|e| e.to_string())?;
+
+    let mut failures = Vec::new();
+    for (action, accelerator) in
+        collect_global_shortcut_bindings(store.config.global_shortcuts.as_ref())
+    {
+        let result =
+            global_shortcut.on_shortcut(accelerator.as_str(), move |app, _shortcut, event| {
+                if event.state == ShortcutState::Pressed {
+                    handle_global_shortcut_action(app, action);
+                }
+            });
+
+        if let Err(error) = result {
+            failures.push(format!("{}: {}", accelerator, error));
+        }
     }
 
-    let interval_ms = interval_minutes.saturating_mul(60_000);
-    current_ms.saturating_sub(last_refresh_ms) >= interval_ms
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("部分快捷键注册失败：{}", failures.join("；")))
+    }
 }
 
-async fn refresh_accounts_usage_in_background<R: Runtime>(
+fn build_tray_account_items<R: Runtime>(
     app: &AppHandle<R>,
-) -> Result<usize, String> {
-    let store = load_accounts_store_data()?;
-    if store.accounts.is_empty() {
-        return Ok(0);
-    }
+    account: &TrayStoredAccount,
+) -> Result<(CheckMenuItem<R>, MenuItem<R>), String> {
+    let title = build_tray_account_title(account);
+    let account_item = CheckMenuItem::with_id(
+        app,
+        format!("account:{}", account.id),
+        if account.pinned.unwrap_or(false) {
+            format!("★ {}", title)
+        } else {
+            title
+        },
+        true,
+        account.is_active,
+        None::<&str>,
+    )
+    .map_err(|e| e.to_string())?;
 
-    let proxy_enabled = store.config.proxy_enabled;
-    let proxy_url = store.config.proxy_url.clone();
-    let mut updated_count = 0usize;
-    let mut usage_updates: HashMap<String, TrayUsageSummary> = HashMap::new();
-
-    for account in &store.accounts {
-        let result = match get_codex_wham_usage(
-            account.id.clone(),
-            proxy_enabled,
-            proxy_url.clone(),
-        )
-        .await
-        {
-            Ok(result) => result,
-            Err(error) => UsageResult {
-                status: "error".to_string(),
-                message: Some(error),
-                plan_type: None,
-                usage: None,
-            },
-        };
+    let detail_item = MenuItem::with_id(
+        app,
+        format!("account-detail:{}", account.id),
+        build_tray_account_detail(account),
+        false,
+        None::<&str>,
+    )
+    .map_err(|e| e.to_string())?;
 
-        if result.status == "ok" {
-            updated_count += 1;
-        }
+    Ok((account_item, detail_item))
+}
 
-        usage_updates.insert(account.id.clone(), build_tray_usage_summary(&result));
+fn format_close_behavior_label(close_behavior: &str) -> &'static str {
+    match close_behavior {
+        "exit" => "直接退出",
+        "tray" => "最小化到托盘",
+        _ => "每次询问",
     }
+}
 
-    let mut latest_store = load_accounts_store_data()?;
-    let mut changed = false;
-    for account in latest_store.accounts.iter_mut() {
-        if let Some(summary) = usage_updates.get(&account.id) {
-            account.usage_info = Some(summary.clone());
-            changed = true;
-        }
+fn format_tray_sort_mode_label(sort_mode: &str) -> &'static str {
+    match sort_mode {
+        "remaining" => "按剩余额度",
+        "reset" => "按重置时间",
+        "alphabetical" => "按名称",
+        _ => "手动顺序",
     }
+}
 
-    if changed {
-        save_accounts_store_data(&latest_store)?;
+fn format_auto_refresh_interval_label(minutes: u64) -> String {
+    if minutes == 0 {
+        "关闭".to_string()
+    } else {
+        format!("每 {} 分钟", minutes)
     }
-    refresh_tray_menu_internal(app)?;
+}
 
-    if let Some(window) = app.get_webview_window("main") {
-        let _ = window.emit(
-            "background-usage-refreshed",
-            BackgroundUsageRefreshedPayload {
-                updated_count,
-                finished_at: now_epoch_ms_string(),
-            },
-        );
+fn build_tray_settings_submenu<R: Runtime>(
+    app: &AppHandle<R>,
+    config: &TrayAppConfig,
+) -> Result<Submenu<R>, String> {
+    let submenu =
+        Submenu::with_id(app, "tray-settings", "设置", true).map_err(|e| e.to_string())?;
+
+    let close_behavior = normalize_tray_close_behavior(config.close_behavior.as_deref());
+    let close_submenu = Submenu::with_id(
+        app,
+        "tray-settings-close",
+        format!("关闭按钮：{}", format_close_behavior_label(close_behavior)),
+        true,
+    )
+    .map_err(|e| e.to_string())?;
+    for value in ["ask", "tray", "exit"] {
+        let item = CheckMenuItem::with_id(
+            app,
+            format!("{}close:{}", TRAY_SETTING_ID_PREFIX, value),
+            format_close_behavior_label(value),
+            true,
+            close_behavior == value,
+            None::<&str>,
+        )
+        .map_err(|e| e.to_string())?;
+        close_submenu.append(&item).map_err(|e| e.to_string())?;
     }
+    submenu.append(&close_submenu).map_err(|e| e.to_string())?;
 
-    Ok(updated_count)
-}
+    let interval = config.auto_refresh_interval.unwrap_or(30);
+    let interval_submenu = Submenu::with_id(
+        app,
+        "tray-settings-interval",
+        format!("自动刷新：{}", format_auto_refresh_interval_label(interval)),
+        true,
+    )
+    .map_err(|e| e.to_string())?;
+    for minutes in TRAY_AUTO_REFRESH_PRESETS {
+        let item = CheckMenuItem::with_id(
+            app,
+            format!("{}interval:{}", TRAY_SETTING_ID_PREFIX, minutes),
+            format_auto_refresh_interval_label(minutes),
+            true,
+            interval == minutes,
+            None::<&str>,
+        )
+        .map_err(|e| e.to_string())?;
+        interval_submenu.append(&item).map_err(|e| e.to_string())?;
+    }
+    if !TRAY_AUTO_REFRESH_PRESETS.contains(&interval) {
+        let custom_item = CheckMenuItem::with_id(
+            app,
+            "tray-settings-interval-custom",
+            format!("自定义：{}", format_auto_refresh_interval_label(interval)),
+            false,
+            true,
+            None::<&str>,
+        )
+        .map_err(|e| e.to_string())?;
+        interval_submenu
+            .append(&custom_item)
+            .map_err(|e| e.to_string())?;
+    }
+    submenu
+        .append(&interval_submenu)
+        .map_err(|e| e.to_string())?;
 
-async fn maybe_run_background_auto_refresh<R: Runtime>(app: &AppHandle<R>) {
-    if AUTO_REFRESH_RUNNING.swap(true, Ordering::SeqCst) {
-        return;
+    let sort_mode = normalize_tray_sort_mode(config.tray_sort_mode.as_deref());
+    let sort_submenu = Submenu::with_id(
+        app,
+        "tray-settings-sort",
+        format!("账号排序：{}", format_tray_sort_mode_label(sort_mode)),
+        true,
+    )
+    .map_err(|e| e.to_string())?;
+    for value in ["manual", "remaining", "reset", "alphabetical"] {
+        let item = CheckMenuItem::with_id(
+            app,
+            format!("{}sort:{}", TRAY_SETTING_ID_PREFIX, value),
+            format_tray_sort_mode_label(value),
+            true,
+            sort_mode == value,
+            None::<&str>,
+        )
+        .map_err(|e| e.to_string())?;
+        sort_submenu.append(&item).map_err(|e| e.to_string())?;
     }
+    submenu.append(&sort_submenu).map_err(|e| e.to_string())?;
 
-    let result = async {
-        let store = load_accounts_store_data()?;
-        let interval_minutes = store.config.auto_refresh_interval.unwrap_or(30);
-        if interval_minutes == 0 || store.accounts.is_empty() {
-            return Ok::<(), String>(());
-        }
+    let hide_unavailable_item = CheckMenuItem::with_id(
+        app,
+        format!("{}hide-unavailable", TRAY_SETTING_ID_PREFIX),
+        "隐藏失效或被封禁的账号",
+        true,
+        config.tray_hide_unavailable.unwrap_or(false),
+        None::<&str>,
+    )
+    .map_err(|e| e.to_string())?;
+    submenu
+        .append(&hide_unavailable_item)
+        .map_err(|e| e.to_string())?;
 
-        let current_ms = now_epoch_ms_u64();
-        let last_refresh_ms = {
-            let guard = LAST_AUTO_REFRESH_MS
-                .lock()
-                .map_err(|_| "自动刷新状态锁不可用".to_string())?;
-            *guard
-        };
+    let proxy_item = CheckMenuItem::with_id(
+        app,
+        format!("{}proxy", TRAY_SETTING_ID_PREFIX),
+        "使用代理",
+        true,
+        config.proxy_enabled.unwrap_or(false),
+        None::<&str>,
+    )
+    .map_err(|e| e.to_string())?;
+    submenu.append(&proxy_item).map_err(|e| e.to_string())?;
 
-        if !should_run_background_auto_refresh(interval_minutes, last_refresh_ms, current_ms) {
-            return Ok(());
-        }
+    let auto_restart_item = CheckMenuItem::with_id(
+        app,
+        format!("{}auto-restart", TRAY_SETTING_ID_PREFIX),
+        "切换后自动重启 Codex",
+        true,
+        config.auto_restart_codex_on_switch.unwrap_or(false),
+        None::<&str>,
+    )
+    .map_err(|e| e.to_string())?;
+    submenu
+        .append(&auto_restart_item)
+        .map_err(|e| e.to_string())?;
+
+    let popover_item = CheckMenuItem::with_id(
+        app,
+        format!("{}popover", TRAY_SETTING_ID_PREFIX),
+        "左键打开迷你面板",
+        true,
+        config.tray_popover_enabled.unwrap_or(false),
+        None::<&str>,
+    )
+    .map_err(|e| e.to_string())?;
+    submenu.append(&popover_item).map_err(|e| e.to_string())?;
 
-        refresh_accounts_usage_in_background(app).await?;
+    Ok(submenu)
+}
 
-        let mut guard = LAST_AUTO_REFRESH_MS
-            .lock()
-            .map_err(|_| "自动刷新状态锁不可用".to_string())?;
-        *guard = current_ms;
-        Ok(())
+/// 将托盘设置菜单项应用到配置上，返回配置是否发生变化
+fn apply_tray_setting(config: &mut TrayAppConfig, setting: &str) -> Result<bool, String> {
+    if let Some(value) = setting.strip_prefix("close:") {
+        let close_behavior = normalize_tray_close_behavior(Some(value));
+        if close_behavior != value {
+            return Err(format!("未知的关闭行为: {}", value));
+        }
+        let changed = config.close_behavior.as_deref() != Some(close_behavior);
+        config.close_behavior = Some(close_behavior.to_string());
+        return Ok(changed);
     }
-    .await;
 
-    if let Err(error) = result {
-        log::warn!("后台自动刷新失败: {}", error);
+    if let Some(value) = setting.strip_prefix("sort:") {
+        let sort_mode = normalize_tray_sort_mode(Some(value));
+        if sort_mode != value {
+            return Err(format!("未知的排序方式: {}", value));
+        }
+        let changed = config.tray_sort_mode.as_deref() != Some(sort_mode);
+        config.tray_sort_mode = Some(sort_mode.to_string());
+        return Ok(changed);
     }
 
-    AUTO_REFRESH_RUNNING.store(false, Ordering::SeqCst);
-}
+    if let Some(value) = setting.strip_prefix("interval:") {
+        let minutes = value
+            .parse::<u64>()
+            .map_err(|_| format!("无效的自动刷新间隔: {}", value))?;
+        let changed = config.auto_refresh_interval != Some(minutes);
+        config.auto_refresh_interval = Some(minutes);
+        return Ok(changed);
+    }
 
-fn start_background_auto_refresh<R: Runtime>(app: &AppHandle<R>) {
-    let app_handle = app.clone();
-    tauri::async_runtime::spawn(async move {
-        loop {
-            maybe_run_background_auto_refresh(&app_handle).await;
-            tokio::time::sleep(Duration::from_secs(30)).await;
+    match setting {
+        "proxy" => {
+            let enabled = !config.proxy_enabled.unwrap_or(false);
+            if enabled && normalize_tray_text(config.proxy_url.as_deref()).is_none() {
+                return Err("代理地址为空，请先在主界面设置代理地址".to_string());
+            }
+            config.proxy_enabled = Some(enabled);
+            Ok(true)
         }
-    });
+        "auto-restart" => {
+            config.auto_restart_codex_on_switch =
+                Some(!config.auto_restart_codex_on_switch.unwrap_or(false));
+            Ok(true)
+        }
+        "popover" => {
+            config.tray_popover_enabled = Some(!config.tray_popover_enabled.unwrap_or(false));
+            Ok(true)
+        }
+        "hide-unavailable" => {
+            config.tray_hide_unavailable = Some(!config.tray_hide_unavailable.unwrap_or(false));
+            Ok(true)
+        }
+        _ => Err(format!("未知的托盘设置项: {}", setting)),
+    }
 }
 
-/// 写入Codex auth.json
-#[tauri::command]
-fn write_codex_auth(auth_config: String) -> Result<(), String> {
-    let path = get_codex_auth_path()?;
+fn update_config_from_tray<R: Runtime>(app: &AppHandle<R>, setting: &str) -> Result<(), String> {
+    let result = update_accounts_store_data(|store| apply_tray_setting(&mut store.config, setting));
 
-    // 确保.codex目录存在
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    if let Ok(Some(store)) = &result {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.emit(
+                "tray-config-changed",
+                TrayConfigChangedPayload {
+                    config: store.config.clone(),
+                },
+            );
+        }
     }
 
-    fs::write(&path, auth_config).map_err(|e| e.to_string())
+    // 勾选项被点击时会自行切换状态，重建菜单以保证与配置一致
+    refresh_tray_menu_internal(app)?;
+    result.map(|_| ())
 }
 
-/// 读取当前Codex auth.json
-#[tauri::command]
-fn read_codex_auth() -> Result<String, String> {
-    let path = get_codex_auth_path()?;
+fn build_tray_menu<R: Runtime>(app: &AppHandle<R>) -> Result<Menu<R>, String> {
+    let store = load_accounts_store_data()?;
+    let menu = Menu::new(app).map_err(|e| e.to_string())?;
 
-    if !path.exists() {
-        return Err("Codex auth.json not found".to_string());
-    }
+    let open_item = MenuItem::with_id(app, TRAY_MENU_OPEN_ID, "打开主界面", true, None::<&str>)
+        .map_err(|e| e.to_string())?;
+    menu.append(&open_item).map_err(|e| e.to_string())?;
 
-    fs::read_to_string(&path).map_err(|e| e.to_string())
-}
+    let separator = PredefinedMenuItem::separator(app).map_err(|e| e.to_string())?;
+    menu.append(&separator).map_err(|e| e.to_string())?;
 
-/// 保存指定账号 auth
-#[tauri::command]
-fn save_account_auth(account_id: String, auth_config: String) -> Result<(), String> {
-    let path = get_account_auth_path(&account_id)?;
-    fs::write(&path, auth_config).map_err(|e| e.to_string())
-}
+    let mut detail_items = Vec::new();
+    let layout = layout_tray_accounts(&store.accounts, &store.config);
+    if layout.top_level.is_empty() && layout.groups.is_empty() {
+        let empty_item = MenuItem::with_id(
+            app,
+            "tray-empty",
+            if store.accounts.is_empty() {
+                "暂无账号，请先在主界面导入"
+            } else {
+                "已隐藏全部失效或被封禁的账号"
+            },
+            false,
+            None::<&str>,
+        )
+        .map_err(|e| e.to_string())?;
+        menu.append(&empty_item).map_err(|e| e.to_string())?;
+    } else {
+        for account in &layout.top_level {
+            let (account_item, detail_item) = build_tray_account_items(app, account)?;
+            menu.append(&account_item).map_err(|e| e.to_string())?;
+            menu.append(&detail_item).map_err(|e| e.to_string())?;
+            detail_items.push((account.id.clone(), detail_item));
 
-/// 读取指定账号 auth
-#[tauri::command]
-fn read_account_auth(account_id: String) -> Result<String, String> {
-    let path = get_account_auth_path(&account_id)?;
-    if !path.exists() {
-        return Err("Account auth not found".to_string());
-    }
-    fs::read_to_string(&path).map_err(|e| e.to_string())
-}
+            let account_separator =
+                PredefinedMenuItem::separator(app).map_err(|e| e.to_string())?;
+            menu.append(&account_separator).map_err(|e| e.to_string())?;
+        }
 
-/// 删除指定账号 auth
-#[tauri::command]
-fn delete_account_auth(account_id: String) -> Result<(), String> {
-    let path = get_account_auth_path(&account_id)?;
-    if path.exists() {
-        fs::remove_file(&path).map_err(|e| e.to_string())?;
-    }
-    Ok(())
-}
+        for (group, accounts) in &layout.groups {
+            let submenu = Submenu::with_id(
+                app,
+                format!("tray-group:{}", group),
+                format!("{} ({})", group, accounts.len()),
+                true,
+            )
+            .map_err(|e| e.to_string())?;
 
-/// 读取文件内容
-#[tauri::command]
-fn read_file_content(file_path: String) -> Result<String, String> {
-    fs::read_to_string(&file_path).map_err(|e| e.to_string())
-}
+            for (index, account) in accounts.iter().enumerate() {
+                if index > 0 {
+                    let account_separator =
+                        PredefinedMenuItem::separator(app).map_err(|e| e.to_string())?;
+                    submenu
+                        .append(&account_separator)
+                        .map_err(|e| e.to_string())?;
+                }
 
-/// 写入文件内容
-#[tauri::command]
-fn write_file_content(file_path: String, content: String) -> Result<(), String> {
-    let path = PathBuf::from(file_path);
+                let (account_item, detail_item) = build_tray_account_items(app, account)?;
+                submenu.append(&account_item).map_err(|e| e.to_string())?;
+                submenu.append(&detail_item).map_err(|e| e.to_string())?;
+                detail_items.push((account.id.clone(), detail_item));
+            }
 
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            menu.append(&submenu).map_err(|e| e.to_string())?;
+        }
+
+        if !layout.groups.is_empty() {
+            let group_separator = PredefinedMenuItem::separator(app).map_err(|e| e.to_string())?;
+            menu.append(&group_separator).map_err(|e| e.to_string())?;
+        }
     }
 
-    fs::write(path, content).map_err(|e| e.to_string())
-}
+    let settings_submenu = build_tray_settings_submenu(app, &store.config)?;
+    menu.append(&settings_submenu).map_err(|e| e.to_string())?;
 
-/// 获取用户主目录
-#[tauri::command]
-fn get_home_dir() -> Result<String, String> {
-    dirs::home_dir()
-        .map(|p| p.to_string_lossy().to_string())
-        .ok_or_else(|| "Cannot find home directory".to_string())
+    let exit_separator = PredefinedMenuItem::separator(app).map_err(|e| e.to_string())?;
+    menu.append(&exit_separator).map_err(|e| e.to_string())?;
+
+    let exit_item = MenuItem::with_id(app, TRAY_MENU_EXIT_ID, "退出", true, None::<&str>)
+        .map_err(|e| e.to_string())?;
+    menu.append(&exit_item).map_err(|e| e.to_string())?;
+
+    if let Some(state) = app.try_state::<TrayDetailItems<R>>() {
+        if let Ok(mut guard) = state.0.lock() {
+            *guard = detail_items;
+        }
+    }
+
+    Ok(menu)
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct RestartCodexProcessesResult {
-    app_restarted: bool,
-    cli_restarted: bool,
+/// 仅根据本地存储重算托盘详情文字（倒计时），不发起网络请求
+fn refresh_tray_detail_texts<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let Some(state) = app.try_state::<TrayDetailItems<R>>() else {
+        return Ok(());
+    };
+    let detail_items = state
+        .0
+        .lock()
+        .map_err(|_| "托盘详情状态锁不可用".to_string())?
+        .clone();
+    if detail_items.is_empty() {
+        return Ok(());
+    }
+
+    let store = load_accounts_store_data()?;
+    for (account_id, detail_item) in detail_items {
+        if let Some(account) = store
+            .accounts
+            .iter()
+            .find(|account| account.id == account_id)
+        {
+            detail_item
+                .set_text(build_tray_account_detail(account))
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    Ok(())
 }
 
-#[cfg(windows)]
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct WindowsRestartCodexResult {
-    app_restarted: bool,
-    cli_restarted: bool,
+fn start_tray_countdown_timer<R: Runtime>(app: &AppHandle<R>) {
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            if let Err(error) = refresh_tray_detail_texts(&app_handle) {
+                log::debug!("托盘倒计时更新跳过: {}", error);
+            }
+        }
+    });
 }
 
-#[cfg(windows)]
-fn escape_powershell_single_quoted(value: &str) -> String {
-    value.replace('\'', "''")
+fn refresh_tray_menu_internal<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    let tray = app
+        .tray_by_id(TRAY_ID)
+        .ok_or_else(|| "托盘图标不存在".to_string())?;
+    let menu = build_tray_menu(app)?;
+    tray.set_menu(Some(menu)).map_err(|e| e.to_string())
 }
 
-#[cfg(windows)]
-fn build_codex_cli_launch_command(codex_path: Option<String>) -> String {
-    let normalized = normalize_codex_command_input(codex_path);
-    format!("& '{}'", escape_powershell_single_quoted(&normalized))
+fn now_epoch_ms_u64() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
 }
 
-#[cfg(windows)]
-fn run_windows_powershell(script: &str) -> Result<String, String> {
-    let output = std::process::Command::new("powershell.exe")
-        .args([
-            "-NoProfile",
-            "-ExecutionPolicy",
-            "Bypass",
-            "-Command",
-            script,
-        ])
-        .output()
-        .map_err(|e| e.to_string())?;
+/// 计算账号的下次刷新时间：活跃账号缩短间隔，额度重置后尽快刷新，失败后等待熔断冷却结束
+/// （退避只由 `record_account_refresh_attempt` 计算的冷却时间决定，这里不再叠加）
+fn plan_account_refresh(
+    account: &TrayStoredAccount,
+    state: Option<&AccountRefreshState>,
+    interval_minutes: u64,
+    now_ms: u64,
+) -> AccountRefreshPlan {
+    let plan = |next_refresh_at_ms: Option<u64>, reason: &str| AccountRefreshPlan {
+        account_id: account.id.clone(),
+        next_refresh_at_ms,
+        reason: reason.to_string(),
+        status: None,
+    };
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
-        if stderr.is_empty() {
-            return Err(format!("PowerShell exited with status {}", output.status));
-        }
-        return Err(stderr);
+    if interval_minutes == 0 {
+        return plan(None, "disabled");
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
-}
+    let Some(state) = state.filter(|state| state.last_attempt_ms > 0) else {
+        return plan(Some(now_ms), "initial");
+    };
 
-#[cfg(windows)]
-fn restart_codex_processes_windows(
-    codex_path: Option<String>,
-) -> Result<RestartCodexProcessesResult, String> {
-    let launch_command =
-        escape_powershell_single_quoted(&build_codex_cli_launch_command(codex_path));
+    if let Some(cooldown_until_ms) = state.cooldown_until_ms {
+        if let Some(status) = &state.attention_status {
+            return AccountRefreshPlan {
+                status: Some(status.clone()),
+                ..plan(Some(cooldown_until_ms), "attention")
+            };
+        }
+        return plan(Some(cooldown_until_ms), "backoff");
+    }
 
-    let script = format!(
-        r#"
-$ErrorActionPreference = 'Stop'
+    let interval_ms = interval_minutes.saturating_mul(60_000);
+    let regular_ms = if account.is_active {
+        (interval_ms / ACTIVE_ACCOUNT_REFRESH_DIVISOR).max(MIN_ACCOUNT_REFRESH_INTERVAL_MS)
+    } else {
+        interval_ms
+    };
 
-function Resolve-ExecutablePath([string]$commandLine) {{
-    if (-not $commandLine) {{
-        return $null
-    }}
+    let regular_next = state.last_attempt_ms.saturating_add(regular_ms);
+    let reset_next = tray_account_next_reset_ms(account)
+        .and_then(|reset_ms| u64::try_from(reset_ms).ok())
+        .filter(|reset_ms| *reset_ms > state.last_attempt_ms)
+        .map(|reset_ms| reset_ms.saturating_add(RESET_REFRESH_GRACE_MS));
 
-    if ($commandLine -match '^\s*"([^"]+)"') {{
-        return $Matches[1]
-    }}
+    match reset_next {
+        Some(reset_next) if reset_next < regular_next => plan(Some(reset_next), "reset"),
+        _ if account.is_active => plan(Some(regular_next), "active"),
+        _ => plan(Some(regular_next), "idle"),
+    }
+}
 
-    if ($commandLine -match '^\s*([^\s]+)') {{
-        return $Matches[1]
-    }}
+fn current_account_refresh_plans(
+    store: &TrayAccountsStore,
+    now_ms: u64,
+) -> Vec<AccountRefreshPlan> {
+    let interval_minutes = store.config.auto_refresh_interval.unwrap_or(30);
+    let states = ACCOUNT_REFRESH_STATES
+        .lock()
+        .map(|states| states.clone())
+        .unwrap_or_default();
 
-    return $null
-}}
+    store
+        .accounts
+        .iter()
+        .map(|account| {
+            let state = states
+                .iter()
+                .find(|state| state.account_id == account.id)
+                .map(|state| with_monotonic_last_attempt(state, now_ms));
+            plan_account_refresh(account, state.as_ref(), interval_minutes, now_ms)
+        })
+        .collect()
+}
 
-function Resolve-PackageFullNameFromPath([string]$path) {{
-    if (-not $path) {{
-        return $null
-    }}
+/// 按单调时钟换算上次刷新时间，避免系统时间调整影响刷新间隔
+fn with_monotonic_last_attempt(state: &AccountRefreshState, now_ms: u64) -> AccountRefreshState {
+    let mut state = state.clone();
+    if let Some(last_attempt_at) = state.last_attempt_at {
+        let elapsed_ms = u64::try_from(last_attempt_at.elapsed().as_millis()).unwrap_or(u64::MAX);
+        state.last_attempt_ms = now_ms.saturating_sub(elapsed_ms).max(1);
+    }
+    state
+}
 
-    if ($path -match 'WindowsApps\\([^\\]+)\\app\\Codex\.exe$') {{
-        return $Matches[1]
-    }}
+/// 可以通过冷却后重试恢复的临时性失败
+fn is_transient_usage_failure(status: &str) -> bool {
+    status == "error" || status == "rate_limited"
+}
 
-    return $null
-}}
+/// 登录失效、封禁、无额度等需要用户处理的失败：按最长冷却时间退避，
+/// 直到手动刷新或账号 auth 更新，避免每个刷新周期都重复请求
+fn requires_user_action(status: &str) -> bool {
+    status != "ok" && status != "cooldown" && !is_transient_usage_failure(status)
+}
 
-function Resolve-CodexDesktopLaunchTarget([string]$executablePath, [string]$commandLine) {{
-    $candidatePaths = @()
-    if ($executablePath) {{
-        $candidatePaths += $executablePath
-    }}
+/// 熔断冷却时长：达到阈值后按 2 的幂次增长并封顶，其中一半为随机抖动，避免多个账号同时重试
+fn compute_circuit_cooldown_ms(consecutive_failures: u32, jitter_fraction: f64) -> Option<u64> {
+    if consecutive_failures < CIRCUIT_BREAKER_THRESHOLD {
+        return None;
+    }
 
-    $resolvedFromCommand = Resolve-ExecutablePath $commandLine
-    if ($resolvedFromCommand) {{
-        $candidatePaths += $resolvedFromCommand
-    }}
+    let exponent = (consecutive_failures - CIRCUIT_BREAKER_THRESHOLD).min(16);
+    let cooldown_ms = CIRCUIT_BREAKER_BASE_COOLDOWN_MS
+        .saturating_mul(1u64 << exponent)
+        .min(CIRCUIT_BREAKER_MAX_COOLDOWN_MS);
+    let jitter_ms = (cooldown_ms as f64 / 2.0 * jitter_fraction.clamp(0.0, 1.0)).round() as u64;
+    Some(cooldown_ms / 2 + jitter_ms)
+}
 
-    foreach ($candidatePath in $candidatePaths) {{
-        $packageFullName = Resolve-PackageFullNameFromPath $candidatePath
-        if (-not $packageFullName) {{
-            continue
-        }}
+fn with_account_refresh_state<T>(
+    account_id: &str,
+    update: impl FnOnce(&mut AccountRefreshState) -> T,
+) -> Option<T> {
+    let mut states = ACCOUNT_REFRESH_STATES.lock().ok()?;
+    let index = match states
+        .iter()
+        .position(|state| state.account_id == account_id)
+    {
+        Some(index) => index,
+        None => {
+            states.push(AccountRefreshState {
+                account_id: account_id.to_string(),
+                ..Default::default()
+            });
+            states.len() - 1
+        }
+    };
+    Some(update(&mut states[index]))
+}
+
+/// 把一次刷新结果计入账号状态，返回熔断后的下次重试时间；被限流时至少冷却到服务端给出的 `retry_at_ms`
+fn apply_refresh_attempt(
+    state: &mut AccountRefreshState,
+    status: &str,
+    message: Option<&str>,
+    attempted_ms: u64,
+    retry_at_ms: Option<u64>,
+    jitter_fraction: f64,
+) -> Option<u64> {
+    state.last_attempt_ms = attempted_ms;
+    state.last_attempt_at = Some(Instant::now());
+    if status == "ok" {
+        state.consecutive_failures = 0;
+        state.cooldown_until_ms = None;
+        state.last_error = None;
+        state.attention_status = None;
+        return None;
+    }
 
-        $package = Get-AppxPackage | Where-Object {{ $_.PackageFullName -eq $packageFullName }} | Select-Object -First 1
-        if (-not $package) {{
-            $package = Get-AppxPackage -Name 'OpenAI.Codex' -ErrorAction SilentlyContinue | Sort-Object Version -Descending | Select-Object -First 1
-        }}
-        if (-not $package) {{
-            continue
-        }}
+    state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+    state.last_error = message.map(|message| message.to_string());
+    state.attention_status = requires_user_action(status).then(|| status.to_string());
+    state.cooldown_until_ms = if is_transient_usage_failure(status) {
+        compute_circuit_cooldown_ms(state.consecutive_failures, jitter_fraction)
+            .map(|cooldown_ms| attempted_ms.saturating_add(cooldown_ms))
+    } else if state.attention_status.is_some() {
+        Some(attempted_ms.saturating_add(CIRCUIT_BREAKER_MAX_COOLDOWN_MS))
+    } else {
+        None
+    };
+    if status == "rate_limited" {
+        state.cooldown_until_ms = state.cooldown_until_ms.max(retry_at_ms);
+    }
+    state.cooldown_until_ms
+}
 
-        try {{
-            $manifest = Get-AppxPackageManifest -Package $package.PackageFullName
-            $application = $manifest.Package.Applications.Application |
-                Where-Object {{ $_.Executable -eq 'app/Codex.exe' }} |
-                Select-Object -First 1
+fn record_account_refresh_attempt(
+    account_id: &str,
+    status: &str,
+    message: Option<&str>,
+    attempted_ms: u64,
+    retry_at_ms: Option<u64>,
+) -> Option<u64> {
+    with_account_refresh_state(account_id, |state| {
+        apply_refresh_attempt(
+            state,
+            status,
+            message,
+            attempted_ms,
+            retry_at_ms,
+            rand::random::<f64>(),
+        )
+    })
+    .flatten()
+}
 
-            if (-not $application) {{
-                $application = $manifest.Package.Applications.Application | Select-Object -First 1
-            }}
+fn reset_circuit_breaker(state: &mut AccountRefreshState) {
+    state.consecutive_failures = 0;
+    state.cooldown_until_ms = None;
+    state.attention_status = None;
+}
 
-            if ($application -and $application.Id) {{
-                return [pscustomobject]@{{
-                    mode = 'appx'
-                    value = 'shell:AppsFolder\{{0}}!{{1}}' -f $package.PackageFamilyName, $application.Id
-                }}
-            }}
-        }} catch {{
-            continue
-        }}
-    }}
+/// 手动刷新时解除熔断（账号为空时解除全部）
+fn reset_account_circuit_breaker(account_id: Option<&str>) {
+    let Ok(mut states) = ACCOUNT_REFRESH_STATES.lock() else {
+        return;
+    };
+    states
+        .iter_mut()
+        .filter(|state| match account_id {
+            Some(account_id) => state.account_id == account_id,
+            None => true,
+        })
+        .for_each(reset_circuit_breaker);
+}
+
+fn build_cooldown_usage_result(
+    next_retry_at_ms: u64,
+    consecutive_failures: u32,
+    last_error: Option<&str>,
+    now_ms: u64,
+) -> UsageResult {
+    let remaining_ms = i64::try_from(next_retry_at_ms.saturating_sub(now_ms)).unwrap_or(i64::MAX);
+    let reason = last_error
+        .map(|error| format!("：{}", error))
+        .unwrap_or_default();
+    UsageResult {
+        status: "cooldown".to_string(),
+        message: Some(format!(
+            "连续失败 {} 次，{}后重试{}",
+            consecutive_failures,
+            format_reset_countdown(remaining_ms),
+            reason
+        )),
+        plan_type: None,
+        next_retry_at_ms: Some(next_retry_at_ms),
+        usage: None,
+    }
+}
 
-    foreach ($candidatePath in $candidatePaths) {{
-        if ($candidatePath) {{
-            return [pscustomobject]@{{
-                mode = 'path'
-                value = $candidatePath
-            }}
-        }}
-    }}
+/// 账号处于熔断冷却期时直接返回冷却结果，不再发起请求
+fn circuit_breaker_result(account_id: &str, now_ms: u64) -> Option<UsageResult> {
+    let states = ACCOUNT_REFRESH_STATES.lock().ok()?;
+    let state = states.iter().find(|state| state.account_id == account_id)?;
+    cooldown_usage_result(state, now_ms)
+}
 
-    return $null
-}}
+fn cooldown_usage_result(state: &AccountRefreshState, now_ms: u64) -> Option<UsageResult> {
+    let cooldown_until_ms = state.cooldown_until_ms.filter(|until| *until > now_ms)?;
+    // 需要用户处理的失败沿用原状态与提示，只附带下次自动重试时间
+    if let Some(status) = &state.attention_status {
+        return Some(UsageResult {
+            status: status.clone(),
+            message: state.last_error.clone(),
+            plan_type: None,
+            next_retry_at_ms: Some(cooldown_until_ms),
+            usage: None,
+        });
+    }
+    Some(build_cooldown_usage_result(
+        cooldown_until_ms,
+        state.consecutive_failures,
+        state.last_error.as_deref(),
+        now_ms,
+    ))
+}
 
-$desktopProcesses = @(
-    Get-CimInstance Win32_Process | Where-Object {{
-        $_.CommandLine -and (
-            ($_.Name -ieq 'Codex.exe' -and $_.CommandLine -match 'OpenAI\.Codex') -or
-            ($_.Name -ieq 'codex.exe' -and $_.CommandLine -match '\\app\\resources\\codex\.exe')
-        )
-    }}
-)
+fn normalize_refresh_concurrency(value: Option<u64>) -> usize {
+    match value {
+        Some(0) | None => DEFAULT_REFRESH_CONCURRENCY,
+        Some(value) => usize::try_from(value)
+            .unwrap_or(MAX_REFRESH_CONCURRENCY)
+            .min(MAX_REFRESH_CONCURRENCY),
+    }
+}
 
-$desktopMain = $desktopProcesses |
-    Where-Object {{ $_.Name -ieq 'Codex.exe' -and $_.CommandLine -notmatch '--type=' }} |
-    Select-Object -First 1
+/// 单个账号刷新的总时限：覆盖重试策略允许的全部请求与重试等待
+fn usage_refresh_deadline(timeouts: &HttpTimeouts) -> Duration {
+    let policy = RetryPolicy::default();
+    (timeouts.connect + timeouts.read) * policy.max_attempts + policy.max_total_delay()
+}
 
-if (-not $desktopMain) {{
-    $desktopMain = $desktopProcesses | Select-Object -First 1
-}}
+async fn fetch_account_usage_result(
+    account_id: String,
+    proxy_enabled: Option<bool>,
+    proxy_url: Option<String>,
+    deadline: Duration,
+) -> UsageResult {
+    let request = get_usage(account_id.clone(), proxy_enabled, proxy_url, None);
+    let result = match tokio::time::timeout(deadline, request).await {
+        Ok(Ok(result)) => return result,
+        Ok(Err(error)) => UsageResult {
+            status: "error".to_string(),
+            message: Some(error),
+            plan_type: None,
+            next_retry_at_ms: None,
+            usage: None,
+        },
+        Err(_) => UsageResult {
+            status: "error".to_string(),
+            message: Some(format!("请求超时（{} 秒）", deadline.as_secs())),
+            plan_type: None,
+            next_retry_at_ms: None,
+            usage: None,
+        },
+    };
+    apply_session_fallback(&account_id, result, now_epoch_ms_u64())
+}
 
-$desktopLaunchTarget = $null
-if ($desktopMain) {{
-    $desktopLaunchTarget = Resolve-CodexDesktopLaunchTarget $desktopMain.ExecutablePath $desktopMain.CommandLine
-}}
+fn save_account_usage_summary(account_id: &str, summary: TrayUsageSummary) -> Result<(), String> {
+    update_accounts_store_data(|store| {
+        let Some(account) = store
+            .accounts
+            .iter_mut()
+            .find(|account| account.id == account_id)
+        else {
+            return Ok(false);
+        };
 
-foreach ($process in $desktopProcesses) {{
-    Stop-Process -Id $process.ProcessId -Force -ErrorAction SilentlyContinue
-}}
+        account.usage_info = Some(merge_usage_summary(account.usage_info.take(), summary));
+        Ok(true)
+    })?;
+    Ok(())
+}
 
-$appRestarted = $false
-if ($desktopProcesses.Count -gt 0 -and $desktopLaunchTarget) {{
-    Start-Sleep -Milliseconds 700
-    if ($desktopLaunchTarget.mode -eq 'appx') {{
-        Start-Process -FilePath 'explorer.exe' -ArgumentList $desktopLaunchTarget.value | Out-Null
-    }} else {{
-        Start-Process -FilePath $desktopLaunchTarget.value | Out-Null
-    }}
-    $appRestarted = $true
-}}
-
-$cliProcesses = @(
-    Get-CimInstance Win32_Process | Where-Object {{
-        $_.CommandLine -and
-        $_.CommandLine -notmatch 'codex-auth-manager' -and
-        $_.CommandLine -notmatch 'OpenAI\.Codex' -and
-        $_.CommandLine -notmatch '\\app\\resources\\codex\.exe' -and
-        (
-            ((@('powershell.exe', 'pwsh.exe', 'cmd.exe') -contains $_.Name.ToLower()) -and $_.CommandLine -match '(^|[\s"''`])codex([\s"''`]|$)') -or
-            ((@('node.exe', 'codex.exe') -contains $_.Name.ToLower()) -and $_.CommandLine -match 'codex')
-        )
-    }}
-)
+/// 冷却、限流等临时失败的结果不含用量，保留上次的额度与窗口，只更新状态、提示与下次重试时间
+fn merge_usage_summary(
+    previous: Option<TrayUsageSummary>,
+    summary: TrayUsageSummary,
+) -> TrayUsageSummary {
+    let has_usage = summary.five_hour_limit.is_some()
+        || summary.weekly_limit.is_some()
+        || summary.code_review_limit.is_some()
+        || summary.windows.is_some();
+    let keeps_usage = summary
+        .status
+        .as_deref()
+        .is_some_and(allows_session_fallback);
+    match previous {
+        Some(previous) if !has_usage && keeps_usage => TrayUsageSummary {
+            status: summary.status,
+            message: summary.message,
+            next_retry_at_ms: summary.next_retry_at_ms,
+            ..previous
+        },
+        _ => summary,
+    }
+}
 
-foreach ($process in $cliProcesses) {{
-    Stop-Process -Id $process.ProcessId -Force -ErrorAction SilentlyContinue
-}}
+/// 按工作线程消息更新整体进度，并生成对应的单账号事件
+fn apply_refresh_worker_message(
+    progress: &mut UsageRefreshProgressPayload,
+    message: UsageRefreshWorkerMessage,
+) -> UsageRefreshAccountPayload {
+    match message {
+        UsageRefreshWorkerMessage::Started(account_id) => {
+            progress.in_flight += 1;
+            UsageRefreshAccountPayload {
+                account_id,
+                phase: "started".to_string(),
+                code: None,
+                message: None,
+                summary: None,
+            }
+        }
+        UsageRefreshWorkerMessage::Finished(account_id, result) => {
+            progress.in_flight = progress.in_flight.saturating_sub(1);
+            progress.completed += 1;
+            let succeeded = result.status == "ok";
+            if succeeded {
+                progress.succeeded += 1;
+            } else {
+                progress.failed += 1;
+            }
 
-$cliRestarted = $false
-if ($cliProcesses.Count -gt 0) {{
-    Start-Sleep -Milliseconds 400
-    $launchCommand = '{}'
-    Start-Process -FilePath 'powershell.exe' -ArgumentList @('-NoExit', '-Command', $launchCommand) | Out-Null
-    $cliRestarted = $true
-}}
+            UsageRefreshAccountPayload {
+                account_id,
+                phase: if succeeded { "succeeded" } else { "failed" }.to_string(),
+                code: (!succeeded).then(|| result.status.clone()),
+                message: result.message.clone(),
+                summary: Some(build_tray_usage_summary(&result)),
+            }
+        }
+        UsageRefreshWorkerMessage::Cancelled(account_id) => {
+            progress.in_flight = progress.in_flight.saturating_sub(1);
+            UsageRefreshAccountPayload {
+                account_id,
+                phase: "cancelled".to_string(),
+                code: None,
+                message: None,
+                summary: None,
+            }
+        }
+    }
+}
 
-[pscustomobject]@{{
-    appRestarted = $appRestarted
-    cliRestarted = $cliRestarted
-}} | ConvertTo-Json -Compress
-"#,
-        launch_command
-    );
+/// 以有限并发刷新指定账号（为空时刷新全部）的用量，每个账号的结果返回后立即写回存储
+/// 刷新过程中逐个账号发送 `usage-refresh-account`，并同步发送整体进度 `usage-refresh-progress`
+/// 取消后不再领取新账号，正在进行的请求也会立即放弃
+async fn refresh_accounts_usage_in_background<R: Runtime>(
+    app: &AppHandle<R>,
+    account_ids: Option<&[String]>,
+    cancel: &CancellationToken,
+) -> Result<usize, String> {
+    let store = load_accounts_store_data()?;
+    let target_ids: VecDeque<String> = store
+        .accounts
+        .iter()
+        .filter(|account| match account_ids {
+            Some(account_ids) => account_ids.contains(&account.id),
+            None => true,
+        })
+        .map(|account| account.id.clone())
+        .collect();
+    if target_ids.is_empty() {
+        return Ok(0);
+    }
 
-    let stdout = run_windows_powershell(&script)?;
-    let result: WindowsRestartCodexResult =
-        serde_json::from_str(stdout.trim()).map_err(|e| e.to_string())?;
+    let proxy_enabled = store.config.proxy_enabled;
+    let proxy_url = store.config.proxy_url.clone();
+    let deadline = usage_refresh_deadline(&resolve_http_timeouts(&store.config));
+    let concurrency =
+        normalize_refresh_concurrency(store.config.refresh_concurrency).min(target_ids.len());
+    let total = target_ids.len();
+    let queue = Arc::new(Mutex::new(target_ids));
+    let (result_tx, mut result_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    for _ in 0..concurrency {
+        let queue = queue.clone();
+        let result_tx = result_tx.clone();
+        let proxy_url = proxy_url.clone();
+        let cancel = cancel.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                if cancel.is_cancelled() {
+                    break;
+                }
 
-    Ok(RestartCodexProcessesResult {
-        app_restarted: result.app_restarted,
-        cli_restarted: result.cli_restarted,
-    })
-}
+                let next = queue.lock().ok().and_then(|mut queue| queue.pop_front());
+                let Some(account_id) = next else {
+                    break;
+                };
 
-#[tauri::command]
-fn restart_codex_processes(
-    codex_path: Option<String>,
-) -> Result<RestartCodexProcessesResult, String> {
-    #[cfg(windows)]
-    {
-        restart_codex_processes_windows(codex_path)
+                if result_tx
+                    .send(UsageRefreshWorkerMessage::Started(account_id.clone()))
+                    .is_err()
+                {
+                    break;
+                }
+                let request = fetch_account_usage_result(
+                    account_id.clone(),
+                    proxy_enabled,
+                    proxy_url.clone(),
+                    deadline,
+                );
+                let message = match cancel.run_until_cancelled(request).await {
+                    Some(result) => {
+                        UsageRefreshWorkerMessage::Finished(account_id, Box::new(result))
+                    }
+                    None => UsageRefreshWorkerMessage::Cancelled(account_id),
+                };
+                if result_tx.send(message).is_err() {
+                    break;
+                }
+            }
+        });
     }
+    drop(result_tx);
+
+    let mut progress = UsageRefreshProgressPayload {
+        total,
+        completed: 0,
+        succeeded: 0,
+        failed: 0,
+        in_flight: 0,
+        finished: false,
+    };
+    update_refresh_job_progress(&progress);
+    let _ = app.emit("usage-refresh-progress", progress.clone());
+
+    while let Some(message) = result_rx.recv().await {
+        let account_payload = apply_refresh_worker_message(&mut progress, message);
+        if let Some(summary) = account_payload.summary.clone() {
+            let account_id = &account_payload.account_id;
+            if let Err(error) = save_account_usage_summary(account_id, summary) {
+                log::warn!("保存账号用量失败 ({}): {}", account_id, error);
+            }
+            if let Err(error) = refresh_tray_detail_texts(app) {
+                log::warn!("更新托盘账号详情失败: {}", error);
+            }
+        }
 
-    #[cfg(not(windows))]
-    {
-        let _ = codex_path;
-        Err("Restarting Codex processes is only supported on Windows".to_string())
+        update_refresh_job_progress(&progress);
+        let _ = app.emit("usage-refresh-account", account_payload);
+        let _ = app.emit("usage-refresh-progress", progress.clone());
     }
-}
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct StartCodexLoginResult {
-    status: String,
-    auth_json: Option<String>,
-    changed_at: Option<String>,
-    message: Option<String>,
-}
+    let updated_count = progress.succeeded;
+    progress.finished = true;
+    let _ = app.emit("usage-refresh-progress", progress);
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum LoginCommandMode {
-    Direct,
-    Cmd,
-    PowerShell,
-}
+    refresh_tray_menu_internal(app)?;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct LoginInvocation {
-    program: String,
-    args: Vec<String>,
-    mode: LoginCommandMode,
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.emit(
+            "background-usage-refreshed",
+            BackgroundUsageRefreshedPayload {
+                updated_count,
+                finished_at: now_epoch_ms_string(),
+            },
+        );
+    }
+
+    Ok(updated_count)
 }
 
-#[derive(Debug, Clone)]
-struct AuthSnapshot {
-    modified: Option<SystemTime>,
-    content: Option<String>,
+/// 计划刷新时间已到的账号
+fn due_refresh_account_ids(store: &TrayAccountsStore, current_ms: u64) -> Vec<String> {
+    current_account_refresh_plans(store, current_ms)
+        .into_iter()
+        .filter(|plan| {
+            plan.next_refresh_at_ms
+                .is_some_and(|next_refresh_at_ms| next_refresh_at_ms <= current_ms)
+        })
+        .map(|plan| plan.account_id)
+        .collect()
 }
 
-fn get_auth_snapshot(path: &Path) -> Result<AuthSnapshot, String> {
-    if !path.exists() {
-        return Ok(AuthSnapshot {
-            modified: None,
-            content: None,
-        });
+async fn maybe_run_background_auto_refresh<R: Runtime>(app: &AppHandle<R>) {
+    if AUTO_REFRESH_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
     }
 
-    let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
-    let content = fs::read_to_string(path).ok();
+    let result = async {
+        let store = load_accounts_store_data()?;
+        let interval_minutes = store.config.auto_refresh_interval.unwrap_or(30);
+        if interval_minutes == 0 || store.accounts.is_empty() {
+            return Ok::<(), String>(());
+        }
 
-    Ok(AuthSnapshot { modified, content })
-}
+        let due_account_ids = due_refresh_account_ids(&store, now_epoch_ms_u64());
+        if due_account_ids.is_empty() {
+            return Ok(());
+        }
 
-fn auth_snapshot_changed(previous: &AuthSnapshot, current: &AuthSnapshot) -> bool {
-    if previous.modified != current.modified {
-        return true;
+        begin_refresh_job("background");
+        run_refresh_job(app, Some(due_account_ids)).await?;
+        Ok(())
     }
-    previous.content != current.content
-}
-
-fn validate_login_auth_json(auth_json: &str) -> Result<(), String> {
-    let auth: AuthConfig = serde_json::from_str(auth_json).map_err(|e| e.to_string())?;
-    let tokens = auth
-        .tokens
-        .ok_or_else(|| "auth.json \u{7f3a}\u{5c11} tokens \u{5b57}\u{6bb5}".to_string())?;
-
-    take_non_empty_token(tokens.id_token, "auth.json \u{7f3a}\u{5c11} id_token")?;
-    take_non_empty_token(
-        tokens.access_token,
-        "auth.json \u{7f3a}\u{5c11} access_token",
-    )?;
-    take_non_empty_token(
-        tokens.refresh_token,
-        "auth.json \u{7f3a}\u{5c11} refresh_token",
-    )?;
-    take_non_empty_token(tokens.account_id, "auth.json \u{7f3a}\u{5c11} account_id")?;
+    .await;
 
-    Ok(())
-}
+    if let Err(error) = result {
+        log::warn!("后台自动刷新失败: {}", error);
+    }
 
-fn normalize_codex_command_input(codex_path: Option<String>) -> String {
-    let value = codex_path.unwrap_or_default();
-    let trimmed = value.trim();
-    let normalized = match trimmed.as_bytes() {
-        [first, middle @ .., last]
-            if (*first == b'"' && *last == b'"') || (*first == b'\'' && *last == b'\'') =>
+    run_queued_refresh_jobs(app);
+}
+
+/// 两次轮询之间墙上时钟比单调时钟多走的时间超过阈值（或轮询被明显推迟），说明系统刚从休眠中恢复
+fn detect_resume_gap(wall_elapsed_ms: u64, monotonic_elapsed_ms: u64, tick_ms: u64) -> bool {
+    wall_elapsed_ms.saturating_sub(monotonic_elapsed_ms) >= SUSPEND_GAP_THRESHOLD_MS
+        || monotonic_elapsed_ms >= tick_ms.saturating_add(SUSPEND_GAP_THRESHOLD_MS)
+}
+
+/// 网络探测目标：与 HTTP 客户端使用同一套代理设置（包括环境变量代理与 NO_PROXY），
+/// 经代理访问时探测代理端口，直连时探测用量接口所在主机
+fn network_probe_target(
+    proxy: &ProxySettings,
+    api_base_url: &str,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Option<(String, u16)> {
+    let api_url = reqwest::Url::parse(api_base_url).ok()?;
+    let api_host = api_url.host_str()?.to_string();
+    let direct = || Some((api_host.clone(), api_url.port_or_known_default()?));
+    let proxy_url = match &proxy.mode {
+        ProxyMode::Direct => return direct(),
+        ProxyMode::Manual(_)
+            if proxy
+                .no_proxy
+                .as_deref()
+                .is_some_and(|no_proxy| no_proxy_matches(no_proxy, &api_host)) =>
         {
-            std::str::from_utf8(middle).unwrap_or(trimmed).trim()
+            return direct()
         }
-        _ => trimmed,
+        ProxyMode::Manual(proxy_url) => proxy_url.clone(),
+        ProxyMode::Env => match env_proxy_url(&api_url, env) {
+            Some(proxy_url) => proxy_url,
+            None => return direct(),
+        },
     };
 
-    if normalized.is_empty() {
-        "codex".to_string()
+    let proxy_url = if proxy_url.contains("://") {
+        proxy_url
     } else {
-        normalized.to_string()
-    }
+        format!("http://{}", proxy_url)
+    };
+    let url = reqwest::Url::parse(&proxy_url).ok()?;
+    let port = url.port_or_known_default().unwrap_or(match url.scheme() {
+        "socks5" | "socks5h" => 1080,
+        _ => 80,
+    });
+    Some((url.host_str()?.to_string(), port))
 }
 
-#[cfg(windows)]
-fn resolve_command_candidates(command: &str) -> Vec<String> {
-    if command.contains('\\') || command.contains('/') || command.contains(':') {
-        return vec![command.to_string()];
+/// 按环境变量解析请求使用的代理：按协议读取 HTTPS_PROXY / HTTP_PROXY，其次 ALL_PROXY（小写优先），
+/// 命中 NO_PROXY 时直连
+fn env_proxy_url(url: &reqwest::Url, env: &dyn Fn(&str) -> Option<String>) -> Option<String> {
+    let lookup = |name: &str| {
+        env(&name.to_lowercase())
+            .or_else(|| env(name))
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    let host = url.host_str()?;
+    if lookup("NO_PROXY").is_some_and(|no_proxy| no_proxy_matches(&no_proxy, host)) {
+        return None;
     }
-
-    let output = std::process::Command::new("where.exe")
-        .arg(command)
-        .output();
-    let Ok(output) = output else {
-        return vec![command.to_string()];
+    let scheme_proxy = if url.scheme() == "https" {
+        "HTTPS_PROXY"
+    } else {
+        "HTTP_PROXY"
     };
-
-    if !output.status.success() {
-        return vec![command.to_string()];
+    lookup(scheme_proxy).or_else(|| lookup("ALL_PROXY"))
+}
+
+/// NO_PROXY 列表是否包含该主机：支持 `*`、完整主机名与域名后缀（可带前导点）
+fn no_proxy_matches(no_proxy: &str, host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.').to_ascii_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| entry == "*" || host == entry || host.ends_with(&format!(".{}", entry)))
+}
+
+/// 需要探测的全部目标：全局代理设置对应的目标，以及各账号级代理
+fn network_probe_targets(
+    store: &TrayAccountsStore,
+    api_base_url: &str,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Vec<(String, u16)> {
+    let config = &store.config;
+    let account_proxies = store
+        .accounts
+        .iter()
+        .filter_map(|account| account.proxy_url.as_deref())
+        .map(Some);
+    let mut targets = Vec::new();
+    for account_proxy in std::iter::once(None).chain(account_proxies) {
+        let Ok(proxy) = resolve_proxy_settings(
+            config,
+            config.proxy_enabled,
+            config.proxy_url.clone(),
+            account_proxy,
+            None,
+        ) else {
+            continue;
+        };
+        if let Some(target) = network_probe_target(&proxy, api_base_url, env) {
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
     }
+    targets
+}
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut candidates: Vec<String> = stdout
-        .lines()
-        .map(str::trim)
-        .filter(|line| !line.is_empty())
-        .map(|line| line.to_string())
-        .collect();
+/// 只在即将刷新、上次刷新有失败或网络仍处于断开状态时才探测，平时不额外发起连接
+fn should_probe_network(network_reachable: bool, refresh_due: bool, refresh_failed: bool) -> bool {
+    !network_reachable || refresh_due || refresh_failed
+}
 
-    if candidates.is_empty() {
-        candidates.push(command.to_string());
+/// 通过 TCP 连接探测网络是否可达，任一目标连通即视为在线；未开启自动刷新时不探测
+async fn probe_network_reachability(store: &TrayAccountsStore) -> Option<bool> {
+    if store.config.auto_refresh_interval.unwrap_or(30) == 0 {
+        return None;
     }
 
-    candidates
+    let api_base_url = resolve_api_base_url(
+        std::env::var(API_BASE_URL_ENV).ok(),
+        store.config.api_base_url.as_deref(),
+    );
+    let targets = network_probe_targets(store, &api_base_url, &|name| std::env::var(name).ok());
+    if targets.is_empty() {
+        return None;
+    }
+    for (host, port) in targets {
+        let connect = tokio::net::TcpStream::connect((host.as_str(), port));
+        if matches!(
+            tokio::time::timeout(NETWORK_PROBE_TIMEOUT, connect).await,
+            Ok(Ok(_))
+        ) {
+            return Some(true);
+        }
+    }
+    Some(false)
 }
 
-#[cfg(not(windows))]
-fn resolve_command_candidates(command: &str) -> Vec<String> {
-    vec![command.to_string()]
-}
+/// 休眠恢复或网络重新连通后立即刷新全部账号，并解除因断网进入的熔断
+async fn run_wake_refresh<R: Runtime>(app: &AppHandle<R>, trigger: &str) {
+    let enabled = load_accounts_store_data()
+        .map(|store| {
+            store.config.auto_refresh_interval.unwrap_or(30) > 0 && !store.accounts.is_empty()
+        })
+        .unwrap_or(false);
+    if !enabled || AUTO_REFRESH_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
 
-fn command_priority(candidate: &str) -> usize {
-    let lower = candidate.to_ascii_lowercase();
-    if lower.ends_with(".cmd") || lower.ends_with(".bat") || lower.ends_with(".com") {
-        0
-    } else if lower.ends_with(".exe") {
-        1
-    } else if lower.ends_with(".ps1") {
-        2
-    } else {
-        3
+    reset_account_circuit_breaker(None);
+    begin_refresh_job(trigger);
+    if let Err(error) = run_refresh_job(app, None).await {
+        log::warn!("唤醒后刷新用量失败: {}", error);
     }
-}
 
-fn resolve_login_invocation(codex_path: Option<String>) -> Result<LoginInvocation, String> {
-    let requested = normalize_codex_command_input(codex_path);
-    let mut candidates = resolve_command_candidates(&requested);
-    candidates.sort_by_key(|candidate| command_priority(candidate));
+    run_queued_refresh_jobs(app);
+}
 
-    let selected = candidates
-        .first()
-        .cloned()
-        .ok_or_else(|| "未找到可用的 codex 命令".to_string())?;
-    let selected_path = PathBuf::from(&selected);
-    let lower = selected.to_ascii_lowercase();
-
-    if lower.ends_with(".ps1") {
-        return Ok(LoginInvocation {
-            program: "powershell".to_string(),
-            args: vec![
-                "-NoLogo".to_string(),
-                "-NoProfile".to_string(),
-                "-ExecutionPolicy".to_string(),
-                "Bypass".to_string(),
-                "-File".to_string(),
-                selected,
-                "login".to_string(),
-            ],
-            mode: LoginCommandMode::PowerShell,
-        });
-    }
+fn start_background_auto_refresh<R: Runtime>(app: &AppHandle<R>) {
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let tick_ms = BACKGROUND_REFRESH_TICK.as_millis() as u64;
+        let mut last_tick_at = Instant::now();
+        let mut last_tick_wall_ms = now_epoch_ms_u64();
+        let mut network_reachable = true;
+        let mut last_failed_job_id = 0;
 
-    if lower.ends_with(".cmd") || lower.ends_with(".bat") {
-        return Ok(LoginInvocation {
-            program: "cmd".to_string(),
-            args: vec!["/C".to_string(), selected, "login".to_string()],
-            mode: LoginCommandMode::Cmd,
-        });
-    }
+        loop {
+            let monotonic_elapsed_ms =
+                u64::try_from(last_tick_at.elapsed().as_millis()).unwrap_or(u64::MAX);
+            let wall_elapsed_ms = now_epoch_ms_u64().saturating_sub(last_tick_wall_ms);
+            last_tick_at = Instant::now();
+            last_tick_wall_ms = now_epoch_ms_u64();
+
+            let resumed = detect_resume_gap(wall_elapsed_ms, monotonic_elapsed_ms, tick_ms);
+            let failed_job_id = current_refresh_job()
+                .filter(|job| job.finished_at_ms.is_some() && job.failed > 0)
+                .map(|job| job.job_id)
+                .filter(|job_id| *job_id != last_failed_job_id);
+            let store = load_accounts_store_data().ok();
+            let refresh_due = store.as_ref().is_some_and(|store| {
+                !due_refresh_account_ids(store, now_epoch_ms_u64()).is_empty()
+            });
+
+            let mut reconnected = false;
+            let probe =
+                should_probe_network(network_reachable, refresh_due, failed_job_id.is_some());
+            if let Some(store) = store.as_ref().filter(|_| probe && !resumed) {
+                last_failed_job_id = failed_job_id.unwrap_or(last_failed_job_id);
+                if let Some(reachable) = probe_network_reachability(store).await {
+                    reconnected = reachable && !network_reachable;
+                    network_reachable = reachable;
+                }
+            }
 
-    if selected_path.is_absolute() || selected.contains('\\') || selected.contains('/') {
-        return Ok(LoginInvocation {
-            program: selected,
-            args: vec!["login".to_string()],
-            mode: LoginCommandMode::Direct,
-        });
-    }
+            if resumed {
+                log::info!("检测到系统休眠恢复，立即刷新用量");
+                run_wake_refresh(&app_handle, "resume").await;
+            } else if reconnected {
+                log::info!("检测到网络重新连通，立即刷新用量");
+                run_wake_refresh(&app_handle, "reconnect").await;
+            } else {
+                maybe_run_background_auto_refresh(&app_handle).await;
+            }
 
-    Ok(LoginInvocation {
-        program: selected,
-        args: vec!["login".to_string()],
-        mode: LoginCommandMode::Direct,
-    })
+            tokio::time::sleep(BACKGROUND_REFRESH_TICK).await;
+        }
+    });
 }
 
-fn build_login_command(invocation: &LoginInvocation) -> Command {
-    let mut command = Command::new(&invocation.program);
-    command.args(&invocation.args);
-    command.stdin(std::process::Stdio::null());
-    command.stdout(std::process::Stdio::null());
-    command.stderr(std::process::Stdio::null());
-
-    #[cfg(windows)]
-    command.creation_flags(CREATE_NO_WINDOW);
+fn next_refresh_job_id() -> u64 {
+    REFRESH_JOB_SEQ.fetch_add(1, Ordering::SeqCst) + 1
+}
 
-    command
+fn begin_refresh_job(trigger: &str) -> RefreshJobStatus {
+    begin_refresh_job_with_id(next_refresh_job_id(), trigger)
 }
 
-fn normalize_login_timeout_seconds(timeout_seconds: Option<u64>) -> u64 {
-    match timeout_seconds {
-        Some(0) | None => DEFAULT_LOGIN_TIMEOUT_SECONDS,
-        Some(value) => value.max(30),
+fn begin_refresh_job_with_id(job_id: u64, trigger: &str) -> RefreshJobStatus {
+    if let Ok(mut guard) = REFRESH_JOB_CANCEL.lock() {
+        *guard = Some(CancellationToken::new());
+    }
+    let job = RefreshJobStatus {
+        job_id,
+        trigger: trigger.to_string(),
+        state: "running".to_string(),
+        total: 0,
+        completed: 0,
+        succeeded: 0,
+        failed: 0,
+        started_at_ms: now_epoch_ms_u64(),
+        finished_at_ms: None,
+        error: None,
+    };
+    if let Ok(mut guard) = REFRESH_JOB.lock() {
+        *guard = Some(job.clone());
     }
+    job
 }
 
-async fn terminate_login_process(child: &mut Child) {
-    if child.try_wait().ok().flatten().is_none() {
-        let _ = child.kill().await;
-    }
+fn current_refresh_job() -> Option<RefreshJobStatus> {
+    REFRESH_JOB.lock().ok().and_then(|guard| guard.clone())
 }
 
-fn build_login_success_result(auth_json: String, changed_at: String) -> StartCodexLoginResult {
-    StartCodexLoginResult {
-        status: "success".to_string(),
-        auth_json: Some(auth_json),
-        changed_at: Some(changed_at),
-        message: None,
-    }
+/// 当前任务的取消令牌；尚未开始任务时返回一个不会被取消的令牌
+fn current_refresh_job_cancel_token() -> CancellationToken {
+    REFRESH_JOB_CANCEL
+        .lock()
+        .ok()
+        .and_then(|guard| guard.clone())
+        .unwrap_or_default()
 }
 
-fn build_login_error_result(status: &str, message: impl Into<String>) -> StartCodexLoginResult {
-    StartCodexLoginResult {
-        status: status.to_string(),
-        auth_json: None,
-        changed_at: None,
-        message: Some(message.into()),
+fn update_refresh_job_progress(progress: &UsageRefreshProgressPayload) {
+    let Ok(mut guard) = REFRESH_JOB.lock() else {
+        return;
+    };
+    if let Some(job) = guard.as_mut() {
+        job.total = progress.total;
+        job.completed = progress.completed;
+        job.succeeded = progress.succeeded;
+        job.failed = progress.failed;
     }
 }
 
-fn try_read_updated_auth(
-    auth_path: &Path,
-    baseline: &AuthSnapshot,
-) -> Result<Option<StartCodexLoginResult>, String> {
-    let current = get_auth_snapshot(auth_path)?;
-    if !auth_snapshot_changed(baseline, &current) {
-        return Ok(None);
+/// 执行当前刷新任务并记录结束状态；调用方需已持有 AUTO_REFRESH_RUNNING
+async fn run_refresh_job<R: Runtime>(
+    app: &AppHandle<R>,
+    account_ids: Option<Vec<String>>,
+) -> Result<usize, String> {
+    let cancel = current_refresh_job_cancel_token();
+    let result = refresh_accounts_usage_in_background(app, account_ids.as_deref(), &cancel).await;
+    let cancelled = cancel.is_cancelled();
+
+    if let Ok(mut guard) = REFRESH_JOB.lock() {
+        if let Some(job) = guard.as_mut() {
+            job.finished_at_ms = Some(now_epoch_ms_u64());
+            match &result {
+                Ok(_) if cancelled => job.state = "cancelled".to_string(),
+                Ok(_) => job.state = "completed".to_string(),
+                Err(error) => {
+                    job.state = "failed".to_string();
+                    job.error = Some(error.clone());
+                }
+            }
+        }
     }
 
-    let Some(content) = current.content else {
-        return Ok(None);
-    };
+    if let Some(job) = current_refresh_job() {
+        let _ = app.emit("refresh-job-finished", job);
+    }
 
-    match validate_login_auth_json(&content) {
-        Ok(()) => {
-            let changed_at = current
-                .modified
-                .and_then(epoch_ms_from_system_time)
-                .map(|value| value.to_string())
-                .unwrap_or_else(now_epoch_ms_string);
-            Ok(Some(build_login_success_result(content, changed_at)))
+    result
+}
+
+/// 将手动刷新请求并入排队请求：任一请求要刷新全部时合并为全部，否则合并账号并去重；返回排队任务的编号
+fn queue_refresh_request(account_ids: Option<Vec<String>>) -> u64 {
+    let mut guard = PENDING_REFRESH_REQUEST
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+    let pending = guard.get_or_insert_with(|| PendingRefreshRequest {
+        job_id: next_refresh_job_id(),
+        account_ids: Some(Vec::new()),
+    });
+    match (pending.account_ids.as_mut(), account_ids) {
+        (Some(queued), Some(account_ids)) => {
+            for account_id in account_ids {
+                if !queued.contains(&account_id) {
+                    queued.push(account_id);
+                }
+            }
         }
-        Err(_) => Ok(None),
+        _ => pending.account_ids = None,
     }
+    pending.job_id
 }
 
-#[tauri::command]
-async fn start_codex_login(
-    codex_path: Option<String>,
-    timeout_seconds: Option<u64>,
-) -> Result<StartCodexLoginResult, String> {
-    LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
-    let auth_path = get_codex_auth_path()?;
-    let baseline = get_auth_snapshot(&auth_path)?;
-    let invocation = resolve_login_invocation(codex_path)?;
-    let timeout = Duration::from_secs(normalize_login_timeout_seconds(timeout_seconds));
+fn take_pending_refresh_request() -> Option<PendingRefreshRequest> {
+    PENDING_REFRESH_REQUEST
+        .lock()
+        .ok()
+        .and_then(|mut guard| guard.take())
+}
 
-    let mut child = build_login_command(&invocation).spawn().map_err(|error| {
-        if error.kind() == std::io::ErrorKind::NotFound {
-            format!(
-                "未找到 Codex CLI，请检查设置中的命令路径：{}",
-                invocation.program
-            )
-        } else {
-            format!("启动 Codex 登录失败：{}", error)
-        }
-    })?;
+fn has_pending_refresh_request() -> bool {
+    PENDING_REFRESH_REQUEST
+        .lock()
+        .is_ok_and(|guard| guard.is_some())
+}
+
+/// 排队中尚未开始的任务状态
+fn queued_refresh_job_status(job_id: u64) -> RefreshJobStatus {
+    RefreshJobStatus {
+        job_id,
+        trigger: "manual".to_string(),
+        state: "queued".to_string(),
+        total: 0,
+        completed: 0,
+        succeeded: 0,
+        failed: 0,
+        started_at_ms: now_epoch_ms_u64(),
+        finished_at_ms: None,
+        error: None,
+    }
+}
 
-    let started_at = Instant::now();
+/// 依次执行排队的手动刷新请求；调用方需已持有 AUTO_REFRESH_RUNNING，没有排队请求时释放运行标记
+fn run_queued_refresh_jobs<R: Runtime>(app: &AppHandle<R>) -> Option<RefreshJobStatus> {
     loop {
-        if LOGIN_CANCEL_REQUESTED.load(Ordering::SeqCst) {
-            terminate_login_process(&mut child).await;
-            LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
-            return Ok(build_login_error_result(
-                "cancelled",
-                "已取消快速登录，停止等待授权".to_string(),
-            ));
-        }
-
-        if let Some(result) = try_read_updated_auth(&auth_path, &baseline)? {
-            terminate_login_process(&mut child).await;
-            LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
-            return Ok(result);
-        }
-
-        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
-            if let Some(result) = try_read_updated_auth(&auth_path, &baseline)? {
-                LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
-                return Ok(result);
+        if let Some(request) = take_pending_refresh_request() {
+            match &request.account_ids {
+                Some(account_ids) => account_ids
+                    .iter()
+                    .for_each(|account_id| reset_account_circuit_breaker(Some(account_id))),
+                None => reset_account_circuit_breaker(None),
             }
 
-            let message = if status.success() {
-                "Codex 登录进程已结束，但未检测到新的 auth.json".to_string()
-            } else {
-                match status.code() {
-                    Some(code) => format!("Codex 登录进程异常结束，退出码：{}", code),
-                    None => "Codex 登录进程已被终止".to_string(),
+            let job = begin_refresh_job_with_id(request.job_id, "manual");
+            let app_handle = app.clone();
+            tauri::async_runtime::spawn(async move {
+                if let Err(error) = run_refresh_job(&app_handle, request.account_ids).await {
+                    log::warn!("刷新任务失败: {}", error);
                 }
-            };
+                run_queued_refresh_jobs(&app_handle);
+            });
+            return Some(job);
+        }
 
-            LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
-            return Ok(build_login_error_result("process_error", message));
+        AUTO_REFRESH_RUNNING.store(false, Ordering::SeqCst);
+        // 释放标记的同时可能有新请求排队，重新取得标记后接着执行
+        if !has_pending_refresh_request() || AUTO_REFRESH_RUNNING.swap(true, Ordering::SeqCst) {
+            return None;
         }
+    }
+}
 
-        if started_at.elapsed() >= timeout {
-            terminate_login_process(&mut child).await;
-            LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
-            return Ok(build_login_error_result(
-                "timeout",
-                format!(
-                    "在 {} 秒内未检测到新的 auth.json，请完成浏览器授权后重试",
-                    timeout.as_secs()
-                ),
-            ));
+/// 启动手动刷新任务；已有任务（包括只刷新到期账号的后台任务）运行时，
+/// 请求的账号排队并在其结束后执行，返回排队任务的编号
+fn start_refresh_job_internal<R: Runtime>(
+    app: &AppHandle<R>,
+    account_ids: Option<Vec<String>>,
+) -> Result<RefreshJobStatus, String> {
+    let job_id = queue_refresh_request(account_ids);
+    if !AUTO_REFRESH_RUNNING.swap(true, Ordering::SeqCst) {
+        if let Some(job) = run_queued_refresh_jobs(app) {
+            return Ok(job);
         }
+    }
 
-        tokio::time::sleep(Duration::from_millis(1000)).await;
+    Ok(current_refresh_job()
+        .filter(|job| job.job_id == job_id)
+        .unwrap_or_else(|| queued_refresh_job_status(job_id)))
+}
+
+/// 立即刷新全部账号用量，与后台自动刷新共用运行标记避免重复请求
+fn trigger_usage_refresh_now<R: Runtime>(app: &AppHandle<R>) {
+    if let Err(error) = start_refresh_job_internal(app, None) {
+        log::warn!("立即刷新用量失败: {}", error);
     }
 }
 
+/// 启动后台刷新任务（账号为空时刷新全部），与自动刷新去重
 #[tauri::command]
-fn cancel_codex_login() -> Result<(), String> {
-    LOGIN_CANCEL_REQUESTED.store(true, Ordering::SeqCst);
-    Ok(())
+fn start_refresh_job(
+    app: AppHandle,
+    account_ids: Option<Vec<String>>,
+) -> Result<RefreshJobStatus, String> {
+    start_refresh_job_internal(&app, account_ids)
 }
 
+/// 取消正在运行的刷新任务：不再发起新的请求，已发出的请求立即放弃
 #[tauri::command]
-fn refresh_tray_menu(app: AppHandle) -> Result<(), String> {
-    refresh_tray_menu_internal(&app)
+fn cancel_refresh_job(app: AppHandle) -> Result<bool, String> {
+    let (running, queued) = cancel_refresh_jobs();
+    if let Some(job) = &queued {
+        let _ = app.emit("refresh-job-finished", job);
+    }
+    Ok(running || queued.is_some())
 }
 
-#[tauri::command]
-fn show_main_window(app: AppHandle) -> Result<(), String> {
-    show_main_window_internal(&app)
+/// 取消运行中的任务并丢弃排队的请求；返回是否有运行中的任务，以及被丢弃的排队任务（状态为已取消）
+fn cancel_refresh_jobs() -> (bool, Option<RefreshJobStatus>) {
+    let queued = take_pending_refresh_request().map(|request| {
+        let mut job = queued_refresh_job_status(request.job_id);
+        job.state = "cancelled".to_string();
+        job.finished_at_ms = Some(job.started_at_ms);
+        job
+    });
+    let running = current_refresh_job().is_some_and(|job| job.state == "running");
+    if running {
+        current_refresh_job_cancel_token().cancel();
+    }
+    (running, queued)
 }
 
+/// 获取最近一次刷新任务的状态
 #[tauri::command]
-fn hide_to_tray(app: AppHandle) -> Result<(), String> {
-    hide_to_tray_internal(&app)
+fn get_refresh_job_status() -> Result<Option<RefreshJobStatus>, String> {
+    Ok(current_refresh_job())
 }
 
+/// 获取各账号的下次计划刷新时间
 #[tauri::command]
-fn exit_application(app: AppHandle) -> Result<(), String> {
-    app.exit(0);
-    Ok(())
+fn get_refresh_schedule() -> Result<Vec<AccountRefreshPlan>, String> {
+    let store = load_accounts_store_data()?;
+    Ok(current_account_refresh_plans(&store, now_epoch_ms_u64()))
 }
 
-fn initialize_tray<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
-    let menu = build_tray_menu(app)?;
-    let mut tray_builder = TrayIconBuilder::with_id(TRAY_ID)
-        .menu(&menu)
-        .show_menu_on_left_click(false)
-        .on_menu_event(|app, event| {
-            let menu_id = event.id.as_ref();
-            if menu_id == TRAY_MENU_OPEN_ID {
-                let _ = show_main_window_internal(app);
-                return;
-            }
-            if menu_id == TRAY_MENU_EXIT_ID {
-                app.exit(0);
-                return;
-            }
-            if let Some(account_id) = menu_id.strip_prefix("account:") {
-                let _ = switch_account_from_tray(app, account_id);
-            }
-        })
-        .on_tray_icon_event(|tray, event| {
-            if matches!(
-                event,
-                TrayIconEvent::Click {
-                    button: MouseButton::Left,
-                    button_state: MouseButtonState::Up,
-                    ..
-                } | TrayIconEvent::DoubleClick {
-                    button: MouseButton::Left,
-                    ..
-                }
-            ) {
-                let _ = show_main_window_internal(tray.app_handle());
-            }
-        });
+/// 写入Codex auth.json
+#[tauri::command]
+fn write_codex_auth(auth_config: String) -> Result<(), String> {
+    let path = get_codex_auth_path()?;
 
-    if let Some(icon) = app.default_window_icon().cloned() {
-        tray_builder = tray_builder.icon(icon);
+    // 确保.codex目录存在
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
     }
 
-    tray_builder.build(app).map_err(|e| e.to_string())?;
-    Ok(())
+    fs::write(&path, auth_config).map_err(|e| e.to_string())
 }
 
-/// 获取用量绑定映射路径
-fn get_usage_bindings_path() -> Result<PathBuf, String> {
-    let dir = get_app_data_dir()?;
-    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
-    Ok(dir.join("usage-bindings.json"))
-}
+/// 读取当前Codex auth.json
+#[tauri::command]
+fn read_codex_auth() -> Result<String, String> {
+    let path = get_codex_auth_path()?;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct SessionBinding {
-    session_id: String,
-    created_at: String,
-    file_path: String,
-    bound_at: String,
-}
+    if !path.exists() {
+        return Err("Codex auth.json not found".to_string());
+    }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
-struct UsageBindingsStore {
-    version: String,
-    bindings: HashMap<String, Vec<SessionBinding>>,
+    fs::read_to_string(&path).map_err(|e| e.to_string())
 }
 
-fn load_usage_bindings_unlocked() -> Result<UsageBindingsStore, String> {
-    let path = get_usage_bindings_path()?;
-    if !path.exists() {
-        return Ok(UsageBindingsStore {
-            version: "1.0.0".to_string(),
-            bindings: HashMap::new(),
-        });
+/// 保存指定账号 auth
+#[tauri::command]
+fn save_account_auth(account_id: String, auth_config: String) -> Result<(), String> {
+    let path = get_account_auth_path(&account_id)?;
+    let changed = fs::read_to_string(&path).map_or(true, |current| current != auth_config);
+    fs::write(&path, auth_config).map_err(|e| e.to_string())?;
+    // auth 更新后登录失效等状态可能已恢复，解除该账号的退避
+    if changed {
+        reset_account_circuit_breaker(Some(&account_id));
     }
-    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-    let store: UsageBindingsStore = serde_json::from_str(&content).map_err(|e| e.to_string())?;
-    Ok(store)
+    Ok(())
 }
 
-fn save_usage_bindings_unlocked(store: &UsageBindingsStore) -> Result<(), String> {
-    let path = get_usage_bindings_path()?;
-    let data = serde_json::to_string_pretty(store).map_err(|e| e.to_string())?;
-    fs::write(&path, data).map_err(|e| e.to_string())
+/// 读取指定账号 auth
+#[tauri::command]
+fn read_account_auth(account_id: String) -> Result<String, String> {
+    let path = get_account_auth_path(&account_id)?;
+    if !path.exists() {
+        return Err("Account auth not found".to_string());
+    }
+    fs::read_to_string(&path).map_err(|e| e.to_string())
 }
 
-fn update_usage_bindings(account_id: &str, binding: SessionBinding) -> Result<(), String> {
-    let _guard = USAGE_BINDINGS_LOCK
-        .lock()
-        .map_err(|_| "Bindings lock poisoned".to_string())?;
-    let mut store = load_usage_bindings_unlocked()?;
-    for (existing_account, existing_entries) in store.bindings.iter() {
-        if existing_account == account_id {
-            continue;
-        }
-        if existing_entries
-            .iter()
-            .any(|b| b.session_id == binding.session_id || b.file_path == binding.file_path)
-        {
-            return Err("Session file already bound to another account".to_string());
-        }
-    }
-    let entries = store.bindings.entry(account_id.to_string()).or_default();
-    if let Some(existing) = entries
-        .iter_mut()
-        .find(|b| b.session_id == binding.session_id)
-    {
-        *existing = binding;
-    } else {
-        entries.push(binding);
-    }
-    entries.sort_by(|a, b| {
-        a.created_at
-            .cmp(&b.created_at)
-            .then(a.bound_at.cmp(&b.bound_at))
-    });
-    if entries.len() > 200 {
-        let start = entries.len().saturating_sub(200);
-        entries.drain(0..start);
+/// 删除指定账号 auth
+#[tauri::command]
+fn delete_account_auth(account_id: String) -> Result<(), String> {
+    let path = get_account_auth_path(&account_id)?;
+    if path.exists() {
+        fs::remove_file(&path).map_err(|e| e.to_string())?;
     }
-    save_usage_bindings_unlocked(&store)
+    Ok(())
 }
 
-fn get_latest_bound_session_path(account_id: &str) -> Result<PathBuf, String> {
-    let _guard = USAGE_BINDINGS_LOCK
-        .lock()
-        .map_err(|_| "Bindings lock poisoned".to_string())?;
-    let store = load_usage_bindings_unlocked()?;
-    let entries = store
-        .bindings
-        .get(account_id)
-        .ok_or_else(|| "No usage bindings found for account".to_string())?;
+/// 读取文件内容
+#[tauri::command]
+fn read_file_content(file_path: String) -> Result<String, String> {
+    fs::read_to_string(&file_path).map_err(|e| e.to_string())
+}
 
-    let mut best_path: Option<PathBuf> = None;
-    let mut best_mtime: Option<SystemTime> = None;
+/// 写入文件内容
+#[tauri::command]
+fn write_file_content(file_path: String, content: String) -> Result<(), String> {
+    let path = PathBuf::from(file_path);
 
-    for entry in entries.iter().rev() {
-        let path = PathBuf::from(&entry.file_path);
-        if !path.exists() {
-            continue;
-        }
-        let mtime = fs::metadata(&path)
-            .and_then(|m| m.modified())
-            .unwrap_or(UNIX_EPOCH);
-        if best_mtime.map_or(true, |current| mtime > current) {
-            best_mtime = Some(mtime);
-            best_path = Some(path);
-        }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
     }
 
-    best_path.ok_or_else(|| "No valid bound session files found".to_string())
+    fs::write(path, content).map_err(|e| e.to_string())
 }
 
-#[derive(Debug, Deserialize)]
-struct AuthTokens {
-    id_token: Option<String>,
-    access_token: Option<String>,
-    refresh_token: Option<String>,
-    account_id: Option<String>,
+/// 获取用户主目录
+#[tauri::command]
+fn get_home_dir() -> Result<String, String> {
+    dirs::home_dir()
+        .map(|p| p.to_string_lossy().to_string())
+        .ok_or_else(|| "Cannot find home directory".to_string())
 }
 
-#[derive(Debug, Deserialize)]
-struct AuthConfig {
-    tokens: Option<AuthTokens>,
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RestartCodexProcessesResult {
+    app_restarted: bool,
+    cli_restarted: bool,
 }
 
+#[cfg(windows)]
 #[derive(Debug, Deserialize)]
-struct WhamAccountsCheckResponse {
-    accounts: Vec<WhamAccountEntry>,
+#[serde(rename_all = "camelCase")]
+struct WindowsRestartCodexResult {
+    app_restarted: bool,
+    cli_restarted: bool,
 }
 
-#[derive(Debug, Deserialize)]
-struct WhamAccountEntry {
-    id: String,
-    account_user_id: Option<String>,
-    structure: Option<String>,
-    plan_type: Option<String>,
-    name: Option<String>,
+#[cfg(windows)]
+fn escape_powershell_single_quoted(value: &str) -> String {
+    value.replace('\'', "''")
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-struct WhamAccountMetadata {
-    workspace_name: Option<String>,
-    account_user_id: Option<String>,
-    account_structure: Option<String>,
-    plan_type: Option<String>,
+#[cfg(windows)]
+fn build_codex_cli_launch_command(codex_path: Option<String>) -> String {
+    let normalized = normalize_codex_command_input(codex_path);
+    format!("& '{}'", escape_powershell_single_quoted(&normalized))
 }
 
-fn build_http_client(
-    proxy_enabled: Option<bool>,
-    proxy_url: Option<String>,
-) -> Result<Client, String> {
-    let mut client_builder = Client::builder();
-    if proxy_enabled.unwrap_or(false) {
-        let proxy_value = proxy_url.unwrap_or_default();
-        if proxy_value.trim().is_empty() {
-            return Err("\u{4ee3}\u{7406}\u{5df2}\u{5f00}\u{542f}\u{4f46}\u{4ee3}\u{7406}\u{5730}\u{5740}\u{4e3a}\u{7a7a}".to_string());
+#[cfg(windows)]
+fn run_windows_powershell(script: &str) -> Result<String, String> {
+    let output = std::process::Command::new("powershell.exe")
+        .args([
+            "-NoProfile",
+            "-ExecutionPolicy",
+            "Bypass",
+            "-Command",
+            script,
+        ])
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if stderr.is_empty() {
+            return Err(format!("PowerShell exited with status {}", output.status));
         }
-        let proxy = Proxy::all(&proxy_value).map_err(|e| e.to_string())?;
-        client_builder = client_builder.proxy(proxy);
+        return Err(stderr);
     }
 
-    client_builder.build().map_err(|e| e.to_string())
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
-fn take_non_empty_token(value: Option<String>, missing_message: &str) -> Result<String, String> {
-    let value = value.unwrap_or_default();
-    if value.trim().is_empty() {
-        return Err(missing_message.to_string());
-    }
-    Ok(value)
-}
+#[cfg(windows)]
+fn restart_codex_processes_windows(
+    codex_path: Option<String>,
+) -> Result<RestartCodexProcessesResult, String> {
+    let launch_command =
+        escape_powershell_single_quoted(&build_codex_cli_launch_command(codex_path));
 
-fn is_current_chatgpt_account(chatgpt_account_id: &str) -> bool {
-    get_current_auth_account_id()
-        .map(|current_account_id| current_account_id == chatgpt_account_id)
-        .unwrap_or(false)
-}
+    let script = format!(
+        r#"
+$ErrorActionPreference = 'Stop'
 
-fn extract_auth_credentials(auth_json: &str) -> Result<(String, String), String> {
-    let auth: AuthConfig = serde_json::from_str(auth_json).map_err(|e| e.to_string())?;
-    let tokens = auth
-        .tokens
-        .ok_or_else(|| "Missing tokens in auth.json".to_string())?;
+function Resolve-ExecutablePath([string]$commandLine) {{
+    if (-not $commandLine) {{
+        return $null
+    }}
 
-    let access_token = take_non_empty_token(tokens.access_token, "Missing access token")?;
-    let chatgpt_account_id = take_non_empty_token(tokens.account_id, "Missing ChatGPT account ID")?;
+    if ($commandLine -match '^\s*"([^"]+)"') {{
+        return $Matches[1]
+    }}
 
-    Ok((access_token, chatgpt_account_id))
-}
+    if ($commandLine -match '^\s*([^\s]+)') {{
+        return $Matches[1]
+    }}
 
-async fn fetch_wham_account_metadata(
-    auth_json: &str,
-    proxy_enabled: Option<bool>,
-    proxy_url: Option<String>,
-) -> Result<Option<WhamAccountMetadata>, String> {
-    let (access_token, chatgpt_account_id) = extract_auth_credentials(auth_json)?;
-    let client = build_http_client(proxy_enabled, proxy_url)?;
+    return $null
+}}
 
-    let response = client
-        .get("https://chatgpt.com/backend-api/wham/accounts/check")
-        .header("Authorization", format!("Bearer {}", access_token))
-        .header("Accept", "application/json")
-        .header("ChatGPT-Account-Id", &chatgpt_account_id)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
+function Resolve-PackageFullNameFromPath([string]$path) {{
+    if (-not $path) {{
+        return $null
+    }}
 
-    if !response.status().is_success() {
-        return Err(format!(
-            "wham/accounts/check 请求失败: {}",
-            response.status()
-        ));
-    }
+    if ($path -match 'WindowsApps\\([^\\]+)\\app\\Codex\.exe$') {{
+        return $Matches[1]
+    }}
+
+    return $null
+}}
+
+function Resolve-CodexDesktopLaunchTarget([string]$executablePath, [string]$commandLine) {{
+    $candidatePaths = @()
+    if ($executablePath) {{
+        $candidatePaths += $executablePath
+    }}
+
+    $resolvedFromCommand = Resolve-ExecutablePath $commandLine
+    if ($resolvedFromCommand) {{
+        $candidatePaths += $resolvedFromCommand
+    }}
+
+    foreach ($candidatePath in $candidatePaths) {{
+        $packageFullName = Resolve-PackageFullNameFromPath $candidatePath
+        if (-not $packageFullName) {{
+            continue
+        }}
+
+        $package = Get-AppxPackage | Where-Object {{ $_.PackageFullName -eq $packageFullName }} | Select-Object -First 1
+        if (-not $package) {{
+            $package = Get-AppxPackage -Name 'OpenAI.Codex' -ErrorAction SilentlyContinue | Sort-Object Version -Descending | Select-Object -First 1
+        }}
+        if (-not $package) {{
+            continue
+        }}
+
+        try {{
+            $manifest = Get-AppxPackageManifest -Package $package.PackageFullName
+            $application = $manifest.Package.Applications.Application |
+                Where-Object {{ $_.Executable -eq 'app/Codex.exe' }} |
+                Select-Object -First 1
+
+            if (-not $application) {{
+                $application = $manifest.Package.Applications.Application | Select-Object -First 1
+            }}
+
+            if ($application -and $application.Id) {{
+                return [pscustomobject]@{{
+                    mode = 'appx'
+                    value = 'shell:AppsFolder\{{0}}!{{1}}' -f $package.PackageFamilyName, $application.Id
+                }}
+            }}
+        }} catch {{
+            continue
+        }}
+    }}
+
+    foreach ($candidatePath in $candidatePaths) {{
+        if ($candidatePath) {{
+            return [pscustomobject]@{{
+                mode = 'path'
+                value = $candidatePath
+            }}
+        }}
+    }}
+
+    return $null
+}}
+
+$desktopProcesses = @(
+    Get-CimInstance Win32_Process | Where-Object {{
+        $_.CommandLine -and (
+            ($_.Name -ieq 'Codex.exe' -and $_.CommandLine -match 'OpenAI\.Codex') -or
+            ($_.Name -ieq 'codex.exe' -and $_.CommandLine -match '\\app\\resources\\codex\.exe')
+        )
+    }}
+)
+
+$desktopMain = $desktopProcesses |
+    Where-Object {{ $_.Name -ieq 'Codex.exe' -and $_.CommandLine -notmatch '--type=' }} |
+    Select-Object -First 1
+
+if (-not $desktopMain) {{
+    $desktopMain = $desktopProcesses | Select-Object -First 1
+}}
+
+$desktopLaunchTarget = $null
+if ($desktopMain) {{
+    $desktopLaunchTarget = Resolve-CodexDesktopLaunchTarget $desktopMain.ExecutablePath $desktopMain.CommandLine
+}}
+
+foreach ($process in $desktopProcesses) {{
+    Stop-Process -Id $process.ProcessId -Force -ErrorAction SilentlyContinue
+}}
+
+$appRestarted = $false
+if ($desktopProcesses.Count -gt 0 -and $desktopLaunchTarget) {{
+    Start-Sleep -Milliseconds 700
+    if ($desktopLaunchTarget.mode -eq 'appx') {{
+        Start-Process -FilePath 'explorer.exe' -ArgumentList $desktopLaunchTarget.value | Out-Null
+    }} else {{
+        Start-Process -FilePath $desktopLaunchTarget.value | Out-Null
+    }}
+    $appRestarted = $true
+}}
+
+$cliProcesses = @(
+    Get-CimInstance Win32_Process | Where-Object {{
+        $_.CommandLine -and
+        $_.CommandLine -notmatch 'codex-auth-manager' -and
+        $_.CommandLine -notmatch 'OpenAI\.Codex' -and
+        $_.CommandLine -notmatch '\\app\\resources\\codex\.exe' -and
+        (
+            ((@('powershell.exe', 'pwsh.exe', 'cmd.exe') -contains $_.Name.ToLower()) -and $_.CommandLine -match '(^|[\s"''`])codex([\s"''`]|$)') -or
+            ((@('node.exe', 'codex.exe') -contains $_.Name.ToLower()) -and $_.CommandLine -match 'codex')
+        )
+    }}
+)
+
+foreach ($process in $cliProcesses) {{
+    Stop-Process -Id $process.ProcessId -Force -ErrorAction SilentlyContinue
+}}
+
+$cliRestarted = $false
+if ($cliProcesses.Count -gt 0) {{
+    Start-Sleep -Milliseconds 400
+    $launchCommand = '{}'
+    Start-Process -FilePath 'powershell.exe' -ArgumentList @('-NoExit', '-Command', $launchCommand) | Out-Null
+    $cliRestarted = $true
+}}
+
+[pscustomobject]@{{
+    appRestarted = $appRestarted
+    cliRestarted = $cliRestarted
+}} | ConvertTo-Json -Compress
+"#,
+        launch_command
+    );
+
+    let stdout = run_windows_powershell(&script)?;
+    let result: WindowsRestartCodexResult =
+        serde_json::from_str(stdout.trim()).map_err(|e| e.to_string())?;
+
+    Ok(RestartCodexProcessesResult {
+        app_restarted: result.app_restarted,
+        cli_restarted: result.cli_restarted,
+    })
+}
+
+#[tauri::command]
+fn restart_codex_processes(
+    codex_path: Option<String>,
+) -> Result<RestartCodexProcessesResult, String> {
+    #[cfg(windows)]
+    {
+        restart_codex_processes_windows(codex_path)
+    }
+
+    #[cfg(not(windows))]
+    {
+        let _ = codex_path;
+        Err("Restarting Codex processes is only supported on Windows".to_string())
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct StartCodexLoginResult {
+    status: String,
+    auth_json: Option<String>,
+    changed_at: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LoginCommandMode {
+    Direct,
+    Cmd,
+    PowerShell,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LoginInvocation {
+    program: String,
+    args: Vec<String>,
+    mode: LoginCommandMode,
+}
+
+#[derive(Debug, Clone)]
+struct AuthSnapshot {
+    modified: Option<SystemTime>,
+    content: Option<String>,
+}
+
+fn get_auth_snapshot(path: &Path) -> Result<AuthSnapshot, String> {
+    if !path.exists() {
+        return Ok(AuthSnapshot {
+            modified: None,
+            content: None,
+        });
+    }
+
+    let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let content = fs::read_to_string(path).ok();
+
+    Ok(AuthSnapshot { modified, content })
+}
+
+fn auth_snapshot_changed(previous: &AuthSnapshot, current: &AuthSnapshot) -> bool {
+    if previous.modified != current.modified {
+        return true;
+    }
+    previous.content != current.content
+}
+
+fn validate_login_auth_json(auth_json: &str) -> Result<(), String> {
+    let auth: AuthConfig = serde_json::from_str(auth_json).map_err(|e| e.to_string())?;
+    let tokens = auth
+        .tokens
+        .ok_or_else(|| "auth.json \u{7f3a}\u{5c11} tokens \u{5b57}\u{6bb5}".to_string())?;
+
+    take_non_empty_token(tokens.id_token, "auth.json \u{7f3a}\u{5c11} id_token")?;
+    take_non_empty_token(
+        tokens.access_token,
+        "auth.json \u{7f3a}\u{5c11} access_token",
+    )?;
+    take_non_empty_token(
+        tokens.refresh_token,
+        "auth.json \u{7f3a}\u{5c11} refresh_token",
+    )?;
+    take_non_empty_token(tokens.account_id, "auth.json \u{7f3a}\u{5c11} account_id")?;
+
+    Ok(())
+}
+
+fn normalize_codex_command_input(codex_path: Option<String>) -> String {
+    let value = codex_path.unwrap_or_default();
+    let trimmed = value.trim();
+    let normalized = match trimmed.as_bytes() {
+        [first, middle @ .., last]
+            if (*first == b'"' && *last == b'"') || (*first == b'\'' && *last == b'\'') =>
+        {
+            std::str::from_utf8(middle).unwrap_or(trimmed).trim()
+        }
+        _ => trimmed,
+    };
+
+    if normalized.is_empty() {
+        "codex".to_string()
+    } else {
+        normalized.to_string()
+    }
+}
+
+#[cfg(windows)]
+fn resolve_command_candidates(command: &str) -> Vec<String> {
+    if command.contains('\\') || command.contains('/') || command.contains(':') {
+        return vec![command.to_string()];
+    }
+
+    let output = std::process::Command::new("where.exe")
+        .arg(command)
+        .output();
+    let Ok(output) = output else {
+        return vec![command.to_string()];
+    };
+
+    if !output.status.success() {
+        return vec![command.to_string()];
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut candidates: Vec<String> = stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect();
+
+    if candidates.is_empty() {
+        candidates.push(command.to_string());
+    }
+
+    candidates
+}
+
+#[cfg(not(windows))]
+fn resolve_command_candidates(command: &str) -> Vec<String> {
+    vec![command.to_string()]
+}
+
+fn command_priority(candidate: &str) -> usize {
+    let lower = candidate.to_ascii_lowercase();
+    if lower.ends_with(".cmd") || lower.ends_with(".bat") || lower.ends_with(".com") {
+        0
+    } else if lower.ends_with(".exe") {
+        1
+    } else if lower.ends_with(".ps1") {
+        2
+    } else {
+        3
+    }
+}
+
+fn resolve_login_invocation(codex_path: Option<String>) -> Result<LoginInvocation, String> {
+    let requested = normalize_codex_command_input(codex_path);
+    let mut candidates = resolve_command_candidates(&requested);
+    candidates.sort_by_key(|candidate| command_priority(candidate));
+
+    let selected = candidates
+        .first()
+        .cloned()
+        .ok_or_else(|| "未找到可用的 codex 命令".to_string())?;
+    let selected_path = PathBuf::from(&selected);
+    let lower = selected.to_ascii_lowercase();
+
+    if lower.ends_with(".ps1") {
+        return Ok(LoginInvocation {
+            program: "powershell".to_string(),
+            args: vec![
+                "-NoLogo".to_string(),
+                "-NoProfile".to_string(),
+                "-ExecutionPolicy".to_string(),
+                "Bypass".to_string(),
+                "-File".to_string(),
+                selected,
+                "login".to_string(),
+            ],
+            mode: LoginCommandMode::PowerShell,
+        });
+    }
+
+    if lower.ends_with(".cmd") || lower.ends_with(".bat") {
+        return Ok(LoginInvocation {
+            program: "cmd".to_string(),
+            args: vec!["/C".to_string(), selected, "login".to_string()],
+            mode: LoginCommandMode::Cmd,
+        });
+    }
+
+    if selected_path.is_absolute() || selected.contains('\\') || selected.contains('/') {
+        return Ok(LoginInvocation {
+            program: selected,
+            args: vec!["login".to_string()],
+            mode: LoginCommandMode::Direct,
+        });
+    }
+
+    Ok(LoginInvocation {
+        program: selected,
+        args: vec!["login".to_string()],
+        mode: LoginCommandMode::Direct,
+    })
+}
+
+fn build_login_command(invocation: &LoginInvocation) -> Command {
+    let mut command = Command::new(&invocation.program);
+    command.args(&invocation.args);
+    command.stdin(std::process::Stdio::null());
+    command.stdout(std::process::Stdio::null());
+    command.stderr(std::process::Stdio::null());
+
+    #[cfg(windows)]
+    command.creation_flags(CREATE_NO_WINDOW);
+
+    command
+}
+
+fn normalize_login_timeout_seconds(timeout_seconds: Option<u64>) -> u64 {
+    match timeout_seconds {
+        Some(0) | None => DEFAULT_LOGIN_TIMEOUT_SECONDS,
+        Some(value) => value.max(30),
+    }
+}
+
+async fn terminate_login_process(child: &mut Child) {
+    if child.try_wait().ok().flatten().is_none() {
+        let _ = child.kill().await;
+    }
+}
+
+fn build_login_success_result(auth_json: String, changed_at: String) -> StartCodexLoginResult {
+    StartCodexLoginResult {
+        status: "success".to_string(),
+        auth_json: Some(auth_json),
+        changed_at: Some(changed_at),
+        message: None,
+    }
+}
+
+fn build_login_error_result(status: &str, message: impl Into<String>) -> StartCodexLoginResult {
+    StartCodexLoginResult {
+        status: status.to_string(),
+        auth_json: None,
+        changed_at: None,
+        message: Some(message.into()),
+    }
+}
+
+fn try_read_updated_auth(
+    auth_path: &Path,
+    baseline: &AuthSnapshot,
+) -> Result<Option<StartCodexLoginResult>, String> {
+    let current = get_auth_snapshot(auth_path)?;
+    if !auth_snapshot_changed(baseline, &current) {
+        return Ok(None);
+    }
+
+    let Some(content) = current.content else {
+        return Ok(None);
+    };
+
+    match validate_login_auth_json(&content) {
+        Ok(()) => {
+            let changed_at = current
+                .modified
+                .and_then(epoch_ms_from_system_time)
+                .map(|value| value.to_string())
+                .unwrap_or_else(now_epoch_ms_string);
+            Ok(Some(build_login_success_result(content, changed_at)))
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+#[tauri::command]
+async fn start_codex_login(
+    codex_path: Option<String>,
+    timeout_seconds: Option<u64>,
+) -> Result<StartCodexLoginResult, String> {
+    LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+    let auth_path = get_codex_auth_path()?;
+    let baseline = get_auth_snapshot(&auth_path)?;
+    let invocation = resolve_login_invocation(codex_path)?;
+    let timeout = Duration::from_secs(normalize_login_timeout_seconds(timeout_seconds));
+
+    let mut child = build_login_command(&invocation).spawn().map_err(|error| {
+        if error.kind() == std::io::ErrorKind::NotFound {
+            format!(
+                "未找到 Codex CLI，请检查设置中的命令路径：{}",
+                invocation.program
+            )
+        } else {
+            format!("启动 Codex 登录失败：{}", error)
+        }
+    })?;
+
+    let started_at = Instant::now();
+    loop {
+        if LOGIN_CANCEL_REQUESTED.load(Ordering::SeqCst) {
+            terminate_login_process(&mut child).await;
+            LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+            return Ok(build_login_error_result(
+                "cancelled",
+                "已取消快速登录，停止等待授权".to_string(),
+            ));
+        }
+
+        if let Some(result) = try_read_updated_auth(&auth_path, &baseline)? {
+            terminate_login_process(&mut child).await;
+            LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+            return Ok(result);
+        }
+
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            if let Some(result) = try_read_updated_auth(&auth_path, &baseline)? {
+                LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+                return Ok(result);
+            }
+
+            let message = if status.success() {
+                "Codex 登录进程已结束，但未检测到新的 auth.json".to_string()
+            } else {
+                match status.code() {
+                    Some(code) => format!("Codex 登录进程异常结束，退出码：{}", code),
+                    None => "Codex 登录进程已被终止".to_string(),
+                }
+            };
+
+            LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+            return Ok(build_login_error_result("process_error", message));
+        }
+
+        if started_at.elapsed() >= timeout {
+            terminate_login_process(&mut child).await;
+            LOGIN_CANCEL_REQUESTED.store(false, Ordering::SeqCst);
+            return Ok(build_login_error_result(
+                "timeout",
+                format!(
+                    "在 {} 秒内未检测到新的 auth.json，请完成浏览器授权后重试",
+                    timeout.as_secs()
+                ),
+            ));
+        }
+
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+    }
+}
+
+#[tauri::command]
+fn cancel_codex_login() -> Result<(), String> {
+    LOGIN_CANCEL_REQUESTED.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+#[tauri::command]
+fn refresh_tray_menu(app: AppHandle) -> Result<(), String> {
+    refresh_tray_menu_internal(&app)
+}
+
+#[tauri::command]
+fn reload_global_shortcuts(app: AppHandle) -> Result<(), String> {
+    register_global_shortcuts(&app)
+}
+
+#[tauri::command]
+fn show_main_window(app: AppHandle) -> Result<(), String> {
+    show_main_window_internal(&app)
+}
+
+#[tauri::command]
+fn hide_to_tray(app: AppHandle) -> Result<(), String> {
+    hide_to_tray_internal(&app)
+}
+
+#[tauri::command]
+fn hide_tray_popover(app: AppHandle) -> Result<(), String> {
+    hide_tray_popover_internal(&app)
+}
+
+#[tauri::command]
+fn switch_account_from_popover(app: AppHandle, account_id: String) -> Result<(), String> {
+    switch_account_from_tray(&app, &account_id)?;
+    hide_tray_popover_internal(&app)
+}
+
+#[tauri::command]
+fn exit_application(app: AppHandle) -> Result<(), String> {
+    app.exit(0);
+    Ok(())
+}
+
+fn initialize_tray<R: Runtime>(app: &AppHandle<R>) -> Result<(), String> {
+    app.manage(TrayDetailItems::<R>(Mutex::new(Vec::new())));
+    let menu = build_tray_menu(app)?;
+    let mut tray_builder = TrayIconBuilder::with_id(TRAY_ID)
+        .menu(&menu)
+        .show_menu_on_left_click(false)
+        .on_menu_event(|app, event| {
+            let menu_id = event.id.as_ref();
+            if menu_id == TRAY_MENU_OPEN_ID {
+                let _ = show_main_window_internal(app);
+                return;
+            }
+            if menu_id == TRAY_MENU_EXIT_ID {
+                app.exit(0);
+                return;
+            }
+            if let Some(setting) = menu_id.strip_prefix(TRAY_SETTING_ID_PREFIX) {
+                if let Err(error) = update_config_from_tray(app, setting) {
+                    log::warn!("托盘设置更新失败: {}", error);
+                }
+                return;
+            }
+            if let Some(account_id) = menu_id.strip_prefix("account:") {
+                let _ = switch_account_from_tray(app, account_id);
+            }
+        })
+        .on_tray_icon_event(|tray, event| match event {
+            TrayIconEvent::Click {
+                button: MouseButton::Left,
+                button_state: MouseButtonState::Up,
+                position,
+                ..
+            } => {
+                let app = tray.app_handle();
+                let popover_enabled = load_accounts_store_data()
+                    .map(|store| store.config.tray_popover_enabled.unwrap_or(false))
+                    .unwrap_or(false);
+                if !popover_enabled {
+                    let _ = show_main_window_internal(app);
+                    return;
+                }
+                if let Err(error) = toggle_tray_popover(app, position) {
+                    log::warn!("打开迷你面板失败: {}", error);
+                    let _ = show_main_window_internal(app);
+                }
+            }
+            TrayIconEvent::DoubleClick {
+                button: MouseButton::Left,
+                ..
+            } => {
+                let app = tray.app_handle();
+                let _ = hide_tray_popover_internal(app);
+                let _ = show_main_window_internal(app);
+            }
+            _ => {}
+        });
+
+    if let Some(icon) = app.default_window_icon().cloned() {
+        tray_builder = tray_builder.icon(icon);
+    }
+
+    tray_builder.build(app).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// 获取用量绑定映射路径
+fn get_usage_bindings_path() -> Result<PathBuf, String> {
+    let dir = get_app_data_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir.join("usage-bindings.json"))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SessionBinding {
+    session_id: String,
+    created_at: String,
+    file_path: String,
+    bound_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct UsageBindingsStore {
+    version: String,
+    bindings: HashMap<String, Vec<SessionBinding>>,
+}
+
+fn load_usage_bindings_unlocked() -> Result<UsageBindingsStore, String> {
+    let path = get_usage_bindings_path()?;
+    if !path.exists() {
+        return Ok(UsageBindingsStore {
+            version: "1.0.0".to_string(),
+            bindings: HashMap::new(),
+        });
+    }
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let store: UsageBindingsStore = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    Ok(store)
+}
+
+fn save_usage_bindings_unlocked(store: &UsageBindingsStore) -> Result<(), String> {
+    let path = get_usage_bindings_path()?;
+    let data = serde_json::to_string_pretty(store).map_err(|e| e.to_string())?;
+    fs::write(&path, data).map_err(|e| e.to_string())
+}
+
+fn update_usage_bindings(account_id: &str, binding: SessionBinding) -> Result<(), String> {
+    let _guard = USAGE_BINDINGS_LOCK
+        .lock()
+        .map_err(|_| "Bindings lock poisoned".to_string())?;
+    let mut store = load_usage_bindings_unlocked()?;
+    for (existing_account, existing_entries) in store.bindings.iter() {
+        if existing_account == account_id {
+            continue;
+        }
+        if existing_entries
+            .iter()
+            .any(|b| b.session_id == binding.session_id || b.file_path == binding.file_path)
+        {
+            return Err("Session file already bound to another account".to_string());
+        }
+    }
+    let entries = store.bindings.entry(account_id.to_string()).or_default();
+    if let Some(existing) = entries
+        .iter_mut()
+        .find(|b| b.session_id == binding.session_id)
+    {
+        *existing = binding;
+    } else {
+        entries.push(binding);
+    }
+    entries.sort_by(|a, b| {
+        a.created_at
+            .cmp(&b.created_at)
+            .then(a.bound_at.cmp(&b.bound_at))
+    });
+    if entries.len() > 200 {
+        let start = entries.len().saturating_sub(200);
+        entries.drain(0..start);
+    }
+    save_usage_bindings_unlocked(&store)
+}
+
+fn get_latest_bound_session_path(account_id: &str) -> Result<PathBuf, String> {
+    let indexed =
+        with_scanned_session_index(|index| index.latest_for_account(account_id).map(PathBuf::from));
+    if let Ok(Some(path)) = indexed {
+        return Ok(path);
+    }
+
+    let _guard = USAGE_BINDINGS_LOCK
+        .lock()
+        .map_err(|_| "Bindings lock poisoned".to_string())?;
+    let store = load_usage_bindings_unlocked()?;
+    let entries = store
+        .bindings
+        .get(account_id)
+        .ok_or_else(|| "No usage bindings found for account".to_string())?;
+
+    let mut best_path: Option<PathBuf> = None;
+    let mut best_mtime: Option<SystemTime> = None;
+
+    for entry in entries.iter().rev() {
+        let path = PathBuf::from(&entry.file_path);
+        if !path.exists() {
+            continue;
+        }
+        let mtime = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .unwrap_or(UNIX_EPOCH);
+        if best_mtime.map_or(true, |current| mtime > current) {
+            best_mtime = Some(mtime);
+            best_path = Some(path);
+        }
+    }
+
+    best_path.ok_or_else(|| "No valid bound session files found".to_string())
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthTokens {
+    id_token: Option<String>,
+    access_token: Option<String>,
+    refresh_token: Option<String>,
+    account_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthConfig {
+    tokens: Option<AuthTokens>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhamAccountsCheckResponse {
+    accounts: Vec<WhamAccountEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WhamAccountEntry {
+    id: String,
+    account_user_id: Option<String>,
+    structure: Option<String>,
+    plan_type: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WhamAccountMetadata {
+    workspace_name: Option<String>,
+    account_user_id: Option<String>,
+    account_structure: Option<String>,
+    plan_type: Option<String>,
+}
+
+/// 解析后端 API 根地址：环境变量优先，其次为配置，最后使用官方地址
+fn resolve_api_base_url(env_value: Option<String>, config_value: Option<&str>) -> String {
+    env_value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .or_else(|| {
+            config_value
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        })
+        .unwrap_or(DEFAULT_API_BASE_URL)
+        .trim_end_matches('/')
+        .to_string()
+}
+
+fn current_api_base_url() -> String {
+    let config_value = load_accounts_store_data()
+        .ok()
+        .and_then(|store| store.config.api_base_url);
+    resolve_api_base_url(
+        std::env::var(API_BASE_URL_ENV).ok(),
+        config_value.as_deref(),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct HttpTimeouts {
+    connect: Duration,
+    read: Duration,
+}
+
+fn normalize_request_timeout_seconds(value: Option<u64>, default_seconds: u64) -> Duration {
+    let seconds = match value {
+        Some(0) | None => default_seconds,
+        Some(value) => value.min(MAX_REQUEST_TIMEOUT_SECONDS),
+    };
+    Duration::from_secs(seconds)
+}
+
+fn resolve_http_timeouts(config: &TrayAppConfig) -> HttpTimeouts {
+    HttpTimeouts {
+        connect: normalize_request_timeout_seconds(
+            config.request_connect_timeout_seconds,
+            DEFAULT_CONNECT_TIMEOUT_SECONDS,
+        ),
+        read: normalize_request_timeout_seconds(
+            config.request_read_timeout_seconds,
+            DEFAULT_READ_TIMEOUT_SECONDS,
+        ),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ProxyMode {
+    /// 使用环境变量 HTTP(S)_PROXY / ALL_PROXY / NO_PROXY（reqwest 默认行为）
+    Env,
+    Direct,
+    Manual(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProxyCredentials {
+    username: String,
+    password: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProxySettings {
+    mode: ProxyMode,
+    /// 使用账号级代理时为 true
+    account_override: bool,
+    no_proxy: Option<String>,
+    /// 保存的代理凭据，只用于全局手动代理；账号级代理的凭据写在其 URL 中
+    credentials: Option<ProxyCredentials>,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ProxyCredentialsStatus {
+    username: Option<String>,
+    has_password: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ProxyTestResult {
+    ok: bool,
+    mode: String,
+    proxy: Option<String>,
+    status_code: Option<u16>,
+    latency_ms: Option<u64>,
+    error: Option<String>,
+    error_code: Option<String>,
+}
+
+/// 解析代理设置：账号级代理优先；其次 proxy_enabled 为 true 时使用手动代理；否则按 proxy_mode 走环境变量代理或直连
+fn resolve_proxy_settings(
+    config: &TrayAppConfig,
+    proxy_enabled: Option<bool>,
+    proxy_url: Option<String>,
+    account_proxy: Option<&str>,
+    credentials: Option<ProxyCredentials>,
+) -> Result<ProxySettings, String> {
+    let account_proxy = account_proxy
+        .map(str::trim)
+        .filter(|value| !value.is_empty());
+    let mode = match account_proxy {
+        Some(ACCOUNT_PROXY_DIRECT) => ProxyMode::Direct,
+        Some(url) => ProxyMode::Manual(url.to_string()),
+        None if proxy_enabled.unwrap_or(false) => {
+            let proxy_value = proxy_url.unwrap_or_default();
+            if proxy_value.trim().is_empty() {
+                return Err("\u{4ee3}\u{7406}\u{5df2}\u{5f00}\u{542f}\u{4f46}\u{4ee3}\u{7406}\u{5730}\u{5740}\u{4e3a}\u{7a7a}".to_string());
+            }
+            ProxyMode::Manual(proxy_value.trim().to_string())
+        }
+        None if config.proxy_mode.as_deref() == Some(PROXY_MODE_DIRECT) => ProxyMode::Direct,
+        None => ProxyMode::Env,
+    };
+    // 账号级代理可能指向其他主机，不能把全局代理的凭据发给它
+    let uses_global_manual_proxy = account_proxy.is_none() && matches!(mode, ProxyMode::Manual(_));
+
+    Ok(ProxySettings {
+        mode,
+        account_override: account_proxy.is_some(),
+        no_proxy: config
+            .proxy_no_proxy
+            .as_deref()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| value.to_string()),
+        credentials: credentials
+            .filter(|credentials| !credentials.username.trim().is_empty())
+            .filter(|_| uses_global_manual_proxy),
+    })
+}
+
+/// 额外信任的 CA 证书（PEM 文件路径）以及是否同时信任系统证书库，用于 TLS 拦截的公司网络
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct TlsSettings {
+    ca_bundle_paths: Vec<String>,
+    use_system_certs: bool,
+}
+
+fn resolve_tls_settings(config: &TrayAppConfig) -> TlsSettings {
+    TlsSettings {
+        ca_bundle_paths: config
+            .tls_ca_bundle_paths
+            .iter()
+            .flatten()
+            .map(|path| path.trim())
+            .filter(|path| !path.is_empty())
+            .map(|path| path.to_string())
+            .collect(),
+        use_system_certs: config.tls_use_system_certs.unwrap_or(false),
+    }
+}
+
+fn load_ca_certificates(path: &str) -> Result<Vec<reqwest::Certificate>, String> {
+    let pem = fs::read(path).map_err(|e| format!("读取 CA 证书失败 ({}): {}", path, e))?;
+    let certificates = reqwest::Certificate::from_pem_bundle(&pem)
+        .map_err(|e| format!("解析 CA 证书失败 ({}): {}", path, e))?;
+    if certificates.is_empty() {
+        return Err(format!("CA 证书文件中没有 PEM 证书: {}", path));
+    }
+    Ok(certificates)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HttpClientSettings {
+    proxy: ProxySettings,
+    timeouts: HttpTimeouts,
+    tls: TlsSettings,
+}
+
+fn build_http_client_with(settings: &HttpClientSettings) -> Result<Client, String> {
+    let mut client_builder = Client::builder()
+        .connect_timeout(settings.timeouts.connect)
+        .read_timeout(settings.timeouts.read)
+        .tls_built_in_native_certs(settings.tls.use_system_certs);
+
+    for path in &settings.tls.ca_bundle_paths {
+        for certificate in load_ca_certificates(path)? {
+            client_builder = client_builder.add_root_certificate(certificate);
+        }
+    }
+
+    let proxy_settings = &settings.proxy;
+    match &proxy_settings.mode {
+        ProxyMode::Env => {}
+        ProxyMode::Direct => client_builder = client_builder.no_proxy(),
+        ProxyMode::Manual(proxy_url) => {
+            let mut proxy = Proxy::all(proxy_url).map_err(|e| e.to_string())?;
+            if let Some(credentials) = &proxy_settings.credentials {
+                proxy = proxy.basic_auth(&credentials.username, &credentials.password);
+            }
+            proxy = proxy.no_proxy(
+                proxy_settings
+                    .no_proxy
+                    .as_deref()
+                    .and_then(reqwest::NoProxy::from_string),
+            );
+            client_builder = client_builder.proxy(proxy);
+        }
+    }
+
+    client_builder.build().map_err(|e| e.to_string())
+}
+
+fn resolve_http_client_settings(
+    account_id: Option<&str>,
+    proxy_enabled: Option<bool>,
+    proxy_url: Option<String>,
+) -> Result<HttpClientSettings, String> {
+    let store = load_accounts_store_data().ok();
+    let config = store
+        .as_ref()
+        .map(|store| store.config.clone())
+        .unwrap_or_default();
+    let account_proxy = account_id.and_then(|account_id| {
+        store
+            .as_ref()?
+            .accounts
+            .iter()
+            .find(|account| account.id == account_id)?
+            .proxy_url
+            .clone()
+    });
+    let credentials = load_proxy_credentials().unwrap_or_else(|error| {
+        log::warn!("读取代理凭据失败: {}", error);
+        None
+    });
+    let proxy = resolve_proxy_settings(
+        &config,
+        proxy_enabled,
+        proxy_url,
+        account_proxy.as_deref(),
+        credentials,
+    )?;
+    Ok(HttpClientSettings {
+        proxy,
+        timeouts: resolve_http_timeouts(&config),
+        tls: resolve_tls_settings(&config),
+    })
+}
+
+fn build_account_http_client(
+    account_id: Option<&str>,
+    proxy_enabled: Option<bool>,
+    proxy_url: Option<String>,
+) -> Result<Client, String> {
+    let settings = resolve_http_client_settings(account_id, proxy_enabled, proxy_url)?;
+    if settings.proxy.account_override {
+        // 账号级代理单独缓存，避免与全局客户端来回替换
+        return account_proxy_http_client(&settings);
+    }
+    managed_http_client(&settings)
+}
+
+/// 客户端缓存键：除设置本身外还包含 CA 证书文件的大小与修改时间，文件内容更新后会重新加载
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HttpClientKey {
+    settings: HttpClientSettings,
+    ca_bundle_stamps: Vec<Option<(u64, SystemTime)>>,
+}
+
+fn http_client_key(settings: &HttpClientSettings) -> HttpClientKey {
+    HttpClientKey {
+        settings: settings.clone(),
+        ca_bundle_stamps: settings
+            .tls
+            .ca_bundle_paths
+            .iter()
+            .map(|path| {
+                let metadata = fs::metadata(path).ok()?;
+                Some((metadata.len(), metadata.modified().ok()?))
+            })
+            .collect(),
+    }
+}
+
+struct ManagedHttpClient {
+    key: HttpClientKey,
+    client: Client,
+}
+
+/// 全局共用一个 HTTP 客户端以保留连接池，代理、超时或证书设置变化时替换
+fn managed_http_client(settings: &HttpClientSettings) -> Result<Client, String> {
+    let key = http_client_key(settings);
+    let mut managed = MANAGED_HTTP_CLIENT.lock().map_err(|e| e.to_string())?;
+    if let Some(current) = managed.as_ref().filter(|current| current.key == key) {
+        return Ok(current.client.clone());
+    }
+
+    let client = build_http_client_with(settings)?;
+    *managed = Some(ManagedHttpClient {
+        key,
+        client: client.clone(),
+    });
+    Ok(client)
+}
+
+/// 账号级代理的客户端按设置缓存，最多保留 ACCOUNT_PROXY_HTTP_CLIENT_LIMIT 个，超出时淘汰最久未用的
+fn account_proxy_http_client(settings: &HttpClientSettings) -> Result<Client, String> {
+    let key = http_client_key(settings);
+    let mut clients = ACCOUNT_PROXY_HTTP_CLIENTS
+        .lock()
+        .map_err(|e| e.to_string())?;
+    if let Some(index) = clients.iter().position(|current| current.key == key) {
+        let current = clients.remove(index).expect("index is in range");
+        let client = current.client.clone();
+        clients.push_back(current);
+        return Ok(client);
+    }
+
+    let client = build_http_client_with(settings)?;
+    // 同一账号代理的旧设置不会再被使用，直接替换
+    clients.retain(|current| current.key.settings.proxy != key.settings.proxy);
+    while clients.len() >= ACCOUNT_PROXY_HTTP_CLIENT_LIMIT {
+        clients.pop_front();
+    }
+    clients.push_back(ManagedHttpClient {
+        key,
+        client: client.clone(),
+    });
+    Ok(client)
+}
+
+fn build_http_client(
+    proxy_enabled: Option<bool>,
+    proxy_url: Option<String>,
+) -> Result<Client, String> {
+    build_account_http_client(None, proxy_enabled, proxy_url)
+}
+
+/// 代理用户名单独保存在 ~/.codex_manager 下，密码保存在系统钥匙串中，均不写入前端可读的 accounts.json
+fn get_proxy_credentials_path() -> Result<PathBuf, String> {
+    let dir = get_codex_manager_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir.join("proxy-credentials.json"))
+}
+
+fn write_private_file(path: &Path, data: &str) -> Result<(), String> {
+    fs::write(path, data).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600)).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// proxy-credentials.json 的内容；旧版本把密码明文写在这里，读取时会迁移到钥匙串
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProxyCredentialsRecord {
+    username: String,
+    #[serde(default, skip_serializing)]
+    password: Option<String>,
+}
+
+fn read_proxy_credentials_record(path: &Path) -> Result<Option<ProxyCredentialsRecord>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let record: ProxyCredentialsRecord =
+        serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    Ok(Some(record).filter(|record| !record.username.trim().is_empty()))
+}
+
+fn write_proxy_credentials_record(path: &Path, username: &str) -> Result<(), String> {
+    let record = ProxyCredentialsRecord {
+        username: username.to_string(),
+        password: None,
+    };
+    let data = serde_json::to_string_pretty(&record).map_err(|e| e.to_string())?;
+    write_private_file(path, &data)
+}
+
+fn proxy_keyring_entry(username: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(PROXY_KEYRING_SERVICE, username)
+        .map_err(|e| format!("访问系统钥匙串失败: {}", e))
+}
+
+fn read_proxy_password(username: &str) -> Result<String, String> {
+    match proxy_keyring_entry(username)?.get_password() {
+        Ok(password) => Ok(password),
+        Err(keyring::Error::NoEntry) => Ok(String::new()),
+        Err(error) => Err(format!("读取系统钥匙串失败: {}", error)),
+    }
+}
+
+fn delete_proxy_password(username: &str) -> Result<(), String> {
+    match proxy_keyring_entry(username)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(error) => Err(format!("删除钥匙串中的代理密码失败: {}", error)),
+    }
+}
+
+/// 写入代理密码（为空时删除）
+fn write_proxy_password(username: &str, password: &str) -> Result<(), String> {
+    if password.is_empty() {
+        return delete_proxy_password(username);
+    }
+    proxy_keyring_entry(username)?
+        .set_password(password)
+        .map_err(|e| format!("写入系统钥匙串失败: {}", e))
+}
+
+fn read_proxy_credentials_from_keyring() -> Result<Option<ProxyCredentials>, String> {
+    let path = get_proxy_credentials_path()?;
+    let Some(record) = read_proxy_credentials_record(&path)? else {
+        return Ok(None);
+    };
+
+    let password = match record.password {
+        Some(password) => {
+            write_proxy_password(&record.username, &password)?;
+            write_proxy_credentials_record(&path, &record.username)?;
+            log::info!("已将代理密码迁移到系统钥匙串");
+            password
+        }
+        None => read_proxy_password(&record.username)?,
+    };
+    Ok(Some(ProxyCredentials {
+        username: record.username,
+        password,
+    }))
+}
+
+/// 每次请求都要解析代理设置，钥匙串读取较慢（部分系统还会弹出授权），读取一次后缓存在内存中
+fn load_proxy_credentials() -> Result<Option<ProxyCredentials>, String> {
+    let mut cache = PROXY_CREDENTIALS_CACHE.lock().map_err(|e| e.to_string())?;
+    if let Some(credentials) = cache.as_ref() {
+        return Ok(credentials.clone());
+    }
+    let credentials = read_proxy_credentials_from_keyring()?;
+    *cache = Some(credentials.clone());
+    Ok(credentials)
+}
+
+/// 保存代理用户名与密码（用户名为空时清除；密码为空表示保留已保存的密码）
+#[tauri::command]
+fn set_proxy_credentials(username: String, password: Option<String>) -> Result<(), String> {
+    let path = get_proxy_credentials_path()?;
+    let username = username.trim();
+    let previous_username = read_proxy_credentials_record(&path)
+        .ok()
+        .flatten()
+        .map(|record| record.username);
+    let password = match password {
+        Some(password) => password,
+        None => match previous_username.as_deref() {
+            Some(previous_username) => read_proxy_password(previous_username)?,
+            None => String::new(),
+        },
+    };
+
+    if let Some(previous_username) = previous_username.filter(|previous| previous != username) {
+        delete_proxy_password(&previous_username)?;
+    }
+    if username.is_empty() {
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| e.to_string())?;
+        }
+    } else {
+        write_proxy_password(username, &password)?;
+        write_proxy_credentials_record(&path, username)?;
+    }
+
+    if let Ok(mut cache) = PROXY_CREDENTIALS_CACHE.lock() {
+        *cache = None;
+    }
+    Ok(())
+}
+
+/// 获取代理凭据状态（不返回密码）
+#[tauri::command]
+fn get_proxy_credentials_status() -> Result<ProxyCredentialsStatus, String> {
+    let credentials = load_proxy_credentials()?;
+    Ok(ProxyCredentialsStatus {
+        username: credentials
+            .as_ref()
+            .map(|credentials| credentials.username.clone()),
+        has_password: credentials
+            .map(|credentials| !credentials.password.is_empty())
+            .unwrap_or(false),
+    })
+}
+
+/// 代理测试只请求 API 根地址、不携带令牌：2xx/3xx 或预期中的 401 说明请求已经到达后端
+fn is_proxy_test_success(status: reqwest::StatusCode) -> bool {
+    status.is_success() || status.is_redirection() || status == reqwest::StatusCode::UNAUTHORIZED
+}
+
+fn proxy_test_status_error(status: reqwest::StatusCode) -> String {
+    if status == reqwest::StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+        "代理需要认证，请检查代理用户名和密码 (407)".to_string()
+    } else {
+        format!("后端返回异常状态 {}", status)
+    }
+}
+
+/// 测试当前（或指定账号的）代理设置能否连通后端，并返回延迟
+#[tauri::command]
+async fn test_proxy(
+    account_id: Option<String>,
+    proxy_enabled: Option<bool>,
+    proxy_url: Option<String>,
+) -> Result<ProxyTestResult, String> {
+    let settings = resolve_http_client_settings(account_id.as_deref(), proxy_enabled, proxy_url)?;
+    let (mode, proxy) = match &settings.proxy.mode {
+        ProxyMode::Env => (PROXY_MODE_ENV.to_string(), None),
+        ProxyMode::Direct => (PROXY_MODE_DIRECT.to_string(), None),
+        ProxyMode::Manual(url) => ("manual".to_string(), Some(url.clone())),
+    };
+    let client = build_http_client_with(&settings)?;
+
+    let started_at = Instant::now();
+    let result = client.get(current_api_base_url()).send().await;
+    let latency_ms = u64::try_from(started_at.elapsed().as_millis()).unwrap_or(u64::MAX);
+
+    Ok(match result {
+        Ok(response) => {
+            let status = response.status();
+            let ok = is_proxy_test_success(status);
+            ProxyTestResult {
+                ok,
+                mode,
+                proxy,
+                status_code: Some(status.as_u16()),
+                latency_ms: Some(latency_ms),
+                error: (!ok).then(|| proxy_test_status_error(status)),
+                error_code: None,
+            }
+        }
+        Err(error) => ProxyTestResult {
+            ok: false,
+            mode,
+            proxy,
+            status_code: None,
+            latency_ms: None,
+            error_code: is_certificate_error(&error).then(|| TLS_ERROR_STATUS.to_string()),
+            error: Some(error.to_string()),
+        },
+    })
+}
+
+fn take_non_empty_token(value: Option<String>, missing_message: &str) -> Result<String, String> {
+    let value = value.unwrap_or_default();
+    if value.trim().is_empty() {
+        return Err(missing_message.to_string());
+    }
+    Ok(value)
+}
+
+fn is_current_chatgpt_account(chatgpt_account_id: &str) -> bool {
+    get_current_auth_account_id()
+        .map(|current_account_id| current_account_id == chatgpt_account_id)
+        .unwrap_or(false)
+}
+
+fn extract_auth_credentials(auth_json: &str) -> Result<(String, String), String> {
+    let auth: AuthConfig = serde_json::from_str(auth_json).map_err(|e| e.to_string())?;
+    let tokens = auth
+        .tokens
+        .ok_or_else(|| "Missing tokens in auth.json".to_string())?;
+
+    let access_token = take_non_empty_token(tokens.access_token, "Missing access token")?;
+    let chatgpt_account_id = take_non_empty_token(tokens.account_id, "Missing ChatGPT account ID")?;
+
+    Ok((access_token, chatgpt_account_id))
+}
+
+async fn fetch_wham_account_metadata(
+    auth_json: &str,
+    proxy_enabled: Option<bool>,
+    proxy_url: Option<String>,
+) -> Result<Option<WhamAccountMetadata>, String> {
+    let (access_token, chatgpt_account_id) = extract_auth_credentials(auth_json)?;
+    let client = build_http_client(proxy_enabled, proxy_url)?;
+    request_wham_account_metadata(
+        &client,
+        &current_api_base_url(),
+        &access_token,
+        &chatgpt_account_id,
+    )
+    .await
+}
+
+async fn request_wham_account_metadata(
+    client: &Client,
+    base_url: &str,
+    access_token: &str,
+    chatgpt_account_id: &str,
+) -> Result<Option<WhamAccountMetadata>, String> {
+    let response = client
+        .get(format!("{}/wham/accounts/check", base_url))
+        .header("Authorization", format!("Bearer {}", access_token))
+        .header("Accept", "application/json")
+        .header("ChatGPT-Account-Id", chatgpt_account_id)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.status().is_success() {
+        record_diagnostic_event(DiagnosticEvent {
+            recorded_at_ms: now_epoch_ms_u64(),
+            kind: "response".to_string(),
+            endpoint: "wham/accounts/check".to_string(),
+            status_code: Some(response.status().as_u16()),
+            ..Default::default()
+        });
+        return Err(format!(
+            "wham/accounts/check 请求失败: {}",
+            response.status()
+        ));
+    }
+
+    let body = response.text().await.map_err(|e| e.to_string())?;
+    let value: WhamAccountsCheckResponse =
+        serde_json::from_str(&body).map_err(|e| e.to_string())?;
+    let matched = value
+        .accounts
+        .into_iter()
+        .find(|account| account.id == chatgpt_account_id);
+
+    Ok(matched.map(|account| WhamAccountMetadata {
+        workspace_name: match account.structure.as_deref() {
+            Some("workspace") => account.name.filter(|name| !name.trim().is_empty()),
+            _ => None,
+        },
+        account_user_id: account.account_user_id,
+        account_structure: account.structure,
+        plan_type: account.plan_type,
+    }))
+}
+
+fn get_current_auth_account_id() -> Result<String, String> {
+    let path = get_codex_auth_path()?;
+    if !path.exists() {
+        return Err("Codex auth.json not found".to_string());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let auth: AuthConfig = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+    let tokens = auth
+        .tokens
+        .ok_or_else(|| "Missing tokens in auth.json".to_string())?;
+    take_non_empty_token(tokens.account_id, "Missing account_id in auth.json")
+}
+
+#[tauri::command]
+async fn get_wham_account_metadata(
+    account_id: String,
+    proxy_enabled: Option<bool>,
+    proxy_url: Option<String>,
+) -> Result<Option<WhamAccountMetadata>, String> {
+    if account_id.is_empty() {
+        return Ok(None);
+    }
+
+    let auth_json = read_account_auth(account_id)?;
+    fetch_wham_account_metadata(&auth_json, proxy_enabled, proxy_url).await
+}
+
+// ==================== 用量解析相关结构 ====================
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[allow(dead_code)]
+struct RateLimitEntry {
+    used_percent: f64,
+    window_minutes: u32,
+    resets_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct RateLimits {
+    primary: Option<RateLimitEntry>,
+    secondary: Option<RateLimitEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventMsg {
+    #[serde(rename = "type")]
+    msg_type: String,
+    payload: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageData {
+    pub five_hour_percent_left: Option<f64>,
+    pub five_hour_reset_time_ms: Option<i64>,
+    pub weekly_percent_left: Option<f64>,
+    pub weekly_reset_time_ms: Option<i64>,
+    pub code_review_percent_left: Option<f64>,
+    pub code_review_reset_time_ms: Option<i64>,
+    pub five_hour_burn_rate_per_hour: Option<f64>,
+    pub five_hour_projected_exhaustion_ms: Option<i64>,
+    pub weekly_burn_rate_per_hour: Option<f64>,
+    pub weekly_projected_exhaustion_ms: Option<i64>,
+    pub last_updated: String,
+    pub source_file: Option<String>,
+    pub details: Option<WhamUsageDetails>,
+    /// 数据来源：wham（在线接口）或 session（本地会话文件）
+    pub source: Option<String>,
+    /// 获取时数据距今的毫秒数
+    pub age_ms: Option<u64>,
+    /// 全部额度窗口；上面的 five_hour / weekly / code_review 字段由其中对应窗口派生
+    pub windows: Vec<UsageWindow>,
+    /// 当前会话的上下文窗口，只有当前账号的本地 session 数据中才有
+    pub context_window: Option<ContextWindowUsage>,
+}
+
+/// wham/usage 响应：额度窗口保留原始 JSON，由 build_wham_usage_data 逐个解析校验；
+/// 账号标识、credits、推广等附加字段逐个宽松读取，类型不符的字段忽略并记入 mismatches
+#[derive(Debug, Default)]
+struct WhamUsagePayload {
+    user_id: Option<String>,
+    account_id: Option<String>,
+    email: Option<String>,
+    plan_type: Option<String>,
+    /// rate_limit（部分响应使用 rate_limits）
+    rate_limit: Option<serde_json::Value>,
+    code_review_rate_limit: Option<serde_json::Value>,
+    additional_rate_limits: Vec<serde_json::Value>,
+    additional_rate_limit_details: Vec<WhamAdditionalRateLimit>,
+    allowed: Option<bool>,
+    limit_reached: Option<bool>,
+    code_review_allowed: Option<bool>,
+    code_review_limit_reached: Option<bool>,
+    credits: Option<WhamCredits>,
+    spend_control: Option<WhamSpendControl>,
+    rate_limit_reached_type: Option<String>,
+    promo: Option<WhamPromo>,
+    /// 类型不符而被忽略的字段及原因，用于记录诊断事件
+    mismatches: Vec<String>,
+}
+
+impl WhamUsagePayload {
+    fn from_value(value: &serde_json::Value) -> Self {
+        let mut mismatches = Vec::new();
+        let present = |key: &str| value.get(key).filter(|field| !field.is_null()).cloned();
+        let rate_limit = present("rate_limit").or_else(|| present("rate_limits"));
+        let code_review_rate_limit = present("code_review_rate_limit");
+        let additional_rate_limits = match present("additional_rate_limits") {
+            Some(serde_json::Value::Array(items)) => items,
+            Some(_) => {
+                mismatches.push("additional_rate_limits: expected an array".to_string());
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+        let additional_rate_limit_details = additional_rate_limits
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| {
+                WhamAdditionalRateLimit::deserialize(item)
+                    .map_err(|error| {
+                        mismatches.push(format!("additional_rate_limits[{}]: {}", index, error))
+                    })
+                    .ok()
+            })
+            .collect();
+        let mut status_flag = |status: &Option<serde_json::Value>, prefix: &str, key: &str| {
+            status
+                .as_ref()
+                .and_then(|status| lenient_field::<bool>(status, prefix, key, &mut mismatches))
+        };
+        let allowed = status_flag(&rate_limit, "rate_limit", "allowed");
+        let limit_reached = status_flag(&rate_limit, "rate_limit", "limit_reached");
+        let code_review_allowed =
+            status_flag(&code_review_rate_limit, "code_review_rate_limit", "allowed");
+        let code_review_limit_reached = status_flag(
+            &code_review_rate_limit,
+            "code_review_rate_limit",
+            "limit_reached",
+        );
+        Self {
+            user_id: lenient_field(value, "", "user_id", &mut mismatches),
+            account_id: lenient_field(value, "", "account_id", &mut mismatches),
+            email: lenient_field(value, "", "email", &mut mismatches),
+            plan_type: lenient_field(value, "", "plan_type", &mut mismatches),
+            allowed,
+            limit_reached,
+            code_review_allowed,
+            code_review_limit_reached,
+            credits: lenient_field(value, "", "credits", &mut mismatches),
+            spend_control: lenient_field(value, "", "spend_control", &mut mismatches),
+            rate_limit_reached_type: lenient_field(
+                value,
+                "",
+                "rate_limit_reached_type",
+                &mut mismatches,
+            ),
+            promo: lenient_field(value, "", "promo", &mut mismatches),
+            rate_limit,
+            code_review_rate_limit,
+            additional_rate_limits,
+            additional_rate_limit_details,
+            mismatches,
+        }
+    }
+}
+
+/// 读取单个可选字段：缺失或为 null 时返回 None，类型不符时记录原因后同样返回 None
+fn lenient_field<T: serde::de::DeserializeOwned>(
+    value: &serde_json::Value,
+    prefix: &str,
+    key: &str,
+    mismatches: &mut Vec<String>,
+) -> Option<T> {
+    let field = value.get(key).filter(|field| !field.is_null())?;
+    match T::deserialize(field) {
+        Ok(parsed) => Some(parsed),
+        Err(error) if prefix.is_empty() => {
+            mismatches.push(format!("{}: {}", key, error));
+            None
+        }
+        Err(error) => {
+            mismatches.push(format!("{}.{}: {}", prefix, key, error));
+            None
+        }
+    }
+}
+
+/// 数值字段可能是整数、浮点数或数字字符串
+fn deserialize_lenient_f64<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(value.as_ref().and_then(json_to_f64))
+}
+
+fn deserialize_lenient_i64<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(value.as_ref().and_then(json_to_i64))
+}
+
+fn json_to_f64(value: &serde_json::Value) -> Option<f64> {
+    value
+        .as_f64()
+        .or_else(|| value.as_str().and_then(|s| s.trim().parse::<f64>().ok()))
+}
+
+fn json_to_i64(value: &serde_json::Value) -> Option<i64> {
+    value
+        .as_i64()
+        .or_else(|| value.as_f64().map(|v| v.round() as i64))
+        .or_else(|| value.as_str().and_then(|s| s.trim().parse::<i64>().ok()))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct WhamRateLimitWindow {
+    #[serde(deserialize_with = "deserialize_lenient_f64")]
+    pub used_percent: Option<f64>,
+    #[serde(deserialize_with = "deserialize_lenient_i64")]
+    pub limit_window_seconds: Option<i64>,
+    #[serde(deserialize_with = "deserialize_lenient_i64")]
+    pub reset_after_seconds: Option<i64>,
+    #[serde(deserialize_with = "deserialize_lenient_i64")]
+    pub reset_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct WhamRateLimitStatus {
+    pub allowed: Option<bool>,
+    pub limit_reached: Option<bool>,
+    pub primary_window: Option<WhamRateLimitWindow>,
+    pub secondary_window: Option<WhamRateLimitWindow>,
+}
+
+/// 按功能计量的额外额度（如特定模型）
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct WhamAdditionalRateLimit {
+    pub limit_name: Option<String>,
+    pub metered_feature: Option<String>,
+    pub rate_limit: Option<WhamRateLimitStatus>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct WhamCredits {
+    pub has_credits: Option<bool>,
+    pub unlimited: Option<bool>,
+    pub overage_limit_reached: Option<bool>,
+    #[serde(deserialize_with = "deserialize_lenient_string")]
+    pub balance: Option<String>,
+    /// 服务端给出的可用消息数估算，格式未固定，原样保留
+    pub approx_local_messages: Option<serde_json::Value>,
+    pub approx_cloud_messages: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct WhamSpendControl {
+    pub reached: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct WhamPromo {
+    pub campaign_id: Option<String>,
+    pub message: Option<String>,
+}
+
+/// 通用额度窗口：按响应中出现的顺序保留全部窗口（主额度、代码审查、额外额度等）
+/// 与 UsageData 一致使用 snake_case，别名用于读取旧版本保存的 camelCase 数据
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct UsageWindow {
+    /// 来源字段路径，如 rate_limit.primary_window
+    pub key: String,
+    pub label: String,
+    #[serde(alias = "windowMinutes")]
+    pub window_minutes: Option<u32>,
+    #[serde(alias = "percentLeft")]
+    pub percent_left: f64,
+    #[serde(alias = "resetTimeMs")]
+    pub reset_time_ms: i64,
+}
+
+impl UsageWindow {
+    /// 主额度窗口对应的旧版字段：按窗口长度判断；响应未给出长度时按位置，
+    /// primary_window 视为 5 小时、secondary_window 视为每周
+    fn legacy_limit_kind(&self) -> Option<LimitKind> {
+        if !self.key.starts_with("rate_limit.") {
+            return None;
+        }
+        match self.window_minutes {
+            Some(_) => detect_limit_kind(self.window_minutes),
+            None if self.key == "rate_limit.primary_window" => Some(LimitKind::FiveHour),
+            None if self.key == "rate_limit.secondary_window" => Some(LimitKind::Weekly),
+            None => None,
+        }
+    }
+
+    /// 是否已由 5 小时 / 每周 / 代码审查字段展示；其余窗口（每日、额外额度等）需要单独展示
+    fn is_legacy_limit(&self) -> bool {
+        self.key == "code_review_rate_limit.primary_window"
+            || self.key.starts_with("rate_limits.")
+            || self.legacy_limit_kind().is_some()
+    }
+}
+
+/// 与 Codex CLI 的显示一致：系统提示等固定开销不计入上下文窗口的用量
+const CONTEXT_WINDOW_BASELINE_TOKENS: u64 = 12_000;
+
+/// 当前会话的上下文窗口用量（来自 session 中 token_count 事件）
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ContextWindowUsage {
+    #[serde(alias = "percentLeft")]
+    pub percent_left: f64,
+    /// 最近一轮请求占用的 token 数
+    #[serde(alias = "usedTokens")]
+    pub used_tokens: u64,
+    /// 模型的上下文窗口大小
+    #[serde(alias = "totalTokens")]
+    pub total_tokens: u64,
+}
+
+impl ContextWindowUsage {
+    fn new(used_tokens: u64, total_tokens: u64) -> Self {
+        let percent_left = if total_tokens <= CONTEXT_WINDOW_BASELINE_TOKENS {
+            0.0
+        } else {
+            let effective = (total_tokens - CONTEXT_WINDOW_BASELINE_TOKENS) as f64;
+            let used = used_tokens.saturating_sub(CONTEXT_WINDOW_BASELINE_TOKENS) as f64;
+            ((effective - used) / effective * 100.0).clamp(0.0, 100.0)
+        };
+        Self {
+            percent_left,
+            used_tokens,
+            total_tokens,
+        }
+    }
+}
+
+/// wham/usage 中额度窗口之外的账号状态：账号标识、是否被拦截、credits、消费上限与推广信息
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct WhamUsageDetails {
+    pub user_id: Option<String>,
+    pub account_id: Option<String>,
+    pub email: Option<String>,
+    pub allowed: Option<bool>,
+    pub limit_reached: Option<bool>,
+    pub rate_limit_reached_type: Option<String>,
+    pub code_review_allowed: Option<bool>,
+    pub code_review_limit_reached: Option<bool>,
+    pub credits: Option<WhamCredits>,
+    pub spend_control_reached: Option<bool>,
+    pub additional_rate_limits: Vec<WhamAdditionalRateLimit>,
+    pub promo: Option<WhamPromo>,
+    /// 服务端已拒绝请求（即使剩余百分比大于 0）
+    pub blocked: bool,
+}
+
+/// 余额可能是字符串也可能是数字，统一保存为字符串
+fn deserialize_lenient_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(match value {
+        Some(serde_json::Value::String(value)) => Some(value),
+        Some(serde_json::Value::Number(value)) => Some(value.to_string()),
+        _ => None,
+    })
+}
+
+fn build_wham_usage_details(payload: &WhamUsagePayload) -> WhamUsageDetails {
+    let spend_control_reached = payload
+        .spend_control
+        .as_ref()
+        .and_then(|control| control.reached);
+    // 只看服务端明确给出的拦截标记；rate_limit_reached_type 仅用于说明原因
+    let blocked = payload.allowed == Some(false)
+        || payload.limit_reached == Some(true)
+        || spend_control_reached == Some(true);
+
+    WhamUsageDetails {
+        user_id: payload.user_id.clone(),
+        account_id: payload.account_id.clone(),
+        email: payload.email.clone(),
+        allowed: payload.allowed,
+        limit_reached: payload.limit_reached,
+        rate_limit_reached_type: payload
+            .rate_limit_reached_type
+            .clone()
+            .filter(|value| !value.trim().is_empty()),
+        code_review_allowed: payload.code_review_allowed,
+        code_review_limit_reached: payload.code_review_limit_reached,
+        credits: payload.credits.clone(),
+        spend_control_reached,
+        additional_rate_limits: payload.additional_rate_limit_details.clone(),
+        promo: payload
+            .promo
+            .clone()
+            .filter(|promo| promo.campaign_id.is_some() || promo.message.is_some()),
+        blocked,
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageResult {
+    pub status: String,
+    pub message: Option<String>,
+    pub plan_type: Option<String>,
+    pub next_retry_at_ms: Option<u64>,
+    pub usage: Option<UsageData>,
+}
+
+/// 获取用量历史文件路径（JSON Lines，仅追加写入）
+fn get_usage_history_path() -> Result<PathBuf, String> {
+    let dir = get_app_data_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir.join("usage-history.jsonl"))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct UsageHistoryWindow {
+    window: String,
+    percent_left: f64,
+    reset_time_ms: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct UsageHistoryEntry {
+    account_id: String,
+    recorded_at_ms: u64,
+    status: String,
+    plan_type: Option<String>,
+    windows: Vec<UsageHistoryWindow>,
+    /// 采样来源：wham（在线刷新）或 session（本地会话中的 rate_limits）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct UsageHistoryPoint {
+    recorded_at_ms: u64,
+    percent_left: f64,
+    reset_time_ms: Option<i64>,
+}
+
+fn build_usage_history_entry(
+    account_id: &str,
+    result: &UsageResult,
+    recorded_at_ms: u64,
+) -> UsageHistoryEntry {
+    let windows = match &result.usage {
+        Some(usage) => [
+            (
+                "fiveHour",
+                usage.five_hour_percent_left,
+                usage.five_hour_reset_time_ms,
+            ),
+            (
+                "weekly",
+                usage.weekly_percent_left,
+                usage.weekly_reset_time_ms,
+            ),
+            (
+                "codeReview",
+                usage.code_review_percent_left,
+                usage.code_review_reset_time_ms,
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(window, percent_left, reset_time_ms)| {
+            percent_left.map(|percent_left| UsageHistoryWindow {
+                window: window.to_string(),
+                percent_left,
+                reset_time_ms,
+            })
+        })
+        .collect(),
+        None => Vec::new(),
+    };
+
+    UsageHistoryEntry {
+        account_id: account_id.to_string(),
+        recorded_at_ms,
+        status: result.status.clone(),
+        plan_type: result.plan_type.clone(),
+        windows,
+        source: result.usage.as_ref().and_then(|usage| usage.source.clone()),
+    }
+}
+
+/// 读取全部历史记录，跳过因异常中断而损坏的行
+fn read_usage_history_unlocked() -> Result<Vec<UsageHistoryEntry>, String> {
+    let path = get_usage_history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let file = fs::File::open(&path).map_err(|e| e.to_string())?;
+    Ok(BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<UsageHistoryEntry>(&line).ok())
+        .collect())
+}
+
+fn usage_history_cutoff_ms(retention_days: u64, now_ms: u64) -> Option<u64> {
+    if retention_days == 0 {
+        return None;
+    }
+    Some(now_ms.saturating_sub(retention_days.saturating_mul(24 * 60 * 60 * 1000)))
+}
+
+/// 按保留天数清理过期记录并重写历史文件
+fn compact_usage_history_unlocked(retention_days: u64, now_ms: u64) -> Result<(), String> {
+    let Some(cutoff_ms) = usage_history_cutoff_ms(retention_days, now_ms) else {
+        return Ok(());
+    };
+
+    let entries = read_usage_history_unlocked()?;
+    if entries
+        .iter()
+        .all(|entry| entry.recorded_at_ms >= cutoff_ms)
+    {
+        return Ok(());
+    }
+
+    let mut data = String::new();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.recorded_at_ms >= cutoff_ms)
+    {
+        data.push_str(&serde_json::to_string(entry).map_err(|e| e.to_string())?);
+        data.push('\n');
+    }
+    fs::write(get_usage_history_path()?, data).map_err(|e| e.to_string())
+}
+
+/// 追加一条用量快照，必要时顺带清理过期记录
+fn record_usage_history(
+    account_id: &str,
+    result: &UsageResult,
+    recorded_at_ms: u64,
+) -> Result<(), String> {
+    let config = load_accounts_store_data()?.config;
+    if config.usage_history_enabled == Some(false) {
+        return Ok(());
+    }
+
+    let now_ms = now_epoch_ms_u64();
+    let entry = build_usage_history_entry(account_id, result, recorded_at_ms);
+    let line = serde_json::to_string(&entry).map_err(|e| e.to_string())?;
+
+    let _guard = USAGE_HISTORY_LOCK
+        .lock()
+        .map_err(|_| "用量历史锁不可用".to_string())?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(get_usage_history_path()?)
+        .map_err(|e| e.to_string())?;
+    writeln!(file, "{}", line).map_err(|e| e.to_string())?;
+    if let Ok(mut history) = USAGE_FORECAST_HISTORY.lock() {
+        if let Some(history) = history.as_mut() {
+            push_forecast_history(history, entry, now_ms);
+        }
+    }
+
+    let compacted_at_ms = USAGE_HISTORY_COMPACTED_AT_MS.load(Ordering::SeqCst);
+    if now_ms.saturating_sub(compacted_at_ms) >= USAGE_HISTORY_COMPACT_INTERVAL_MS {
+        USAGE_HISTORY_COMPACTED_AT_MS.store(now_ms, Ordering::SeqCst);
+        let retention_days = config
+            .usage_history_retention_days
+            .unwrap_or(DEFAULT_USAGE_HISTORY_RETENTION_DAYS);
+        compact_usage_history_unlocked(retention_days, now_ms)?;
+    }
+
+    Ok(())
+}
+
+fn filter_usage_history(
+    entries: Vec<UsageHistoryEntry>,
+    account_id: Option<&str>,
+    since_ms: Option<u64>,
+    until_ms: Option<u64>,
+) -> Vec<UsageHistoryEntry> {
+    entries
+        .into_iter()
+        .filter(|entry| match account_id {
+            Some(account_id) => entry.account_id == account_id,
+            None => true,
+        })
+        .filter(|entry| entry.recorded_at_ms >= since_ms.unwrap_or(0))
+        .filter(|entry| entry.recorded_at_ms <= until_ms.unwrap_or(u64::MAX))
+        .collect()
+}
+
+/// 单个窗口的时间序列；在线与 session 采样交错写入，按采样时间排序
+fn build_usage_history_series(
+    entries: &[UsageHistoryEntry],
+    window: &str,
+) -> Vec<UsageHistoryPoint> {
+    let mut points: Vec<UsageHistoryPoint> = entries
+        .iter()
+        .filter_map(|entry| {
+            entry
+                .windows
+                .iter()
+                .find(|item| item.window == window)
+                .map(|item| UsageHistoryPoint {
+                    recorded_at_ms: entry.recorded_at_ms,
+                    percent_left: item.percent_left,
+                    reset_time_ms: item.reset_time_ms,
+                })
+        })
+        .collect();
+    points.sort_by_key(|point| point.recorded_at_ms);
+    points
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct LimitForecast {
+    burn_rate_per_hour: f64,
+    projected_exhaustion_ms: Option<i64>,
+}
+
+fn is_same_reset_cycle(earlier: &UsageHistoryPoint, later: &UsageHistoryPoint) -> bool {
+    if earlier.percent_left + 0.5 < later.percent_left {
+        return false;
+    }
+    match (earlier.reset_time_ms, later.reset_time_ms) {
+        (Some(a), Some(b)) => (a - b).abs() <= FORECAST_RESET_TOLERANCE_MS,
+        _ => true,
+    }
+}
+
+/// 根据同一重置周期内的近期采样估算消耗速率（百分比/小时），并预测重置前耗尽的时间
+fn forecast_limit_exhaustion(points: &[UsageHistoryPoint]) -> Option<LimitForecast> {
+    let latest = points.last()?;
+    let mut earliest = latest;
+    for point in points.iter().rev().skip(1) {
+        if latest.recorded_at_ms.saturating_sub(point.recorded_at_ms) > FORECAST_LOOKBACK_MS
+            || !is_same_reset_cycle(point, earliest)
+        {
+            break;
+        }
+        earliest = point;
+    }
+
+    let span_ms = latest
+        .recorded_at_ms
+        .saturating_sub(earliest.recorded_at_ms);
+    if span_ms < FORECAST_MIN_SPAN_MS {
+        return None;
+    }
+
+    let consumed = earliest.percent_left - latest.percent_left;
+    if consumed <= 0.0 {
+        return Some(LimitForecast {
+            burn_rate_per_hour: 0.0,
+            projected_exhaustion_ms: None,
+        });
+    }
+
+    let rate_per_ms = consumed / span_ms as f64;
+    let exhaustion_ms = latest.recorded_at_ms as f64 + latest.percent_left.max(0.0) / rate_per_ms;
+    let exhaustion_ms = exhaustion_ms.round() as i64;
+    let projected_exhaustion_ms = match latest.reset_time_ms {
+        Some(reset_time_ms) if exhaustion_ms >= reset_time_ms => None,
+        _ => Some(exhaustion_ms),
+    };
+
+    Some(LimitForecast {
+        burn_rate_per_hour: rate_per_ms * 3_600_000.0,
+        projected_exhaustion_ms,
+    })
+}
+
+/// 把新记录加入预测用的近期历史，并丢弃超出回看时长的记录
+fn push_forecast_history(
+    history: &mut HashMap<String, Vec<UsageHistoryEntry>>,
+    entry: UsageHistoryEntry,
+    now_ms: u64,
+) {
+    let since_ms = now_ms.saturating_sub(FORECAST_LOOKBACK_MS);
+    let entries = history.entry(entry.account_id.clone()).or_default();
+    entries.retain(|item| item.recorded_at_ms >= since_ms);
+    if entry.recorded_at_ms >= since_ms {
+        entries.push(entry);
+    }
+}
+
+/// 该账号回看时长内的用量历史；只在首次使用时读取历史文件，之后由写入时同步维护
+fn recent_usage_history(account_id: &str, now_ms: u64) -> Result<Vec<UsageHistoryEntry>, String> {
+    let _guard = USAGE_HISTORY_LOCK
+        .lock()
+        .map_err(|_| "用量历史锁不可用".to_string())?;
+    let mut history = USAGE_FORECAST_HISTORY
+        .lock()
+        .map_err(|_| "用量历史锁不可用".to_string())?;
+    if history.is_none() {
+        let since_ms = now_ms.saturating_sub(FORECAST_LOOKBACK_MS);
+        let mut loaded = HashMap::new();
+        for entry in read_usage_history_unlocked()? {
+            if entry.recorded_at_ms >= since_ms {
+                push_forecast_history(&mut loaded, entry, now_ms);
+            }
+        }
+        *history = Some(loaded);
+    }
+    let since_ms = now_ms.saturating_sub(FORECAST_LOOKBACK_MS);
+    Ok(history
+        .as_ref()
+        .and_then(|history| history.get(account_id))
+        .map(|entries| {
+            entries
+                .iter()
+                .filter(|entry| entry.recorded_at_ms >= since_ms)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// 用该账号的用量历史（在线刷新与本地 session 采样）为 5 小时与每周窗口补充消耗速率与预计耗尽时间
+fn apply_usage_forecast(account_id: &str, usage: &mut UsageData) -> Result<(), String> {
+    let entries = recent_usage_history(account_id, now_epoch_ms_u64())?;
+
+    if let Some(forecast) =
+        forecast_limit_exhaustion(&build_usage_history_series(&entries, "fiveHour"))
+    {
+        usage.five_hour_burn_rate_per_hour = Some(forecast.burn_rate_per_hour);
+        usage.five_hour_projected_exhaustion_ms = forecast.projected_exhaustion_ms;
+    }
+    if let Some(forecast) =
+        forecast_limit_exhaustion(&build_usage_history_series(&entries, "weekly"))
+    {
+        usage.weekly_burn_rate_per_hour = Some(forecast.burn_rate_per_hour);
+        usage.weekly_projected_exhaustion_ms = forecast.projected_exhaustion_ms;
+    }
+    Ok(())
+}
+
+/// 查询用量历史快照（可按账号与时间范围过滤）
+#[tauri::command]
+fn get_usage_history(
+    account_id: Option<String>,
+    since_ms: Option<u64>,
+    until_ms: Option<u64>,
+) -> Result<Vec<UsageHistoryEntry>, String> {
+    let entries = {
+        let _guard = USAGE_HISTORY_LOCK
+            .lock()
+            .map_err(|_| "用量历史锁不可用".to_string())?;
+        read_usage_history_unlocked()?
+    };
+    Ok(filter_usage_history(
+        entries,
+        account_id.as_deref(),
+        since_ms,
+        until_ms,
+    ))
+}
+
+/// 查询某账号单个额度窗口（fiveHour / weekly / codeReview）的剩余百分比时间序列
+#[tauri::command]
+fn get_usage_history_series(
+    account_id: String,
+    window: String,
+    since_ms: Option<u64>,
+    until_ms: Option<u64>,
+) -> Result<Vec<UsageHistoryPoint>, String> {
+    let entries = get_usage_history(Some(account_id), since_ms, until_ms)?;
+    Ok(build_usage_history_series(&entries, &window))
+}
+
+/// 清空用量历史（指定账号时只清除该账号的记录）
+#[tauri::command]
+fn clear_usage_history(account_id: Option<String>) -> Result<(), String> {
+    let _guard = USAGE_HISTORY_LOCK
+        .lock()
+        .map_err(|_| "用量历史锁不可用".to_string())?;
+    if let Ok(mut history) = USAGE_FORECAST_HISTORY.lock() {
+        *history = None;
+    }
+    let path = get_usage_history_path()?;
+    let Some(account_id) = account_id else {
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| e.to_string())?;
+        }
+        return Ok(());
+    };
+
+    let mut data = String::new();
+    for entry in read_usage_history_unlocked()?
+        .iter()
+        .filter(|entry| entry.account_id != account_id)
+    {
+        data.push_str(&serde_json::to_string(entry).map_err(|e| e.to_string())?);
+        data.push('\n');
+    }
+    fs::write(&path, data).map_err(|e| e.to_string())
+}
+
+/// 获取 codex sessions 目录路径
+fn get_codex_sessions_dir() -> Result<PathBuf, String> {
+    dirs::home_dir()
+        .map(|p| p.join(".codex").join("sessions"))
+        .ok_or_else(|| "Cannot find home directory".to_string())
+}
+
+/// session 上下文窗口变化事件：当前账号的最新上下文窗口（会话已结束时为空）
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SessionContextWindowPayload {
+    account_id: String,
+    context_window: Option<TrayContextWindowSummary>,
+}
+
+/// 当前账号的上下文窗口随 session 写入而变化：写回用量摘要，并通知主界面与托盘，不必等到下次刷新用量
+fn sync_active_context_window<R: Runtime>(app: &AppHandle<R>) {
+    let Some((account_id, session_key)) = active_account_session_key() else {
+        return;
+    };
+    let context_window = find_session_context_window(&session_key, now_epoch_ms_u64())
+        .as_ref()
+        .map(summarize_context_window);
+    let result = update_accounts_store_data(|store| {
+        let Some(usage_info) = store
+            .accounts
+            .iter_mut()
+            .find(|account| account.id == account_id)
+            .and_then(|account| account.usage_info.as_mut())
+        else {
+            return Ok(false);
+        };
+        if usage_info.context_window == context_window {
+            return Ok(false);
+        }
+        usage_info.context_window = context_window.clone();
+        Ok(true)
+    });
+
+    match result {
+        Ok(Some(_)) => {
+            let _ = app.emit(
+                "session-context-window-changed",
+                SessionContextWindowPayload {
+                    account_id,
+                    context_window,
+                },
+            );
+            if let Err(error) = refresh_tray_detail_texts(app) {
+                log::debug!("托盘上下文更新跳过: {}", error);
+            }
+        }
+        Ok(None) => {}
+        Err(error) => log::debug!("更新上下文窗口失败: {}", error),
+    }
+}
+
+fn start_session_watcher<R: Runtime>(app: &AppHandle<R>) {
+    let sessions_dir = match get_codex_sessions_dir() {
+        Ok(dir) => dir,
+        Err(err) => {
+            log::warn!("Failed to resolve sessions dir: {}", err);
+            return;
+        }
+    };
+
+    if !sessions_dir.exists() {
+        log::warn!("Sessions directory not found for watcher");
+        return;
+    }
+
+    let app_handle = app.clone();
+    std::thread::spawn(move || {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(move |res| {
+            let _ = tx.send(res);
+        }) {
+            Ok(w) => w,
+            Err(err) => {
+                log::error!("Failed to start watcher: {}", err);
+                return;
+            }
+        };
+
+        if let Err(err) = watcher.watch(&sessions_dir, RecursiveMode::Recursive) {
+            log::error!("Failed to watch sessions dir: {}", err);
+            return;
+        }
+        SESSION_WATCHER_ACTIVE.store(true, Ordering::SeqCst);
+        if let Err(err) = with_scanned_session_index(|_| ()) {
+            log::warn!("Failed to build session index: {}", err);
+        }
+
+        for res in rx {
+            let event = match res {
+                Ok(ev) => ev,
+                Err(_) => continue,
+            };
+
+            let removed = matches!(event.kind, EventKind::Remove(_));
+            if !removed && !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+
+            for path in event.paths {
+                if is_session_file(&path) {
+                    if removed || !path.exists() {
+                        forget_session_index_entry(&path);
+                        continue;
+                    }
+                    let previous = session_index_entry(&path).unwrap_or_default();
+                    if let Err(err) = bind_session_file_to_current_auth(&path) {
+                        log::debug!("Bind session skipped: {}", err);
+                    }
+                    match refresh_session_index_entry(&path) {
+                        Ok(entry) => {
+                            if entry.context_window != previous.context_window {
+                                sync_active_context_window(&app_handle);
+                            }
+                            if entry.rate_limits != previous.rate_limits {
+                                record_session_usage_history(&path, entry);
+                            }
+                        }
+                        Err(err) => log::debug!("Session index skipped: {}", err),
+                    }
+                }
+            }
+        }
+        SESSION_WATCHER_ACTIVE.store(false, Ordering::SeqCst);
+    });
+}
+
+fn is_session_file(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "jsonl")
+}
+
+/// 递归遍历 sessions 目录查找所有 .jsonl 文件
+fn collect_session_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    if dir.is_dir() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                collect_session_files(&path, files)?;
+            } else if is_session_file(&path) {
+                files.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 查找最新的 session 日志文件
+fn find_latest_session_file() -> Result<PathBuf, String> {
+    let sessions_dir = get_codex_sessions_dir()?;
+
+    if !sessions_dir.exists() {
+        return Err("Sessions directory not found".to_string());
+    }
+
+    with_scanned_session_index(|index| index.latest().map(PathBuf::from))?
+        .ok_or_else(|| "No session files found".to_string())
+}
+
+/// 获取 session 索引的保存路径
+fn get_session_index_path() -> Result<PathBuf, String> {
+    let dir = get_app_data_dir()?;
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir.join("session-index.json"))
+}
+
+/// 旧版本单独保存增量读取进度的文件，已并入 session 索引
+fn get_legacy_session_tail_state_path() -> Result<PathBuf, String> {
+    Ok(get_app_data_dir()?.join("session-tail-state.json"))
+}
+
+/// 记录文件开头的字节数，用于识别同名文件被替换
+const SESSION_HEAD_BYTES: u64 = 128;
+/// 写盘时只为修改时间最新的文件保留 rate_limits 与上下文窗口，更早的文件只保存元信息，需要时重新读取
+const SESSION_TAIL_MAX_FILES: usize = 500;
+/// 监听器持续更新时，索引最多每隔该时长写盘一次
+const SESSION_INDEX_SAVE_INTERVAL_MS: u64 = 5_000;
+/// 监听器未运行时，查询最多每隔该时长增量扫描一次
+const SESSION_INDEX_RESCAN_INTERVAL_MS: u64 = 30_000;
+/// 超过该时长未写入的 session 视为会话已结束，不再显示其上下文窗口
+const SESSION_CONTEXT_WINDOW_MAX_AGE_MS: u64 = 3 * 60 * 60 * 1000;
+const SESSION_INDEX_MAX_EMAILS: usize = 8;
+/// 索引结构变化时递增，旧版本的索引会被丢弃并重新扫描
+const SESSION_INDEX_VERSION: &str = "1.4.0";
+
+/// session 索引中的单个文件：元信息、账号归属与增量读取进度
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+struct SessionIndexEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified_ms: Option<i64>,
+    /// 上次读取时的文件大小，与修改时间一起决定启动扫描时是否需要重新读取
+    len: u64,
+    /// 绑定的账号（与 usage-bindings.json 同步）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account_id: Option<String>,
+    /// session_meta 中记录的 ChatGPT 账号 ID
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meta_account_id: Option<String>,
+    /// session_meta / turn_context 中出现的邮箱
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    emails: Vec<String>,
+    /// 已解析的完整行的结束位置
+    offset: u64,
+    /// 文件开头已读取的字节数（最多 SESSION_HEAD_BYTES）及其哈希
+    head_len: u64,
+    head_hash: u64,
+    /// 已解析内容中最新的 rate_limits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rate_limits: Option<RateLimits>,
+    /// 已解析内容中最新的上下文窗口用量
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context_window: Option<ContextWindowUsage>,
+    /// 写盘时省略了 rate_limits / 上下文窗口，再次读取该文件时需从头解析
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    trimmed: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SessionIndex {
+    version: String,
+    files: HashMap<String, SessionIndexEntry>,
+    /// 修改时间最新的文件，随索引更新增量维护
+    #[serde(skip)]
+    latest_file: Option<String>,
+    /// 各绑定账号修改时间最新的文件
+    #[serde(skip)]
+    latest_by_account: HashMap<String, String>,
+    #[serde(skip)]
+    scanned: bool,
+    #[serde(skip)]
+    scanned_at_ms: u64,
+    #[serde(skip)]
+    dirty: bool,
+    #[serde(skip)]
+    saved_at_ms: u64,
+}
+
+impl Default for SessionIndex {
+    fn default() -> Self {
+        Self {
+            version: SESSION_INDEX_VERSION.to_string(),
+            files: HashMap::new(),
+            latest_file: None,
+            latest_by_account: HashMap::new(),
+            scanned: false,
+            scanned_at_ms: 0,
+            dirty: false,
+            saved_at_ms: 0,
+        }
+    }
+}
+
+impl SessionIndex {
+    /// 修改时间最新的文件
+    fn latest(&self) -> Option<&String> {
+        self.latest_file.as_ref()
+    }
+
+    /// 绑定到该账号的文件中修改时间最新的一个
+    fn latest_for_account(&self, account_id: &str) -> Option<&String> {
+        self.latest_by_account.get(account_id)
+    }
+
+    /// 候选文件是否比当前记录的文件更新；修改时间相同时按路径比较，保证结果稳定
+    fn is_newer(&self, candidate: &str, current: Option<&String>) -> bool {
+        let rank = |path: &str| {
+            self.files
+                .get(path)
+                .map(|entry| (entry.modified_ms, path.to_string()))
+        };
+        match current {
+            Some(current) => rank(candidate) > rank(current),
+            None => self.files.contains_key(candidate),
+        }
+    }
+
+    /// 全量查找满足条件的最新文件，只在当前最新的文件被移除或变旧时使用
+    fn find_latest(&self, filter: impl Fn(&SessionIndexEntry) -> bool) -> Option<String> {
+        self.files
+            .iter()
+            .filter(|(_, entry)| filter(entry))
+            .max_by(|(a_path, a), (b_path, b)| {
+                (a.modified_ms, a_path.as_str()).cmp(&(b.modified_ms, b_path.as_str()))
+            })
+            .map(|(path, _)| path.clone())
+    }
+
+    /// 重建最新文件记录，用于加载索引或批量移除之后
+    fn rebuild_latest(&mut self) {
+        self.latest_file = self.find_latest(|_| true);
+        let mut latest_by_account = HashMap::new();
+        for (path, entry) in &self.files {
+            if let Some(account_id) = &entry.account_id {
+                let current: Option<&String> = latest_by_account.get(account_id);
+                if self.is_newer(path, current) {
+                    latest_by_account.insert(account_id.clone(), path.clone());
+                }
+            }
+        }
+        self.latest_by_account = latest_by_account;
+    }
+
+    /// 单个文件变化后更新最新文件记录
+    fn update_latest(&mut self, path: &str, previous: Option<SessionIndexEntry>) {
+        let current = self.files.get(path);
+        let became_older = previous.as_ref().is_some_and(|previous| {
+            current.map_or(true, |current| current.modified_ms < previous.modified_ms)
+        });
+        let current_account = current.and_then(|entry| entry.account_id.clone());
+
+        if self.latest_file.as_deref() == Some(path) && became_older {
+            self.latest_file = self.find_latest(|_| true);
+        } else if self.is_newer(path, self.latest_file.as_ref()) {
+            self.latest_file = Some(path.to_string());
+        }
+
+        if let Some(previous_account) = previous.and_then(|entry| entry.account_id) {
+            let moved = current_account.as_deref() != Some(previous_account.as_str());
+            if (moved || became_older)
+                && self
+                    .latest_by_account
+                    .get(&previous_account)
+                    .map(String::as_str)
+                    == Some(path)
+            {
+                match self.find_latest(|entry| {
+                    entry.account_id.as_deref() == Some(previous_account.as_str())
+                }) {
+                    Some(latest) => self.latest_by_account.insert(previous_account, latest),
+                    None => self.latest_by_account.remove(&previous_account),
+                };
+            }
+        }
+        if let Some(account_id) = current_account {
+            if self.is_newer(path, self.latest_by_account.get(&account_id)) {
+                self.latest_by_account.insert(account_id, path.to_string());
+            }
+        }
+    }
+
+    fn insert_entry(&mut self, path: String, entry: SessionIndexEntry) {
+        let previous = self.files.insert(path.clone(), entry);
+        self.dirty = true;
+        self.update_latest(&path, previous);
+    }
+
+    fn remove_entry(&mut self, path: &str) -> bool {
+        let Some(previous) = self.files.remove(path) else {
+            return false;
+        };
+        self.dirty = true;
+        self.update_latest(path, Some(previous));
+        true
+    }
+
+    fn set_entry_account(&mut self, path: &str, account_id: &str) {
+        let Some(entry) = self.files.get(path) else {
+            return;
+        };
+        if entry.account_id.as_deref() == Some(account_id) {
+            return;
+        }
+        let mut entry = entry.clone();
+        entry.account_id = Some(account_id.to_string());
+        self.insert_entry(path.to_string(), entry);
+    }
+
+    fn retain_entries(&mut self, keep: impl Fn(&str) -> bool) {
+        let before = self.files.len();
+        self.files.retain(|path, _| keep(path));
+        if self.files.len() != before {
+            self.dirty = true;
+            self.rebuild_latest();
+        }
+    }
+
+    /// 写盘用的副本：保存全部文件的元信息与读取进度，
+    /// 只有修改时间最新的 SESSION_TAIL_MAX_FILES 个文件保留 rate_limits 与上下文窗口
+    fn trimmed_for_save(&self) -> SessionIndex {
+        let mut paths: Vec<(&String, Option<i64>)> = self
+            .files
+            .iter()
+            .map(|(path, entry)| (path, entry.modified_ms))
+            .collect();
+        paths.sort_by_key(|(path, modified_ms)| std::cmp::Reverse((*modified_ms, *path)));
+        let files = paths
+            .into_iter()
+            .enumerate()
+            .filter_map(|(rank, (path, _))| {
+                let mut entry = self.files.get(path)?.clone();
+                if rank >= SESSION_TAIL_MAX_FILES
+                    && (entry.rate_limits.is_some() || entry.context_window.is_some())
+                {
+                    entry.rate_limits = None;
+                    entry.context_window = None;
+                    entry.trimmed = true;
+                }
+                Some((path.clone(), entry))
+            })
+            .collect();
+        SessionIndex {
+            files,
+            ..SessionIndex::default()
+        }
+    }
+}
+
+/// 加载索引，并丢弃已不存在的文件
+fn load_session_index() -> SessionIndex {
+    if let Ok(legacy_path) = get_legacy_session_tail_state_path() {
+        if legacy_path.exists() {
+            if let Err(err) = fs::remove_file(&legacy_path) {
+                log::debug!("Failed to remove legacy session tail state: {}", err);
+            }
+        }
+    }
+    let path = match get_session_index_path() {
+        Ok(path) if path.exists() => path,
+        _ => return SessionIndex::default(),
+    };
+    let mut index = match fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|content| {
+            serde_json::from_str::<SessionIndex>(&content).map_err(|e| e.to_string())
+        }) {
+        Ok(index) => index,
+        Err(err) => {
+            log::warn!("Session index ignored: {}", err);
+            return SessionIndex::default();
+        }
+    };
+    if index.version != SESSION_INDEX_VERSION {
+        return SessionIndex::default();
+    }
+    index.files.retain(|file, _| Path::new(file).exists());
+    index.rebuild_latest();
+    index
+}
+
+fn save_session_index(index: &mut SessionIndex) -> Result<(), String> {
+    let path = get_session_index_path()?;
+    let data = serde_json::to_string(&index.trimmed_for_save()).map_err(|e| e.to_string())?;
+    write_file_atomic(&path, data.as_bytes())?;
+    index.dirty = false;
+    index.saved_at_ms = now_epoch_ms_u64();
+    Ok(())
+}
+
+/// 有改动时按间隔写盘；来不及写入的改动会在下次启动时由增量扫描补齐
+fn persist_session_index(index: &mut SessionIndex) {
+    if !index.dirty
+        || now_epoch_ms_u64().saturating_sub(index.saved_at_ms) < SESSION_INDEX_SAVE_INTERVAL_MS
+    {
+        return;
+    }
+    if let Err(err) = save_session_index(index) {
+        log::warn!("Failed to save session index: {}", err);
+    }
+}
+
+fn with_session_index<T>(f: impl FnOnce(&mut SessionIndex) -> T) -> Result<T, String> {
+    let mut guard = SESSION_INDEX
+        .lock()
+        .map_err(|_| "Session index lock poisoned".to_string())?;
+    let index = guard.get_or_insert_with(load_session_index);
+    let result = f(index);
+    persist_session_index(index);
+    Ok(result)
+}
+
+/// 是否需要在查询前增量扫描：从未扫描过，或监听器未运行且距上次扫描已超过间隔
+fn session_index_needs_rescan(index: &SessionIndex, watcher_active: bool, now_ms: u64) -> bool {
+    !index.scanned
+        || (!watcher_active
+            && now_ms.saturating_sub(index.scanned_at_ms) >= SESSION_INDEX_RESCAN_INTERVAL_MS)
+}
+
+/// 查询前确保索引已扫描过；监听器未运行时无法感知变化，按间隔增量扫描
+fn with_scanned_session_index<T>(f: impl FnOnce(&SessionIndex) -> T) -> Result<T, String> {
+    let needs_rescan = || {
+        let watcher_active = SESSION_WATCHER_ACTIVE.load(Ordering::SeqCst);
+        with_session_index(|index| {
+            session_index_needs_rescan(index, watcher_active, now_epoch_ms_u64())
+        })
+    };
+    if needs_rescan()? {
+        let _scan_guard = SESSION_INDEX_SCAN_LOCK
+            .lock()
+            .map_err(|_| "Session index scan lock poisoned".to_string())?;
+        // 等待期间其他线程可能已完成扫描
+        if needs_rescan()? {
+            rescan_session_index()?;
+        }
+    }
+    with_session_index(|index| f(index))
+}
+
+/// 增量扫描 sessions 目录：只重新读取新增或大小、修改时间变化的文件，并同步账号绑定
+/// 读取文件时不持有索引锁，监听器在此期间更新过的文件以索引中的为准
+fn rescan_session_index() -> Result<(), String> {
+    let sessions_dir = get_codex_sessions_dir()?;
+    let snapshot = with_session_index(|index| index.files.clone())?;
+    let mut files = Vec::new();
+    collect_session_files(&sessions_dir, &mut files)
+        .map_err(|e| format!("Failed to read sessions directory: {}", e))?;
+
+    let mut seen = std::collections::HashSet::new();
+    let mut updates = Vec::new();
+    for path in files {
+        let key = path.to_string_lossy().to_string();
+        let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+        };
+        let modified_ms = metadata.modified().ok().and_then(epoch_ms_from_system_time);
+        let previous = snapshot.get(&key);
+        let unchanged = previous
+            .is_some_and(|entry| entry.len == metadata.len() && entry.modified_ms == modified_ms);
+        if !unchanged {
+            let mut entry = resumable_session_entry(previous);
+            if let Err(err) = tail_session_file(&path, &mut entry) {
+                log::debug!("Session index skipped {}: {}", key, err);
+                continue;
+            }
+            updates.push((key.clone(), entry));
+        }
+        seen.insert(key);
+    }
+
+    with_session_index(|index| {
+        for (key, entry) in updates {
+            let current = index.files.get(&key);
+            if current == snapshot.get(&key) && current != Some(&entry) {
+                index.insert_entry(key, entry);
+            }
+        }
+        // 扫描开始后才由监听器加入的文件不在本次结果中，仍然保留
+        index.retain_entries(|file| {
+            seen.contains(file) || (!snapshot.contains_key(file) && Path::new(file).exists())
+        });
+        sync_session_index_bindings(index);
+        index.scanned = true;
+        index.scanned_at_ms = now_epoch_ms_u64();
+    })
+}
+
+/// 把 usage-bindings.json 中的账号绑定同步到索引
+fn sync_session_index_bindings(index: &mut SessionIndex) {
+    let store = match USAGE_BINDINGS_LOCK.lock() {
+        Ok(_guard) => load_usage_bindings_unlocked(),
+        Err(_) => return,
+    };
+    let Ok(store) = store else {
+        return;
+    };
+    for (account_id, bindings) in store.bindings {
+        for binding in bindings {
+            index.set_entry_account(&binding.file_path, &account_id);
+        }
+    }
+}
+
+fn read_session_head(file: &mut fs::File) -> Result<Vec<u8>, String> {
+    let mut head = Vec::new();
+    Read::by_ref(file)
+        .take(SESSION_HEAD_BYTES)
+        .read_to_end(&mut head)
+        .map_err(|e| e.to_string())?;
+    Ok(head)
+}
+
+/// FNV-1a 哈希：结果需要跨版本写盘比较，不能使用随进程变化的 DefaultHasher
+fn session_head_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn looks_like_email(text: &str) -> bool {
+    let mut parts = text.split('@');
+    let (Some(local), Some(domain), None) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    text.len() <= 254
+        && !local.is_empty()
+        && domain.contains('.')
+        && !text.chars().any(char::is_whitespace)
+}
+
+/// 收集 JSON 中字段名含 email 的邮箱，用于按邮箱查找账号的 session
+/// 只看字段名而不是任意字符串，避免 git@github.com 之类的仓库地址占满名额
+fn collect_session_emails(value: &serde_json::Value, emails: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Array(items) => items
+            .iter()
+            .for_each(|item| collect_session_emails(item, emails)),
+        serde_json::Value::Object(map) => {
+            for (key, item) in map {
+                match item.as_str() {
+                    Some(text) if key.to_ascii_lowercase().contains("email") => {
+                        if emails.len() < SESSION_INDEX_MAX_EMAILS
+                            && looks_like_email(text)
+                            && !emails.iter().any(|email| email == text)
+                        {
+                            emails.push(text.to_string());
+                        }
+                    }
+                    _ => collect_session_emails(item, emails),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// session_meta 顶层记录的 ChatGPT 账号 ID（若该版本的 Codex 有记录）
+fn find_session_account_id(value: &serde_json::Value) -> Option<String> {
+    let map = value.as_object()?;
+    ["chatgpt_account_id", "account_id"].iter().find_map(|key| {
+        map.get(*key)?
+            .as_str()
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+    })
+}
+
+/// 把一行 session 事件计入索引项
+fn apply_session_line(entry: &mut SessionIndexEntry, line: &[u8]) {
+    let Ok(event) = serde_json::from_slice::<EventMsg>(line) else {
+        return;
+    };
+    let Some(payload) = event.payload else {
+        return;
+    };
+    match event.msg_type.as_str() {
+        "session_meta" => {
+            if entry.session_id.is_none() {
+                let text = |key: &str| {
+                    payload
+                        .get(key)
+                        .and_then(|v| v.as_str())
+                        .map(str::to_string)
+                };
+                entry.session_id = text("id");
+                entry.created_at = text("timestamp");
+            }
+            if entry.meta_account_id.is_none() {
+                entry.meta_account_id = find_session_account_id(&payload);
+            }
+            collect_session_emails(&payload, &mut entry.emails);
+        }
+        "turn_context" => collect_session_emails(&payload, &mut entry.emails),
+        // token_count 类型的事件
+        "event_msg" | "token_count" => {
+            if let Some(rate_limits) = payload
+                .get("rate_limits")
+                .and_then(|v| serde_json::from_value::<RateLimits>(v.clone()).ok())
+            {
+                entry.rate_limits = Some(rate_limits);
+            }
+            if let Some(context_window) = payload.get("info").and_then(parse_session_context_window)
+            {
+                entry.context_window = Some(context_window);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// token_count 事件的 info：最近一轮的 token 用量与模型上下文窗口
+fn parse_session_context_window(info: &serde_json::Value) -> Option<ContextWindowUsage> {
+    let total_tokens = info
+        .get("model_context_window")?
+        .as_u64()
+        .filter(|tokens| *tokens > 0)?;
+    let used_tokens = info
+        .get("last_token_usage")?
+        .get("total_tokens")?
+        .as_u64()?;
+    Some(ContextWindowUsage::new(used_tokens, total_tokens))
+}
+
+/// 只解析上次读取位置之后追加的完整行；文件被截断或替换时从头重新读取
+fn tail_session_file(path: &Path, entry: &mut SessionIndexEntry) -> Result<(), String> {
+    let mut file = fs::File::open(path).map_err(|e| format!("Failed to open file: {}", e))?;
+    let metadata = file.metadata().map_err(|e| e.to_string())?;
+    let head = read_session_head(&mut file)?;
+    let recorded_head = usize::try_from(entry.head_len).unwrap_or(usize::MAX);
+    let replaced =
+        head.len() < recorded_head || session_head_hash(&head[..recorded_head]) != entry.head_hash;
+    if metadata.len() < entry.offset || replaced {
+        *entry = SessionIndexEntry::default();
+    }
+    entry.head_len = head.len() as u64;
+    entry.head_hash = session_head_hash(&head);
+    entry.modified_ms = metadata.modified().ok().and_then(epoch_ms_from_system_time);
+
+    file.seek(SeekFrom::Start(entry.offset))
+        .map_err(|e| e.to_string())?;
+    let mut appended = Vec::new();
+    file.read_to_end(&mut appended)
+        .map_err(|e| format!("Failed to read file: {}", e))?;
+    entry.len = entry.offset + appended.len() as u64;
+    let complete_len = appended
+        .iter()
+        .rposition(|byte| *byte == b'\n')
+        .map_or(0, |index| index + 1);
+    for line in appended[..complete_len].split(|byte| *byte == b'\n') {
+        apply_session_line(entry, line);
+    }
+    // 尚未写完换行的末行：能完整解析就先采用，但不推进偏移，等换行写入后再计入
+    apply_session_line(entry, &appended[complete_len..]);
+    entry.offset += complete_len as u64;
+    Ok(())
+}
+
+/// 继续增量读取的起点；写盘时省略了 rate_limits 的索引项需从头解析，只保留账号绑定与开头哈希
+fn resumable_session_entry(previous: Option<&SessionIndexEntry>) -> SessionIndexEntry {
+    match previous {
+        Some(previous) if previous.trimmed => SessionIndexEntry {
+            account_id: previous.account_id.clone(),
+            head_len: previous.head_len,
+            head_hash: previous.head_hash,
+            ..SessionIndexEntry::default()
+        },
+        Some(previous) => previous.clone(),
+        None => SessionIndexEntry::default(),
+    }
+}
+
+fn update_session_index_entry(
+    index: &mut SessionIndex,
+    path: &Path,
+) -> Result<SessionIndexEntry, String> {
+    let key = path.to_string_lossy().to_string();
+    let previous = index.files.get(&key).cloned();
+    let mut entry = resumable_session_entry(previous.as_ref());
+    if let Err(err) = tail_session_file(path, &mut entry) {
+        index.remove_entry(&key);
+        return Err(err);
+    }
+    if previous.as_ref() != Some(&entry) {
+        index.insert_entry(key, entry.clone());
+    }
+    Ok(entry)
+}
+
+/// 增量读取 session 文件并更新索引，返回最新的索引项
+fn refresh_session_index_entry(path: &Path) -> Result<SessionIndexEntry, String> {
+    with_session_index(|index| update_session_index_entry(index, path))?
+}
+
+/// session 文件被删除后移出索引
+fn forget_session_index_entry(path: &Path) {
+    let _ = with_session_index(|index| {
+        index.remove_entry(path.to_string_lossy().as_ref());
+    });
+}
+
+/// 记录 session 文件绑定的账号
+fn set_session_index_account(path: &Path, account_id: &str) {
+    let _ = with_session_index(|index| {
+        index.set_entry_account(path.to_string_lossy().as_ref(), account_id);
+    });
+}
+
+/// 索引中记录的该文件
+fn session_index_entry(path: &Path) -> Option<SessionIndexEntry> {
+    with_session_index(|index| index.files.get(path.to_string_lossy().as_ref()).cloned())
+        .ok()
+        .flatten()
+}
+
+/// session 采样对应的本地账号：ChatGPT 账号 ID 相同的账号中优先当前账号
+fn find_session_history_account<'a>(
+    store: &'a TrayAccountsStore,
+    chatgpt_account_id: &str,
+) -> Option<&'a TrayStoredAccount> {
+    store
+        .accounts
+        .iter()
+        .filter(|account| account.account_info.account_id.as_deref() == Some(chatgpt_account_id))
+        .max_by_key(|account| account.is_active)
+}
+
+/// 写入用量历史的 session 采样：采样时间与各窗口的重置时间
+#[derive(Debug, Clone, PartialEq)]
+struct SessionHistorySample {
+    recorded_at_ms: u64,
+    resets_at: Vec<i64>,
+}
+
+impl SessionHistorySample {
+    fn new(rate_limits: &RateLimits, recorded_at_ms: u64) -> Self {
+        Self {
+            recorded_at_ms,
+            resets_at: [&rate_limits.primary, &rate_limits.secondary]
+                .into_iter()
+                .flatten()
+                .map(|limit| limit.resets_at)
+                .collect(),
+        }
+    }
+
+    /// 距上次写入超过采样间隔，或窗口已重置
+    fn is_due_after(&self, previous: Option<&SessionHistorySample>) -> bool {
+        previous.map_or(true, |previous| {
+            previous.resets_at != self.resets_at
+                || self.recorded_at_ms.saturating_sub(previous.recorded_at_ms)
+                    >= SESSION_HISTORY_SAMPLE_INTERVAL_MS
+        })
+    }
+}
+
+/// 是否写入该采样；写入时记下采样，之后同一账号在间隔内的采样被跳过
+fn take_session_history_sample(chatgpt_account_id: &str, sample: SessionHistorySample) -> bool {
+    let Ok(mut samples) = SESSION_HISTORY_SAMPLES.lock() else {
+        return false;
+    };
+    let samples = samples.get_or_insert_with(HashMap::new);
+    if !sample.is_due_after(samples.get(chatgpt_account_id)) {
+        return false;
+    }
+    samples.insert(chatgpt_account_id.to_string(), sample);
+    true
+}
+
+/// session 中出现新的 rate_limits 时写入用量历史，使两次在线刷新之间的消耗也能参与预测；
+/// 同一账号按采样间隔降采样，避免每条 token_count 都读写存储
+fn record_session_usage_history(path: &Path, entry: SessionIndexEntry) {
+    let Some(rate_limits) = entry.rate_limits.as_ref() else {
+        return;
+    };
+    let Some(chatgpt_account_id) = entry
+        .meta_account_id
+        .clone()
+        .or_else(|| entry.account_id.clone())
+    else {
+        return;
+    };
+    let recorded_at_ms = entry
+        .modified_ms
+        .and_then(|ms| u64::try_from(ms).ok())
+        .unwrap_or_else(now_epoch_ms_u64);
+    if !take_session_history_sample(
+        &chatgpt_account_id,
+        SessionHistorySample::new(rate_limits, recorded_at_ms),
+    ) {
+        return;
+    }
+    let Ok(store) = load_accounts_store_data() else {
+        return;
+    };
+    let Some(account) = find_session_history_account(&store, &chatgpt_account_id) else {
+        return;
+    };
+    let Ok(usage) = usage_data_from_session_entry(entry, path) else {
+        return;
+    };
+    let result = UsageResult {
+        status: "ok".to_string(),
+        message: None,
+        plan_type: None,
+        next_retry_at_ms: None,
+        usage: Some(usage),
+    };
+    if let Err(error) = record_usage_history(&account.id, &result, recorded_at_ms) {
+        log::debug!("记录 session 用量历史失败 ({}): {}", account.id, error);
+    }
+}
 
-    let body = response.text().await.map_err(|e| e.to_string())?;
-    let value: WhamAccountsCheckResponse =
-        serde_json::from_str(&body).map_err(|e| e.to_string())?;
-    let matched = value
-        .accounts
+/// 从 JSONL 文件中解析最新的 rate_limits 信息（增量读取，已解析的部分直接使用索引）
+fn parse_rate_limits_from_file(file_path: &PathBuf) -> Result<UsageData, String> {
+    let entry = refresh_session_index_entry(file_path)?;
+    usage_data_from_session_entry(entry, file_path)
+}
+
+fn usage_data_from_session_entry(
+    entry: SessionIndexEntry,
+    file_path: &Path,
+) -> Result<UsageData, String> {
+    let rate_limits = entry
+        .rate_limits
+        .ok_or_else(|| "No rate limits found in session file".to_string())?;
+
+    // 转换为 UsageData
+    let primary = rate_limits
+        .primary
+        .ok_or_else(|| "No primary rate limit found".to_string())?;
+    let secondary = rate_limits
+        .secondary
+        .ok_or_else(|| "No secondary rate limit found".to_string())?;
+
+    let primary_used = validate_used_percent(primary.used_percent)?;
+    let secondary_used = validate_used_percent(secondary.used_percent)?;
+    let five_hour_reset_ms = normalize_unix_timestamp_ms(primary.resets_at)?;
+    let weekly_reset_ms = normalize_unix_timestamp_ms(secondary.resets_at)?;
+    let last_updated = fs::metadata(file_path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(epoch_ms_from_system_time)
+        .map(|ms| ms.to_string())
+        .unwrap_or_else(now_epoch_ms_string);
+
+    Ok(UsageData {
+        five_hour_percent_left: Some(100.0 - primary_used),
+        five_hour_reset_time_ms: Some(five_hour_reset_ms),
+        weekly_percent_left: Some(100.0 - secondary_used),
+        weekly_reset_time_ms: Some(weekly_reset_ms),
+        code_review_percent_left: None,
+        code_review_reset_time_ms: None,
+        five_hour_burn_rate_per_hour: None,
+        five_hour_projected_exhaustion_ms: None,
+        weekly_burn_rate_per_hour: None,
+        weekly_projected_exhaustion_ms: None,
+        last_updated,
+        source_file: Some(file_path.to_string_lossy().to_string()),
+        details: None,
+        source: Some(USAGE_SOURCE_SESSION.to_string()),
+        age_ms: None,
+        windows: build_session_usage_windows(
+            &primary,
+            primary_used,
+            five_hour_reset_ms,
+            &secondary,
+            secondary_used,
+            weekly_reset_ms,
+        ),
+        context_window: entry.context_window,
+    })
+}
+
+fn now_epoch_ms_string() -> String {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_millis().to_string(),
+        Err(_) => "0".to_string(),
+    }
+}
+
+fn epoch_ms_from_system_time(time: SystemTime) -> Option<i64> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .and_then(|d| i64::try_from(d.as_millis()).ok())
+}
+
+fn normalize_unix_timestamp_ms(timestamp: i64) -> Result<i64, String> {
+    if timestamp <= 0 {
+        return Err("Invalid reset timestamp".to_string());
+    }
+
+    let ms = if timestamp >= 1_000_000_000_000 {
+        timestamp
+    } else {
+        timestamp * 1000
+    };
+
+    if ms < MIN_VALID_EPOCH_MS || ms > MAX_VALID_EPOCH_MS {
+        return Err("Reset timestamp out of valid range".to_string());
+    }
+
+    Ok(ms)
+}
+
+fn validate_used_percent(value: f64) -> Result<f64, String> {
+    if value.is_nan() || value < 0.0 || value > 100.0 {
+        return Err("Invalid used_percent in rate_limits".to_string());
+    }
+    Ok(value)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LimitKind {
+    FiveHour,
+    Weekly,
+}
+
+/// 按窗口长度判断旧版 5 小时 / 每周字段对应的窗口：只接受恰好 300 / 10080 分钟的窗口，
+/// 每日等其他长度的窗口只出现在 windows 中
+fn detect_limit_kind(window_minutes: Option<u32>) -> Option<LimitKind> {
+    match window_minutes? {
+        300 => Some(LimitKind::FiveHour),
+        10_080 => Some(LimitKind::Weekly),
+        _ => None,
+    }
+}
+
+/// 按窗口长度生成展示名称
+fn usage_window_label(window_minutes: Option<u32>) -> String {
+    match window_minutes {
+        Some(300) => "5小时".to_string(),
+        Some(1_440) => "每日".to_string(),
+        Some(10_080) => "每周".to_string(),
+        Some(minutes) if minutes > 0 && minutes % 1_440 == 0 => format!("{}天", minutes / 1_440),
+        Some(minutes) if minutes > 0 && minutes % 60 == 0 => format!("{}小时", minutes / 60),
+        Some(minutes) if minutes > 0 => format!("{}分钟", minutes),
+        _ => "额度".to_string(),
+    }
+}
+
+/// 解析单个额度窗口：剩余百分比来自 used_percent（0~1 的小数按比例处理）、remaining / limit
+/// 或 used / limit；重置时间来自绝对时间戳或剩余秒数；数值字段接受整数、浮点与数字字符串
+fn parse_usage_window(
+    entry: &serde_json::Value,
+    key: String,
+    label_prefix: Option<&str>,
+    now_ms: i64,
+) -> Result<UsageWindow, String> {
+    let number = |fields: &[&str]| {
+        fields
+            .iter()
+            .find_map(|field| entry.get(*field).and_then(json_to_f64))
+    };
+    let integer = |fields: &[&str]| {
+        fields
+            .iter()
+            .find_map(|field| entry.get(*field).and_then(json_to_i64))
+    };
+
+    let limit = number(&["limit", "total", "capacity"]);
+    let percent_left = if let Some(used_percent) = number(&["used_percent", "usedPercent"]) {
+        let used_percent = if used_percent <= 1.0 && used_percent.fract() != 0.0 {
+            used_percent * 100.0
+        } else {
+            used_percent
+        };
+        100.0 - validate_used_percent(used_percent)?
+    } else if let (Some(remaining), Some(limit)) = (number(&["remaining"]), limit) {
+        if limit <= 0.0 {
+            return Err("Invalid limit value".to_string());
+        }
+        remaining / limit * 100.0
+    } else if let (Some(used), Some(limit)) = (number(&["used"]), limit) {
+        if limit <= 0.0 {
+            return Err("Invalid limit value".to_string());
+        }
+        100.0 - used / limit * 100.0
+    } else {
+        return Err("Missing usage fields in rate_limit entry".to_string());
+    };
+
+    let reset_time_ms = match integer(&[
+        "reset_at_ms",
+        "resets_at_ms",
+        "reset_time_ms",
+        "reset_at",
+        "resets_at",
+        "reset",
+    ]) {
+        Some(timestamp) => normalize_unix_timestamp_ms(timestamp)?,
+        None => {
+            let seconds = integer(&["reset_after_seconds", "reset_in_seconds", "reset_in"])
+                .ok_or_else(|| "Missing reset timestamp".to_string())?;
+            now_ms.saturating_add(seconds.saturating_mul(1000))
+        }
+    };
+
+    let window_minutes = integer(&["window_minutes"])
+        .or_else(|| integer(&["limit_window_seconds", "window_seconds"]).map(|s| s / 60))
+        .and_then(|minutes| u32::try_from(minutes).ok())
+        .filter(|minutes| *minutes > 0);
+    let label = usage_window_label(window_minutes);
+    Ok(UsageWindow {
+        key,
+        label: match label_prefix {
+            Some(prefix) => format!("{} · {}", prefix, label),
+            None => label,
+        },
+        window_minutes,
+        percent_left: percent_left.clamp(0.0, 100.0),
+        reset_time_ms,
+    })
+}
+
+/// 额度状态下的全部窗口：primary_window / secondary_window，兼容旧版的 primary / secondary
+/// 以及 limits 数组（或状态本身就是数组）的写法。按位置统一命名：第一、二个窗口记为
+/// primary_window / secondary_window，其余记为 limits[i]；无法解析的窗口跳过并记录原因
+fn collect_status_windows(
+    windows: &mut Vec<UsageWindow>,
+    skipped: &mut Vec<String>,
+    status: &serde_json::Value,
+    key_prefix: &str,
+    label_prefix: Option<&str>,
+    now_ms: i64,
+) {
+    let present = |key: &str| status.get(key).filter(|entry| !entry.is_null());
+    let primary = present("primary_window").or_else(|| present("primary"));
+    let secondary = present("secondary_window").or_else(|| present("secondary"));
+    let entries: Vec<(String, &serde_json::Value)> = if primary.is_some() || secondary.is_some() {
+        [("primary_window", primary), ("secondary_window", secondary)]
+            .into_iter()
+            .filter_map(|(position, entry)| Some((position.to_string(), entry?)))
+            .collect()
+    } else {
+        let entries = status.get("limits").unwrap_or(status).as_array();
+        entries
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, entry)| {
+                let position = match index {
+                    0 => "primary_window".to_string(),
+                    1 => "secondary_window".to_string(),
+                    _ => format!("limits[{}]", index),
+                };
+                (position, entry)
+            })
+            .collect()
+    };
+
+    for (position, entry) in entries {
+        let key = format!("{}.{}", key_prefix, position);
+        match parse_usage_window(entry, key.clone(), label_prefix, now_ms) {
+            Ok(window) => windows.push(window),
+            Err(error) => skipped.push(format!("{}: {}", key, error)),
+        }
+    }
+}
+
+/// 由 wham/usage 响应构建用量数据：全部额度窗口按主额度、代码审查、additional_rate_limits 的顺序保留
+/// 无法解析而被跳过的窗口记入 skipped
+fn build_wham_usage_data(
+    payload: &WhamUsagePayload,
+    now_ms: i64,
+    skipped: &mut Vec<String>,
+) -> Result<UsageData, String> {
+    let rate_limit = payload
+        .rate_limit
+        .as_ref()
+        .ok_or_else(|| "Missing rate_limit in response".to_string())?;
+
+    let mut windows = Vec::new();
+    collect_status_windows(
+        &mut windows,
+        skipped,
+        rate_limit,
+        "rate_limit",
+        None,
+        now_ms,
+    );
+    let rate_limit_window_count = windows.len();
+    if rate_limit_window_count == 0 {
+        return Err("Missing usable rate_limit data".to_string());
+    }
+    if let Some(code_review) = &payload.code_review_rate_limit {
+        collect_status_windows(
+            &mut windows,
+            skipped,
+            code_review,
+            "code_review_rate_limit",
+            Some("代码审查"),
+            now_ms,
+        );
+    }
+    for (index, additional) in payload.additional_rate_limits.iter().enumerate() {
+        let name = ["limit_name", "metered_feature"]
+            .into_iter()
+            .filter_map(|key| additional.get(key).and_then(|name| name.as_str()))
+            .find(|name| !name.trim().is_empty())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("额外额度 {}", index + 1));
+        if let Some(status) = additional
+            .get("rate_limit")
+            .filter(|status| !status.is_null())
+        {
+            collect_status_windows(
+                &mut windows,
+                skipped,
+                status,
+                &format!("additional_rate_limits[{}].rate_limit", index),
+                Some(&name),
+                now_ms,
+            );
+        }
+    }
+
+    // 旧版字段：主额度窗口按长度（缺少长度时按位置）归入 5 小时 / 每周
+    let primary_windows = &windows[..rate_limit_window_count];
+    let find_kind = |kind: LimitKind| {
+        primary_windows
+            .iter()
+            .find(|window| window.legacy_limit_kind() == Some(kind))
+    };
+    let five_hour = find_kind(LimitKind::FiveHour);
+    let weekly = find_kind(LimitKind::Weekly);
+    let code_review = windows
+        .iter()
+        .find(|window| window.key == "code_review_rate_limit.primary_window");
+
+    Ok(UsageData {
+        five_hour_percent_left: five_hour.map(|window| window.percent_left),
+        five_hour_reset_time_ms: five_hour.map(|window| window.reset_time_ms),
+        weekly_percent_left: weekly.map(|window| window.percent_left),
+        weekly_reset_time_ms: weekly.map(|window| window.reset_time_ms),
+        code_review_percent_left: code_review.map(|window| window.percent_left),
+        code_review_reset_time_ms: code_review.map(|window| window.reset_time_ms),
+        five_hour_burn_rate_per_hour: None,
+        five_hour_projected_exhaustion_ms: None,
+        weekly_burn_rate_per_hour: None,
+        weekly_projected_exhaustion_ms: None,
+        last_updated: now_ms.to_string(),
+        source_file: None,
+        details: Some(build_wham_usage_details(payload)),
+        source: Some(USAGE_SOURCE_WHAM.to_string()),
+        age_ms: None,
+        windows,
+        context_window: None,
+    })
+}
+
+/// 本地 session 文件中的 primary / secondary 额度窗口
+fn build_session_usage_windows(
+    primary: &RateLimitEntry,
+    primary_used: f64,
+    primary_reset_ms: i64,
+    secondary: &RateLimitEntry,
+    secondary_used: f64,
+    secondary_reset_ms: i64,
+) -> Vec<UsageWindow> {
+    [
+        (
+            "rate_limits.primary",
+            primary,
+            primary_used,
+            primary_reset_ms,
+        ),
+        (
+            "rate_limits.secondary",
+            secondary,
+            secondary_used,
+            secondary_reset_ms,
+        ),
+    ]
+    .into_iter()
+    .map(|(key, entry, used_percent, reset_time_ms)| UsageWindow {
+        key: key.to_string(),
+        label: usage_window_label(Some(entry.window_minutes)),
+        window_minutes: Some(entry.window_minutes),
+        percent_left: 100.0 - used_percent,
+        reset_time_ms,
+    })
+    .collect()
+}
+
+fn bind_session_file_to_account(account_id: &str, file_path: &PathBuf) -> Result<(), String> {
+    let (session_id, created_at) = match refresh_session_index_entry(file_path) {
+        Ok(SessionIndexEntry {
+            session_id: Some(session_id),
+            created_at,
+            ..
+        }) => (session_id, created_at.unwrap_or_default()),
+        _ => {
+            let fallback = fs::metadata(file_path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs().to_string())
+                .unwrap_or_else(|| "0".to_string());
+            (file_path.to_string_lossy().to_string(), fallback)
+        }
+    };
+
+    let binding = SessionBinding {
+        session_id,
+        created_at,
+        file_path: file_path.to_string_lossy().to_string(),
+        bound_at: now_epoch_ms_string(),
+    };
+
+    update_usage_bindings(account_id, binding)?;
+    set_session_index_account(file_path, account_id);
+    Ok(())
+}
+
+fn bind_session_file_to_current_auth(file_path: &PathBuf) -> Result<(), String> {
+    let account_id = get_current_auth_account_id()?;
+    bind_session_file_to_account(&account_id, file_path)
+}
+
+/// 获取账号的用量信息（通过解析本地 session 日志）
+#[tauri::command]
+fn get_usage_from_sessions() -> Result<UsageData, String> {
+    let latest_file = find_latest_session_file()?;
+    parse_rate_limits_from_file(&latest_file)
+}
+
+/// 获取绑定账号的用量信息
+#[tauri::command]
+fn get_bound_usage(account_id: String) -> Result<UsageData, String> {
+    if account_id.is_empty() {
+        return Err("Missing account id".to_string());
+    }
+
+    let path = get_latest_bound_session_path(&account_id)?;
+    let mut data = parse_rate_limits_from_file(&path)?;
+    data.source_file = Some(path.to_string_lossy().to_string());
+    Ok(data)
+}
+
+/// 在线获取失败时可以附带本地 session 数据的状态（封禁、无权限等需要用户处理的状态除外）
+fn allows_session_fallback(status: &str) -> bool {
+    matches!(
+        status,
+        "error"
+            | "cooldown"
+            | "rate_limited"
+            | "tls_error"
+            | "expired"
+            | "stale_token"
+            | "missing_token"
+    )
+}
+
+fn usage_age_ms(usage: &UsageData, now_ms: u64) -> Option<u64> {
+    usage
+        .last_updated
+        .parse::<u64>()
+        .ok()
+        .map(|updated_ms| now_ms.saturating_sub(updated_ms))
+}
+
+/// 本地数据中至少有一个额度窗口尚未重置才有参考价值
+fn is_session_usage_current(usage: &UsageData, now_ms: u64) -> bool {
+    let now_ms = i64::try_from(now_ms).unwrap_or(i64::MAX);
+    [usage.five_hour_reset_time_ms, usage.weekly_reset_time_ms]
         .into_iter()
-        .find(|account| account.id == chatgpt_account_id);
+        .flatten()
+        .any(|reset_time_ms| reset_time_ms > now_ms)
+}
+
+/// 本地数据是否比在线数据更新；无法确定年龄时以在线数据为准
+fn is_session_usage_fresher(session: &UsageData, online: &UsageData, now_ms: u64) -> bool {
+    match (usage_age_ms(session, now_ms), usage_age_ms(online, now_ms)) {
+        (Some(session_age_ms), Some(online_age_ms)) => session_age_ms < online_age_ms,
+        _ => false,
+    }
+}
+
+/// 合并在线结果与本地 session 数据：取仍有效且更新的一份用量，并记录来源与数据年龄
+/// 在线结果的状态与提示始终保留，本地数据只作为附带的参考用量，不会把失败改写为成功
+fn choose_usage_source(
+    mut result: UsageResult,
+    session: Option<UsageData>,
+    now_ms: u64,
+) -> UsageResult {
+    let session = session.filter(|session| is_session_usage_current(session, now_ms));
+    match result.usage.as_mut() {
+        Some(usage) => {
+            usage.age_ms = usage_age_ms(usage, now_ms);
+            if !session
+                .as_ref()
+                .is_some_and(|session| is_session_usage_fresher(session, usage, now_ms))
+            {
+                return result;
+            }
+        }
+        None if !allows_session_fallback(&result.status) => return result,
+        None => {}
+    }
+    let Some(mut session) = session else {
+        return result;
+    };
+
+    session.source = Some(USAGE_SOURCE_SESSION.to_string());
+    session.age_ms = usage_age_ms(&session, now_ms);
+    if let Some(online) = result.usage.take() {
+        session.details = session.details.or(online.details);
+    }
+    result.usage = Some(session);
+    result
+}
+
+/// 用量回退需要的本地账号信息：绑定 session 时使用的 ChatGPT 账号 ID，以及是否为当前账号
+#[derive(Debug, Clone)]
+struct AccountSessionKey {
+    chatgpt_account_id: Option<String>,
+    is_active: bool,
+}
+
+/// 每次获取用量都要查找本地账号，读取一次 accounts.json 后缓存在内存中，保存账号数据时失效
+fn with_account_session_keys<T>(
+    f: impl FnOnce(&HashMap<String, AccountSessionKey>) -> Option<T>,
+) -> Option<T> {
+    let mut cache = ACCOUNT_SESSION_KEYS_CACHE.lock().ok()?;
+    if cache.is_none() {
+        let store = load_accounts_store_data().ok()?;
+        let keys = store
+            .accounts
+            .into_iter()
+            .map(|account| {
+                let key = AccountSessionKey {
+                    chatgpt_account_id: normalize_tray_text(
+                        account.account_info.account_id.as_deref(),
+                    ),
+                    is_active: account.is_active,
+                };
+                (account.id, key)
+            })
+            .collect();
+        *cache = Some(keys);
+    }
+    f(cache.as_ref()?)
+}
+
+fn load_account_session_key(account_id: &str) -> Option<AccountSessionKey> {
+    with_account_session_keys(|keys| keys.get(account_id).cloned())
+}
+
+/// 当前账号的本地 ID，以及查找其 session 使用的键（ChatGPT 账号 ID，缺失时为本地 ID）
+fn active_account_session_key() -> Option<(String, String)> {
+    with_account_session_keys(|keys| {
+        keys.iter()
+            .find(|(_, key)| key.is_active)
+            .map(|(account_id, key)| {
+                let session_key = key
+                    .chatgpt_account_id
+                    .clone()
+                    .unwrap_or_else(|| account_id.clone());
+                (account_id.clone(), session_key)
+            })
+    })
+}
+
+/// session 在截止时长内仍有写入时才返回其上下文窗口，不要求包含 rate_limits
+fn recent_session_context_window(
+    entry: &SessionIndexEntry,
+    now_ms: u64,
+) -> Option<ContextWindowUsage> {
+    let modified_ms = u64::try_from(entry.modified_ms?).ok()?;
+    if now_ms.saturating_sub(modified_ms) > SESSION_CONTEXT_WINDOW_MAX_AGE_MS {
+        return None;
+    }
+    entry.context_window.clone()
+}
 
-    Ok(matched.map(|account| WhamAccountMetadata {
-        workspace_name: match account.structure.as_deref() {
-            Some("workspace") => account.name.filter(|name| !name.trim().is_empty()),
-            _ => None,
-        },
-        account_user_id: account.account_user_id,
-        account_structure: account.structure,
-        plan_type: account.plan_type,
-    }))
+/// 账号修改时间最新的 session 中的上下文窗口
+fn find_session_context_window(account_id: &str, now_ms: u64) -> Option<ContextWindowUsage> {
+    with_scanned_session_index(|index| {
+        let path = index.latest_for_account(account_id)?;
+        recent_session_context_window(index.files.get(path)?, now_ms)
+    })
+    .ok()
+    .flatten()
 }
 
-fn get_current_auth_account_id() -> Result<String, String> {
-    let path = get_codex_auth_path()?;
-    if !path.exists() {
-        return Err("Codex auth.json not found".to_string());
+/// 上下文窗口只属于当前账号正在进行的会话，其他来源的用量不携带
+fn with_context_window(
+    mut result: UsageResult,
+    context_window: Option<ContextWindowUsage>,
+) -> UsageResult {
+    if let Some(usage) = result.usage.as_mut() {
+        usage.context_window = context_window;
     }
-    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-    let auth: AuthConfig = serde_json::from_str(&content).map_err(|e| e.to_string())?;
-    let tokens = auth
-        .tokens
-        .ok_or_else(|| "Missing tokens in auth.json".to_string())?;
-    take_non_empty_token(tokens.account_id, "Missing account_id in auth.json")
+    result
+}
+
+/// 在线获取失败时回退到绑定的本地 session 数据；当前账号额外读取最近会话的上下文窗口
+/// 非当前账号在线获取成功时不查询 session 索引
+fn apply_session_fallback(account_id: &str, result: UsageResult, now_ms: u64) -> UsageResult {
+    if account_id.is_empty() {
+        return with_context_window(choose_usage_source(result, None, now_ms), None);
+    }
+    let key = load_account_session_key(account_id);
+    let needs_fallback = result.usage.is_none() && allows_session_fallback(&result.status);
+    let is_active = key.as_ref().is_some_and(|key| key.is_active);
+    if !needs_fallback && !is_active {
+        return with_context_window(choose_usage_source(result, None, now_ms), None);
+    }
+    // 绑定记录以 auth.json 中的 ChatGPT 账号 ID 为键
+    let bound_account_id = key
+        .and_then(|key| key.chatgpt_account_id)
+        .unwrap_or_else(|| account_id.to_string());
+    let session = get_bound_usage(bound_account_id.clone()).ok();
+    let context_window = if is_active {
+        find_session_context_window(&bound_account_id, now_ms)
+    } else {
+        None
+    };
+    with_context_window(choose_usage_source(result, session, now_ms), context_window)
 }
 
+/// 统一的用量获取：优先 wham/usage，在线失败时回退到绑定的本地 session 数据
 #[tauri::command]
-async fn get_wham_account_metadata(
+async fn get_usage(
     account_id: String,
     proxy_enabled: Option<bool>,
     proxy_url: Option<String>,
-) -> Result<Option<WhamAccountMetadata>, String> {
-    if account_id.is_empty() {
-        return Ok(None);
+    force: Option<bool>,
+) -> Result<UsageResult, String> {
+    let result = get_codex_wham_usage(account_id.clone(), proxy_enabled, proxy_url, force)
+        .await
+        .unwrap_or_else(|error| UsageResult {
+            status: "error".to_string(),
+            message: Some(error),
+            plan_type: None,
+            next_retry_at_ms: None,
+            usage: None,
+        });
+    Ok(apply_session_fallback(
+        &account_id,
+        result,
+        now_epoch_ms_u64(),
+    ))
+}
+
+/// 通过 wham/usage API 获取 Codex quota，并记录到用量历史
+/// 连续失败的账号会进入冷却期，`force` 为 true（手动刷新）时解除冷却
+#[tauri::command]
+async fn get_codex_wham_usage(
+    account_id: String,
+    proxy_enabled: Option<bool>,
+    proxy_url: Option<String>,
+    force: Option<bool>,
+) -> Result<UsageResult, String> {
+    if !account_id.is_empty() {
+        if force == Some(true) {
+            reset_account_circuit_breaker(Some(&account_id));
+        } else if let Some(result) = circuit_breaker_result(&account_id, now_epoch_ms_u64()) {
+            return Ok(result);
+        }
     }
 
-    let auth_json = read_account_auth(account_id)?;
-    fetch_wham_account_metadata(&auth_json, proxy_enabled, proxy_url).await
+    let mut result = fetch_codex_wham_usage(account_id.clone(), proxy_enabled, proxy_url).await?;
+    if !account_id.is_empty() {
+        if let Err(error) = record_usage_history(&account_id, &result, now_epoch_ms_u64()) {
+            log::warn!("记录用量历史失败 ({}): {}", account_id, error);
+        }
+        let attempted_ms = now_epoch_ms_u64();
+        if let Some(next_retry_at_ms) = record_account_refresh_attempt(
+            &account_id,
+            &result.status,
+            result.message.as_deref(),
+            attempted_ms,
+            result.next_retry_at_ms,
+        ) {
+            let failures =
+                with_account_refresh_state(&account_id, |state| state.consecutive_failures)
+                    .unwrap_or(CIRCUIT_BREAKER_THRESHOLD);
+            if result.status == "rate_limited" || requires_user_action(&result.status) {
+                result.next_retry_at_ms = Some(next_retry_at_ms);
+            } else {
+                result = build_cooldown_usage_result(
+                    next_retry_at_ms,
+                    failures,
+                    result.message.as_deref(),
+                    attempted_ms,
+                );
+            }
+        }
+        if let Some(usage) = result.usage.as_mut() {
+            if let Err(error) = apply_usage_forecast(&account_id, usage) {
+                log::warn!("估算用量消耗失败 ({}): {}", account_id, error);
+            }
+        }
+    }
+    Ok(result)
 }
 
-// ==================== 用量解析相关结构 ====================
+async fn fetch_codex_wham_usage(
+    account_id: String,
+    proxy_enabled: Option<bool>,
+    proxy_url: Option<String>,
+) -> Result<UsageResult, String> {
+    if account_id.is_empty() {
+        return Ok(UsageResult {
+            status: "missing_account_id".to_string(),
+            message: Some("\u{7f3a}\u{5c11} ChatGPT account ID".to_string()),
+            plan_type: None,
+            next_retry_at_ms: None,
+            usage: None,
+        });
+    }
 
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct RateLimitEntry {
-    used_percent: f64,
-    window_minutes: u32,
-    resets_at: i64,
-}
+    let auth_json = read_account_auth(account_id.clone())?;
+    let auth: AuthConfig = serde_json::from_str(&auth_json).map_err(|e| e.to_string())?;
+    let tokens = match auth.tokens {
+        Some(tokens) => tokens,
+        None => {
+            return Ok(UsageResult {
+                status: "missing_token".to_string(),
+                message: Some("\u{7f3a}\u{5c11} access token".to_string()),
+                plan_type: None,
+                next_retry_at_ms: None,
+                usage: None,
+            })
+        }
+    };
 
-#[derive(Debug, Deserialize)]
-struct RateLimits {
-    primary: Option<RateLimitEntry>,
-    secondary: Option<RateLimitEntry>,
-}
+    let access_token =
+        match take_non_empty_token(tokens.access_token, "\u{7f3a}\u{5c11} access token") {
+            Ok(value) => value,
+            Err(message) => {
+                return Ok(UsageResult {
+                    status: "missing_token".to_string(),
+                    message: Some(message),
+                    plan_type: None,
+                    next_retry_at_ms: None,
+                    usage: None,
+                })
+            }
+        };
+    let chatgpt_account_id =
+        match take_non_empty_token(tokens.account_id, "\u{7f3a}\u{5c11} ChatGPT account ID") {
+            Ok(value) => value,
+            Err(message) => {
+                return Ok(UsageResult {
+                    status: "missing_account_id".to_string(),
+                    message: Some(message),
+                    plan_type: None,
+                    next_retry_at_ms: None,
+                    usage: None,
+                })
+            }
+        };
+    let is_current_account = is_current_chatgpt_account(&chatgpt_account_id);
 
-#[derive(Debug, Deserialize)]
-struct EventMsg {
-    #[serde(rename = "type")]
-    msg_type: String,
-    payload: Option<serde_json::Value>,
-}
+    let client = match build_account_http_client(Some(&account_id), proxy_enabled, proxy_url) {
+        Ok(client) => client,
+        Err(message) => {
+            return Ok(UsageResult {
+                status: "error".to_string(),
+                message: Some(message),
+                plan_type: None,
+                next_retry_at_ms: None,
+                usage: None,
+            })
+        }
+    };
 
-#[derive(Debug, Serialize)]
-pub struct UsageData {
-    pub five_hour_percent_left: Option<f64>,
-    pub five_hour_reset_time_ms: Option<i64>,
-    pub weekly_percent_left: Option<f64>,
-    pub weekly_reset_time_ms: Option<i64>,
-    pub code_review_percent_left: Option<f64>,
-    pub code_review_reset_time_ms: Option<i64>,
-    pub last_updated: String,
-    pub source_file: Option<String>,
+    request_wham_usage(
+        &client,
+        &current_api_base_url(),
+        &access_token,
+        &chatgpt_account_id,
+        is_current_account,
+        &RetryPolicy::default(),
+    )
+    .await
 }
 
-#[derive(Debug, Serialize)]
-pub struct UsageResult {
-    pub status: String,
-    pub message: Option<String>,
-    pub plan_type: Option<String>,
-    pub usage: Option<UsageData>,
-}
+/// 请求 wham/usage 并把 HTTP 状态映射为用量结果；临时错误按重试策略退避重试，429 返回 rate_limited
+async fn request_wham_usage(
+    client: &Client,
+    base_url: &str,
+    access_token: &str,
+    chatgpt_account_id: &str,
+    is_current_account: bool,
+    policy: &RetryPolicy,
+) -> Result<UsageResult, String> {
+    let url = format!("{}/wham/usage", base_url);
+    let send_request = || {
+        client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Accept", "application/json")
+            .header("ChatGPT-Account-Id", chatgpt_account_id)
+            .send()
+    };
 
-/// 获取 codex sessions 目录路径
-fn get_codex_sessions_dir() -> Result<PathBuf, String> {
-    dirs::home_dir()
-        .map(|p| p.join(".codex").join("sessions"))
-        .ok_or_else(|| "Cannot find home directory".to_string())
+    let mut attempt = 0;
+    let (response, started_at) = loop {
+        attempt += 1;
+        let can_retry = attempt < policy.max_attempts;
+        let backoff = policy.backoff_delay(attempt, rand::random::<f64>());
+        let started_at = Instant::now();
+        match send_request().await {
+            Ok(response) => {
+                let retry_after = response
+                    .headers()
+                    .get(reqwest::header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .map(|value| value.to_string());
+                match classify_response(response.status(), retry_after.as_deref()) {
+                    ResponseClass::RateLimited(wait) => {
+                        record_diagnostic_event(DiagnosticEvent {
+                            message: retry_after.map(|value| format!("Retry-After: {}", value)),
+                            ..DiagnosticEvent::response(
+                                "wham/usage",
+                                attempt,
+                                response.status(),
+                                started_at,
+                            )
+                        });
+                        let wait = wait.unwrap_or(backoff);
+                        if can_retry && wait <= policy.max_inline_retry_after {
+                            log::warn!("wham/usage 被限流，{} 毫秒后重试", wait.as_millis());
+                            tokio::time::sleep(wait).await;
+                            continue;
+                        }
+                        return Ok(build_rate_limited_result(wait, now_epoch_ms_u64()));
+                    }
+                    ResponseClass::Transient if can_retry => {
+                        record_diagnostic_event(DiagnosticEvent::response(
+                            "wham/usage",
+                            attempt,
+                            response.status(),
+                            started_at,
+                        ));
+                        log::warn!(
+                            "wham/usage 第 {} 次请求返回 {}，{} 毫秒后重试",
+                            attempt,
+                            response.status(),
+                            backoff.as_millis()
+                        );
+                        tokio::time::sleep(backoff).await;
+                    }
+                    _ => break (response, started_at),
+                }
+            }
+            Err(error) if can_retry && is_transient_error(&error) => {
+                record_diagnostic_event(DiagnosticEvent::request_error(
+                    "wham/usage",
+                    attempt,
+                    started_at,
+                    &error,
+                ));
+                log::warn!(
+                    "wham/usage 第 {} 次请求失败，{} 毫秒后重试: {}",
+                    attempt,
+                    backoff.as_millis(),
+                    error
+                );
+                tokio::time::sleep(backoff).await;
+            }
+            Err(error) if is_certificate_error(&error) => {
+                record_diagnostic_event(DiagnosticEvent::request_error(
+                    "wham/usage",
+                    attempt,
+                    started_at,
+                    &error,
+                ));
+                return Ok(UsageResult {
+                    status: TLS_ERROR_STATUS.to_string(),
+                    message: Some(format!(
+                        "证书校验失败，如处于 TLS 拦截的代理网络，请在设置中添加 CA 证书或启用系统证书库: {}",
+                        error_chain_message(&error)
+                    )),
+                    plan_type: None,
+                    next_retry_at_ms: None,
+                    usage: None,
+                });
+            }
+            Err(error) => {
+                record_diagnostic_event(DiagnosticEvent::request_error(
+                    "wham/usage",
+                    attempt,
+                    started_at,
+                    &error,
+                ));
+                let message = if attempt > 1 {
+                    format!("\u{8bf7}\u{6c42}\u{5931}\u{8d25}\u{ff08}\u{5df2}\u{91cd}\u{8bd5}\u{ff09}: {}", error)
+                } else {
+                    format!("请求失败: {}", error)
+                };
+                return Ok(UsageResult {
+                    status: "error".to_string(),
+                    message: Some(message),
+                    plan_type: None,
+                    next_retry_at_ms: None,
+                    usage: None,
+                });
+            }
+        }
+    };
+
+    let status = response.status();
+    let body = match response.text().await {
+        Ok(body) => body,
+        Err(error) => {
+            record_diagnostic_event(DiagnosticEvent::request_error(
+                "wham/usage",
+                attempt,
+                started_at,
+                &error,
+            ));
+            return Ok(UsageResult {
+                status: "error".to_string(),
+                message: Some(format!("读取响应失败: {}", error)),
+                plan_type: None,
+                next_retry_at_ms: None,
+                usage: None,
+            });
+        }
+    };
+    record_diagnostic_event(DiagnosticEvent {
+        body_excerpt: (!status.is_success()).then(|| body.clone()),
+        ..DiagnosticEvent::response("wham/usage", attempt, status, started_at)
+    });
+
+    if status == reqwest::StatusCode::UNAUTHORIZED {
+        let (status, message) = if is_current_account {
+            (
+                "expired".to_string(),
+                "\u{5f53}\u{524d}\u{8d26}\u{53f7}\u{767b}\u{5f55}\u{6001}\u{5df2}\u{5931}\u{6548}\u{ff0c}\u{8bf7}\u{91cd}\u{65b0}\u{767b}\u{5f55} Codex".to_string(),
+            )
+        } else {
+            (
+                "stale_token".to_string(),
+                "\u{8be5}\u{8d26}\u{53f7}\u{7f13}\u{5b58}\u{7684} access token \u{5df2}\u{5931}\u{6548}\u{ff0c}\u{8bf7}\u{5207}\u{6362}\u{5230}\u{8be5}\u{8d26}\u{53f7}\u{5e76}\u{91cd}\u{65b0}\u{5b8c}\u{6210}\u{4e00}\u{6b21} Codex \u{767b}\u{5f55}".to_string(),
+            )
+        };
+
+        return Ok(UsageResult {
+            status,
+            message: Some(message),
+            plan_type: None,
+            next_retry_at_ms: None,
+            usage: None,
+        });
+    }
+
+    if status == reqwest::StatusCode::FORBIDDEN {
+        return Ok(UsageResult {
+            status: "forbidden".to_string(),
+            message: Some("\u{8d26}\u{53f7}\u{5df2}\u{88ab}\u{5c01}\u{7981}\u{6216}\u{65e0}\u{6743}\u{8bbf}\u{95ee}".to_string()),
+            plan_type: None,
+            next_retry_at_ms: None,
+            usage: None,
+        });
+    }
+
+    if !status.is_success() {
+        return Ok(UsageResult {
+            status: "error".to_string(),
+            message: Some(format!(
+                "wham/usage \u{8bf7}\u{6c42}\u{5931}\u{8d25}: {}",
+                status
+            )),
+            plan_type: None,
+            next_retry_at_ms: None,
+            usage: None,
+        });
+    }
+
+    // 响应不是 JSON 时按普通失败处理，计入熔断
+    let value: serde_json::Value = match serde_json::from_str(&body) {
+        Ok(value) => value,
+        Err(error) => {
+            record_diagnostic_event(DiagnosticEvent::parse_error(
+                "wham/usage",
+                error.to_string(),
+                &body,
+            ));
+            return Ok(UsageResult {
+                status: "error".to_string(),
+                message: Some(format!("wham/usage 响应解析失败: {}", error)),
+                plan_type: None,
+                next_retry_at_ms: None,
+                usage: None,
+            });
+        }
+    };
+    let payload = WhamUsagePayload::from_value(&value);
+    let mut skipped = payload.mismatches.clone();
+    let usage = build_wham_usage_data(&payload, now_epoch_ms_u64() as i64, &mut skipped);
+    if !skipped.is_empty() {
+        log::debug!("wham/usage 部分字段已忽略: {}", skipped.join("; "));
+        record_diagnostic_event(DiagnosticEvent::parse_error(
+            "wham/usage",
+            skipped.join("; "),
+            &body,
+        ));
+    }
+
+    match usage {
+        Ok(usage) => Ok(UsageResult {
+            status: "ok".to_string(),
+            message: None,
+            plan_type: payload.plan_type,
+            next_retry_at_ms: None,
+            usage: Some(usage),
+        }),
+        Err(error) => {
+            record_diagnostic_event(DiagnosticEvent::parse_error(
+                "wham/usage",
+                error.clone(),
+                &body,
+            ));
+            Ok(UsageResult {
+                status: "no_usage".to_string(),
+                message: Some(error),
+                plan_type: payload.plan_type,
+                next_retry_at_ms: None,
+                usage: None,
+            })
+        }
+    }
 }
 
-fn start_session_watcher() {
-    let sessions_dir = match get_codex_sessions_dir() {
-        Ok(dir) => dir,
-        Err(err) => {
-            log::warn!("Failed to resolve sessions dir: {}", err);
-            return;
+/// 最近的请求/响应元数据与解析错误，写入前即脱敏，用于导出诊断包
+#[derive(Debug, Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+struct DiagnosticEvent {
+    recorded_at_ms: u64,
+    /// response / request_error / parse_error
+    kind: String,
+    endpoint: String,
+    attempt: Option<u32>,
+    status_code: Option<u16>,
+    duration_ms: Option<u64>,
+    message: Option<String>,
+    body_excerpt: Option<String>,
+}
+
+impl DiagnosticEvent {
+    fn response(
+        endpoint: &str,
+        attempt: u32,
+        status: reqwest::StatusCode,
+        started_at: Instant,
+    ) -> Self {
+        Self {
+            recorded_at_ms: now_epoch_ms_u64(),
+            kind: "response".to_string(),
+            endpoint: endpoint.to_string(),
+            attempt: Some(attempt),
+            status_code: Some(status.as_u16()),
+            duration_ms: u64::try_from(started_at.elapsed().as_millis()).ok(),
+            ..Default::default()
         }
-    };
+    }
 
-    if !sessions_dir.exists() {
-        log::warn!("Sessions directory not found for watcher");
-        return;
+    fn request_error(
+        endpoint: &str,
+        attempt: u32,
+        started_at: Instant,
+        error: &reqwest::Error,
+    ) -> Self {
+        Self {
+            recorded_at_ms: now_epoch_ms_u64(),
+            kind: "request_error".to_string(),
+            endpoint: endpoint.to_string(),
+            attempt: Some(attempt),
+            duration_ms: u64::try_from(started_at.elapsed().as_millis()).ok(),
+            message: Some(error.to_string()),
+            ..Default::default()
+        }
     }
 
-    std::thread::spawn(move || {
-        let (tx, rx) = std::sync::mpsc::channel();
-        let mut watcher = match notify::recommended_watcher(move |res| {
-            let _ = tx.send(res);
-        }) {
-            Ok(w) => w,
-            Err(err) => {
-                log::error!("Failed to start watcher: {}", err);
-                return;
-            }
-        };
+    fn parse_error(endpoint: &str, message: String, body: &str) -> Self {
+        Self {
+            recorded_at_ms: now_epoch_ms_u64(),
+            kind: "parse_error".to_string(),
+            endpoint: endpoint.to_string(),
+            message: Some(message),
+            body_excerpt: Some(body.to_string()),
+            ..Default::default()
+        }
+    }
+}
 
-        if let Err(err) = watcher.watch(&sessions_dir, RecursiveMode::Recursive) {
-            log::error!("Failed to watch sessions dir: {}", err);
-            return;
+/// 响应体优先按 JSON 字段脱敏，无法解析时按文本脱敏，并截断到固定长度
+fn redact_body_excerpt(body: &str) -> String {
+    let redacted = match serde_json::from_str::<serde_json::Value>(body) {
+        Ok(mut value) => {
+            redact::redact_json(&mut value);
+            value.to_string()
         }
+        Err(_) => redact::redact_text(body),
+    };
+    redacted
+        .chars()
+        .take(DIAGNOSTIC_BODY_EXCERPT_CHARS)
+        .collect()
+}
+
+/// 脱敏后追加到事件队列，超过上限时丢弃最旧的事件
+fn push_diagnostic_event(events: &mut VecDeque<DiagnosticEvent>, mut event: DiagnosticEvent) {
+    event.message = event.message.map(|message| redact::redact_text(&message));
+    event.body_excerpt = event
+        .body_excerpt
+        .filter(|body| !body.trim().is_empty())
+        .map(|body| redact_body_excerpt(&body));
+
+    while events.len() >= MAX_DIAGNOSTIC_EVENTS {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
 
-        for res in rx {
-            let event = match res {
-                Ok(ev) => ev,
-                Err(_) => continue,
-            };
+fn record_diagnostic_event(event: DiagnosticEvent) {
+    if let Ok(mut events) = DIAGNOSTIC_EVENTS.lock() {
+        push_diagnostic_event(&mut events, event);
+    }
+}
 
-            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
-                continue;
-            }
+fn redacted_json_string<T: Serialize>(value: &T) -> Result<String, String> {
+    let mut value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+    redact::redact_json(&mut value);
+    serde_json::to_string_pretty(&value).map_err(|e| e.to_string())
+}
 
-            for path in event.paths {
-                if path.extension().map_or(false, |ext| ext == "jsonl") {
-                    if let Err(err) = bind_session_file_to_current_auth(&path) {
-                        log::debug!("Bind session skipped: {}", err);
-                    }
-                }
-            }
+fn build_diagnostics_summary(
+    app_version: &str,
+    store: Option<&TrayAccountsStore>,
+    now_ms: u64,
+) -> serde_json::Value {
+    let store_stats = store.map(|store| {
+        let mut status_counts: HashMap<String, usize> = HashMap::new();
+        for account in &store.accounts {
+            let status = account
+                .usage_info
+                .as_ref()
+                .and_then(|usage| usage.status.clone())
+                .unwrap_or_else(|| "unknown".to_string());
+            *status_counts.entry(status).or_default() += 1;
         }
+        serde_json::json!({
+            "version": store.version,
+            "accountCount": store.accounts.len(),
+            "activeCount": store.accounts.iter().filter(|account| account.is_active).count(),
+            "pinnedCount": store.accounts.iter().filter(|account| account.pinned == Some(true)).count(),
+            "groupCount": store
+                .accounts
+                .iter()
+                .filter_map(|account| account.group.as_deref())
+                .collect::<std::collections::HashSet<_>>()
+                .len(),
+            "accountProxyCount": store.accounts.iter().filter(|account| account.proxy_url.is_some()).count(),
+            "statusCounts": status_counts,
+        })
     });
+
+    serde_json::json!({
+        "appVersion": app_version,
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "generatedAtMs": now_ms,
+        "apiBaseUrl": redact::redact_text(&current_api_base_url()),
+        "apiBaseUrlFromEnv": std::env::var(API_BASE_URL_ENV).is_ok(),
+        "store": store_stats,
+        "refreshJob": current_refresh_job(),
+    })
 }
 
-/// 查找最新的 session 日志文件
-fn find_latest_session_file() -> Result<PathBuf, String> {
-    let sessions_dir = get_codex_sessions_dir()?;
+/// 导出脱敏后的诊断包（zip），返回文件路径；未指定路径时写入应用数据目录
+#[tauri::command]
+fn export_diagnostics_bundle(
+    app: AppHandle,
+    target_path: Option<String>,
+) -> Result<String, String> {
+    let now_ms = now_epoch_ms_u64();
+    let path = match target_path.filter(|path| !path.trim().is_empty()) {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let dir = get_app_data_dir()?.join("diagnostics");
+            fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+            dir.join(format!("codex-manager-diagnostics-{}.zip", now_ms))
+        }
+    };
 
-    if !sessions_dir.exists() {
-        return Err("Sessions directory not found".to_string());
-    }
+    let store = load_accounts_store_data();
+    let store_error = store.as_ref().err().map(|error| redact::redact_text(error));
+    let store = store.ok();
+    let events: Vec<DiagnosticEvent> = DIAGNOSTIC_EVENTS
+        .lock()
+        .map(|events| events.iter().cloned().collect())
+        .unwrap_or_default();
+    let refresh_states: Vec<serde_json::Value> = ACCOUNT_REFRESH_STATES
+        .lock()
+        .map(|states| {
+            states
+                .iter()
+                .map(|state| {
+                    serde_json::json!({
+                        "accountId": state.account_id,
+                        "lastAttemptMs": state.last_attempt_ms,
+                        "consecutiveFailures": state.consecutive_failures,
+                        "cooldownUntilMs": state.cooldown_until_ms,
+                        "lastError": state.last_error,
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
 
-    let mut all_files: Vec<PathBuf> = Vec::new();
+    let mut summary = build_diagnostics_summary(
+        &app.package_info().version.to_string(),
+        store.as_ref(),
+        now_ms,
+    );
+    summary["storeError"] = serde_json::json!(store_error);
 
-    // 递归遍历 sessions 目录查找所有 .jsonl 文件
-    fn collect_jsonl_files(dir: &PathBuf, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
-        if dir.is_dir() {
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    collect_jsonl_files(&path, files)?;
-                } else if path.extension().map_or(false, |ext| ext == "jsonl") {
-                    files.push(path);
-                }
-            }
-        }
-        Ok(())
+    let entries = [
+        ("summary.json", redacted_json_string(&summary)?),
+        (
+            "config.json",
+            redacted_json_string(&store.as_ref().map(|store| &store.config))?,
+        ),
+        (
+            "accounts.json",
+            redacted_json_string(&store.as_ref().map(|store| &store.accounts))?,
+        ),
+        (
+            "refresh-states.json",
+            redacted_json_string(&refresh_states)?,
+        ),
+        ("events.json", redacted_json_string(&events)?),
+    ];
+
+    let file = fs::File::create(&path).map_err(|e| e.to_string())?;
+    let mut archive = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for (name, content) in entries {
+        archive
+            .start_file(name, options)
+            .map_err(|e| e.to_string())?;
+        archive
+            .write_all(content.as_bytes())
+            .map_err(|e| e.to_string())?;
     }
+    archive.finish().map_err(|e| e.to_string())?;
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+fn build_rate_limited_result(wait: Duration, now_ms: u64) -> UsageResult {
+    let wait_ms = u64::try_from(wait.as_millis()).unwrap_or(u64::MAX);
+    UsageResult {
+        status: "rate_limited".to_string(),
+        message: Some(format!(
+            "请求过于频繁，{}后重试",
+            format_reset_countdown(i64::try_from(wait_ms).unwrap_or(i64::MAX))
+        )),
+        plan_type: None,
+        next_retry_at_ms: Some(now_ms.saturating_add(wait_ms)),
+        usage: None,
+    }
+}
 
-    collect_jsonl_files(&sessions_dir, &mut all_files)
-        .map_err(|e| format!("Failed to read sessions directory: {}", e))?;
+/// 从指定文件解析用量信息
+#[tauri::command]
+fn get_usage_from_file(file_path: String) -> Result<UsageData, String> {
+    let path = PathBuf::from(file_path);
+    if !path.exists() {
+        return Err("Usage source file not found".to_string());
+    }
+    parse_rate_limits_from_file(&path)
+}
 
-    if all_files.is_empty() {
-        return Err("No session files found".to_string());
+/// session 与账号的匹配依据
+const SESSION_MATCH_META: &str = "session_meta";
+const SESSION_MATCH_BINDING: &str = "binding";
+const SESSION_MATCH_EMAIL: &str = "email";
+
+/// 按账号查找到的 session 用量
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountSessionUsage {
+    usage: UsageData,
+    /// session_meta（会话元数据中的账号 ID）、binding（会话期间记录的当前账号）或 email（仅邮箱相同）
+    matched_by: String,
+    /// high / medium / low
+    confidence: String,
+}
+
+fn session_match_confidence(matched_by: &str) -> &'static str {
+    match matched_by {
+        SESSION_MATCH_META => "high",
+        SESSION_MATCH_BINDING => "medium",
+        _ => "low",
     }
+}
 
-    // 按修改时间排序，获取最新的
-    all_files.sort_by(|a, b| {
-        let a_time = fs::metadata(a).and_then(|m| m.modified()).ok();
-        let b_time = fs::metadata(b).and_then(|m| m.modified()).ok();
-        b_time.cmp(&a_time)
-    });
+/// 判断 session 是否属于该账号：优先比较记录的账号 ID，邮箱只在无法确认账号 ID 时兜底
+fn match_session_entry(
+    entry: &SessionIndexEntry,
+    account_id: Option<&str>,
+    account_email: Option<&str>,
+) -> Option<&'static str> {
+    let recorded_ids = [
+        (entry.meta_account_id.as_deref(), SESSION_MATCH_META),
+        (entry.account_id.as_deref(), SESSION_MATCH_BINDING),
+    ];
+    if let Some(account_id) = account_id {
+        if let Some((_, matched_by)) = recorded_ids
+            .iter()
+            .find(|(recorded, _)| *recorded == Some(account_id))
+        {
+            return Some(matched_by);
+        }
+        // 已确认属于其他账号的 session 不再按邮箱匹配（同一邮箱可能对应多个工作区）
+        if recorded_ids.iter().any(|(recorded, _)| recorded.is_some()) {
+            return None;
+        }
+    }
+    let account_email = account_email?;
+    entry
+        .emails
+        .iter()
+        .any(|email| email.eq_ignore_ascii_case(account_email))
+        .then_some(SESSION_MATCH_EMAIL)
+}
+
+/// 在全部历史 session 中查找属于该账号且带完整额度信息的最新文件，按账号 ID 匹配的优先于按邮箱匹配的
+fn find_account_session<'a>(
+    index: &'a SessionIndex,
+    account_id: Option<&str>,
+    account_email: Option<&str>,
+) -> Option<(&'static str, &'a String, &'a SessionIndexEntry)> {
+    index
+        .files
+        .iter()
+        .filter(|(_, entry)| {
+            entry
+                .rate_limits
+                .as_ref()
+                .is_some_and(|limits| limits.primary.is_some() && limits.secondary.is_some())
+        })
+        .filter_map(|(path, entry)| {
+            let matched_by = match_session_entry(entry, account_id, account_email)?;
+            Some((matched_by, path, entry))
+        })
+        .max_by_key(|(matched_by, path, entry)| {
+            (*matched_by != SESSION_MATCH_EMAIL, entry.modified_ms, *path)
+        })
+}
 
-    Ok(all_files[0].clone())
+/// 只有邮箱时，从已保存的账号中找出唯一对应的 ChatGPT 账号 ID
+fn resolve_account_id_by_email(account_email: &str) -> Option<String> {
+    let store = load_accounts_store_data().ok()?;
+    let mut ids = store
+        .accounts
+        .iter()
+        .filter(|account| {
+            account
+                .account_info
+                .email
+                .eq_ignore_ascii_case(account_email)
+        })
+        .filter_map(|account| account.account_info.account_id.clone())
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+    match ids.as_slice() {
+        [account_id] => Some(account_id.clone()),
+        _ => None,
+    }
 }
 
-/// 从 JSONL 文件中解析最新的 rate_limits 信息
-fn parse_rate_limits_from_file(file_path: &PathBuf) -> Result<UsageData, String> {
-    let file = fs::File::open(file_path).map_err(|e| format!("Failed to open file: {}", e))?;
+fn find_account_session_usage(
+    account_id: Option<&str>,
+    account_email: Option<&str>,
+) -> Result<AccountSessionUsage, String> {
+    let sessions_dir = get_codex_sessions_dir()?;
 
-    let reader = BufReader::new(file);
-    let mut latest_rate_limits: Option<RateLimits> = None;
+    if !sessions_dir.exists() {
+        return Err("Sessions directory not found".to_string());
+    }
 
-    // 读取所有行，找到最后一个有效的 rate_limits
-    for line in reader.lines() {
-        let line = match line {
-            Ok(l) => l,
-            Err(_) => continue,
-        };
+    let found = with_scanned_session_index(|index| {
+        find_account_session(index, account_id, account_email)
+            .map(|(matched_by, path, entry)| (matched_by, PathBuf::from(path), entry.clone()))
+    })?;
 
-        if line.is_empty() {
-            continue;
-        }
+    let (matched_by, path, entry) = found.ok_or_else(|| {
+        format!(
+            "No usage data found for account: {}",
+            account_id.or(account_email).unwrap_or_default()
+        )
+    })?;
+    Ok(AccountSessionUsage {
+        usage: usage_data_from_session_entry(entry, &path)?,
+        matched_by: matched_by.to_string(),
+        confidence: session_match_confidence(matched_by).to_string(),
+    })
+}
 
-        // 尝试解析 JSON
-        let event: EventMsg = match serde_json::from_str(&line) {
-            Ok(e) => e,
-            Err(_) => continue,
-        };
+/// 获取指定账号的用量信息（从本地 session 中查找）
+/// 优先按会话时记录的 ChatGPT 账号 ID 匹配，并返回匹配依据与可信度
+#[tauri::command]
+fn get_account_usage(
+    account_id: Option<String>,
+    account_email: Option<String>,
+) -> Result<AccountSessionUsage, String> {
+    let non_empty = |value: Option<String>| {
+        value
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    let account_email = non_empty(account_email);
+    let account_id = non_empty(account_id).or_else(|| {
+        account_email
+            .as_deref()
+            .and_then(resolve_account_id_by_email)
+    });
+    if account_id.is_none() && account_email.is_none() {
+        return Err("Missing account id".to_string());
+    }
+    find_account_session_usage(account_id.as_deref(), account_email.as_deref())
+}
 
-        // 检查是否是 token_count 类型的事件
-        if event.msg_type == "event_msg" || event.msg_type == "token_count" {
-            if let Some(payload) = event.payload {
-                // 尝试从 payload 中提取 rate_limits
-                if let Some(rate_limits) = payload.get("rate_limits") {
-                    if let Ok(rl) = serde_json::from_value::<RateLimits>(rate_limits.clone()) {
-                        latest_rate_limits = Some(rl);
-                    }
-                }
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    tauri::Builder::default()
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_shell::init())
+        .plugin(tauri_plugin_global_shortcut::Builder::new().build())
+        .setup(|app| {
+            if cfg!(debug_assertions) {
+                app.handle().plugin(
+                    tauri_plugin_log::Builder::default()
+                        .level(log::LevelFilter::Info)
+                        .build(),
+                )?;
             }
-        }
-    }
+            start_session_watcher(app.handle());
+            initialize_tray(&app.handle())?;
+            start_tray_countdown_timer(app.handle());
+            if let Err(error) = register_global_shortcuts(app.handle()) {
+                log::warn!("注册全局快捷键失败: {}", error);
+            }
+            start_background_auto_refresh(&app.handle());
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            load_accounts_store,
+            save_accounts_store,
+            write_codex_auth,
+            read_codex_auth,
+            refresh_tray_menu,
+            reload_global_shortcuts,
+            get_refresh_schedule,
+            set_proxy_credentials,
+            get_proxy_credentials_status,
+            test_proxy,
+            export_diagnostics_bundle,
+            start_refresh_job,
+            cancel_refresh_job,
+            get_refresh_job_status,
+            get_usage_history,
+            get_usage_history_series,
+            clear_usage_history,
+            show_main_window,
+            hide_to_tray,
+            hide_tray_popover,
+            switch_account_from_popover,
+            exit_application,
+            start_codex_login,
+            cancel_codex_login,
+            save_account_auth,
+            read_account_auth,
+            delete_account_auth,
+            read_file_content,
+            write_file_content,
+            get_home_dir,
+            restart_codex_processes,
+            get_wham_account_metadata,
+            get_codex_wham_usage,
+            get_usage,
+            get_usage_from_sessions,
+            get_bound_usage,
+            get_usage_from_file,
+            get_account_usage,
+        ])
+        .run(tauri::generate_context!())
+        .expect("error while running tauri application");
+}
 
-    let rate_limits =
-        latest_rate_limits.ok_or_else(|| "No rate limits found in session file".to_string())?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // 转换为 UsageData
-    let primary = rate_limits
-        .primary
-        .ok_or_else(|| "No primary rate limit found".to_string())?;
-    let secondary = rate_limits
-        .secondary
-        .ok_or_else(|| "No secondary rate limit found".to_string())?;
+    fn sample_auth_json(id_token: &str) -> String {
+        format!(
+            r#"{{
+  "OPENAI_API_KEY": null,
+  "tokens": {{
+    "id_token": "{}",
+    "access_token": "access",
+    "refresh_token": "refresh",
+    "account_id": "account"
+  }},
+  "last_refresh": "2026-03-27T00:00:00.000Z"
+}}"#,
+            id_token
+        )
+    }
 
-    let primary_used = validate_used_percent(primary.used_percent)?;
-    let secondary_used = validate_used_percent(secondary.used_percent)?;
-    let five_hour_reset_ms = normalize_unix_timestamp_ms(primary.resets_at)?;
-    let weekly_reset_ms = normalize_unix_timestamp_ms(secondary.resets_at)?;
-    let last_updated = fs::metadata(file_path)
-        .and_then(|m| m.modified())
-        .ok()
-        .and_then(epoch_ms_from_system_time)
-        .map(|ms| ms.to_string())
-        .unwrap_or_else(now_epoch_ms_string);
+    #[test]
+    fn empty_codex_path_falls_back_to_default() {
+        assert_eq!(normalize_codex_command_input(None), "codex");
+        assert_eq!(
+            normalize_codex_command_input(Some("   ".to_string())),
+            "codex"
+        );
+    }
 
-    Ok(UsageData {
-        five_hour_percent_left: Some(100.0 - primary_used),
-        five_hour_reset_time_ms: Some(five_hour_reset_ms),
-        weekly_percent_left: Some(100.0 - secondary_used),
-        weekly_reset_time_ms: Some(weekly_reset_ms),
-        code_review_percent_left: None,
-        code_review_reset_time_ms: None,
-        last_updated,
-        source_file: Some(file_path.to_string_lossy().to_string()),
-    })
-}
+    #[test]
+    fn surrounding_quotes_are_removed_from_codex_path() {
+        assert_eq!(
+            normalize_codex_command_input(Some(
+                "\"C:\\Program Files\\OpenAI\\codex.exe\"".to_string(),
+            )),
+            r"C:\Program Files\OpenAI\codex.exe"
+        );
+        assert_eq!(
+            normalize_codex_command_input(Some(
+                "'C:\\Program Files\\OpenAI\\codex.exe'".to_string(),
+            )),
+            r"C:\Program Files\OpenAI\codex.exe"
+        );
+    }
 
-fn now_epoch_ms_string() -> String {
-    match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(duration) => duration.as_millis().to_string(),
-        Err(_) => "0".to_string(),
+    #[test]
+    fn timeout_seconds_have_reasonable_floor() {
+        assert_eq!(
+            normalize_login_timeout_seconds(None),
+            DEFAULT_LOGIN_TIMEOUT_SECONDS
+        );
+        assert_eq!(
+            normalize_login_timeout_seconds(Some(0)),
+            DEFAULT_LOGIN_TIMEOUT_SECONDS
+        );
+        assert_eq!(normalize_login_timeout_seconds(Some(5)), 30);
+        assert_eq!(normalize_login_timeout_seconds(Some(120)), 120);
     }
-}
 
-fn epoch_ms_from_system_time(time: SystemTime) -> Option<i64> {
-    time.duration_since(UNIX_EPOCH)
-        .ok()
-        .and_then(|d| i64::try_from(d.as_millis()).ok())
-}
+    #[test]
+    fn auth_change_detection_checks_content_and_mtime() {
+        let old = AuthSnapshot {
+            modified: None,
+            content: Some("old".to_string()),
+        };
+        let same = AuthSnapshot {
+            modified: None,
+            content: Some("old".to_string()),
+        };
+        let changed = AuthSnapshot {
+            modified: None,
+            content: Some("new".to_string()),
+        };
 
-fn normalize_unix_timestamp_ms(timestamp: i64) -> Result<i64, String> {
-    if timestamp <= 0 {
-        return Err("Invalid reset timestamp".to_string());
+        assert!(!auth_snapshot_changed(&old, &same));
+        assert!(auth_snapshot_changed(&old, &changed));
     }
 
-    let ms = if timestamp >= 1_000_000_000_000 {
-        timestamp
-    } else {
-        timestamp * 1000
-    };
-
-    if ms < MIN_VALID_EPOCH_MS || ms > MAX_VALID_EPOCH_MS {
-        return Err("Reset timestamp out of valid range".to_string());
+    #[test]
+    fn login_auth_validation_requires_id_token() {
+        assert!(validate_login_auth_json(&sample_auth_json("token")).is_ok());
+        assert!(validate_login_auth_json(&sample_auth_json("")).is_err());
+        assert!(validate_login_auth_json("{\"tokens\":{}}").is_err());
     }
 
-    Ok(ms)
-}
+    #[test]
+    fn direct_invocation_is_used_for_explicit_exe_path() {
+        let invocation = resolve_login_invocation(Some(r"C:\tools\codex.exe".to_string()))
+            .expect("should build invocation");
 
-fn validate_used_percent(value: f64) -> Result<f64, String> {
-    if value.is_nan() || value < 0.0 || value > 100.0 {
-        return Err("Invalid used_percent in rate_limits".to_string());
+        assert_eq!(invocation.program, r"C:\tools\codex.exe");
+        assert_eq!(invocation.args, vec!["login".to_string()]);
+        assert_eq!(invocation.mode, LoginCommandMode::Direct);
     }
-    Ok(value)
-}
 
-#[derive(Debug)]
-struct ParsedLimit {
-    percent_left: f64,
-    reset_time_ms: i64,
-    window_minutes: Option<u32>,
-}
+    #[test]
+    fn powershell_invocation_is_used_for_ps1_path() {
+        let invocation = resolve_login_invocation(Some(r"C:\tools\codex.ps1".to_string()))
+            .expect("should build invocation");
 
-#[derive(Debug, Default)]
-struct ParsedUsageLimits {
-    five_hour: Option<ParsedLimit>,
-    weekly: Option<ParsedLimit>,
-}
+        assert_eq!(invocation.program, "powershell");
+        assert_eq!(invocation.mode, LoginCommandMode::PowerShell);
+        assert!(invocation.args.iter().any(|arg| arg == "login"));
+    }
 
-fn json_to_f64(value: &serde_json::Value) -> Option<f64> {
-    value
-        .as_f64()
-        .or_else(|| value.as_i64().map(|v| v as f64))
-        .or_else(|| value.as_str().and_then(|s| s.parse::<f64>().ok()))
-}
+    #[test]
+    fn tray_close_behavior_defaults_to_ask() {
+        assert_eq!(normalize_tray_close_behavior(None), "ask");
+        assert_eq!(normalize_tray_close_behavior(Some("unknown")), "ask");
+        assert_eq!(normalize_tray_close_behavior(Some("tray")), "tray");
+        assert_eq!(normalize_tray_close_behavior(Some("exit")), "exit");
+    }
 
-fn json_to_i64(value: &serde_json::Value) -> Option<i64> {
-    value
-        .as_i64()
-        .or_else(|| value.as_u64().and_then(|v| i64::try_from(v).ok()))
-        .or_else(|| value.as_f64().map(|v| v.round() as i64))
-        .or_else(|| value.as_str().and_then(|s| s.parse::<i64>().ok()))
-}
+    #[test]
+    fn tray_account_title_includes_quota_summary() {
+        let account = TrayStoredAccount {
+            id: "1".to_string(),
+            alias: "测试账号".to_string(),
+            account_info: TrayAccountInfo {
+                email: "test@example.com".to_string(),
+                plan_type: "team".to_string(),
+                account_id: None,
+                user_id: None,
+                account_user_id: None,
+                account_structure: None,
+                workspace_name: Some("团队空间".to_string()),
+                subscription_active_until: Some("2026-04-26T13:24:00Z".to_string()),
+                organizations: None,
+            },
+            usage_info: Some(TrayUsageSummary {
+                status: Some("ok".to_string()),
+                message: None,
+                plan_type: Some("team".to_string()),
+                context_window: None,
+                five_hour_limit: Some(TrayLimitSummary {
+                    percent_left: 46.0,
+                    reset_time: "0".to_string(),
+                    reset_time_ms: None,
+                    burn_rate_per_hour: None,
+                    projected_exhaustion_ms: None,
+                }),
+                weekly_limit: Some(TrayLimitSummary {
+                    percent_left: 84.0,
+                    reset_time: "0".to_string(),
+                    reset_time_ms: None,
+                    burn_rate_per_hour: None,
+                    projected_exhaustion_ms: None,
+                }),
+                code_review_limit: None,
+                last_updated: Some("0".to_string()),
+                source_file: None,
+                next_retry_at_ms: None,
+                details: None,
+                source: None,
+                source_age_ms: None,
+                windows: None,
+            }),
+            is_active: true,
+            created_at: "0".to_string(),
+            updated_at: "0".to_string(),
+            group: None,
+            pinned: None,
+            sort_index: None,
+            proxy_url: None,
+        };
 
-fn extract_reset_time_ms(value: &serde_json::Value) -> Option<i64> {
-    let direct_fields = [
-        "reset_at_ms",
-        "resets_at_ms",
-        "reset_time_ms",
-        "reset_at",
-        "resets_at",
-        "reset",
-    ];
+        assert_eq!(
+            build_tray_account_title(&account),
+            "test@example.com / 团队空间"
+        );
+        assert_eq!(
+            build_tray_account_detail(&account),
+            "5H 46%  周 84%  审查 --  到期 2026-04-26"
+        );
+    }
 
-    for field in direct_fields.iter() {
-        if let Some(raw) = value.get(*field).and_then(json_to_i64) {
-            return Some(raw);
-        }
+    fn wham_usage_from(rate_limit: serde_json::Value) -> UsageData {
+        let payload =
+            WhamUsagePayload::from_value(&serde_json::json!({ "rate_limit": rate_limit }));
+        build_wham_usage_data(&payload, 1_700_000_000_000, &mut Vec::new())
+            .expect("should build usage")
     }
 
-    let now_ms = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .ok()
-        .and_then(|d| i64::try_from(d.as_millis()).ok())
-        .unwrap_or(0);
+    #[test]
+    fn wham_usage_supports_free_plan_weekly_only_window() {
+        let usage = wham_usage_from(serde_json::json!({
+            "allowed": true,
+            "limit_reached": false,
+            "primary_window": {
+                "used_percent": 0,
+                "limit_window_seconds": 604800,
+                "reset_after_seconds": 604800,
+                "reset_at": 1777128605
+            },
+            "secondary_window": null
+        }));
 
-    if let Some(seconds) = value.get("reset_in_seconds").and_then(json_to_i64) {
-        return Some(now_ms.saturating_add(seconds.saturating_mul(1000)));
+        assert!(usage.five_hour_percent_left.is_none());
+        assert_eq!(usage.weekly_percent_left, Some(100.0));
+        assert_eq!(usage.weekly_reset_time_ms, Some(1_777_128_605_000));
     }
 
-    if let Some(seconds) = value.get("reset_after_seconds").and_then(json_to_i64) {
-        return Some(now_ms.saturating_add(seconds.saturating_mul(1000)));
+    #[test]
+    fn wham_usage_keeps_dual_window_behavior() {
+        let usage = wham_usage_from(serde_json::json!({
+            "primary_window": {
+                "used_percent": 12,
+                "limit_window_seconds": 18000,
+                "reset_at": 1777000000
+            },
+            "secondary_window": {
+                "used_percent": 34,
+                "limit_window_seconds": 604800,
+                "reset_at": 1777600000
+            }
+        }));
+
+        assert_eq!(usage.five_hour_percent_left, Some(88.0));
+        assert_eq!(usage.weekly_percent_left, Some(66.0));
+        assert_eq!(usage.windows.len(), 2);
+
+        // 没有 limit_window_seconds 时按位置归入 5 小时 / 每周
+        let usage = wham_usage_from(serde_json::json!({
+            "primary_window": {"used_percent": 12, "reset_at": 1777000000},
+            "secondary_window": {"used_percent": 34, "reset_at": 1777600000}
+        }));
+        assert_eq!(usage.five_hour_percent_left, Some(88.0));
+        assert_eq!(usage.five_hour_reset_time_ms, Some(1_777_000_000_000));
+        assert_eq!(usage.weekly_percent_left, Some(66.0));
+        assert!(usage.windows.iter().all(UsageWindow::is_legacy_limit));
+
+        let payload = WhamUsagePayload::from_value(&serde_json::json!({ "plan_type": "plus" }));
+        assert_eq!(
+            build_wham_usage_data(&payload, 0, &mut Vec::new()).unwrap_err(),
+            "Missing rate_limit in response"
+        );
     }
 
-    if let Some(seconds) = value.get("reset_in").and_then(json_to_i64) {
-        return Some(now_ms.saturating_add(seconds.saturating_mul(1000)));
+    #[test]
+    fn wham_usage_accepts_legacy_shapes_and_lenient_numbers() {
+        // rate_limits 键、primary / secondary 与 0~1 小数形式的 used_percent
+        let payload = WhamUsagePayload::from_value(&serde_json::json!({
+            "rate_limits": {
+                "primary": {"used_percent": 0.25, "window_minutes": 300, "resets_at": 1777000000.0},
+                "secondary": {"used": "30", "limit": 120, "limit_window_seconds": 604800.0, "reset_in_seconds": 60}
+            }
+        }));
+        let usage = build_wham_usage_data(&payload, 1_700_000_000_000, &mut Vec::new()).unwrap();
+        assert_eq!(usage.five_hour_percent_left, Some(75.0));
+        assert_eq!(usage.five_hour_reset_time_ms, Some(1_777_000_000_000));
+        assert_eq!(usage.weekly_percent_left, Some(75.0));
+        assert_eq!(usage.weekly_reset_time_ms, Some(1_700_000_060_000));
+        assert_eq!(usage.windows[0].key, "rate_limit.primary_window");
+
+        // limits 数组与 remaining / limit，无法解析的窗口跳过并记录原因
+        let payload = WhamUsagePayload::from_value(&serde_json::json!({
+            "rate_limit": {"limits": [
+                {"remaining": 40, "limit": 50, "window_seconds": 18000, "reset_at": 1777000000},
+                {"used_percent": 10},
+                {"used_percent": 50, "window_minutes": 1440, "reset_at": 1777000000}
+            ]}
+        }));
+        let mut skipped = Vec::new();
+        let usage = build_wham_usage_data(&payload, 0, &mut skipped).unwrap();
+        assert_eq!(usage.five_hour_percent_left, Some(80.0));
+        assert_eq!(
+            usage
+                .windows
+                .iter()
+                .map(|window| window.key.as_str())
+                .collect::<Vec<_>>(),
+            ["rate_limit.primary_window", "rate_limit.limits[2]"]
+        );
+        assert_eq!(
+            skipped,
+            ["rate_limit.secondary_window: Missing reset timestamp"]
+        );
     }
 
-    None
-}
-
-fn parse_rate_limit_entry(value: &serde_json::Value) -> Result<ParsedLimit, String> {
-    let used_percent = value
-        .get("used_percent")
-        .or_else(|| value.get("usedPercent"))
-        .and_then(json_to_f64);
-    let used = value.get("used").and_then(json_to_f64);
-    let remaining = value.get("remaining").and_then(json_to_f64);
-    let limit = value
-        .get("limit")
-        .or_else(|| value.get("total"))
-        .or_else(|| value.get("capacity"))
-        .and_then(json_to_f64);
-
-    let percent_left = if let Some(used_percent) = used_percent {
-        let used_norm = if used_percent <= 1.0 && used_percent.fract() != 0.0 {
-            used_percent * 100.0
-        } else {
-            used_percent
-        };
-        100.0 - used_norm
-    } else if let (Some(remaining), Some(limit)) = (remaining, limit) {
-        if limit <= 0.0 {
-            return Err("Invalid limit value".to_string());
-        }
-        (remaining / limit) * 100.0
-    } else if let (Some(used), Some(limit)) = (used, limit) {
-        if limit <= 0.0 {
-            return Err("Invalid limit value".to_string());
-        }
-        100.0 - (used / limit) * 100.0
-    } else {
-        return Err("Missing usage fields in rate_limit entry".to_string());
-    };
-
-    let raw_reset =
-        extract_reset_time_ms(value).ok_or_else(|| "Missing reset timestamp".to_string())?;
-    let reset_time_ms = normalize_unix_timestamp_ms(raw_reset)?;
-
-    let window_minutes = value
-        .get("window_minutes")
-        .and_then(json_to_i64)
-        .and_then(|v| u32::try_from(v).ok())
-        .or_else(|| {
-            value
-                .get("window_seconds")
-                .and_then(json_to_i64)
-                .and_then(|v| u32::try_from(v / 60).ok())
-        })
-        .or_else(|| {
-            value
-                .get("limit_window_seconds")
-                .and_then(json_to_i64)
-                .and_then(|v| u32::try_from(v / 60).ok())
-        });
-
-    Ok(ParsedLimit {
-        percent_left: percent_left.clamp(0.0, 100.0),
-        reset_time_ms,
-        window_minutes,
-    })
-}
+    #[test]
+    fn wham_payload_ignores_mistyped_detail_fields() {
+        let payload = WhamUsagePayload::from_value(&serde_json::json!({
+            "plan_type": "plus",
+            "rate_limit": {
+                "allowed": "yes",
+                "limit_reached": false,
+                "primary_window": {"used_percent": 20, "limit_window_seconds": 18000, "reset_at": 1777000000}
+            },
+            "rate_limit_reached_type": 3,
+            "promo": "spring",
+            "credits": {"has_credits": true, "balance": 4},
+            "additional_rate_limits": [
+                {"limit_name": 7},
+                {"limit_name": "mini", "rate_limit": {"primary_window": {"used_percent": "40", "reset_at": 1777000000.5}}}
+            ]
+        }));
+        assert_eq!(payload.plan_type.as_deref(), Some("plus"));
+        assert_eq!(payload.allowed, None);
+        assert_eq!(payload.limit_reached, Some(false));
+        assert_eq!(payload.rate_limit_reached_type, None);
+        assert_eq!(payload.promo, None);
+        assert_eq!(payload.mismatches.len(), 4);
+
+        let usage = build_wham_usage_data(&payload, 0, &mut Vec::new()).unwrap();
+        assert_eq!(usage.five_hour_percent_left, Some(80.0));
+        assert_eq!(usage.windows.len(), 2);
+        assert_eq!(usage.windows[1].label, "mini · 额度");
+        let details = usage.details.unwrap();
+        assert_eq!(
+            details
+                .credits
+                .and_then(|credits| credits.balance)
+                .as_deref(),
+            Some("4")
+        );
+        assert_eq!(details.additional_rate_limits.len(), 1);
+        assert_eq!(
+            details.additional_rate_limits[0]
+                .rate_limit
+                .as_ref()
+                .and_then(|status| status.primary_window.as_ref())
+                .and_then(|window| window.used_percent),
+            Some(40.0)
+        );
+    }
 
-#[derive(Debug, PartialEq, Eq)]
-enum LimitKind {
-    FiveHour,
-    Weekly,
-}
+    #[test]
+    fn tray_account_detail_skips_missing_five_hour_limit() {
+        let account = TrayStoredAccount {
+            id: "1".to_string(),
+            alias: "免费账号".to_string(),
+            account_info: TrayAccountInfo {
+                email: "free@example.com".to_string(),
+                plan_type: "free".to_string(),
+                account_id: None,
+                user_id: None,
+                account_user_id: None,
+                account_structure: None,
+                workspace_name: None,
+                subscription_active_until: None,
+                organizations: None,
+            },
+            usage_info: Some(TrayUsageSummary {
+                status: Some("ok".to_string()),
+                message: None,
+                plan_type: Some("free".to_string()),
+                context_window: None,
+                five_hour_limit: None,
+                weekly_limit: Some(TrayLimitSummary {
+                    percent_left: 100.0,
+                    reset_time: "04-24 10:10".to_string(),
+                    reset_time_ms: None,
+                    burn_rate_per_hour: None,
+                    projected_exhaustion_ms: None,
+                }),
+                code_review_limit: Some(TrayLimitSummary {
+                    percent_left: 100.0,
+                    reset_time: "10:10".to_string(),
+                    reset_time_ms: None,
+                    burn_rate_per_hour: None,
+                    projected_exhaustion_ms: None,
+                }),
+                last_updated: Some("0".to_string()),
+                source_file: None,
+                next_retry_at_ms: None,
+                details: None,
+                source: None,
+                source_age_ms: None,
+                windows: None,
+            }),
+            is_active: false,
+            created_at: "0".to_string(),
+            updated_at: "0".to_string(),
+            group: None,
+            pinned: None,
+            sort_index: None,
+            proxy_url: None,
+        };
 
-fn detect_limit_kind(value: &serde_json::Value, window_minutes: Option<u32>) -> Option<LimitKind> {
-    if let Some(kind) = value
-        .get("type")
-        .and_then(|v| v.as_str())
-        .or_else(|| value.get("name").and_then(|v| v.as_str()))
-    {
-        let kind_lower = kind.to_lowercase();
-        if kind_lower.contains("week") {
-            return Some(LimitKind::Weekly);
-        }
-        if kind_lower.contains("five") || kind_lower.contains("5h") || kind_lower.contains("hour") {
-            return Some(LimitKind::FiveHour);
-        }
+        assert_eq!(
+            build_tray_account_detail(&account),
+            "周 100%  审查 100%  到期 --"
+        );
     }
 
-    if let Some(minutes) = window_minutes {
-        if minutes <= 360 {
-            return Some(LimitKind::FiveHour);
-        }
-        if minutes >= 10080 {
-            return Some(LimitKind::Weekly);
+    fn sample_usage_account(
+        id: &str,
+        status: &str,
+        five_hour: Option<f64>,
+        weekly: Option<f64>,
+    ) -> TrayStoredAccount {
+        TrayStoredAccount {
+            id: id.to_string(),
+            alias: id.to_string(),
+            account_info: TrayAccountInfo {
+                email: format!("{}@example.com", id),
+                plan_type: "plus".to_string(),
+                ..Default::default()
+            },
+            usage_info: Some(TrayUsageSummary {
+                status: Some(status.to_string()),
+                five_hour_limit: five_hour.map(|percent_left| TrayLimitSummary {
+                    percent_left,
+                    reset_time: "10:00".to_string(),
+                    reset_time_ms: None,
+                    burn_rate_per_hour: None,
+                    projected_exhaustion_ms: None,
+                }),
+                weekly_limit: weekly.map(|percent_left| TrayLimitSummary {
+                    percent_left,
+                    reset_time: "04-24 10:00".to_string(),
+                    reset_time_ms: None,
+                    burn_rate_per_hour: None,
+                    projected_exhaustion_ms: None,
+                }),
+                ..Default::default()
+            }),
+            is_active: false,
+            created_at: "0".to_string(),
+            updated_at: "0".to_string(),
+            group: None,
+            pinned: None,
+            sort_index: None,
+            proxy_url: None,
         }
     }
 
-    None
-}
+    #[test]
+    fn global_shortcut_bindings_skip_blank_accelerators() {
+        let shortcuts = TrayGlobalShortcuts {
+            next_account: Some("CommandOrControl+Alt+Right".to_string()),
+            previous_account: Some("   ".to_string()),
+            recommended_account: None,
+            open_window: Some(" CommandOrControl+Alt+M ".to_string()),
+            refresh_all: None,
+        };
 
-fn assign_parsed_limit(
-    limits: &mut ParsedUsageLimits,
-    entry: &serde_json::Value,
-) -> Result<(), String> {
-    let parsed = parse_rate_limit_entry(entry)?;
-    match detect_limit_kind(entry, parsed.window_minutes) {
-        Some(LimitKind::FiveHour) => limits.five_hour = Some(parsed),
-        Some(LimitKind::Weekly) => limits.weekly = Some(parsed),
-        None => {
-            if limits.five_hour.is_none() {
-                limits.five_hour = Some(parsed);
-            } else if limits.weekly.is_none() {
-                limits.weekly = Some(parsed);
-            }
-        }
+        assert!(collect_global_shortcut_bindings(None).is_empty());
+        assert_eq!(
+            collect_global_shortcut_bindings(Some(&shortcuts)),
+            vec![
+                (
+                    GlobalShortcutAction::NextAccount,
+                    "CommandOrControl+Alt+Right".to_string()
+                ),
+                (
+                    GlobalShortcutAction::OpenWindow,
+                    "CommandOrControl+Alt+M".to_string()
+                ),
+            ]
+        );
     }
 
-    Ok(())
-}
-
-fn parse_rate_limits(value: &serde_json::Value) -> Result<ParsedUsageLimits, String> {
-    let mut limits = ParsedUsageLimits::default();
-    let mut handled_explicit_entries = false;
-
-    for key in ["primary", "secondary", "primary_window", "secondary_window"] {
-        if let Some(entry) = value.get(key).filter(|entry| !entry.is_null()) {
-            handled_explicit_entries = true;
-            assign_parsed_limit(&mut limits, entry)?;
-        }
+    #[test]
+    fn account_cycling_wraps_in_both_directions() {
+        assert_eq!(cycle_account_index(0, None, true), None);
+        assert_eq!(cycle_account_index(3, None, true), Some(0));
+        assert_eq!(cycle_account_index(3, None, false), Some(2));
+        assert_eq!(cycle_account_index(3, Some(2), true), Some(0));
+        assert_eq!(cycle_account_index(3, Some(0), false), Some(2));
     }
 
-    if !handled_explicit_entries {
-        let entries = value
-            .get("limits")
-            .and_then(|v| v.as_array())
-            .cloned()
-            .or_else(|| value.as_array().cloned())
-            .ok_or_else(|| "Missing rate_limit entries".to_string())?;
+    #[test]
+    fn recommended_account_prefers_highest_remaining_quota() {
+        let accounts = vec![
+            sample_usage_account("low", "ok", Some(10.0), Some(90.0)),
+            sample_usage_account("banned", "forbidden", Some(100.0), Some(100.0)),
+            sample_usage_account("best", "ok", Some(60.0), Some(70.0)),
+            sample_usage_account("weekly-only", "ok", None, Some(50.0)),
+        ];
 
-        for entry in entries.iter() {
-            assign_parsed_limit(&mut limits, entry)?;
-        }
+        assert_eq!(
+            find_recommended_account_id(&accounts).as_deref(),
+            Some("best")
+        );
+        assert_eq!(find_recommended_account_id(&accounts[1..2]), None);
     }
 
-    if limits.five_hour.is_none() && limits.weekly.is_none() {
-        return Err("Missing usable rate_limit data".to_string());
-    }
+    #[test]
+    fn tray_layout_puts_pinned_first_and_groups_into_submenus() {
+        let mut pinned = sample_usage_account("pinned", "ok", Some(20.0), Some(20.0));
+        pinned.pinned = Some(true);
+        pinned.group = Some("工作".to_string());
+        let mut work = sample_usage_account("work", "ok", Some(80.0), Some(80.0));
+        work.group = Some("工作".to_string());
+        let mut expired = sample_usage_account("expired", "expired", None, None);
+        expired.group = Some(" ".to_string());
+        let accounts = vec![
+            sample_usage_account("low", "ok", Some(10.0), Some(10.0)),
+            work,
+            pinned,
+            expired,
+            sample_usage_account("high", "ok", Some(90.0), Some(95.0)),
+        ];
+        let config = TrayAppConfig {
+            tray_sort_mode: Some("remaining".to_string()),
+            tray_hide_unavailable: Some(true),
+            ..Default::default()
+        };
 
-    Ok(limits)
-}
+        let layout = layout_tray_accounts(&accounts, &config);
+        let top_level: Vec<&str> = layout
+            .top_level
+            .iter()
+            .map(|account| account.id.as_str())
+            .collect();
 
-fn parse_optional_rate_limit(value: &serde_json::Value) -> Option<ParsedLimit> {
-    if let Some(primary) = value.get("primary") {
-        return parse_rate_limit_entry(primary).ok();
+        assert_eq!(top_level, vec!["pinned", "high", "low"]);
+        assert_eq!(layout.groups.len(), 1);
+        assert_eq!(layout.groups[0].0, "工作");
+        assert_eq!(layout.groups[0].1[0].id, "work");
     }
 
-    if let Some(primary) = value.get("primary_window") {
-        return parse_rate_limit_entry(primary).ok();
-    }
+    #[test]
+    fn tray_manual_sort_uses_sort_index_then_insertion_order() {
+        let mut second = sample_usage_account("second", "ok", None, None);
+        second.sort_index = Some(2);
+        let mut first = sample_usage_account("first", "ok", None, None);
+        first.sort_index = Some(1);
+        let accounts = vec![
+            sample_usage_account("unordered", "ok", None, None),
+            second,
+            first,
+        ];
+
+        let sorted: Vec<&str> = sort_tray_accounts(&accounts, "manual")
+            .iter()
+            .map(|account| account.id.as_str())
+            .collect();
 
-    if let Some(entries) = value.get("limits").and_then(|v| v.as_array()) {
-        return entries
-            .first()
-            .and_then(|entry| parse_rate_limit_entry(entry).ok());
+        assert_eq!(sorted, vec!["first", "second", "unordered"]);
+        assert_eq!(normalize_tray_sort_mode(Some("bogus")), "manual");
     }
 
-    if let Some(entries) = value.as_array() {
-        return entries
-            .first()
-            .and_then(|entry| parse_rate_limit_entry(entry).ok());
-    }
+    #[test]
+    fn tray_settings_update_config() {
+        let mut config = TrayAppConfig {
+            close_behavior: Some("ask".to_string()),
+            auto_refresh_interval: Some(30),
+            proxy_url: Some("http://127.0.0.1:7890".to_string()),
+            ..Default::default()
+        };
 
-    parse_rate_limit_entry(value).ok()
-}
+        assert_eq!(apply_tray_setting(&mut config, "close:tray"), Ok(true));
+        assert_eq!(config.close_behavior.as_deref(), Some("tray"));
+        assert_eq!(apply_tray_setting(&mut config, "close:tray"), Ok(false));
+        assert!(apply_tray_setting(&mut config, "close:bogus").is_err());
 
-fn parse_session_meta(file_path: &PathBuf) -> Result<(String, String), String> {
-    let file = fs::File::open(file_path).map_err(|e| format!("Failed to open file: {}", e))?;
-    let reader = BufReader::new(file);
+        assert_eq!(apply_tray_setting(&mut config, "interval:15"), Ok(true));
+        assert_eq!(config.auto_refresh_interval, Some(15));
+        assert!(apply_tray_setting(&mut config, "interval:abc").is_err());
 
-    for line in reader.lines() {
-        let line = match line {
-            Ok(l) => l,
-            Err(_) => continue,
-        };
+        assert_eq!(apply_tray_setting(&mut config, "proxy"), Ok(true));
+        assert_eq!(config.proxy_enabled, Some(true));
+        assert_eq!(apply_tray_setting(&mut config, "auto-restart"), Ok(true));
+        assert_eq!(config.auto_restart_codex_on_switch, Some(true));
 
-        if line.is_empty() {
-            continue;
-        }
+        assert_eq!(apply_tray_setting(&mut config, "sort:remaining"), Ok(true));
+        assert_eq!(config.tray_sort_mode.as_deref(), Some("remaining"));
+        assert_eq!(apply_tray_setting(&mut config, "sort:remaining"), Ok(false));
+        assert!(apply_tray_setting(&mut config, "sort:bogus").is_err());
+        assert_eq!(
+            apply_tray_setting(&mut config, "hide-unavailable"),
+            Ok(true)
+        );
+        assert_eq!(config.tray_hide_unavailable, Some(true));
+    }
 
-        let value: serde_json::Value = match serde_json::from_str(&line) {
-            Ok(v) => v,
-            Err(_) => continue,
+    #[test]
+    fn tray_proxy_toggle_requires_proxy_url() {
+        let mut config = TrayAppConfig {
+            proxy_url: Some("  ".to_string()),
+            ..Default::default()
         };
 
-        if value.get("type").and_then(|v| v.as_str()) == Some("session_meta") {
-            let payload = value
-                .get("payload")
-                .ok_or_else(|| "Missing session payload".to_string())?;
-            let session_id = payload
-                .get("id")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| "Missing session id".to_string())?;
-            let created_at = payload
-                .get("timestamp")
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-            return Ok((session_id.to_string(), created_at));
-        }
+        assert!(apply_tray_setting(&mut config, "proxy").is_err());
+        assert_eq!(config.proxy_enabled, None);
     }
 
-    Err("No session_meta found".to_string())
-}
+    #[test]
+    fn reset_countdown_is_compact_and_rounds_up() {
+        assert_eq!(format_reset_countdown(30_000), "1m");
+        assert_eq!(format_reset_countdown(72 * 60_000), "1h12m");
+        assert_eq!(
+            format_reset_countdown((3 * 1_440 + 4 * 60) * 60_000),
+            "3d4h"
+        );
+        assert_eq!(format_reset_countdown(-5), "0m");
+    }
 
-fn bind_session_file_to_account(account_id: &str, file_path: &PathBuf) -> Result<(), String> {
-    let (session_id, created_at) = parse_session_meta(file_path).or_else(|_| {
-        let fallback = fs::metadata(file_path)
-            .and_then(|m| m.modified())
-            .ok()
-            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
-            .map(|d| d.as_secs().to_string())
-            .unwrap_or_else(|| "0".to_string());
-        Ok::<(String, String), String>((file_path.to_string_lossy().to_string(), fallback))
-    })?;
+    #[test]
+    fn tray_account_detail_shows_countdown_and_pending_marker() {
+        let now_ms = 1_777_000_000_000;
+        let mut account = sample_usage_account("1", "ok", Some(46.0), Some(84.0));
+        let usage = account.usage_info.as_mut().expect("usage should exist");
+        usage.five_hour_limit.as_mut().unwrap().reset_time_ms = Some(now_ms + 72 * 60_000);
+        usage.weekly_limit.as_mut().unwrap().reset_time_ms = Some(now_ms - 1);
 
-    let binding = SessionBinding {
-        session_id,
-        created_at,
-        file_path: file_path.to_string_lossy().to_string(),
-        bound_at: now_epoch_ms_string(),
-    };
+        assert_eq!(
+            build_tray_account_detail_at(&account, now_ms),
+            "5H 46% · 1h12m后重置  周 已重置·待刷新  审查 --  到期 --"
+        );
+    }
 
-    update_usage_bindings(account_id, binding)
-}
+    #[test]
+    fn popover_opens_above_bottom_taskbar_and_stays_on_screen() {
+        let work_area = (0.0, 0.0, 1920.0, 1040.0);
 
-fn bind_session_file_to_current_auth(file_path: &PathBuf) -> Result<(), String> {
-    let account_id = get_current_auth_account_id()?;
-    bind_session_file_to_account(&account_id, file_path)
-}
+        assert_eq!(
+            compute_popover_position((1800.0, 1060.0), (340.0, 460.0), work_area),
+            (1580.0, 580.0)
+        );
+        assert_eq!(
+            compute_popover_position((100.0, 10.0), (340.0, 460.0), work_area),
+            (0.0, 18.0)
+        );
+    }
 
-/// 获取账号的用量信息（通过解析本地 session 日志）
-#[tauri::command]
-fn get_usage_from_sessions() -> Result<UsageData, String> {
-    let latest_file = find_latest_session_file()?;
-    parse_rate_limits_from_file(&latest_file)
-}
+    #[test]
+    fn refresh_concurrency_and_timeouts_have_sane_bounds() {
+        assert_eq!(
+            normalize_refresh_concurrency(None),
+            DEFAULT_REFRESH_CONCURRENCY
+        );
+        assert_eq!(
+            normalize_refresh_concurrency(Some(0)),
+            DEFAULT_REFRESH_CONCURRENCY
+        );
+        assert_eq!(normalize_refresh_concurrency(Some(2)), 2);
+        assert_eq!(
+            normalize_refresh_concurrency(Some(1_000)),
+            MAX_REFRESH_CONCURRENCY
+        );
 
-/// 获取绑定账号的用量信息
-#[tauri::command]
-fn get_bound_usage(account_id: String) -> Result<UsageData, String> {
-    if account_id.is_empty() {
-        return Err("Missing account id".to_string());
+        let timeouts = resolve_http_timeouts(&TrayAppConfig {
+            request_connect_timeout_seconds: Some(5),
+            request_read_timeout_seconds: Some(10_000),
+            ..Default::default()
+        });
+        assert_eq!(timeouts.connect, Duration::from_secs(5));
+        assert_eq!(
+            timeouts.read,
+            Duration::from_secs(MAX_REQUEST_TIMEOUT_SECONDS)
+        );
+        assert_eq!(
+            resolve_http_timeouts(&TrayAppConfig::default()).connect,
+            Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECONDS)
+        );
+        assert_eq!(usage_refresh_deadline(&timeouts), Duration::from_secs(925));
     }
 
-    let path = get_latest_bound_session_path(&account_id)?;
-    let mut data = parse_rate_limits_from_file(&path)?;
-    data.source_file = Some(path.to_string_lossy().to_string());
-    Ok(data)
-}
+    #[test]
+    fn background_auto_refresh_runs_immediately_and_respects_interval() {
+        let account = sample_usage_account("idle", "ok", Some(50.0), Some(80.0));
+        let state = AccountRefreshState {
+            account_id: "idle".to_string(),
+            last_attempt_ms: 60_000,
+            ..Default::default()
+        };
 
-/// 通过 wham/usage API 获取 Codex quota
-#[tauri::command]
-async fn get_codex_wham_usage(
-    account_id: String,
-    proxy_enabled: Option<bool>,
-    proxy_url: Option<String>,
-) -> Result<UsageResult, String> {
-    if account_id.is_empty() {
-        return Ok(UsageResult {
-            status: "missing_account_id".to_string(),
-            message: Some("\u{7f3a}\u{5c11} ChatGPT account ID".to_string()),
-            plan_type: None,
-            usage: None,
-        });
+        let initial = plan_account_refresh(&account, None, 30, 1);
+        assert_eq!(initial.next_refresh_at_ms, Some(1));
+        assert_eq!(
+            plan_account_refresh(&account, None, 0, 1).next_refresh_at_ms,
+            None
+        );
+
+        let idle = plan_account_refresh(&account, Some(&state), 30, 1_800_000);
+        assert_eq!(idle.reason, "idle");
+        assert_eq!(idle.next_refresh_at_ms, Some(1_860_000));
     }
 
-    let auth_json = read_account_auth(account_id)?;
-    let auth: AuthConfig = serde_json::from_str(&auth_json).map_err(|e| e.to_string())?;
-    let tokens = match auth.tokens {
-        Some(tokens) => tokens,
-        None => {
-            return Ok(UsageResult {
-                status: "missing_token".to_string(),
-                message: Some("\u{7f3a}\u{5c11} access token".to_string()),
-                plan_type: None,
-                usage: None,
-            })
-        }
-    };
+    #[test]
+    fn adaptive_refresh_prioritizes_active_resets_and_backs_off_errors() {
+        let state = AccountRefreshState {
+            account_id: "acc".to_string(),
+            last_attempt_ms: 60_000,
+            ..Default::default()
+        };
 
-    let access_token =
-        match take_non_empty_token(tokens.access_token, "\u{7f3a}\u{5c11} access token") {
-            Ok(value) => value,
-            Err(message) => {
-                return Ok(UsageResult {
-                    status: "missing_token".to_string(),
-                    message: Some(message),
-                    plan_type: None,
-                    usage: None,
-                })
-            }
+        let mut active = sample_usage_account("acc", "ok", Some(50.0), Some(80.0));
+        active.is_active = true;
+        let plan = plan_account_refresh(&active, Some(&state), 30, 60_000);
+        assert_eq!(plan.reason, "active");
+        assert_eq!(plan.next_refresh_at_ms, Some(660_000));
+
+        let mut resetting = sample_usage_account("acc", "ok", Some(0.0), Some(80.0));
+        resetting
+            .usage_info
+            .as_mut()
+            .and_then(|usage| usage.five_hour_limit.as_mut())
+            .unwrap()
+            .reset_time_ms = Some(300_000);
+        let plan = plan_account_refresh(&resetting, Some(&state), 30, 60_000);
+        assert_eq!(plan.reason, "reset");
+        assert_eq!(
+            plan.next_refresh_at_ms,
+            Some(300_000 + RESET_REFRESH_GRACE_MS)
+        );
+
+        // 失败后只按熔断冷却时间重试，未进入冷却的失败沿用常规间隔
+        let cooling_down = AccountRefreshState {
+            consecutive_failures: 2,
+            cooldown_until_ms: Some(120_000),
+            ..state.clone()
         };
-    let chatgpt_account_id =
-        match take_non_empty_token(tokens.account_id, "\u{7f3a}\u{5c11} ChatGPT account ID") {
-            Ok(value) => value,
-            Err(message) => {
-                return Ok(UsageResult {
-                    status: "missing_account_id".to_string(),
-                    message: Some(message),
-                    plan_type: None,
-                    usage: None,
-                })
-            }
+        let plan = plan_account_refresh(&resetting, Some(&cooling_down), 30, 60_000);
+        assert_eq!(plan.reason, "backoff");
+        assert_eq!(plan.next_refresh_at_ms, Some(120_000));
+        let needs_login = AccountRefreshState {
+            attention_status: Some("stale_token".to_string()),
+            ..cooling_down.clone()
         };
-    let is_current_account = is_current_chatgpt_account(&chatgpt_account_id);
-
-    let client = match build_http_client(proxy_enabled, proxy_url) {
-        Ok(client) => client,
-        Err(message) => {
-            return Ok(UsageResult {
-                status: "error".to_string(),
-                message: Some(message),
-                plan_type: None,
-                usage: None,
-            })
-        }
-    };
+        let plan = plan_account_refresh(&resetting, Some(&needs_login), 30, 60_000);
+        assert_eq!(plan.reason, "attention");
+        assert_eq!(plan.status.as_deref(), Some("stale_token"));
+        assert_eq!(plan.next_refresh_at_ms, Some(120_000));
+        let failed_once = AccountRefreshState {
+            consecutive_failures: 1,
+            ..state.clone()
+        };
+        let plan = plan_account_refresh(&active, Some(&failed_once), 30, 60_000);
+        assert_eq!(plan.reason, "active");
+        assert_eq!(plan.next_refresh_at_ms, Some(660_000));
+    }
 
-    let send_request = || {
-        client
-            .get("https://chatgpt.com/backend-api/wham/usage")
-            .header("Authorization", format!("Bearer {}", access_token))
-            .header("Accept", "application/json")
-            .header("ChatGPT-Account-Id", &chatgpt_account_id)
-            .send()
-    };
+    #[test]
+    fn usage_history_entries_become_window_series_and_respect_retention() {
+        let result = UsageResult {
+            status: "ok".to_string(),
+            message: None,
+            plan_type: Some("plus".to_string()),
+            next_retry_at_ms: None,
+            usage: Some(UsageData {
+                five_hour_percent_left: Some(40.0),
+                five_hour_reset_time_ms: Some(5_000),
+                weekly_percent_left: Some(75.0),
+                weekly_reset_time_ms: None,
+                code_review_percent_left: None,
+                code_review_reset_time_ms: None,
+                five_hour_burn_rate_per_hour: None,
+                five_hour_projected_exhaustion_ms: None,
+                weekly_burn_rate_per_hour: None,
+                weekly_projected_exhaustion_ms: None,
+                last_updated: "0".to_string(),
+                source_file: None,
+                details: None,
+                source: None,
+                age_ms: None,
+                windows: Vec::new(),
+                context_window: None,
+            }),
+        };
+        let entry = build_usage_history_entry("acc", &result, 1_000);
+        assert_eq!(entry.windows.len(), 2);
 
-    let response = match send_request().await {
-        Ok(resp) => resp,
-        Err(first_err) => {
-            log::warn!("wham/usage \u{9996}\u{6b21}\u{8bf7}\u{6c42}\u{5931}\u{8d25}\u{ff0c}1 \u{79d2}\u{540e}\u{91cd}\u{8bd5}: {}", first_err);
-            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-            match send_request().await {
-                Ok(resp) => resp,
-                Err(retry_err) => {
-                    return Ok(UsageResult {
-                        status: "error".to_string(),
-                        message: Some(format!("\u{8bf7}\u{6c42}\u{5931}\u{8d25}\u{ff08}\u{5df2}\u{91cd}\u{8bd5}\u{ff09}: {}", retry_err)),
-                        plan_type: None,
-                        usage: None,
-                    })
+        let failed = UsageResult {
+            status: "forbidden".to_string(),
+            message: None,
+            plan_type: None,
+            next_retry_at_ms: None,
+            usage: None,
+        };
+        assert_eq!(entry.source, None);
+        let mut session_entry = entry.clone();
+        session_entry.recorded_at_ms = 800;
+        session_entry.windows[0].percent_left = 45.0;
+        session_entry.source = Some(USAGE_SOURCE_SESSION.to_string());
+        let entries = vec![
+            entry,
+            build_usage_history_entry("acc", &failed, 2_000),
+            build_usage_history_entry("other", &result, 3_000),
+            session_entry,
+        ];
+
+        let filtered = filter_usage_history(entries, Some("acc"), Some(500), None);
+        assert_eq!(filtered.len(), 3);
+        // 后写入的 session 采样按采样时间排在前面
+        assert_eq!(
+            build_usage_history_series(&filtered, "fiveHour"),
+            vec![
+                UsageHistoryPoint {
+                    recorded_at_ms: 800,
+                    percent_left: 45.0,
+                    reset_time_ms: Some(5_000),
+                },
+                UsageHistoryPoint {
+                    recorded_at_ms: 1_000,
+                    percent_left: 40.0,
+                    reset_time_ms: Some(5_000),
                 }
-            }
-        }
-    };
+            ]
+        );
 
-    let status = response.status();
-    let body = response.text().await.map_err(|e| e.to_string())?;
+        assert_eq!(usage_history_cutoff_ms(0, 10), None);
+        assert_eq!(
+            usage_history_cutoff_ms(1, 100_000_000),
+            Some(100_000_000 - 86_400_000)
+        );
+    }
 
-    if status == reqwest::StatusCode::UNAUTHORIZED {
-        let (status, message) = if is_current_account {
-            (
-                "expired".to_string(),
-                "\u{5f53}\u{524d}\u{8d26}\u{53f7}\u{767b}\u{5f55}\u{6001}\u{5df2}\u{5931}\u{6548}\u{ff0c}\u{8bf7}\u{91cd}\u{65b0}\u{767b}\u{5f55} Codex".to_string(),
-            )
-        } else {
-            (
-                "stale_token".to_string(),
-                "\u{8be5}\u{8d26}\u{53f7}\u{7f13}\u{5b58}\u{7684} access token \u{5df2}\u{5931}\u{6548}\u{ff0c}\u{8bf7}\u{5207}\u{6362}\u{5230}\u{8be5}\u{8d26}\u{53f7}\u{5e76}\u{91cd}\u{65b0}\u{5b8c}\u{6210}\u{4e00}\u{6b21} Codex \u{767b}\u{5f55}".to_string(),
-            )
+    #[test]
+    fn forecast_history_keeps_only_the_lookback_window() {
+        let entry = |account_id: &str, recorded_at_ms: u64| UsageHistoryEntry {
+            account_id: account_id.to_string(),
+            recorded_at_ms,
+            status: "ok".to_string(),
+            plan_type: None,
+            windows: Vec::new(),
+            source: None,
         };
+        let now_ms = FORECAST_LOOKBACK_MS + 10_000;
+        let mut history = HashMap::new();
+        push_forecast_history(&mut history, entry("a", 5_000), now_ms);
+        push_forecast_history(&mut history, entry("a", 20_000), now_ms);
+        push_forecast_history(&mut history, entry("b", 30_000), now_ms);
+        assert_eq!(history["a"], vec![entry("a", 20_000)]);
+
+        push_forecast_history(&mut history, entry("a", now_ms), now_ms + 15_000);
+        assert_eq!(history["a"], vec![entry("a", now_ms)]);
+        assert_eq!(history["b"], vec![entry("b", 30_000)]);
+    }
 
-        return Ok(UsageResult {
-            status,
-            message: Some(message),
-            plan_type: None,
-            usage: None,
-        });
+    #[test]
+    fn session_history_samples_are_throttled_per_window() {
+        let limits = |resets_at: i64| RateLimits {
+            primary: Some(RateLimitEntry {
+                used_percent: 10.0,
+                window_minutes: 300,
+                resets_at,
+            }),
+            secondary: None,
+        };
+        let first = SessionHistorySample::new(&limits(1_000), 0);
+        assert!(first.is_due_after(None));
+        assert!(!SessionHistorySample::new(&limits(1_000), 60_000).is_due_after(Some(&first)));
+        assert!(
+            SessionHistorySample::new(&limits(1_000), SESSION_HISTORY_SAMPLE_INTERVAL_MS)
+                .is_due_after(Some(&first))
+        );
+        // 窗口重置后立即采样
+        assert!(SessionHistorySample::new(&limits(9_000), 60_000).is_due_after(Some(&first)));
+    }
+
+    #[test]
+    fn burn_rate_forecast_uses_current_cycle_and_ignores_post_reset_exhaustion() {
+        let point = |minutes: u64, percent_left: f64, reset_time_ms: i64| UsageHistoryPoint {
+            recorded_at_ms: minutes * 60_000,
+            percent_left,
+            reset_time_ms: Some(reset_time_ms),
+        };
+        let reset_ms = 10 * 60 * 60_000;
+
+        // 上一周期的高消耗采样不应参与估算
+        let points = vec![
+            point(0, 90.0, 1),
+            point(10, 10.0, 1),
+            point(20, 80.0, reset_ms),
+            point(50, 70.0, reset_ms),
+            point(80, 60.0, reset_ms),
+        ];
+        let forecast = forecast_limit_exhaustion(&points).expect("should forecast");
+        assert!((forecast.burn_rate_per_hour - 20.0).abs() < 1e-9);
+        assert_eq!(
+            forecast.projected_exhaustion_ms,
+            Some(80 * 60_000 + 3 * 60 * 60_000)
+        );
+
+        let slow = vec![
+            point(20, 80.0, 2 * 60 * 60_000),
+            point(80, 79.0, 2 * 60 * 60_000),
+        ];
+        assert_eq!(
+            forecast_limit_exhaustion(&slow).and_then(|forecast| forecast.projected_exhaustion_ms),
+            None
+        );
+        assert_eq!(forecast_limit_exhaustion(&points[4..]), None);
+
+        let mut limit = sample_usage_account("1", "ok", Some(60.0), None)
+            .usage_info
+            .and_then(|usage| usage.five_hour_limit)
+            .unwrap();
+        limit.projected_exhaustion_ms = Some(90 * 60_000);
+        assert_eq!(
+            format_tray_limit_with_countdown(&limit, "5H", 0),
+            "5H 60% · 约1h30m后耗尽"
+        );
     }
 
-    if status == reqwest::StatusCode::FORBIDDEN {
-        return Ok(UsageResult {
-            status: "forbidden".to_string(),
-            message: Some("\u{8d26}\u{53f7}\u{5df2}\u{88ab}\u{5c01}\u{7981}\u{6216}\u{65e0}\u{6743}\u{8bbf}\u{95ee}".to_string()),
-            plan_type: None,
-            usage: None,
-        });
-    }
+    #[test]
+    fn circuit_breaker_opens_with_jittered_backoff_and_resets_on_success() {
+        assert_eq!(compute_circuit_cooldown_ms(1, 0.5), None);
+        assert_eq!(compute_circuit_cooldown_ms(2, 0.0), Some(30_000));
+        assert_eq!(compute_circuit_cooldown_ms(2, 1.0), Some(60_000));
+        assert_eq!(compute_circuit_cooldown_ms(4, 1.0), Some(240_000));
+        assert_eq!(
+            compute_circuit_cooldown_ms(30, 1.0),
+            Some(CIRCUIT_BREAKER_MAX_COOLDOWN_MS)
+        );
 
-    if !status.is_success() {
-        return Ok(UsageResult {
-            status: "error".to_string(),
-            message: Some(format!(
-                "wham/usage \u{8bf7}\u{6c42}\u{5931}\u{8d25}: {}",
-                status
-            )),
-            plan_type: None,
-            usage: None,
-        });
-    }
+        // 使用局部状态，不读写全局的 ACCOUNT_REFRESH_STATES
+        let mut state = AccountRefreshState {
+            account_id: "acc".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            apply_refresh_attempt(&mut state, "error", Some("boom"), 1_000, None, 0.5),
+            None
+        );
+        let next_retry_at_ms =
+            apply_refresh_attempt(&mut state, "error", Some("boom"), 2_000, None, 1.0)
+                .expect("second failure should open the breaker");
+        assert_eq!(next_retry_at_ms, 62_000);
 
-    let value: serde_json::Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
-    let plan_type = value
-        .get("plan_type")
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string());
+        let result = cooldown_usage_result(&state, 2_000).expect("should be cooling down");
+        assert_eq!(result.status, "cooldown");
+        assert_eq!(result.next_retry_at_ms, Some(next_retry_at_ms));
+        assert!(cooldown_usage_result(&state, next_retry_at_ms).is_none());
 
-    let rate_limit_value = match value.get("rate_limit").or_else(|| value.get("rate_limits")) {
-        Some(value) => value,
-        None => {
-            return Ok(UsageResult {
-                status: "no_usage".to_string(),
-                message: Some("Missing rate_limit in response".to_string()),
-                plan_type,
-                usage: None,
-            })
-        }
-    };
+        // 被限流时至少等到服务端给出的时间
+        assert_eq!(
+            apply_refresh_attempt(&mut state, "rate_limited", None, 3_000, Some(900_000), 0.0),
+            Some(900_000)
+        );
 
-    let parsed_limits = match parse_rate_limits(rate_limit_value) {
-        Ok(parsed) => parsed,
-        Err(err) => {
-            return Ok(UsageResult {
-                status: "no_usage".to_string(),
-                message: Some(err),
-                plan_type,
-                usage: None,
-            })
-        }
-    };
+        // 需要用户处理的失败直接按最长冷却时间退避，冷却期间沿用原状态
+        assert_eq!(
+            apply_refresh_attempt(&mut state, "expired", Some("relogin"), 4_000, None, 0.0),
+            Some(4_000 + CIRCUIT_BREAKER_MAX_COOLDOWN_MS)
+        );
+        let result = cooldown_usage_result(&state, 5_000).expect("should back off");
+        assert_eq!(result.status, "expired");
+        assert_eq!(result.message.as_deref(), Some("relogin"));
+        assert_eq!(
+            result.next_retry_at_ms,
+            Some(4_000 + CIRCUIT_BREAKER_MAX_COOLDOWN_MS)
+        );
 
-    let code_review = value
-        .get("code_review_rate_limit")
-        .and_then(parse_optional_rate_limit);
+        // 成功后计数清零
+        apply_refresh_attempt(&mut state, "ok", None, 5_000, None, 0.0);
+        assert_eq!(state.consecutive_failures, 0);
+        assert!(cooldown_usage_result(&state, 5_000).is_none());
 
-    let usage = UsageData {
-        five_hour_percent_left: parsed_limits
-            .five_hour
-            .as_ref()
-            .map(|limit| limit.percent_left),
-        five_hour_reset_time_ms: parsed_limits
-            .five_hour
-            .as_ref()
-            .map(|limit| limit.reset_time_ms),
-        weekly_percent_left: parsed_limits
-            .weekly
-            .as_ref()
-            .map(|limit| limit.percent_left),
-        weekly_reset_time_ms: parsed_limits
-            .weekly
-            .as_ref()
-            .map(|limit| limit.reset_time_ms),
-        code_review_percent_left: code_review.as_ref().map(|l| l.percent_left),
-        code_review_reset_time_ms: code_review.as_ref().map(|l| l.reset_time_ms),
-        last_updated: now_epoch_ms_string(),
-        source_file: None,
-    };
+        state.consecutive_failures = 3;
+        state.cooldown_until_ms = Some(10_000);
+        reset_circuit_breaker(&mut state);
+        assert!(cooldown_usage_result(&state, 5_000).is_none());
+    }
 
-    Ok(UsageResult {
-        status: "ok".to_string(),
-        message: None,
-        plan_type,
-        usage: Some(usage),
-    })
-}
+    #[test]
+    fn cooldown_summary_keeps_the_last_known_quota() {
+        let previous = sample_usage_account("1", "ok", Some(46.0), Some(84.0)).usage_info;
+        let cooldown =
+            build_tray_usage_summary(&build_cooldown_usage_result(62_000, 2, Some("boom"), 2_000));
+        let merged = merge_usage_summary(previous.clone(), cooldown);
+        assert_eq!(merged.status.as_deref(), Some("cooldown"));
+        assert_eq!(merged.next_retry_at_ms, Some(62_000));
+        assert!(merged.message.as_deref().unwrap().contains("boom"));
+        assert_eq!(
+            merged.five_hour_limit.map(|limit| limit.percent_left),
+            Some(46.0)
+        );
+        assert_eq!(
+            merged.weekly_limit.map(|limit| limit.percent_left),
+            Some(84.0)
+        );
 
-fn json_contains_string(value: &serde_json::Value, needle: &str) -> bool {
-    match value {
-        serde_json::Value::String(s) => s == needle,
-        serde_json::Value::Array(items) => items.iter().any(|v| json_contains_string(v, needle)),
-        serde_json::Value::Object(map) => map.values().any(|v| json_contains_string(v, needle)),
-        _ => false,
+        // 封禁等需要用户处理的状态不保留旧额度
+        let forbidden = TrayUsageSummary {
+            status: Some("forbidden".to_string()),
+            ..Default::default()
+        };
+        assert!(merge_usage_summary(previous, forbidden)
+            .five_hour_limit
+            .is_none());
     }
-}
 
-/// 从指定文件解析用量信息
-#[tauri::command]
-fn get_usage_from_file(file_path: String) -> Result<UsageData, String> {
-    let path = PathBuf::from(file_path);
-    if !path.exists() {
-        return Err("Usage source file not found".to_string());
+    #[test]
+    fn atomic_write_replaces_the_file_without_leaving_a_temp_file() {
+        let path =
+            std::env::temp_dir().join(format!("codex-manager-atomic-{}.json", std::process::id()));
+        fs::write(&path, "old").unwrap();
+        write_file_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        let mut tmp_name = path.file_name().unwrap().to_os_string();
+        tmp_name.push(".tmp");
+        assert!(!path.with_file_name(tmp_name).exists());
+        let _ = fs::remove_file(&path);
     }
-    parse_rate_limits_from_file(&path)
-}
 
-/// 获取指定账号的用量信息
-/// 需要先切换到该账号，然后查找其 session 文件
-#[tauri::command]
-fn get_account_usage(account_email: String) -> Result<UsageData, String> {
-    let sessions_dir = get_codex_sessions_dir()?;
+    #[test]
+    fn refresh_progress_payload_counts_worker_messages() {
+        let mut progress = UsageRefreshProgressPayload {
+            total: 2,
+            completed: 0,
+            succeeded: 0,
+            failed: 0,
+            in_flight: 0,
+            finished: false,
+        };
+        let finished = |account_id: &str, status: &str| {
+            UsageRefreshWorkerMessage::Finished(
+                account_id.to_string(),
+                Box::new(UsageResult {
+                    status: status.to_string(),
+                    message: (status != "ok").then(|| "请求超时".to_string()),
+                    plan_type: None,
+                    next_retry_at_ms: None,
+                    usage: None,
+                }),
+            )
+        };
 
-    if !sessions_dir.exists() {
-        return Err("Sessions directory not found".to_string());
-    }
+        let started = apply_refresh_worker_message(
+            &mut progress,
+            UsageRefreshWorkerMessage::Started("a".to_string()),
+        );
+        apply_refresh_worker_message(
+            &mut progress,
+            UsageRefreshWorkerMessage::Started("b".to_string()),
+        );
+        assert_eq!(started.phase, "started");
+        assert!(started.summary.is_none());
+        assert_eq!(progress.in_flight, 2);
 
-    let mut all_files: Vec<PathBuf> = Vec::new();
+        let succeeded = apply_refresh_worker_message(&mut progress, finished("a", "ok"));
+        assert_eq!(succeeded.account_id, "a");
+        assert_eq!(succeeded.phase, "succeeded");
+        assert_eq!(succeeded.code, None);
+        assert!(succeeded.summary.is_some());
 
-    fn collect_jsonl_files(dir: &PathBuf, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
-        if dir.is_dir() {
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.is_dir() {
-                    collect_jsonl_files(&path, files)?;
-                } else if path.extension().map_or(false, |ext| ext == "jsonl") {
-                    files.push(path);
-                }
+        let failed = apply_refresh_worker_message(&mut progress, finished("b", "timeout"));
+        assert_eq!(failed.phase, "failed");
+        assert_eq!(failed.code.as_deref(), Some("timeout"));
+        assert_eq!(failed.message.as_deref(), Some("请求超时"));
+
+        assert_eq!(
+            progress,
+            UsageRefreshProgressPayload {
+                total: 2,
+                completed: 2,
+                succeeded: 1,
+                failed: 1,
+                in_flight: 0,
+                finished: false,
             }
-        }
-        Ok(())
+        );
+        assert_eq!(
+            serde_json::to_value(&progress).unwrap(),
+            serde_json::json!({
+                "total": 2,
+                "completed": 2,
+                "succeeded": 1,
+                "failed": 1,
+                "inFlight": 0,
+                "finished": false,
+            })
+        );
     }
 
-    collect_jsonl_files(&sessions_dir, &mut all_files)
-        .map_err(|e| format!("Failed to read sessions directory: {}", e))?;
-
-    // 按修改时间排序（最新的在前）
-    all_files.sort_by(|a, b| {
-        let a_time = fs::metadata(a).and_then(|m| m.modified()).ok();
-        let b_time = fs::metadata(b).and_then(|m| m.modified()).ok();
-        b_time.cmp(&a_time)
-    });
-
-    // 遍历文件，查找包含指定账号的 rate_limits
-    for file_path in all_files.iter().take(20) {
-        // 只检查最近20个文件
-        let file = match fs::File::open(file_path) {
-            Ok(f) => f,
-            Err(_) => continue,
-        };
+    /// 刷新任务状态是全局的，读写它的测试需串行执行
+    static REFRESH_JOB_TEST_LOCK: Mutex<()> = Mutex::new(());
 
-        let reader = BufReader::new(file);
-        let mut found_account = false;
-        let mut latest_rate_limits: Option<RateLimits> = None;
+    #[test]
+    fn refresh_job_tracks_progress_and_cancellation() {
+        let _guard = REFRESH_JOB_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let job = begin_refresh_job("manual");
+        assert_eq!(job.state, "running");
+        update_refresh_job_progress(&UsageRefreshProgressPayload {
+            total: 3,
+            completed: 1,
+            succeeded: 1,
+            failed: 0,
+            in_flight: 1,
+            finished: false,
+        });
 
-        for line in reader.lines() {
-            let line = match line {
-                Ok(l) => l,
-                Err(_) => continue,
-            };
+        let current = current_refresh_job().expect("job should exist");
+        assert_eq!(current.job_id, job.job_id);
+        assert_eq!((current.total, current.completed), (3, 1));
 
-            if line.is_empty() {
-                continue;
-            }
+        let cancel = current_refresh_job_cancel_token();
+        assert_eq!(cancel_refresh_jobs(), (true, None));
+        assert!(cancel.is_cancelled());
 
-            let value: serde_json::Value = match serde_json::from_str(&line) {
-                Ok(v) => v,
-                Err(_) => continue,
-            };
+        let next_job = begin_refresh_job("background");
+        assert!(next_job.job_id > job.job_id);
+        assert!(!current_refresh_job_cancel_token().is_cancelled());
+        assert!(cancel.is_cancelled());
+    }
 
-            // 仅在明确的上下文里匹配邮箱，避免误判
-            if !found_account && !account_email.is_empty() {
-                if let Some(entry_type) = value.get("type").and_then(|v| v.as_str()) {
-                    if entry_type == "session_meta" || entry_type == "turn_context" {
-                        if json_contains_string(&value, &account_email) {
-                            found_account = true;
-                        }
-                    }
-                }
-            }
+    #[test]
+    fn manual_refresh_requests_queue_behind_a_running_job() {
+        let _guard = REFRESH_JOB_TEST_LOCK
+            .lock()
+            .unwrap_or_else(|error| error.into_inner());
+        let ids = |ids: &[&str]| Some(ids.iter().map(|id| id.to_string()).collect::<Vec<_>>());
 
-            // 解析 rate_limits
-            if let Ok(event) = serde_json::from_value::<EventMsg>(value) {
-                if event.msg_type == "event_msg" || event.msg_type == "token_count" {
-                    if let Some(payload) = event.payload {
-                        if let Some(rate_limits) = payload.get("rate_limits") {
-                            if let Ok(rl) =
-                                serde_json::from_value::<RateLimits>(rate_limits.clone())
-                            {
-                                latest_rate_limits = Some(rl);
-                            }
-                        }
-                    }
-                }
-            }
-        }
+        let job_id = queue_refresh_request(ids(&["a", "b"]));
+        assert_eq!(queue_refresh_request(ids(&["b", "c"])), job_id);
+        assert_eq!(
+            take_pending_refresh_request(),
+            Some(PendingRefreshRequest {
+                job_id,
+                account_ids: ids(&["a", "b", "c"]),
+            })
+        );
+        assert!(!has_pending_refresh_request());
+
+        // 任一请求刷新全部时合并为全部
+        let job_id = queue_refresh_request(ids(&["a"]));
+        assert_eq!(queue_refresh_request(None), job_id);
+        assert_eq!(queue_refresh_request(ids(&["b"])), job_id);
+        let (_, queued) = cancel_refresh_jobs();
+        let queued = queued.expect("queued job should be cancelled");
+        assert_eq!(
+            (queued.job_id, queued.state.as_str()),
+            (job_id, "cancelled")
+        );
+        assert!(!has_pending_refresh_request());
+    }
 
-        // 如果找到了账号且有 rate_limits，返回结果
-        if found_account {
-            if let Some(rate_limits) = latest_rate_limits {
-                if let (Some(primary), Some(secondary)) =
-                    (rate_limits.primary, rate_limits.secondary)
-                {
-                    let primary_used = validate_used_percent(primary.used_percent)?;
-                    let secondary_used = validate_used_percent(secondary.used_percent)?;
-                    let five_hour_reset_ms = normalize_unix_timestamp_ms(primary.resets_at)?;
-                    let weekly_reset_ms = normalize_unix_timestamp_ms(secondary.resets_at)?;
-                    let last_updated = fs::metadata(file_path)
-                        .and_then(|m| m.modified())
-                        .ok()
-                        .and_then(epoch_ms_from_system_time)
-                        .map(|ms| ms.to_string())
-                        .unwrap_or_else(now_epoch_ms_string);
-
-                    return Ok(UsageData {
-                        five_hour_percent_left: Some(100.0 - primary_used),
-                        five_hour_reset_time_ms: Some(five_hour_reset_ms),
-                        weekly_percent_left: Some(100.0 - secondary_used),
-                        weekly_reset_time_ms: Some(weekly_reset_ms),
-                        code_review_percent_left: None,
-                        code_review_reset_time_ms: None,
-                        last_updated,
-                        source_file: Some(file_path.to_string_lossy().to_string()),
-                    });
-                }
-            }
-        }
+    #[test]
+    fn cancellation_abandons_in_flight_requests() {
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            trigger.cancel();
+        });
+        // 永远不会完成的请求也会在取消后立即返回
+        let result = tauri::async_runtime::block_on(
+            cancel.run_until_cancelled(std::future::pending::<UsageResult>()),
+        );
+        canceller.join().unwrap();
+        assert!(result.is_none());
+
+        let mut progress = UsageRefreshProgressPayload {
+            total: 1,
+            completed: 0,
+            succeeded: 0,
+            failed: 0,
+            in_flight: 1,
+            finished: false,
+        };
+        let payload = apply_refresh_worker_message(
+            &mut progress,
+            UsageRefreshWorkerMessage::Cancelled("a".to_string()),
+        );
+        assert_eq!(payload.phase, "cancelled");
+        assert!(payload.summary.is_none());
+        assert_eq!(
+            (progress.in_flight, progress.completed, progress.failed),
+            (0, 0, 0)
+        );
     }
 
-    Err(format!(
-        "No usage data found for account: {}",
-        account_email
-    ))
-}
+    #[test]
+    fn resume_detection_and_network_probe_target() {
+        assert!(!detect_resume_gap(30_500, 30_400, 30_000));
+        assert!(detect_resume_gap(3_600_000, 30_000, 30_000));
+        assert!(detect_resume_gap(3_600_000, 3_600_000, 30_000));
+
+        assert!(!should_probe_network(true, false, false));
+        assert!(should_probe_network(true, true, false));
+        assert!(should_probe_network(true, false, true));
+        assert!(should_probe_network(false, false, false));
+
+        let no_env = |_: &str| None;
+        let mut store = TrayAccountsStore {
+            version: "1.0.0".to_string(),
+            accounts: Vec::new(),
+            config: TrayAppConfig::default(),
+        };
+        assert_eq!(
+            network_probe_targets(&store, DEFAULT_API_BASE_URL, &no_env),
+            [("chatgpt.com".to_string(), 443)]
+        );
+        assert_eq!(
+            network_probe_targets(&store, "http://127.0.0.1:8080/backend-api", &no_env),
+            [("127.0.0.1".to_string(), 8080)]
+        );
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    tauri::Builder::default()
-        .plugin(tauri_plugin_dialog::init())
-        .plugin(tauri_plugin_shell::init())
-        .setup(|app| {
-            if cfg!(debug_assertions) {
-                app.handle().plugin(
-                    tauri_plugin_log::Builder::default()
-                        .level(log::LevelFilter::Info)
-                        .build(),
-                )?;
-            }
-            start_session_watcher();
-            initialize_tray(&app.handle())?;
-            start_background_auto_refresh(&app.handle());
-            Ok(())
-        })
-        .invoke_handler(tauri::generate_handler![
-            load_accounts_store,
-            save_accounts_store,
-            write_codex_auth,
-            read_codex_auth,
-            refresh_tray_menu,
-            show_main_window,
-            hide_to_tray,
-            exit_application,
-            start_codex_login,
-            cancel_codex_login,
-            save_account_auth,
-            read_account_auth,
-            delete_account_auth,
-            read_file_content,
-            write_file_content,
-            get_home_dir,
-            restart_codex_processes,
-            get_wham_account_metadata,
-            get_codex_wham_usage,
-            get_usage_from_sessions,
-            get_bound_usage,
-            get_usage_from_file,
-            get_account_usage,
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
-}
+        // 默认的环境变量代理模式按 HTTPS_PROXY / NO_PROXY 解析
+        let env = |name: &str| match name {
+            "HTTPS_PROXY" => Some("http://corp-proxy:3128".to_string()),
+            "no_proxy" => Some("localhost, .internal".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            network_probe_targets(&store, DEFAULT_API_BASE_URL, &env),
+            [("corp-proxy".to_string(), 3128)]
+        );
+        assert_eq!(
+            network_probe_targets(&store, "https://usage.internal/api", &env),
+            [("usage.internal".to_string(), 443)]
+        );
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        store.config.proxy_enabled = Some(true);
+        store.config.proxy_url = Some("127.0.0.1:7890".to_string());
+        assert_eq!(
+            network_probe_targets(&store, DEFAULT_API_BASE_URL, &env),
+            [("127.0.0.1".to_string(), 7890)]
+        );
+        store.config.proxy_url = Some("socks5://localhost".to_string());
+        let mut account = sample_usage_account("1", "ok", None, None);
+        account.proxy_url = Some("http://10.0.0.2:8080".to_string());
+        store.accounts.push(account);
+        assert_eq!(
+            network_probe_targets(&store, DEFAULT_API_BASE_URL, &no_env),
+            [
+                ("localhost".to_string(), 1080),
+                ("10.0.0.2".to_string(), 8080)
+            ]
+        );
 
-    fn sample_auth_json(id_token: &str) -> String {
-        format!(
-            r#"{{
-  "OPENAI_API_KEY": null,
-  "tokens": {{
-    "id_token": "{}",
-    "access_token": "access",
-    "refresh_token": "refresh",
-    "account_id": "account"
-  }},
-  "last_refresh": "2026-03-27T00:00:00.000Z"
-}}"#,
-            id_token
-        )
+        let state = AccountRefreshState {
+            account_id: "acc".to_string(),
+            last_attempt_ms: 1,
+            last_attempt_at: Instant::now().checked_sub(Duration::from_secs(120)),
+            ..Default::default()
+        };
+        let adjusted = with_monotonic_last_attempt(&state, 10_000_000);
+        assert!((9_870_000..=9_880_000).contains(&adjusted.last_attempt_ms));
     }
 
     #[test]
-    fn empty_codex_path_falls_back_to_default() {
-        assert_eq!(normalize_codex_command_input(None), "codex");
-        assert_eq!(
-            normalize_codex_command_input(Some("   ".to_string())),
-            "codex"
+    fn usage_falls_back_to_current_session_data_when_online_fetch_fails() {
+        let session = |last_updated_ms: u64, reset_ms: i64| UsageData {
+            five_hour_percent_left: Some(30.0),
+            five_hour_reset_time_ms: Some(reset_ms),
+            weekly_percent_left: Some(70.0),
+            weekly_reset_time_ms: None,
+            code_review_percent_left: None,
+            code_review_reset_time_ms: None,
+            five_hour_burn_rate_per_hour: None,
+            five_hour_projected_exhaustion_ms: None,
+            weekly_burn_rate_per_hour: None,
+            weekly_projected_exhaustion_ms: None,
+            last_updated: last_updated_ms.to_string(),
+            source_file: None,
+            details: None,
+            source: Some(USAGE_SOURCE_SESSION.to_string()),
+            age_ms: None,
+            windows: Vec::new(),
+            context_window: None,
+        };
+        let failed = |status: &str| UsageResult {
+            status: status.to_string(),
+            message: Some("offline".to_string()),
+            plan_type: None,
+            next_retry_at_ms: Some(9_000),
+            usage: None,
+        };
+
+        // 失败状态与提示保持不变，本地数据作为附带用量
+        for status in ["error", "expired", "stale_token"] {
+            let result = choose_usage_source(failed(status), Some(session(1_000, 50_000)), 4_000);
+            assert_eq!(result.status, status);
+            assert_eq!(result.message.as_deref(), Some("offline"));
+            assert_eq!(result.next_retry_at_ms, Some(9_000));
+            let usage = result.usage.expect("session usage should be attached");
+            assert_eq!(usage.source.as_deref(), Some(USAGE_SOURCE_SESSION));
+            assert_eq!(usage.age_ms, Some(3_000));
+        }
+
+        // 已重置的窗口没有参考价值，封禁等状态也不附带本地数据
+        assert!(
+            choose_usage_source(failed("error"), Some(session(1_000, 3_000)), 4_000)
+                .usage
+                .is_none()
+        );
+        assert!(
+            choose_usage_source(failed("forbidden"), Some(session(1_000, 50_000)), 4_000)
+                .usage
+                .is_none()
         );
+
+        let online_result = |last_updated_ms: u64| {
+            let mut online = session(last_updated_ms, 50_000);
+            online.source = Some(USAGE_SOURCE_WHAM.to_string());
+            online.five_hour_percent_left = Some(10.0);
+            UsageResult {
+                status: "ok".to_string(),
+                message: None,
+                plan_type: None,
+                next_retry_at_ms: None,
+                usage: Some(online),
+            }
+        };
+        let usage = choose_usage_source(online_result(4_000), None, 4_500)
+            .usage
+            .unwrap();
+        assert_eq!(usage.source.as_deref(), Some(USAGE_SOURCE_WHAM));
+        assert_eq!(usage.age_ms, Some(500));
+
+        // 取两者中更新的一份
+        let usage = choose_usage_source(online_result(4_000), Some(session(3_000, 50_000)), 4_500)
+            .usage
+            .unwrap();
+        assert_eq!(usage.source.as_deref(), Some(USAGE_SOURCE_WHAM));
+        let result = choose_usage_source(online_result(2_000), Some(session(4_000, 50_000)), 4_500);
+        assert_eq!(result.status, "ok");
+        let usage = result.usage.unwrap();
+        assert_eq!(usage.source.as_deref(), Some(USAGE_SOURCE_SESSION));
+        assert_eq!(usage.five_hour_percent_left, Some(30.0));
+        assert_eq!(usage.age_ms, Some(500));
     }
 
     #[test]
-    fn surrounding_quotes_are_removed_from_codex_path() {
-        assert_eq!(
-            normalize_codex_command_input(Some(
-                "\"C:\\Program Files\\OpenAI\\codex.exe\"".to_string(),
-            )),
-            r"C:\Program Files\OpenAI\codex.exe"
+    fn context_window_comes_from_latest_token_count_info() {
+        let line = serde_json::json!({
+            "type": "event_msg",
+            "payload": {
+                "type": "token_count",
+                "info": {
+                    "total_token_usage": {"total_tokens": 900000},
+                    "last_token_usage": {"input_tokens": 60000, "output_tokens": 2000, "total_tokens": 62000},
+                    "model_context_window": 272000
+                },
+                "rate_limits": null
+            }
+        })
+        .to_string();
+        let mut entry = SessionIndexEntry::default();
+        apply_session_line(&mut entry, line.as_bytes());
+        let context_window = entry.context_window.clone().expect("context window");
+        assert_eq!(context_window.used_tokens, 62_000);
+        assert_eq!(context_window.total_tokens, 272_000);
+        // (260000 - 50000) / 260000
+        assert!((context_window.percent_left - 80.769).abs() < 0.01);
+        assert!(entry.rate_limits.is_none());
+
+        // info 为空的 token_count 不覆盖已有数据
+        apply_session_line(
+            &mut entry,
+            br#"{"type":"event_msg","payload":{"type":"token_count","info":null}}"#,
         );
+        assert_eq!(entry.context_window.as_ref(), Some(&context_window));
         assert_eq!(
-            normalize_codex_command_input(Some(
-                "'C:\\Program Files\\OpenAI\\codex.exe'".to_string(),
-            )),
-            r"C:\Program Files\OpenAI\codex.exe"
+            summarize_context_window(&context_window),
+            TrayContextWindowSummary {
+                percent_left: 81.0,
+                used: "62K".to_string(),
+                total: "272K".to_string(),
+            }
         );
+        assert_eq!(ContextWindowUsage::new(5_000, 8_000).percent_left, 0.0);
+        assert_eq!(ContextWindowUsage::new(5_000, 272_000).percent_left, 100.0);
     }
 
     #[test]
-    fn timeout_seconds_have_reasonable_floor() {
+    fn token_counts_are_formatted_compactly() {
+        assert_eq!(format_token_count(850), "850");
+        assert_eq!(format_token_count(62_000), "62K");
+        assert_eq!(format_token_count(45_260), "45.3K");
+        assert_eq!(format_token_count(1_000_000), "1M");
+    }
+
+    #[test]
+    fn context_window_is_shown_only_for_a_recent_session_of_the_active_account() {
+        let now_ms = 1_777_000_000_000_u64;
+        let context_window = ContextWindowUsage::new(62_000, 272_000);
+        let entry = |modified_ms: u64| SessionIndexEntry {
+            modified_ms: Some(modified_ms as i64),
+            context_window: Some(context_window.clone()),
+            ..Default::default()
+        };
+        // 不要求 session 中有 rate_limits，超过截止时长的会话不再显示
         assert_eq!(
-            normalize_login_timeout_seconds(None),
-            DEFAULT_LOGIN_TIMEOUT_SECONDS
+            recent_session_context_window(&entry(now_ms - 60_000), now_ms),
+            Some(context_window.clone())
         );
         assert_eq!(
-            normalize_login_timeout_seconds(Some(0)),
-            DEFAULT_LOGIN_TIMEOUT_SECONDS
+            recent_session_context_window(
+                &entry(now_ms - SESSION_CONTEXT_WINDOW_MAX_AGE_MS - 1),
+                now_ms
+            ),
+            None
         );
-        assert_eq!(normalize_login_timeout_seconds(Some(5)), 30);
-        assert_eq!(normalize_login_timeout_seconds(Some(120)), 120);
-    }
 
-    #[test]
-    fn auth_change_detection_checks_content_and_mtime() {
-        let old = AuthSnapshot {
-            modified: None,
-            content: Some("old".to_string()),
-        };
-        let same = AuthSnapshot {
-            modified: None,
-            content: Some("old".to_string()),
-        };
-        let changed = AuthSnapshot {
-            modified: None,
-            content: Some("new".to_string()),
+        let online = UsageResult {
+            status: "ok".to_string(),
+            message: None,
+            plan_type: None,
+            next_retry_at_ms: None,
+            usage: Some(UsageData {
+                five_hour_percent_left: Some(40.0),
+                five_hour_reset_time_ms: Some(50_000),
+                weekly_percent_left: None,
+                weekly_reset_time_ms: None,
+                code_review_percent_left: None,
+                code_review_reset_time_ms: None,
+                five_hour_burn_rate_per_hour: None,
+                five_hour_projected_exhaustion_ms: None,
+                weekly_burn_rate_per_hour: None,
+                weekly_projected_exhaustion_ms: None,
+                last_updated: "4000".to_string(),
+                source_file: None,
+                details: None,
+                source: Some(USAGE_SOURCE_WHAM.to_string()),
+                age_ms: None,
+                windows: Vec::new(),
+                context_window: None,
+            }),
         };
+        let result = with_context_window(online, Some(context_window));
+        let summary = build_tray_usage_summary(&result);
+        let usage = result.usage.unwrap();
+        assert_eq!(usage.source.as_deref(), Some(USAGE_SOURCE_WHAM));
+        assert_eq!(usage.five_hour_percent_left, Some(40.0));
+        let tray_context = summary.context_window.clone().expect("tray context window");
+        assert_eq!(tray_context.percent_left, 81.0);
+        assert_eq!(
+            (tray_context.used.as_str(), tray_context.total.as_str()),
+            ("62K", "272K")
+        );
 
-        assert!(!auth_snapshot_changed(&old, &same));
-        assert!(auth_snapshot_changed(&old, &changed));
+        let mut account = sample_usage_account("1", "ok", Some(46.0), Some(84.0));
+        account.usage_info = Some(summary);
+        assert!(!build_tray_account_detail_at(&account, 0).contains("上下文"));
+        account.is_active = true;
+        assert!(build_tray_account_detail_at(&account, 0).contains("上下文 81%"));
     }
 
     #[test]
-    fn login_auth_validation_requires_id_token() {
-        assert!(validate_login_auth_json(&sample_auth_json("token")).is_ok());
-        assert!(validate_login_auth_json(&sample_auth_json("")).is_err());
-        assert!(validate_login_auth_json("{\"tokens\":{}}").is_err());
-    }
+    fn tls_settings_trim_paths_and_reject_unusable_ca_files() {
+        let config = TrayAppConfig {
+            tls_ca_bundle_paths: Some(vec![" /etc/corp-ca.pem ".to_string(), "  ".to_string()]),
+            tls_use_system_certs: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve_tls_settings(&config),
+            TlsSettings {
+                ca_bundle_paths: vec!["/etc/corp-ca.pem".to_string()],
+                use_system_certs: true,
+            }
+        );
 
-    #[test]
-    fn direct_invocation_is_used_for_explicit_exe_path() {
-        let invocation = resolve_login_invocation(Some(r"C:\tools\codex.exe".to_string()))
-            .expect("should build invocation");
+        let missing = std::env::temp_dir().join("codex-manager-missing-ca.pem");
+        let error = load_ca_certificates(&missing.to_string_lossy()).unwrap_err();
+        assert!(error.contains("codex-manager-missing-ca.pem"));
 
-        assert_eq!(invocation.program, r"C:\tools\codex.exe");
-        assert_eq!(invocation.args, vec!["login".to_string()]);
-        assert_eq!(invocation.mode, LoginCommandMode::Direct);
+        let empty =
+            std::env::temp_dir().join(format!("codex-manager-empty-ca-{}.pem", std::process::id()));
+        fs::write(&empty, "not a certificate").unwrap();
+        assert!(load_ca_certificates(&empty.to_string_lossy()).is_err());
+        fs::remove_file(&empty).ok();
     }
 
     #[test]
-    fn powershell_invocation_is_used_for_ps1_path() {
-        let invocation = resolve_login_invocation(Some(r"C:\tools\codex.ps1".to_string()))
-            .expect("should build invocation");
+    fn session_tail_reads_appended_lines_and_restarts_on_truncation_or_rotation() {
+        fn token_count_line(primary_used: f64) -> String {
+            format!(
+                "{}\n",
+                serde_json::json!({
+                    "timestamp": "2026-01-01T00:00:00Z",
+                    "type": "event_msg",
+                    "payload": {
+                        "type": "token_count",
+                        "rate_limits": {
+                            "primary": {"used_percent": primary_used, "window_minutes": 300, "resets_at": 1777000000},
+                            "secondary": {"used_percent": 40.0, "window_minutes": 10080, "resets_at": 1777600000}
+                        }
+                    }
+                })
+            )
+        }
+        let primary_used = |state: &SessionIndexEntry| {
+            state
+                .rate_limits
+                .as_ref()
+                .and_then(|limits| limits.primary.as_ref())
+                .map(|primary| primary.used_percent)
+        };
 
-        assert_eq!(invocation.program, "powershell");
-        assert_eq!(invocation.mode, LoginCommandMode::PowerShell);
-        assert!(invocation.args.iter().any(|arg| arg == "login"));
+        let path =
+            std::env::temp_dir().join(format!("codex-manager-tail-{}.jsonl", std::process::id()));
+        let meta = "{\"type\":\"session_meta\",\"payload\":{\"id\":\"a\",\"timestamp\":\"2026-01-01T00:00:00Z\",\"user\":{\"email\":\"a@example.com\"},\"note\":\"mail me @ home\"}}\n";
+        fs::write(&path, format!("{}{}", meta, token_count_line(10.0))).unwrap();
+        let mut state = SessionIndexEntry::default();
+        tail_session_file(&path, &mut state).unwrap();
+        assert_eq!(primary_used(&state), Some(10.0));
+        assert_eq!(state.session_id.as_deref(), Some("a"));
+        assert_eq!(state.created_at.as_deref(), Some("2026-01-01T00:00:00Z"));
+        assert_eq!(state.emails, vec!["a@example.com".to_string()]);
+        let first_offset = state.offset;
+        assert_eq!(first_offset, fs::metadata(&path).unwrap().len());
+
+        // 追加的半行不推进偏移，补全后只解析新增部分
+        let appended = token_count_line(20.0);
+        let (first_half, second_half) = appended.split_at(30);
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(first_half.as_bytes()).unwrap();
+        tail_session_file(&path, &mut state).unwrap();
+        assert_eq!(state.offset, first_offset);
+        assert_eq!(primary_used(&state), Some(10.0));
+        file.write_all(second_half.as_bytes()).unwrap();
+        drop(file);
+        tail_session_file(&path, &mut state).unwrap();
+        assert_eq!(state.offset, first_offset + appended.len() as u64);
+        assert_eq!(primary_used(&state), Some(20.0));
+
+        // 截断后从头读取
+        fs::write(&path, meta).unwrap();
+        tail_session_file(&path, &mut state).unwrap();
+        assert_eq!(state.offset, meta.len() as u64);
+        assert_eq!(primary_used(&state), None);
+
+        // 同名文件被替换（开头内容不同）时也从头读取
+        let rotated_meta = "{\"type\":\"session_meta\",\"payload\":{\"id\":\"b\"}}\n";
+        fs::write(
+            &path,
+            format!(
+                "{}{}{}",
+                rotated_meta,
+                token_count_line(30.0),
+                token_count_line(35.0)
+            ),
+        )
+        .unwrap();
+        tail_session_file(&path, &mut state).unwrap();
+        assert_eq!(primary_used(&state), Some(35.0));
+        assert_eq!(state.offset, fs::metadata(&path).unwrap().len());
+        assert_eq!(state.session_id.as_deref(), Some("b"));
+        assert!(state.emails.is_empty());
+
+        // 写盘时省略了 rate_limits 的索引项从头重新解析
+        let mut trimmed = state.clone();
+        trimmed.rate_limits = None;
+        trimmed.trimmed = true;
+        trimmed.account_id = Some("acc-1".to_string());
+        let mut resumed = resumable_session_entry(Some(&trimmed));
+        assert_eq!(resumed.offset, 0);
+        tail_session_file(&path, &mut resumed).unwrap();
+        assert_eq!(primary_used(&resumed), Some(35.0));
+        assert_eq!(resumed.account_id.as_deref(), Some("acc-1"));
+        assert!(!resumed.trimmed);
+
+        let _ = fs::remove_file(&path);
     }
 
     #[test]
-    fn tray_close_behavior_defaults_to_ask() {
-        assert_eq!(normalize_tray_close_behavior(None), "ask");
-        assert_eq!(normalize_tray_close_behavior(Some("unknown")), "ask");
-        assert_eq!(normalize_tray_close_behavior(Some("tray")), "tray");
-        assert_eq!(normalize_tray_close_behavior(Some("exit")), "exit");
+    fn session_index_tracks_latest_files_incrementally() {
+        let entry = |modified_ms: i64, account_id: Option<&str>| SessionIndexEntry {
+            modified_ms: Some(modified_ms),
+            account_id: account_id.map(str::to_string),
+            ..Default::default()
+        };
+        let mut index = SessionIndex::default();
+        index.insert_entry("old.jsonl".to_string(), entry(1, Some("acc-1")));
+        index.insert_entry("new.jsonl".to_string(), entry(3, None));
+        index.insert_entry("mid.jsonl".to_string(), entry(2, Some("acc-1")));
+        assert_eq!(index.latest().map(String::as_str), Some("new.jsonl"));
+        assert_eq!(
+            index.latest_for_account("acc-1").map(String::as_str),
+            Some("mid.jsonl")
+        );
+
+        // 追加内容使旧文件变为最新
+        index.insert_entry("old.jsonl".to_string(), entry(4, Some("acc-1")));
+        assert_eq!(index.latest().map(String::as_str), Some("old.jsonl"));
+        assert_eq!(
+            index.latest_for_account("acc-1").map(String::as_str),
+            Some("old.jsonl")
+        );
+
+        // 修改时间相同时按路径决定，结果与插入顺序无关
+        index.insert_entry("a-tie.jsonl".to_string(), entry(4, None));
+        assert_eq!(index.latest().map(String::as_str), Some("old.jsonl"));
+
+        // 改绑或删除最新文件后回退到剩余文件中最新的一个
+        index.set_entry_account("old.jsonl", "acc-2");
+        assert_eq!(
+            index.latest_for_account("acc-1").map(String::as_str),
+            Some("mid.jsonl")
+        );
+        assert_eq!(
+            index.latest_for_account("acc-2").map(String::as_str),
+            Some("old.jsonl")
+        );
+        assert!(index.remove_entry("old.jsonl"));
+        assert!(!index.remove_entry("old.jsonl"));
+        assert_eq!(index.latest().map(String::as_str), Some("a-tie.jsonl"));
+        assert_eq!(index.latest_for_account("acc-2"), None);
+        index.retain_entries(|path| path != "mid.jsonl");
+        assert_eq!(index.latest_for_account("acc-1"), None);
+        assert!(index.dirty);
+
+        // 写盘保存全部文件，只有最新的文件保留 rate_limits，且省略空字段
+        let mut index = SessionIndex::default();
+        for modified_ms in 0..(SESSION_TAIL_MAX_FILES as i64 + 10) {
+            let mut entry = entry(modified_ms, None);
+            entry.rate_limits = Some(RateLimits {
+                primary: None,
+                secondary: None,
+            });
+            index.insert_entry(format!("{}.jsonl", modified_ms), entry);
+        }
+        let saved = index.trimmed_for_save();
+        assert_eq!(saved.files.len(), SESSION_TAIL_MAX_FILES + 10);
+        let newest = &saved.files[&format!("{}.jsonl", SESSION_TAIL_MAX_FILES + 9)];
+        assert!(newest.rate_limits.is_some() && !newest.trimmed);
+        let oldest = &saved.files["9.jsonl"];
+        assert!(oldest.rate_limits.is_none() && oldest.trimmed);
+        assert_eq!(oldest.modified_ms, Some(9));
+        assert_eq!(
+            serde_json::to_value(entry(5, None)).unwrap(),
+            serde_json::json!({"modified_ms": 5, "len": 0, "offset": 0, "head_len": 0, "head_hash": 0})
+        );
+
+        // 监听器运行时只扫描一次，未运行时按间隔扫描
+        let mut index = SessionIndex::default();
+        assert!(session_index_needs_rescan(&index, true, 0));
+        index.scanned = true;
+        index.scanned_at_ms = 1_000;
+        assert!(!session_index_needs_rescan(&index, true, u64::MAX));
+        assert!(!session_index_needs_rescan(
+            &index,
+            false,
+            1_000 + SESSION_INDEX_RESCAN_INTERVAL_MS - 1
+        ));
+        assert!(session_index_needs_rescan(
+            &index,
+            false,
+            1_000 + SESSION_INDEX_RESCAN_INTERVAL_MS
+        ));
     }
 
     #[test]
-    fn tray_account_title_includes_quota_summary() {
-        let account = TrayStoredAccount {
-            id: "1".to_string(),
-            alias: "测试账号".to_string(),
-            account_info: TrayAccountInfo {
-                email: "test@example.com".to_string(),
-                plan_type: "team".to_string(),
-                account_id: None,
-                user_id: None,
-                account_user_id: None,
-                account_structure: None,
-                workspace_name: Some("团队空间".to_string()),
-                subscription_active_until: Some("2026-04-26T13:24:00Z".to_string()),
-                organizations: None,
-            },
-            usage_info: Some(TrayUsageSummary {
-                status: Some("ok".to_string()),
-                message: None,
-                plan_type: Some("team".to_string()),
-                context_window: None,
-                five_hour_limit: Some(TrayLimitSummary {
-                    percent_left: 46.0,
-                    reset_time: "0".to_string(),
-                }),
-                weekly_limit: Some(TrayLimitSummary {
-                    percent_left: 84.0,
-                    reset_time: "0".to_string(),
-                }),
-                code_review_limit: None,
-                last_updated: Some("0".to_string()),
-                source_file: None,
+    fn account_session_lookup_prefers_recorded_account_ids_over_email() {
+        let limits = RateLimits {
+            primary: Some(RateLimitEntry {
+                used_percent: 10.0,
+                window_minutes: 300,
+                resets_at: 1777000000,
+            }),
+            secondary: Some(RateLimitEntry {
+                used_percent: 20.0,
+                window_minutes: 10080,
+                resets_at: 1777600000,
             }),
-            is_active: true,
-            created_at: "0".to_string(),
-            updated_at: "0".to_string(),
         };
+        let entry = |modified_ms: i64,
+                     meta_account_id: Option<&str>,
+                     account_id: Option<&str>,
+                     email: &str| SessionIndexEntry {
+            modified_ms: Some(modified_ms),
+            meta_account_id: meta_account_id.map(str::to_string),
+            account_id: account_id.map(str::to_string),
+            emails: vec![email.to_string()],
+            rate_limits: Some(limits.clone()),
+            ..Default::default()
+        };
+        let mut index = SessionIndex::default();
+        index.files.insert(
+            "bound.jsonl".to_string(),
+            entry(1, None, Some("acc-a"), "a@example.com"),
+        );
+        index
+            .files
+            .insert("meta.jsonl".to_string(), entry(2, Some("acc-a"), None, ""));
+        // 同一邮箱的另一个工作区，时间更新但不能算作 acc-a
+        index.files.insert(
+            "other-workspace.jsonl".to_string(),
+            entry(5, None, Some("acc-b"), "a@example.com"),
+        );
+        index.files.insert(
+            "unbound.jsonl".to_string(),
+            entry(4, None, None, "A@Example.com"),
+        );
+        let mut no_limits = entry(9, Some("acc-a"), None, "");
+        no_limits.rate_limits = None;
+        index.files.insert("no-limits.jsonl".to_string(), no_limits);
 
+        let (matched_by, path, _) =
+            find_account_session(&index, Some("acc-a"), Some("a@example.com")).unwrap();
         assert_eq!(
-            build_tray_account_title(&account),
-            "test@example.com / 团队空间"
+            (matched_by, path.as_str()),
+            (SESSION_MATCH_META, "meta.jsonl")
         );
+        assert_eq!(session_match_confidence(matched_by), "high");
+
+        index.files.remove("meta.jsonl");
+        let (matched_by, path, _) = find_account_session(&index, Some("acc-a"), None).unwrap();
         assert_eq!(
-            build_tray_account_detail(&account),
-            "5H 46%  周 84%  审查 --  到期 2026-04-26"
+            (matched_by, path.as_str()),
+            (SESSION_MATCH_BINDING, "bound.jsonl")
         );
-    }
-
-    #[test]
-    fn parse_rate_limits_supports_free_plan_weekly_only_window() {
-        let value = serde_json::json!({
-            "allowed": true,
-            "limit_reached": false,
-            "primary_window": {
-                "used_percent": 0,
-                "limit_window_seconds": 604800,
-                "reset_after_seconds": 604800,
-                "reset_at": 1777128605
-            },
-            "secondary_window": null
-        });
 
-        let parsed = parse_rate_limits(&value).expect("should parse free weekly-only usage");
+        index.files.remove("bound.jsonl");
+        let (matched_by, path, _) =
+            find_account_session(&index, Some("acc-a"), Some("a@example.com")).unwrap();
+        assert_eq!(
+            (matched_by, path.as_str()),
+            (SESSION_MATCH_EMAIL, "unbound.jsonl")
+        );
+        assert_eq!(session_match_confidence(matched_by), "low");
 
-        assert!(parsed.five_hour.is_none());
+        let (matched_by, path, _) =
+            find_account_session(&index, None, Some("a@example.com")).unwrap();
         assert_eq!(
-            parsed
-                .weekly
-                .as_ref()
-                .expect("weekly limit should exist")
-                .percent_left,
-            100.0
+            (matched_by, path.as_str()),
+            (SESSION_MATCH_EMAIL, "other-workspace.jsonl")
         );
+        assert!(find_account_session(&index, Some("acc-c"), None).is_none());
+
+        // 修改时间相同时按路径决定，结果与 HashMap 的遍历顺序无关
+        index
+            .files
+            .insert("tie-a.jsonl".to_string(), entry(7, Some("acc-d"), None, ""));
+        index
+            .files
+            .insert("tie-b.jsonl".to_string(), entry(7, Some("acc-d"), None, ""));
+        let (_, path, _) = find_account_session(&index, Some("acc-d"), None).unwrap();
+        assert_eq!(path, "tie-b.jsonl");
     }
 
     #[test]
-    fn parse_rate_limits_keeps_dual_window_behavior() {
-        let value = serde_json::json!({
-            "primary_window": {
-                "used_percent": 12,
-                "limit_window_seconds": 18000,
-                "reset_at": 1777000000
-            },
-            "secondary_window": {
-                "used_percent": 34,
-                "limit_window_seconds": 604800,
-                "reset_at": 1777600000
+    fn session_meta_identity_reads_only_known_fields() {
+        let remotes: Vec<String> = (0..SESSION_INDEX_MAX_EMAILS + 2)
+            .map(|index| format!("git{}@github.com", index))
+            .collect();
+        let line = serde_json::json!({
+            "type": "session_meta",
+            "payload": {
+                "id": "s",
+                "git": {"remotes": remotes, "account_id": "nested-id"},
+                "instructions": "contact someone@example.com",
+                "user": {"email": "a@example.com"}
             }
-        });
+        })
+        .to_string();
+        let mut entry = SessionIndexEntry::default();
+        apply_session_line(&mut entry, line.as_bytes());
+        assert_eq!(entry.emails, vec!["a@example.com".to_string()]);
+        assert_eq!(entry.meta_account_id, None);
+
+        let line = r#"{"type":"session_meta","payload":{"id":"s","chatgpt_account_id":"acc-1"}}"#;
+        let mut entry = SessionIndexEntry::default();
+        apply_session_line(&mut entry, line.as_bytes());
+        assert_eq!(entry.meta_account_id.as_deref(), Some("acc-1"));
+    }
 
-        let parsed = parse_rate_limits(&value).expect("should parse dual window usage");
+    #[test]
+    fn proxy_settings_prefer_account_then_manual_then_mode() {
+        let mut config = TrayAppConfig {
+            proxy_no_proxy: Some(" localhost, .internal ".to_string()),
+            ..Default::default()
+        };
+        let credentials = ProxyCredentials {
+            username: "user".to_string(),
+            password: "secret".to_string(),
+        };
 
+        let settings = resolve_proxy_settings(
+            &config,
+            Some(true),
+            Some(" http://127.0.0.1:7890 ".to_string()),
+            None,
+            Some(credentials.clone()),
+        )
+        .unwrap();
         assert_eq!(
-            parsed
-                .five_hour
-                .as_ref()
-                .expect("five hour limit should exist")
-                .percent_left,
-            88.0
+            settings.mode,
+            ProxyMode::Manual("http://127.0.0.1:7890".to_string())
         );
+        assert_eq!(settings.no_proxy.as_deref(), Some("localhost, .internal"));
+        assert_eq!(settings.credentials, Some(credentials.clone()));
+        assert!(build_http_client_with(&HttpClientSettings {
+            proxy: settings,
+            timeouts: resolve_http_timeouts(&config),
+            tls: resolve_tls_settings(&config),
+        })
+        .is_ok());
+
+        let account_direct =
+            resolve_proxy_settings(&config, Some(true), None, Some("direct"), None).unwrap();
+        assert_eq!(account_direct.mode, ProxyMode::Direct);
+        let account_proxy = resolve_proxy_settings(
+            &config,
+            Some(true),
+            Some("http://127.0.0.1:7890".to_string()),
+            Some("socks5://proxy-user:pw@10.0.0.2:1080"),
+            Some(credentials.clone()),
+        )
+        .unwrap();
         assert_eq!(
-            parsed
-                .weekly
-                .as_ref()
-                .expect("weekly limit should exist")
-                .percent_left,
-            66.0
+            account_proxy.mode,
+            ProxyMode::Manual("socks5://proxy-user:pw@10.0.0.2:1080".to_string())
+        );
+        // 全局代理的凭据不发送给账号级代理
+        assert_eq!(account_proxy.credentials, None);
+        assert_eq!(
+            resolve_proxy_settings(&config, None, None, None, Some(credentials.clone()))
+                .unwrap()
+                .credentials,
+            None
+        );
+
+        assert!(
+            resolve_proxy_settings(&config, Some(true), Some(" ".to_string()), None, None).is_err()
+        );
+        assert_eq!(
+            resolve_proxy_settings(&config, Some(false), None, None, None)
+                .unwrap()
+                .mode,
+            ProxyMode::Env
+        );
+        config.proxy_mode = Some(PROXY_MODE_DIRECT.to_string());
+        assert_eq!(
+            resolve_proxy_settings(&config, None, None, None, None)
+                .unwrap()
+                .mode,
+            ProxyMode::Direct
         );
     }
 
     #[test]
-    fn tray_account_detail_skips_missing_five_hour_limit() {
-        let account = TrayStoredAccount {
-            id: "1".to_string(),
-            alias: "免费账号".to_string(),
-            account_info: TrayAccountInfo {
-                email: "free@example.com".to_string(),
-                plan_type: "free".to_string(),
-                account_id: None,
-                user_id: None,
-                account_user_id: None,
-                account_structure: None,
-                workspace_name: None,
-                subscription_active_until: None,
-                organizations: None,
-            },
-            usage_info: Some(TrayUsageSummary {
-                status: Some("ok".to_string()),
-                message: None,
-                plan_type: Some("free".to_string()),
-                context_window: None,
-                five_hour_limit: None,
-                weekly_limit: Some(TrayLimitSummary {
-                    percent_left: 100.0,
-                    reset_time: "04-24 10:10".to_string(),
-                }),
-                code_review_limit: Some(TrayLimitSummary {
-                    percent_left: 100.0,
-                    reset_time: "10:10".to_string(),
-                }),
-                last_updated: Some("0".to_string()),
-                source_file: None,
-            }),
-            is_active: false,
-            created_at: "0".to_string(),
-            updated_at: "0".to_string(),
+    fn http_client_key_tracks_ca_bundle_changes() {
+        let path = std::env::temp_dir().join(format!(
+            "codex-manager-ca-{}-{}.pem",
+            std::process::id(),
+            now_epoch_ms_u64()
+        ));
+        fs::write(&path, "a").unwrap();
+        let config = TrayAppConfig {
+            tls_ca_bundle_paths: Some(vec![path.to_string_lossy().to_string()]),
+            ..Default::default()
+        };
+        let settings = HttpClientSettings {
+            proxy: resolve_proxy_settings(&config, None, None, None, None).unwrap(),
+            timeouts: resolve_http_timeouts(&config),
+            tls: resolve_tls_settings(&config),
+        };
+
+        let before = http_client_key(&settings);
+        assert_eq!(before, http_client_key(&settings));
+        fs::write(&path, "updated").unwrap();
+        let after = http_client_key(&settings);
+        let _ = fs::remove_file(&path);
+        assert_ne!(before, after);
+        assert_eq!(after.settings, before.settings);
+
+        let account_settings =
+            resolve_proxy_settings(&config, None, None, Some("direct"), None).unwrap();
+        assert!(account_settings.account_override);
+        assert!(!settings.proxy.account_override);
+    }
+
+    #[test]
+    fn account_proxy_clients_are_cached_per_settings() {
+        let config = TrayAppConfig::default();
+        let settings = |proxy: &str| HttpClientSettings {
+            proxy: resolve_proxy_settings(&config, None, None, Some(proxy), None).unwrap(),
+            timeouts: resolve_http_timeouts(&config),
+            tls: resolve_tls_settings(&config),
+        };
+        let cached = |settings: &HttpClientSettings| {
+            let key = http_client_key(settings);
+            ACCOUNT_PROXY_HTTP_CLIENTS
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|current| current.key == key)
+                .count()
         };
 
+        let first = settings("http://10.9.8.7:3128");
+        account_proxy_http_client(&first).unwrap();
+        account_proxy_http_client(&first).unwrap();
+        assert_eq!(cached(&first), 1);
+
+        for port in 0..ACCOUNT_PROXY_HTTP_CLIENT_LIMIT {
+            account_proxy_http_client(&settings(&format!("http://10.9.8.6:{}", 4000 + port)))
+                .unwrap();
+        }
+        assert_eq!(cached(&first), 0);
+        assert!(
+            ACCOUNT_PROXY_HTTP_CLIENTS.lock().unwrap().len() <= ACCOUNT_PROXY_HTTP_CLIENT_LIMIT
+        );
+    }
+
+    #[test]
+    fn proxy_credentials_record_never_serializes_password() {
+        let legacy: ProxyCredentialsRecord =
+            serde_json::from_str(r#"{"username":"user","password":"secret"}"#).unwrap();
+        assert_eq!(legacy.password.as_deref(), Some("secret"));
         assert_eq!(
-            build_tray_account_detail(&account),
-            "周 100%  审查 100%  到期 --"
+            serde_json::to_value(&legacy).unwrap(),
+            serde_json::json!({ "username": "user" })
         );
     }
 
     #[test]
-    fn background_auto_refresh_runs_immediately_and_respects_interval() {
-        assert!(should_run_background_auto_refresh(30, 0, 1));
-        assert!(!should_run_background_auto_refresh(0, 0, 1));
-        assert!(!should_run_background_auto_refresh(30, 60_000, 1_800_000));
-        assert!(should_run_background_auto_refresh(30, 60_000, 1_900_000));
+    fn proxy_test_only_succeeds_when_the_backend_answers() {
+        use reqwest::StatusCode;
+
+        assert!(is_proxy_test_success(StatusCode::OK));
+        assert!(is_proxy_test_success(StatusCode::FOUND));
+        assert!(is_proxy_test_success(StatusCode::UNAUTHORIZED));
+        assert!(!is_proxy_test_success(StatusCode::FORBIDDEN));
+        assert!(!is_proxy_test_success(
+            StatusCode::PROXY_AUTHENTICATION_REQUIRED
+        ));
+        assert!(!is_proxy_test_success(StatusCode::BAD_GATEWAY));
+        assert!(proxy_test_status_error(StatusCode::PROXY_AUTHENTICATION_REQUIRED).contains("407"));
+    }
+
+    #[test]
+    fn diagnostic_events_are_redacted_and_bounded() {
+        let mut events = VecDeque::new();
+        for attempt in 0..MAX_DIAGNOSTIC_EVENTS as u32 + 5 {
+            push_diagnostic_event(
+                &mut events,
+                DiagnosticEvent {
+                    kind: "response".to_string(),
+                    attempt: Some(attempt),
+                    message: Some("token for someone@example.com".to_string()),
+                    body_excerpt: Some(" ".to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        assert_eq!(events.len(), MAX_DIAGNOSTIC_EVENTS);
+        assert_eq!(events.front().and_then(|event| event.attempt), Some(5));
+        let last = events.back().unwrap();
+        assert_eq!(last.message.as_deref(), Some("token for [EMAIL]"));
+        assert!(last.body_excerpt.is_none());
+    }
+
+    #[test]
+    fn usage_windows_serialize_as_snake_case_and_read_legacy_camel_case() {
+        let window = UsageWindow {
+            key: "rate_limit.primary_window".to_string(),
+            label: "5小时".to_string(),
+            window_minutes: Some(300),
+            percent_left: 80.0,
+            reset_time_ms: 1_900_000_000_000,
+        };
+        let value = serde_json::to_value(&window).unwrap();
+        assert_eq!(value["window_minutes"], 300);
+        assert_eq!(value["percent_left"], 80.0);
+        assert_eq!(value["reset_time_ms"], 1_900_000_000_000_i64);
+
+        let legacy: UsageWindow = serde_json::from_value(serde_json::json!({
+            "key": "rate_limit.primary_window",
+            "label": "5小时",
+            "windowMinutes": 300,
+            "percentLeft": 80.0,
+            "resetTimeMs": 1_900_000_000_000_i64
+        }))
+        .unwrap();
+        assert_eq!(legacy, window);
+
+        let context: ContextWindowUsage = serde_json::from_value(serde_json::json!({
+            "percentLeft": 50.0, "usedTokens": 10, "totalTokens": 20
+        }))
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&context).unwrap(),
+            serde_json::json!({"percent_left": 50.0, "used_tokens": 10, "total_tokens": 20})
+        );
+    }
+
+    #[test]
+    fn session_usage_samples_belong_to_the_matching_stored_account() {
+        let account = |id: &str, chatgpt_account_id: &str, is_active: bool| {
+            let mut account = sample_usage_account(id, "ok", None, None);
+            account.account_info.account_id = Some(chatgpt_account_id.to_string());
+            account.is_active = is_active;
+            account
+        };
+        let store = TrayAccountsStore {
+            version: "1".to_string(),
+            accounts: vec![
+                account("a", "chatgpt-1", false),
+                account("b", "chatgpt-2", false),
+                account("c", "chatgpt-2", true),
+            ],
+            config: TrayAppConfig::default(),
+        };
+
+        let found = |chatgpt_account_id: &str| {
+            find_session_history_account(&store, chatgpt_account_id)
+                .map(|account| account.id.as_str())
+        };
+        assert_eq!(found("chatgpt-1"), Some("a"));
+        assert_eq!(found("chatgpt-2"), Some("c"));
+        assert_eq!(found("chatgpt-3"), None);
     }
 }
@@ -0,0 +1,362 @@
+use super::*;
+
+const ACCOUNT_ID: &str = "acc-123";
+
+const FAST_RETRY_POLICY: RetryPolicy = RetryPolicy {
+    max_attempts: 2,
+    base_delay: Duration::from_millis(1),
+    max_delay: Duration::from_millis(1),
+    max_inline_retry_after: Duration::from_secs(1),
+};
+
+fn run_usage_request(server: &mockito::Server, is_current_account: bool) -> UsageResult {
+    tauri::async_runtime::block_on(request_wham_usage(
+        &Client::new(),
+        &format!("{}/backend-api", server.url()),
+        "token",
+        ACCOUNT_ID,
+        is_current_account,
+        &FAST_RETRY_POLICY,
+    ))
+    .expect("request should produce a usage result")
+}
+
+fn mock_usage(server: &mut mockito::Server, status: usize, body: &str) -> mockito::Mock {
+    server
+        .mock("GET", "/backend-api/wham/usage")
+        .match_header("authorization", "Bearer token")
+        .match_header("chatgpt-account-id", ACCOUNT_ID)
+        .with_status(status)
+        .with_header("content-type", "application/json")
+        .with_body(body)
+        .create()
+}
+
+#[test]
+fn api_base_url_prefers_env_then_config() {
+    assert_eq!(resolve_api_base_url(None, None), DEFAULT_API_BASE_URL);
+    assert_eq!(
+        resolve_api_base_url(None, Some("http://localhost:9000/api/")),
+        "http://localhost:9000/api"
+    );
+    assert_eq!(
+        resolve_api_base_url(
+            Some("http://127.0.0.1:1/x".to_string()),
+            Some("http://localhost:9000/api")
+        ),
+        "http://127.0.0.1:1/x"
+    );
+    assert_eq!(
+        resolve_api_base_url(Some("  ".to_string()), Some("")),
+        DEFAULT_API_BASE_URL
+    );
+}
+
+#[test]
+fn usage_ok_parses_rate_limits() {
+    let mut server = mockito::Server::new();
+    let mock = mock_usage(
+        &mut server,
+        200,
+        r#"{
+            "plan_type": "plus",
+            "rate_limit": {
+                "primary_window": {"used_percent": 25, "limit_window_seconds": 18000, "reset_at": 1900000000},
+                "secondary_window": {"used_percent": 60, "limit_window_seconds": 604800, "reset_at": 1900500000}
+            }
+        }"#,
+    );
+
+    let result = run_usage_request(&server, false);
+    mock.assert();
+    assert_eq!(result.status, "ok");
+    assert_eq!(result.plan_type.as_deref(), Some("plus"));
+    let usage = result.usage.expect("usage should be parsed");
+    assert_eq!(usage.five_hour_percent_left, Some(75.0));
+    assert_eq!(usage.weekly_percent_left, Some(40.0));
+}
+
+#[test]
+fn usage_payload_details_are_typed() {
+    let mut server = mockito::Server::new();
+    let mock = mock_usage(
+        &mut server,
+        200,
+        include_str!("../../skills/wham-usage/examples/free-plan-usage.example.json"),
+    );
+
+    let result = run_usage_request(&server, false);
+    mock.assert();
+    assert_eq!(result.status, "ok");
+    let details = result
+        .usage
+        .and_then(|usage| usage.details)
+        .expect("details should be parsed");
+    assert_eq!(details.email.as_deref(), Some("*********@gmail.com"));
+    assert_eq!(
+        details.user_id.as_deref(),
+        Some("user-Pxyt****************mR1j")
+    );
+    assert!(details.account_id.is_some());
+    assert_eq!(result.plan_type.as_deref(), Some("free"));
+    assert_eq!(details.allowed, Some(true));
+    assert_eq!(details.code_review_limit_reached, Some(false));
+    assert_eq!(details.spend_control_reached, Some(false));
+    assert_eq!(
+        details
+            .credits
+            .as_ref()
+            .and_then(|credits| credits.has_credits),
+        Some(false)
+    );
+    assert_eq!(
+        details.promo.and_then(|promo| promo.campaign_id).as_deref(),
+        Some("plus-1-month-free")
+    );
+    assert!(!details.blocked);
+
+    let payload = |value: serde_json::Value| WhamUsagePayload::from_value(&value);
+    let blocked = build_wham_usage_details(&payload(serde_json::json!({
+        "rate_limit": {"allowed": false, "limit_reached": true},
+        "credits": {"balance": 12.5, "unlimited": false},
+        "spend_control": {"reached": true},
+        "rate_limit_reached_type": "workspace_spend_cap",
+        "additional_rate_limits": [{
+            "limit_name": "GPT-5-Codex-Mini",
+            "metered_feature": "codex_mini",
+            "rate_limit": {"primary_window": {"used_percent": 30, "reset_at": 1900000000}}
+        }]
+    })));
+    assert!(blocked.blocked);
+    assert_eq!(
+        blocked
+            .credits
+            .as_ref()
+            .and_then(|credits| credits.balance.as_deref()),
+        Some("12.5")
+    );
+    assert_eq!(blocked.additional_rate_limits.len(), 1);
+
+    // 只有原因字段、没有拦截标记时不算被拦截
+    let reason_only = build_wham_usage_details(&payload(serde_json::json!({
+        "rate_limit": {"allowed": true, "limit_reached": false},
+        "rate_limit_reached_type": "primary"
+    })));
+    assert!(!reason_only.blocked);
+    assert_eq!(
+        reason_only.rate_limit_reached_type.as_deref(),
+        Some("primary")
+    );
+
+    // 存入 accounts.json 后仍能读回
+    let stored: WhamUsageDetails =
+        serde_json::from_str(&serde_json::to_string(&blocked).unwrap()).unwrap();
+    assert_eq!(stored, blocked);
+}
+
+#[test]
+fn usage_windows_cover_all_limits_and_derive_legacy_fields() {
+    let mut server = mockito::Server::new();
+    let mock = mock_usage(
+        &mut server,
+        200,
+        r#"{
+            "rate_limit": {
+                "primary_window": {"used_percent": 20, "limit_window_seconds": 18000, "reset_at": 1900000000},
+                "secondary_window": {"used_percent": 50, "limit_window_seconds": 86400, "reset_at": 1900050000}
+            },
+            "code_review_rate_limit": {
+                "primary_window": {"used_percent": 10, "limit_window_seconds": 604800, "reset_at": 1900500000}
+            },
+            "additional_rate_limits": [{
+                "limit_name": "GPT-5-Codex-Mini",
+                "rate_limit": {"primary_window": {"used_percent": 40, "limit_window_seconds": 18000, "reset_at": 1900000000}}
+            }]
+        }"#,
+    );
+
+    let result = run_usage_request(&server, false);
+    mock.assert();
+    let usage = result.usage.expect("usage should be parsed");
+    let windows: Vec<(&str, &str, f64)> = usage
+        .windows
+        .iter()
+        .map(|window| {
+            (
+                window.key.as_str(),
+                window.label.as_str(),
+                window.percent_left,
+            )
+        })
+        .collect();
+    assert_eq!(
+        windows,
+        vec![
+            ("rate_limit.primary_window", "5小时", 80.0),
+            ("rate_limit.secondary_window", "每日", 50.0),
+            (
+                "code_review_rate_limit.primary_window",
+                "代码审查 · 每周",
+                90.0
+            ),
+            (
+                "additional_rate_limits[0].rate_limit.primary_window",
+                "GPT-5-Codex-Mini · 5小时",
+                60.0
+            ),
+        ]
+    );
+    // 兼容字段只由恰好 5 小时 / 每周的窗口派生，每日窗口只出现在 windows 中
+    assert_eq!(usage.five_hour_percent_left, Some(80.0));
+    assert_eq!(usage.weekly_percent_left, None);
+    assert_eq!(usage.weekly_reset_time_ms, None);
+    let extra_windows: Vec<&str> = usage
+        .windows
+        .iter()
+        .filter(|window| !window.is_legacy_limit())
+        .map(|window| window.key.as_str())
+        .collect();
+    assert_eq!(
+        extra_windows,
+        vec![
+            "rate_limit.secondary_window",
+            "additional_rate_limits[0].rate_limit.primary_window"
+        ]
+    );
+    assert_eq!(usage.code_review_percent_left, Some(90.0));
+}
+
+#[test]
+fn usage_unauthorized_maps_to_expired_or_stale_token() {
+    let mut server = mockito::Server::new();
+    let mock = mock_usage(&mut server, 401, "{}").expect(2);
+
+    assert_eq!(run_usage_request(&server, true).status, "expired");
+    assert_eq!(run_usage_request(&server, false).status, "stale_token");
+    mock.assert();
+}
+
+#[test]
+fn usage_forbidden_and_server_errors() {
+    let mut server = mockito::Server::new();
+    let forbidden = mock_usage(&mut server, 403, "{}");
+    assert_eq!(run_usage_request(&server, false).status, "forbidden");
+    forbidden.assert();
+    forbidden.remove();
+
+    let failing = mock_usage(&mut server, 500, "oops").expect(2);
+    let result = run_usage_request(&server, false);
+    failing.assert();
+    assert_eq!(result.status, "error");
+    assert!(result.message.unwrap_or_default().contains("500"));
+}
+
+#[test]
+fn usage_rate_limited_honours_retry_after() {
+    let mut server = mockito::Server::new();
+    let limited = server
+        .mock("GET", "/backend-api/wham/usage")
+        .with_status(429)
+        .with_header("retry-after", "120")
+        .create();
+
+    let before_ms = now_epoch_ms_u64();
+    let result = run_usage_request(&server, false);
+    limited.assert();
+    assert_eq!(result.status, "rate_limited");
+    let next_retry_at_ms = result.next_retry_at_ms.expect("retry time should be set");
+    assert!(next_retry_at_ms >= before_ms + 120_000);
+    assert!(next_retry_at_ms <= now_epoch_ms_u64() + 120_000);
+    limited.remove();
+
+    let short_limit = server
+        .mock("GET", "/backend-api/wham/usage")
+        .with_status(429)
+        .with_header("retry-after", "0")
+        .create();
+    let recovered = mock_usage(
+        &mut server,
+        200,
+        r#"{"rate_limit": {"primary_window": {"used_percent": 10, "limit_window_seconds": 18000, "reset_at": 1900000000}}}"#,
+    );
+    let result = run_usage_request(&server, false);
+    short_limit.assert();
+    recovered.assert();
+    assert_eq!(result.status, "ok");
+}
+
+#[test]
+fn failed_responses_are_recorded_redacted_for_diagnostics() {
+    let mut server = mockito::Server::new();
+    let mock = mock_usage(
+        &mut server,
+        418,
+        r#"{"detail": "teapot-diagnostic-marker blocked for someone@example.com", "access_token": "secret-value"}"#,
+    );
+    assert_eq!(run_usage_request(&server, false).status, "error");
+    mock.assert();
+
+    // 事件队列是全局的，其他并行测试也会写入，按本测试独有的标记查找
+    let body = DIAGNOSTIC_EVENTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|event| event.body_excerpt.clone())
+        .find(|body| body.contains("teapot-diagnostic-marker"))
+        .expect("failed response should be recorded");
+    assert!(body.contains("[EMAIL]"));
+    assert!(!body.contains("someone@example.com"));
+    assert!(!body.contains("secret-value"));
+}
+
+#[test]
+fn usage_without_rate_limit_is_no_usage() {
+    let mut server = mockito::Server::new();
+    let mock = mock_usage(&mut server, 200, r#"{"plan_type": "free"}"#);
+
+    let result = run_usage_request(&server, false);
+    mock.assert();
+    assert_eq!(result.status, "no_usage");
+    assert_eq!(result.plan_type.as_deref(), Some("free"));
+}
+
+#[test]
+fn account_metadata_matches_chatgpt_account() {
+    let mut server = mockito::Server::new();
+    let mock = server
+        .mock("GET", "/backend-api/wham/accounts/check")
+        .with_status(200)
+        .with_body(
+            r#"{"accounts": [
+                {"id": "other", "name": "Other", "structure": "workspace"},
+                {"id": "acc-123", "name": "Team Space", "structure": "workspace", "plan_type": "team"}
+            ]}"#,
+        )
+        .create();
+
+    let metadata = tauri::async_runtime::block_on(request_wham_account_metadata(
+        &Client::new(),
+        &format!("{}/backend-api", server.url()),
+        "token",
+        ACCOUNT_ID,
+    ))
+    .expect("metadata request should succeed")
+    .expect("account should match");
+    mock.assert();
+    assert_eq!(metadata.workspace_name.as_deref(), Some("Team Space"));
+    assert_eq!(metadata.plan_type.as_deref(), Some("team"));
+
+    let failing = server
+        .mock("GET", "/backend-api/wham/accounts/check")
+        .with_status(401)
+        .create();
+    let error = tauri::async_runtime::block_on(request_wham_account_metadata(
+        &Client::new(),
+        &format!("{}/backend-api", server.url()),
+        "token",
+        ACCOUNT_ID,
+    ))
+    .expect_err("non-success status should fail");
+    failing.assert();
+    assert!(error.contains("401"));
+}
@@ -0,0 +1,225 @@
+//! 网络请求的重试策略：区分可重试的临时错误，遵循 429 的 Retry-After，并使用带抖动的指数退避
+
+use std::time::{Duration, SystemTime};
+
+use chrono::DateTime;
+use reqwest::StatusCode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RetryPolicy {
+    /// 最多发起的请求次数（含首次）
+    pub(crate) max_attempts: u32,
+    pub(crate) base_delay: Duration,
+    pub(crate) max_delay: Duration,
+    /// Retry-After 不超过该值时在本次请求内等待后重试，否则直接返回限流结果
+    pub(crate) max_inline_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(4),
+            max_inline_retry_after: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 所有重试等待时间的上限，用于推算单次刷新的总时限
+    pub(crate) fn max_total_delay(&self) -> Duration {
+        let retries = self.max_attempts.saturating_sub(1);
+        self.max_delay.max(self.max_inline_retry_after) * retries
+    }
+
+    /// 第 attempt 次（从 1 开始）失败后的退避时长：指数增长并封顶，其中一半为随机抖动
+    pub(crate) fn backoff_delay(&self, attempt: u32, jitter_fraction: f64) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16);
+        let delay = self
+            .base_delay
+            .saturating_mul(1u32 << exponent)
+            .min(self.max_delay);
+        let half = delay / 2;
+        half + half.mul_f64(jitter_fraction.clamp(0.0, 1.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ResponseClass {
+    Success,
+    /// 429，附带服务端建议的等待时间
+    RateLimited(Option<Duration>),
+    /// 5xx / 408 等可以重试的临时错误
+    Transient,
+    /// 其余状态码交给调用方按业务处理
+    Permanent,
+}
+
+pub(crate) fn classify_response(status: StatusCode, retry_after: Option<&str>) -> ResponseClass {
+    if status.is_success() {
+        return ResponseClass::Success;
+    }
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return ResponseClass::RateLimited(
+            retry_after.and_then(|value| parse_retry_after(value, SystemTime::now())),
+        );
+    }
+    if status.is_server_error() && status != StatusCode::NOT_IMPLEMENTED
+        || status == StatusCode::REQUEST_TIMEOUT
+    {
+        return ResponseClass::Transient;
+    }
+    ResponseClass::Permanent
+}
+
+/// 只有超时、连接失败和读取响应体失败可以重试；构造请求失败等错误重试也不会成功，证书校验失败同理
+pub(crate) fn is_transient_error(error: &reqwest::Error) -> bool {
+    !is_certificate_error(error) && (error.is_timeout() || error.is_connect() || error.is_body())
+}
+
+/// 展开错误链，reqwest 的顶层错误信息通常不包含真正的失败原因
+pub(crate) fn error_chain_message(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(current) = source {
+        let current_message = current.to_string();
+        if !message.contains(&current_message) {
+            message.push_str(": ");
+            message.push_str(&current_message);
+        }
+        source = current.source();
+    }
+    message
+}
+
+/// TLS 证书校验失败（如公司代理的 TLS 拦截、自签名证书）
+pub(crate) fn is_certificate_error(error: &reqwest::Error) -> bool {
+    is_certificate_error_chain(error)
+}
+
+/// 沿错误链查找 rustls 的证书错误；hyper 会把 TLS 错误包在 io::Error 里，
+/// 而 io::Error 的 source() 会跳过被包装的错误本身，需要通过 get_ref() 取出
+fn is_certificate_error_chain(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if is_rustls_certificate_error(error) {
+            return true;
+        }
+        if let Some(inner) = error
+            .downcast_ref::<std::io::Error>()
+            .and_then(|io_error| io_error.get_ref())
+        {
+            if is_certificate_error_chain(inner) {
+                return true;
+            }
+        }
+        current = error.source();
+    }
+    false
+}
+
+fn is_rustls_certificate_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(
+        error.downcast_ref::<rustls::Error>(),
+        Some(
+            rustls::Error::InvalidCertificate(_)
+                | rustls::Error::InvalidCertRevocationList(_)
+                | rustls::Error::NoCertificatesPresented
+        )
+    )
+}
+
+/// 解析 Retry-After：支持秒数与 HTTP 日期两种格式
+pub(crate) fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let retry_at = DateTime::parse_from_rfc2822(value).ok()?;
+    let retry_at = SystemTime::from(retry_at);
+    Some(retry_at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_accepts_seconds_and_http_dates() {
+        let now = SystemTime::from(
+            DateTime::parse_from_rfc2822("Sun, 06 Nov 1994 08:49:37 GMT").unwrap(),
+        );
+        assert_eq!(
+            parse_retry_after(" 120 ", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:50:07 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn classifies_statuses_and_backs_off_with_cap() {
+        assert_eq!(
+            classify_response(StatusCode::TOO_MANY_REQUESTS, Some("7")),
+            ResponseClass::RateLimited(Some(Duration::from_secs(7)))
+        );
+        assert_eq!(
+            classify_response(StatusCode::BAD_GATEWAY, None),
+            ResponseClass::Transient
+        );
+        assert_eq!(
+            classify_response(StatusCode::FORBIDDEN, None),
+            ResponseClass::Permanent
+        );
+
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff_delay(1, 0.0), Duration::from_millis(250));
+        assert_eq!(policy.backoff_delay(2, 1.0), Duration::from_secs(1));
+        assert_eq!(policy.backoff_delay(10, 1.0), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn recognises_certificate_failures() {
+        #[derive(Debug)]
+        struct Wrapper(std::io::Error);
+
+        impl std::fmt::Display for Wrapper {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("client error (Connect)")
+            }
+        }
+
+        impl std::error::Error for Wrapper {
+            fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+                Some(&self.0)
+            }
+        }
+
+        let certificate = Wrapper(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            rustls::Error::InvalidCertificate(rustls::CertificateError::UnknownIssuer),
+        ));
+        assert!(is_certificate_error_chain(&certificate));
+
+        // 错误信息里提到证书但并非 rustls 的证书错误
+        let refused = Wrapper(std::io::Error::new(
+            std::io::ErrorKind::ConnectionRefused,
+            "invalid peer certificate: UnknownIssuer",
+        ));
+        assert!(!is_certificate_error_chain(&refused));
+        let handshake = Wrapper(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            rustls::Error::HandshakeNotComplete,
+        ));
+        assert!(!is_certificate_error_chain(&handshake));
+    }
+}
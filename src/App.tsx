@@ -19,7 +19,12 @@ import {
 } from './components';
 import { useAutoRefresh } from './hooks';
 import { useAccountStore } from './stores/useAccountStore';
-import type { AppConfig, StoredAccount } from './types';
+import type {
+  AppConfig,
+  SessionContextWindowEvent,
+  StoredAccount,
+  UsageRefreshAccountEvent,
+} from './types';
 import {
   DEFAULT_ACCOUNT_FILTERS,
   type AccountFilterState,
@@ -29,9 +34,11 @@ import {
 import { getAccountExpiryBucket, getSubscriptionExpirationState } from './utils/accountStatus';
 import { syncCodexProxyEnv } from './utils/codexEnv';
 import {
+  canMoveAccount,
   exportAccountsBackup,
   importAccountsBackup,
   isMissingIdentityError,
+  sortAccountsManually,
   type AddAccountOptions,
 } from './utils/storage';
 
@@ -96,10 +103,13 @@ function App() {
     switchToAccount,
     syncCurrentAccount,
     updateConfig,
+    updateAccountProxy,
+    updateAccountPlacement,
+    moveAccount,
     setError,
     clearError,
   } = useAccountStore();
-  const { refreshAllUsage, refreshSingleAccount, isRefreshing } = useAutoRefresh();
+  const { refreshAllUsage, cancelRefreshAll, refreshSingleAccount, isRefreshing } = useAutoRefresh();
 
   const [showAddModal, setShowAddModal] = useState(false);
   const [showSettings, setShowSettings] = useState(false);
@@ -280,6 +290,9 @@ function App() {
     let unlistenWindowClose: (() => void) | null = null;
     let unlistenTraySwitch: (() => void) | null = null;
     let unlistenBackgroundRefresh: (() => void) | null = null;
+    let unlistenAccountRefresh: (() => void) | null = null;
+    let unlistenContextWindow: (() => void) | null = null;
+    let unlistenTrayConfigChange: (() => void) | null = null;
     let unlistenFocusChange: (() => void) | null = null;
 
     const registerListeners = async () => {
@@ -339,6 +352,30 @@ function App() {
         }
       );
 
+      // 后台刷新期间每个账号完成后即时刷新列表
+      unlistenAccountRefresh = await listen<UsageRefreshAccountEvent>(
+        'usage-refresh-account',
+        async (event) => {
+          if (event.payload?.phase !== 'started') {
+            await loadAccounts();
+          }
+        }
+      );
+
+      // 当前会话的上下文窗口随 session 写入即时更新
+      unlistenContextWindow = await listen<SessionContextWindowEvent>(
+        'session-context-window-changed',
+        (event) => {
+          useAccountStore
+            .getState()
+            .applyContextWindow(event.payload.accountId, event.payload.contextWindow ?? undefined);
+        }
+      );
+
+      unlistenTrayConfigChange = await listen('tray-config-changed', async () => {
+        await loadAccounts();
+      });
+
       unlistenFocusChange = await currentWindow.onFocusChanged(async ({ payload: focused }) => {
         if (!focused || !hasLoadedAccounts) {
           return;
@@ -357,6 +394,9 @@ function App() {
       unlistenWindowClose?.();
       unlistenTraySwitch?.();
       unlistenBackgroundRefresh?.();
+      unlistenAccountRefresh?.();
+      unlistenContextWindow?.();
+      unlistenTrayConfigChange?.();
       unlistenFocusChange?.();
     };
   }, [hasLoadedAccounts, loadAccounts, refreshSingleAccount, setError, showToast]);
@@ -367,7 +407,7 @@ function App() {
     void invoke('refresh_tray_menu').catch((currentError) => {
       console.error('Failed to refresh tray menu:', currentError);
     });
-  }, [accounts, config.closeBehavior, hasLoadedAccounts]);
+  }, [accounts, config.closeBehavior, config.traySortMode, config.trayHideUnavailable, hasLoadedAccounts]);
 
   const handleAddAccount = async (authJson: string, alias?: string) => {
     try {
@@ -613,12 +653,19 @@ function App() {
   };
 
   const handleRefreshAll = async () => {
-    if (isRefreshing) return;
+    if (isRefreshing) {
+      if (refreshingAccountId === 'all') {
+        await cancelRefreshAll();
+      }
+      return;
+    }
     setRefreshingAccountId('all');
     try {
       const result = await refreshAllUsage();
       if (result.skipped) return;
-      if (result.updated > 0) {
+      if (result.cancelled) {
+        showToast(`已取消刷新（已完成 ${result.updated + result.missing} 个账号）`, 'warning');
+      } else if (result.updated > 0) {
         showToast('刷新成功', 'success');
       } else {
         showToast('未找到用量信息，请稍后重试', 'warning');
@@ -632,7 +679,7 @@ function App() {
     if (isRefreshing) return;
     setRefreshingAccountId(accountId);
     try {
-      const result = await refreshSingleAccount(accountId);
+      const result = await refreshSingleAccount(accountId, true);
       if (result.status === 'success') {
         showToast('刷新成功', 'success');
       } else {
@@ -829,7 +876,9 @@ function App() {
     ['free', 'plus', 'pro', 'team'] as const
   ).filter((plan) => accounts.some((account) => account.accountInfo.planType === plan));
 
-  const filteredAccounts = accounts.filter((account) => {
+  // 主界面与托盘的手动排序保持一致，便于在卡片上调整顺序
+  const orderedAccounts = sortAccountsManually(accounts);
+  const filteredAccounts = orderedAccounts.filter((account) => {
     if (filters.plan !== 'all' && account.accountInfo.planType !== filters.plan) {
       return false;
     }
@@ -971,6 +1020,18 @@ function App() {
                           accountName: account.alias,
                         })}
                         onRefresh={() => handleRefresh(account.id)}
+                        onUpdateProxy={(proxyUrl) => updateAccountProxy(account.id, proxyUrl)}
+                        onUpdatePlacement={(placement) => updateAccountPlacement(account.id, placement)}
+                        onMoveUp={
+                          canMoveAccount(orderedAccounts, account, -1)
+                            ? () => void moveAccount(account.id, -1)
+                            : undefined
+                        }
+                        onMoveDown={
+                          canMoveAccount(orderedAccounts, account, 1)
+                            ? () => void moveAccount(account.id, 1)
+                            : undefined
+                        }
                         isRefreshing={isRefreshing}
                         isRefreshingSelf={
                           isRefreshing && (refreshingAccountId === account.id || refreshingAccountId === 'all')
@@ -1,13 +1,18 @@
-import React from 'react';
+import React, { useState } from 'react';
 import type { StoredAccount } from '../types';
 import UsageBar from './UsageBar';
-import { getSubscriptionPresentation } from '../utils/accountStatus';
+import { getSubscriptionPresentation, isLegacyLimitWindow } from '../utils/accountStatus';
 
 interface AccountCardProps {
   account: StoredAccount;
   onSwitch: () => void;
   onDelete: () => void;
   onRefresh: () => void | Promise<void>;
+  onUpdateProxy?: (proxyUrl: string) => Promise<void>;
+  onUpdatePlacement?: (placement: { pinned?: boolean; group?: string }) => Promise<void>;
+  // 在手动排序中上移 / 下移；已在边界时不传
+  onMoveUp?: () => void;
+  onMoveDown?: () => void;
   isRefreshing?: boolean;
   isRefreshingSelf?: boolean;
 }
@@ -30,6 +35,9 @@ const planTypeLabels: Record<string, string> = {
 const accountErrorStates: Record<string, { label: string; className: string }> = {
   expired: { label: '已过期', className: 'bg-red-50 text-red-600 border-red-200' },
   forbidden: { label: '已封号', className: 'bg-red-50 text-red-600 border-red-200' },
+  cooldown: { label: '冷却中', className: 'bg-amber-50 text-amber-600 border-amber-200' },
+  rate_limited: { label: '限流中', className: 'bg-amber-50 text-amber-600 border-amber-200' },
+  tls_error: { label: '证书错误', className: 'bg-red-50 text-red-600 border-red-200' },
   error: { label: '异常', className: 'bg-red-50 text-red-600 border-red-200' },
 };
 
@@ -61,6 +69,15 @@ function resolveTeamName(account: StoredAccount): string | null {
   return uniqueNonPersonalTitles.length === 1 ? uniqueNonPersonalTitles[0] : null;
 }
 
+// 预计耗尽时间（仅在重置前会耗尽时由后端给出）
+function formatExhaustionText(projectedExhaustionMs?: number): string | null {
+  if (!projectedExhaustionMs || projectedExhaustionMs <= Date.now()) return null;
+  const date = new Date(projectedExhaustionMs);
+  const hours = String(date.getHours()).padStart(2, '0');
+  const minutes = String(date.getMinutes()).padStart(2, '0');
+  return `预计 ${hours}:${minutes} 耗尽`;
+}
+
 function getEmailPrefix(email: string): string {
   return email.split('@')[0];
 }
@@ -92,15 +109,54 @@ export const AccountCard: React.FC<AccountCardProps> = ({
   onSwitch,
   onDelete,
   onRefresh,
+  onUpdateProxy,
+  onUpdatePlacement,
+  onMoveUp,
+  onMoveDown,
   isRefreshing = false,
   isRefreshingSelf = false,
 }) => {
   const { accountInfo, usageInfo, isActive } = account;
+  const [isEditingProxy, setIsEditingProxy] = useState(false);
+  const [proxyDraft, setProxyDraft] = useState(account.proxyUrl ?? '');
+  const [proxyError, setProxyError] = useState<string | null>(null);
+  const [isEditingPlacement, setIsEditingPlacement] = useState(false);
+  const [groupDraft, setGroupDraft] = useState(account.group ?? '');
+  const [placementError, setPlacementError] = useState<string | null>(null);
+
+  const handleUpdatePlacement = async (placement: { pinned?: boolean; group?: string }) => {
+    if (!onUpdatePlacement) return;
+    setPlacementError(null);
+    try {
+      await onUpdatePlacement(placement);
+    } catch (error) {
+      setPlacementError(error instanceof Error ? error.message : String(error));
+    }
+  };
+
+  const handleSaveProxy = async () => {
+    if (!onUpdateProxy) return;
+    setProxyError(null);
+    try {
+      await onUpdateProxy(proxyDraft);
+      setIsEditingProxy(false);
+    } catch (error) {
+      setProxyError(error instanceof Error ? error.message : String(error));
+    }
+  };
   const displayName = resolveCardTitle(account);
-  const hasUsage = !!usageInfo && (!usageInfo.status || usageInfo.status === 'ok');
+  // 在线获取失败时仍展示附带的本地 session 用量
+  const hasUsage =
+    !!usageInfo &&
+    (!usageInfo.status || usageInfo.status === 'ok' || usageInfo.source === 'session');
   const fiveHourLeft = usageInfo?.fiveHourLimit?.percentLeft;
   const weeklyLeft = usageInfo?.weeklyLimit?.percentLeft;
   const codeReviewLeft = usageInfo?.codeReviewLimit?.percentLeft;
+  // 5h / 周 / 代码审查之外的额度窗口（如每日额度、额外额度）
+  const additionalWindows = (usageInfo?.windows ?? []).filter(
+    (window) => !isLegacyLimitWindow(window)
+  );
+  const contextWindow = isActive ? usageInfo?.contextWindow : undefined;
   const fiveHourReset = usageInfo?.fiveHourLimit?.resetTime;
   const weeklyReset = usageInfo?.weeklyLimit?.resetTime;
 
@@ -120,6 +176,10 @@ export const AccountCard: React.FC<AccountCardProps> = ({
 
   const weeklyResetText = normalizeWeeklyReset(weeklyReset);
   const fiveHourResetText = normalizeFiveHourReset(fiveHourReset);
+  const fiveHourExhaustionText = formatExhaustionText(
+    usageInfo?.fiveHourLimit?.projectedExhaustionMs
+  );
+  const weeklyExhaustionText = formatExhaustionText(usageInfo?.weeklyLimit?.projectedExhaustionMs);
   const subscriptionInfo = getSubscriptionPresentation(accountInfo.subscriptionActiveUntil);
   const showFiveHourSection = !hasUsage || !!usageInfo?.fiveHourLimit;
 
@@ -179,6 +239,32 @@ export const AccountCard: React.FC<AccountCardProps> = ({
                   {errorState.label}
                 </span>
               )}
+              {!errorState && usageInfo?.details?.blocked && (
+                <span
+                  className="dash-pill bg-red-50 text-red-600 border-red-200 shrink-0"
+                  title={usageInfo.details.rate_limit_reached_type ?? undefined}
+                >
+                  已受限
+                </span>
+              )}
+              {account.pinned && (
+                <span className="dash-pill bg-blue-50 text-blue-600 shrink-0">置顶</span>
+              )}
+              {account.group && (
+                <span className="dash-pill shrink-0 max-w-[96px] truncate" title={`分组：${account.group}`}>
+                  {account.group}
+                </span>
+              )}
+              {usageInfo?.source === 'session' && (
+                <span className="dash-pill shrink-0" title={usageInfo.message}>
+                  本地数据
+                </span>
+              )}
+              {usageInfo?.details?.credits?.balance && (
+                <span className="dash-pill shrink-0" title="Credits 余额">
+                  余额 {usageInfo.details.credits.balance}
+                </span>
+              )}
             </div>
             <p
               className="text-xs text-[var(--dash-text-secondary)] truncate whitespace-nowrap mt-1"
@@ -212,6 +298,44 @@ export const AccountCard: React.FC<AccountCardProps> = ({
               <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M4 4v5h.582m15.356 2A8.001 8.001 0 004.582 9m0 0H9m11 11v-5h-.581m0 0a8.003 8.003 0 01-15.357-2m15.357 2H15" />
             </svg>
           </button>
+          {onUpdateProxy && (
+            <button
+              onClick={() => {
+                setProxyDraft(account.proxyUrl ?? '');
+                setProxyError(null);
+                setIsEditingProxy(!isEditingProxy);
+              }}
+              className={`h-8 w-8 rounded-full border transition-colors flex items-center justify-center ${
+                account.proxyUrl
+                  ? 'border-blue-200 text-blue-600'
+                  : 'border-[var(--dash-border)] text-[var(--dash-text-secondary)] hover:text-[var(--dash-text-primary)] hover:border-slate-300'
+              }`}
+              title={account.proxyUrl ? `账号代理：${account.proxyUrl}` : '账号代理'}
+            >
+              <svg className="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M21 12a9 9 0 01-9 9m9-9a9 9 0 00-9-9m9 9H3m9 9a9 9 0 01-9-9m9 9c1.657 0 3-4.03 3-9s-1.343-9-3-9m0 18c-1.657 0-3-4.03-3-9s1.343-9 3-9m-9 9a9 9 0 019-9" />
+              </svg>
+            </button>
+          )}
+          {onUpdatePlacement && (
+            <button
+              onClick={() => {
+                setGroupDraft(account.group ?? '');
+                setPlacementError(null);
+                setIsEditingPlacement(!isEditingPlacement);
+              }}
+              className={`h-8 w-8 rounded-full border transition-colors flex items-center justify-center ${
+                account.pinned || account.group
+                  ? 'border-blue-200 text-blue-600'
+                  : 'border-[var(--dash-border)] text-[var(--dash-text-secondary)] hover:text-[var(--dash-text-primary)] hover:border-slate-300'
+              }`}
+              title="置顶、分组与排序"
+            >
+              <svg className="w-4 h-4" fill="none" stroke="currentColor" viewBox="0 0 24 24">
+                <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M7 16V4m0 0L3 8m4-4l4 4m6 0v12m0 0l4-4m-4 4l-4-4" />
+              </svg>
+            </button>
+          )}
           <button
             onClick={onDelete}
             className="h-8 w-8 rounded-full border border-[var(--dash-border)] text-[var(--dash-text-secondary)] hover:text-red-600 hover:border-red-200 transition-colors flex items-center justify-center"
@@ -224,6 +348,74 @@ export const AccountCard: React.FC<AccountCardProps> = ({
         </div>
       </div>
 
+      {isEditingProxy && (
+        <div className="mt-3 space-y-1.5">
+          <div className="flex items-center gap-2">
+            <input
+              type="text"
+              value={proxyDraft}
+              onChange={(e) => setProxyDraft(e.target.value)}
+              placeholder="留空跟随全局设置，direct 表示直连"
+              className="flex-1 min-w-0 h-8 px-3 bg-white border border-[var(--dash-border)] rounded-xl text-xs text-[var(--dash-text-primary)] placeholder-[var(--dash-text-muted)] focus:border-blue-400 outline-none transition-colors"
+            />
+            <button
+              onClick={() => void handleSaveProxy()}
+              className="h-8 px-3 bg-[var(--dash-accent)] text-white rounded-full text-xs font-medium hover:brightness-110 transition-colors whitespace-nowrap"
+            >
+              保存
+            </button>
+          </div>
+          {proxyError && <p className="text-xs text-red-500 break-all">{proxyError}</p>}
+        </div>
+      )}
+
+      {isEditingPlacement && (
+        <div className="mt-3 space-y-1.5">
+          <div className="flex items-center gap-2">
+            <input
+              type="text"
+              value={groupDraft}
+              onChange={(e) => setGroupDraft(e.target.value)}
+              placeholder="托盘分组，留空表示不分组"
+              className="flex-1 min-w-0 h-8 px-3 bg-white border border-[var(--dash-border)] rounded-xl text-xs text-[var(--dash-text-primary)] placeholder-[var(--dash-text-muted)] focus:border-blue-400 outline-none transition-colors"
+            />
+            <button
+              onClick={() => void handleUpdatePlacement({ group: groupDraft })}
+              className="h-8 px-3 bg-[var(--dash-accent)] text-white rounded-full text-xs font-medium hover:brightness-110 transition-colors whitespace-nowrap"
+            >
+              保存
+            </button>
+          </div>
+          <div className="flex items-center gap-2">
+            <button
+              onClick={() => void handleUpdatePlacement({ pinned: !account.pinned })}
+              className={`h-8 px-3 rounded-full border text-xs transition-colors whitespace-nowrap ${
+                account.pinned
+                  ? 'border-blue-200 bg-blue-50 text-blue-600'
+                  : 'border-[var(--dash-border)] text-[var(--dash-text-secondary)] hover:text-[var(--dash-text-primary)] hover:border-slate-300'
+              }`}
+            >
+              {account.pinned ? '取消置顶' : '置顶'}
+            </button>
+            <button
+              onClick={onMoveUp}
+              disabled={!onMoveUp}
+              className="h-8 px-3 rounded-full border border-[var(--dash-border)] text-xs text-[var(--dash-text-secondary)] hover:text-[var(--dash-text-primary)] hover:border-slate-300 transition-colors disabled:opacity-50 disabled:pointer-events-none"
+            >
+              上移
+            </button>
+            <button
+              onClick={onMoveDown}
+              disabled={!onMoveDown}
+              className="h-8 px-3 rounded-full border border-[var(--dash-border)] text-xs text-[var(--dash-text-secondary)] hover:text-[var(--dash-text-primary)] hover:border-slate-300 transition-colors disabled:opacity-50 disabled:pointer-events-none"
+            >
+              下移
+            </button>
+          </div>
+          {placementError && <p className="text-xs text-red-500 break-all">{placementError}</p>}
+        </div>
+      )}
+
       <div className="account-card__divider" />
 
       <div className="space-y-3">
@@ -236,6 +428,11 @@ export const AccountCard: React.FC<AccountCardProps> = ({
                   {fiveHourResetText}
                 </span>
               )}
+              {fiveHourExhaustionText && (
+                <span className="text-[10px] text-[var(--dash-danger)]">
+                  {fiveHourExhaustionText}
+                </span>
+              )}
               <span className="ml-auto text-lg font-semibold text-[var(--dash-text-primary)]">
                 {fiveHourLeft !== undefined ? `${fiveHourLeft}%` : '--'}
               </span>
@@ -256,6 +453,11 @@ export const AccountCard: React.FC<AccountCardProps> = ({
                 {weeklyResetText}
               </span>
             )}
+            {weeklyExhaustionText && (
+              <span className="text-[10px] text-[var(--dash-danger)] truncate">
+                {weeklyExhaustionText}
+              </span>
+            )}
             <span className="ml-auto text-lg font-semibold text-[var(--dash-text-primary)] shrink-0">
               {weeklyLeft !== undefined ? `${weeklyLeft}%` : '--'}
             </span>
@@ -281,6 +483,35 @@ export const AccountCard: React.FC<AccountCardProps> = ({
           )}
         </div>
 
+        {additionalWindows.map((window) => (
+          <div key={window.key} className="space-y-2">
+            <div className="flex items-baseline justify-between gap-2 min-w-0">
+              <span className="text-xs text-[var(--dash-text-muted)] truncate" title={window.label}>
+                {window.label}
+              </span>
+              <span className="text-lg font-semibold text-[var(--dash-text-primary)] shrink-0">
+                {Math.round(window.percent_left)}%
+              </span>
+            </div>
+            <UsageBar label={window.label} showLabel={false} percentLeft={Math.round(window.percent_left)} />
+          </div>
+        ))}
+
+        {contextWindow && (
+          <div className="space-y-2">
+            <div className="flex items-baseline gap-2 min-w-0 flex-nowrap">
+              <span className="text-xs text-[var(--dash-text-muted)] shrink-0">当前会话上下文</span>
+              <span className="text-[10px] text-[var(--dash-text-muted)] truncate">
+                {contextWindow.used} / {contextWindow.total}
+              </span>
+              <span className="ml-auto text-lg font-semibold text-[var(--dash-text-primary)] shrink-0">
+                {contextWindow.percentLeft}%
+              </span>
+            </div>
+            <UsageBar label="当前会话上下文" showLabel={false} percentLeft={contextWindow.percentLeft} />
+          </div>
+        )}
+
         <div className="space-y-2">
           <div className="flex items-baseline gap-2 min-w-0 flex-nowrap">
             <span className="text-xs text-[var(--dash-text-muted)] shrink-0">订阅到期</span>
@@ -92,7 +92,7 @@ export const Header: React.FC<HeaderProps> = ({
           <>
             <button
               onClick={onRefreshAll}
-              disabled={isLoading || isRefreshing}
+              disabled={isLoading || (isRefreshing && !isRefreshingAll)}
               className="h-10 px-3 rounded-full border border-[var(--dash-border)] text-[var(--dash-text-secondary)] hover:text-[var(--dash-text-primary)] hover:border-slate-300 bg-white/70 transition-colors flex items-center gap-2 disabled:opacity-50"
             >
               <svg
@@ -103,7 +103,9 @@ export const Header: React.FC<HeaderProps> = ({
               >
                 <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M4 4v5h.582m15.356 2A8.001 8.001 0 004.582 9m0 0H9m11 11v-5h-.581m0 0a8.003 8.003 0 01-15.357-2m15.357 2H15" />
               </svg>
-              <span className="text-sm hidden md:inline">{'\u5237\u65b0\u7528\u91cf'}</span>
+              <span className="text-sm hidden md:inline">
+                {isRefreshingAll ? '取消刷新' : '\u5237\u65b0\u7528\u91cf'}
+              </span>
             </button>
 
             <div
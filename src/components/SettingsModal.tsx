@@ -1,5 +1,40 @@
-import React, { useState } from 'react';
-import type { AppConfig } from '../types';
+import React, { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { save } from '@tauri-apps/plugin-dialog';
+import type {
+  AppConfig,
+  GlobalShortcutsConfig,
+  ProxyCredentialsStatus,
+  ProxyTestResult,
+} from '../types';
+
+const SHORTCUT_FIELDS: Array<{ key: keyof GlobalShortcutsConfig; label: string; placeholder: string }> = [
+  { key: 'nextAccount', label: '切换到下一个账号', placeholder: 'CommandOrControl+Alt+Right' },
+  { key: 'previousAccount', label: '切换到上一个账号', placeholder: 'CommandOrControl+Alt+Left' },
+  { key: 'recommendedAccount', label: '切换到推荐账号', placeholder: 'CommandOrControl+Alt+R' },
+  { key: 'openWindow', label: '打开主窗口', placeholder: 'CommandOrControl+Alt+M' },
+  { key: 'refreshAll', label: '刷新全部用量', placeholder: 'CommandOrControl+Alt+U' },
+];
+
+const inputClassName =
+  'w-full h-10 px-3 bg-white border border-[var(--dash-border)] rounded-xl text-sm text-[var(--dash-text-primary)] placeholder-[var(--dash-text-muted)] focus:border-blue-400 outline-none transition-colors';
+
+function formatProxyTestResult(result: ProxyTestResult): string {
+  if (result.ok) {
+    return `连接成功：HTTP ${result.statusCode}，耗时 ${result.latencyMs} ms`;
+  }
+  return `连接失败：${result.error ?? `HTTP ${result.statusCode}`}`;
+}
+
+// 去掉空白项，留空表示不注册该快捷键
+function normalizeShortcuts(shortcuts: GlobalShortcutsConfig): GlobalShortcutsConfig {
+  const normalized: GlobalShortcutsConfig = {};
+  for (const { key } of SHORTCUT_FIELDS) {
+    const value = shortcuts[key]?.trim();
+    if (value) normalized[key] = value;
+  }
+  return normalized;
+}
 
 interface SettingsModalProps {
   isOpen: boolean;
@@ -12,23 +47,135 @@ function SettingsModalContent({ config, onClose, onSave }: Omit<SettingsModalPro
   const [autoRefreshInterval, setAutoRefreshInterval] = useState(config.autoRefreshInterval);
   const [codexPath, setCodexPath] = useState(config.codexPath);
   const [closeBehavior, setCloseBehavior] = useState(config.closeBehavior);
+  const [traySortMode, setTraySortMode] = useState(config.traySortMode ?? 'manual');
+  const [trayHideUnavailable, setTrayHideUnavailable] = useState(config.trayHideUnavailable ?? false);
   const [proxyEnabled, setProxyEnabled] = useState(config.proxyEnabled);
   const [proxyUrl, setProxyUrl] = useState(config.proxyUrl);
+  const [proxyMode, setProxyMode] = useState(config.proxyMode ?? 'env');
+  const [proxyNoProxy, setProxyNoProxy] = useState(config.proxyNoProxy ?? '');
+  const [proxyUsername, setProxyUsername] = useState('');
+  const [proxyPassword, setProxyPassword] = useState('');
+  const [tlsCaBundlePaths, setTlsCaBundlePaths] = useState((config.tlsCaBundlePaths ?? []).join('\n'));
+  const [tlsUseSystemCerts, setTlsUseSystemCerts] = useState(config.tlsUseSystemCerts ?? false);
+  const [credentialsStatus, setCredentialsStatus] = useState<ProxyCredentialsStatus | null>(null);
+  const [proxyMessage, setProxyMessage] = useState<string | null>(null);
+  const [isTestingProxy, setIsTestingProxy] = useState(false);
+  const [globalShortcuts, setGlobalShortcuts] = useState<GlobalShortcutsConfig>(
+    config.globalShortcuts ?? {}
+  );
+  const [shortcutError, setShortcutError] = useState<string | null>(null);
   const [isSaving, setIsSaving] = useState(false);
+  const [diagnosticsMessage, setDiagnosticsMessage] = useState<string | null>(null);
+
+  // 代理密码保存在系统钥匙串中，这里只读取用户名和是否已保存密码
+  useEffect(() => {
+    let cancelled = false;
+    invoke<ProxyCredentialsStatus>('get_proxy_credentials_status')
+      .then((status) => {
+        if (cancelled) return;
+        setCredentialsStatus(status);
+        setProxyUsername(status.username ?? '');
+      })
+      .catch((error) => console.warn('读取代理凭据失败:', error));
+    return () => {
+      cancelled = true;
+    };
+  }, []);
+
+  const credentialsChanged =
+    proxyUsername.trim() !== (credentialsStatus?.username ?? '') || proxyPassword !== '';
+
+  const saveProxyCredentials = async () => {
+    // 密码留空表示保留已保存的密码
+    await invoke('set_proxy_credentials', {
+      username: proxyUsername,
+      password: proxyPassword === '' ? null : proxyPassword,
+    });
+    setProxyPassword('');
+    setCredentialsStatus(await invoke<ProxyCredentialsStatus>('get_proxy_credentials_status'));
+  };
+
+  // 用未保存的代理地址测试连通性；修改过的代理凭据会先保存
+  const handleTestProxy = async () => {
+    setIsTestingProxy(true);
+    setProxyMessage(null);
+    try {
+      if (credentialsChanged) {
+        await saveProxyCredentials();
+      }
+      const result = await invoke<ProxyTestResult>('test_proxy', {
+        proxyEnabled,
+        proxyUrl,
+      });
+      setProxyMessage(formatProxyTestResult(result));
+    } catch (error) {
+      setProxyMessage(`连接失败：${error instanceof Error ? error.message : String(error)}`);
+    } finally {
+      setIsTestingProxy(false);
+    }
+  };
+
+  // 导出脱敏后的诊断包，便于排查用量刷新失败
+  const handleExportDiagnostics = async () => {
+    try {
+      const targetPath = await save({
+        defaultPath: `codex-manager-diagnostics-${Date.now()}.zip`,
+        filters: [{ name: 'Zip', extensions: ['zip'] }],
+      });
+      if (!targetPath) return;
+      const savedPath = await invoke<string>('export_diagnostics_bundle', { targetPath });
+      setDiagnosticsMessage(`已导出：${savedPath}`);
+    } catch (error) {
+      setDiagnosticsMessage(`导出失败：${error instanceof Error ? error.message : String(error)}`);
+    }
+  };
 
   const handleSave = async () => {
     const normalizedAutoRefreshInterval =
       autoRefreshInterval <= 0 ? 0 : Math.max(1, Math.round(autoRefreshInterval));
 
+    const normalizedCaBundlePaths = tlsCaBundlePaths
+      .split('\n')
+      .map((path) => path.trim())
+      .filter(Boolean);
+    const nextShortcuts = normalizeShortcuts(globalShortcuts);
+    const shortcutsChanged =
+      JSON.stringify(nextShortcuts) !== JSON.stringify(normalizeShortcuts(config.globalShortcuts ?? {}));
+
     setIsSaving(true);
+    setShortcutError(null);
     try {
       await onSave({
         autoRefreshInterval: normalizedAutoRefreshInterval,
         codexPath,
         closeBehavior,
+        traySortMode,
+        trayHideUnavailable,
         proxyEnabled,
         proxyUrl,
+        proxyMode,
+        proxyNoProxy: proxyNoProxy.trim() || undefined,
+        tlsCaBundlePaths: normalizedCaBundlePaths.length > 0 ? normalizedCaBundlePaths : undefined,
+        tlsUseSystemCerts,
+        globalShortcuts: nextShortcuts,
       });
+      if (credentialsChanged) {
+        try {
+          await saveProxyCredentials();
+        } catch (error) {
+          setProxyMessage(`保存代理凭据失败：${error instanceof Error ? error.message : String(error)}`);
+          return;
+        }
+      }
+      if (shortcutsChanged) {
+        try {
+          await invoke('reload_global_shortcuts');
+        } catch (error) {
+          // 设置已保存，只提示注册失败的快捷键
+          setShortcutError(error instanceof Error ? error.message : String(error));
+          return;
+        }
+      }
       onClose();
     } catch (error) {
       console.error('Failed to save settings:', error);
@@ -39,7 +186,7 @@ function SettingsModalContent({ config, onClose, onSave }: Omit<SettingsModalPro
 
   return (
     <div className="fixed inset-0 bg-slate-900/40 flex items-center justify-center z-50 animate-fade-in">
-      <div className="bg-white rounded-2xl p-6 w-full max-w-sm mx-4 border border-[var(--dash-border)] shadow-[0_24px_60px_rgba(15,23,42,0.2)]">
+      <div className="bg-white rounded-2xl p-6 w-full max-w-sm mx-4 max-h-[90vh] overflow-y-auto border border-[var(--dash-border)] shadow-[0_24px_60px_rgba(15,23,42,0.2)]">
         <div className="flex justify-between items-center mb-5">
           <h2 className="text-base font-semibold text-[var(--dash-text-primary)]">设置</h2>
           <button
@@ -125,6 +272,62 @@ function SettingsModalContent({ config, onClose, onSave }: Omit<SettingsModalPro
             </div>
           </div>
 
+          <div className="pt-4 border-t border-slate-200 space-y-3">
+            <div>
+              <label className="block text-[var(--dash-text-secondary)] text-xs font-medium mb-2">
+                托盘账号排序
+              </label>
+              <div className="grid grid-cols-2 gap-2">
+                {[
+                  { value: 'manual', label: '手动顺序' },
+                  { value: 'remaining', label: '按剩余额度' },
+                  { value: 'reset', label: '按重置时间' },
+                  { value: 'alphabetical', label: '按名称' },
+                ].map((option) => {
+                  const selected = traySortMode === option.value;
+                  return (
+                    <button
+                      key={option.value}
+                      type="button"
+                      onClick={() => setTraySortMode(option.value as AppConfig['traySortMode'])}
+                      className={`h-10 rounded-xl border text-sm transition-colors ${
+                        selected
+                          ? 'border-blue-500 bg-blue-50 text-blue-600'
+                          : 'border-[var(--dash-border)] bg-white text-[var(--dash-text-secondary)] hover:text-[var(--dash-text-primary)] hover:border-slate-300'
+                      }`}
+                    >
+                      {option.label}
+                    </button>
+                  );
+                })}
+              </div>
+              <p className="text-xs text-[var(--dash-text-muted)] mt-1">
+                置顶的账号始终排在最前；手动顺序可在账号卡片上调整
+              </p>
+            </div>
+            <div className="flex items-center justify-between">
+              <div>
+                <p className="text-sm text-[var(--dash-text-primary)]">托盘中隐藏不可用账号</p>
+                <p className="text-xs text-[var(--dash-text-muted)] mt-1">
+                  已失效或被封禁的账号不显示在托盘菜单中
+                </p>
+              </div>
+              <button
+                type="button"
+                onClick={() => setTrayHideUnavailable(!trayHideUnavailable)}
+                className={`relative h-8 w-14 rounded-full transition-colors ${
+                  trayHideUnavailable ? 'bg-emerald-500' : 'bg-slate-200'
+                }`}
+              >
+                <span
+                  className={`absolute top-1 left-1 h-6 w-6 bg-white rounded-full shadow transition-transform ${
+                    trayHideUnavailable ? 'translate-x-6' : 'translate-x-0'
+                  }`}
+                />
+              </button>
+            </div>
+          </div>
+
           <div className="pt-4 border-t border-slate-200 space-y-3">
             <div className="flex items-center justify-between">
               <div>
@@ -162,6 +365,155 @@ function SettingsModalContent({ config, onClose, onSave }: Omit<SettingsModalPro
                 支持 http(s) 或 socks5，例如 socks5://127.0.0.1:7890
               </p>
             </div>
+            {!proxyEnabled && (
+              <div>
+                <label className="block text-[var(--dash-text-secondary)] text-xs font-medium mb-2">
+                  未启用代理时
+                </label>
+                <div className="grid grid-cols-2 gap-2">
+                  {[
+                    { value: 'env', label: '跟随环境变量' },
+                    { value: 'direct', label: '直连' },
+                  ].map((option) => {
+                    const selected = proxyMode === option.value;
+                    return (
+                      <button
+                        key={option.value}
+                        type="button"
+                        onClick={() => setProxyMode(option.value as NonNullable<AppConfig['proxyMode']>)}
+                        className={`h-10 rounded-xl border text-sm transition-colors ${
+                          selected
+                            ? 'border-blue-500 bg-blue-50 text-blue-600'
+                            : 'border-[var(--dash-border)] bg-white text-[var(--dash-text-secondary)] hover:text-[var(--dash-text-primary)] hover:border-slate-300'
+                        }`}
+                      >
+                        {option.label}
+                      </button>
+                    );
+                  })}
+                </div>
+              </div>
+            )}
+            <div>
+              <label className="block text-[var(--dash-text-secondary)] text-xs font-medium mb-1.5">
+                不走代理的地址
+              </label>
+              <input
+                type="text"
+                value={proxyNoProxy}
+                onChange={(e) => setProxyNoProxy(e.target.value)}
+                placeholder="localhost, 127.0.0.1, .internal"
+                className={inputClassName}
+              />
+              <p className="text-xs text-[var(--dash-text-muted)] mt-1">逗号分隔，对手动代理和账号代理生效</p>
+            </div>
+            <div className="grid grid-cols-2 gap-2">
+              <div>
+                <label className="block text-[var(--dash-text-secondary)] text-xs font-medium mb-1.5">
+                  代理用户名
+                </label>
+                <input
+                  type="text"
+                  value={proxyUsername}
+                  onChange={(e) => setProxyUsername(e.target.value)}
+                  placeholder="可选"
+                  autoComplete="off"
+                  className={inputClassName}
+                />
+              </div>
+              <div>
+                <label className="block text-[var(--dash-text-secondary)] text-xs font-medium mb-1.5">
+                  代理密码
+                </label>
+                <input
+                  type="password"
+                  value={proxyPassword}
+                  onChange={(e) => setProxyPassword(e.target.value)}
+                  placeholder={credentialsStatus?.hasPassword ? '已保存，留空不修改' : '可选'}
+                  autoComplete="new-password"
+                  className={inputClassName}
+                />
+              </div>
+            </div>
+            <p className="text-xs text-[var(--dash-text-muted)]">
+              仅用于上面的全局代理，账号级代理请在其地址中填写凭据；密码保存在系统钥匙串中，清空用户名即可删除凭据
+            </p>
+            <div className="flex items-center gap-3">
+              <button
+                type="button"
+                onClick={() => void handleTestProxy()}
+                disabled={isTestingProxy}
+                className="h-8 px-3 rounded-full border border-[var(--dash-border)] text-xs text-[var(--dash-text-secondary)] hover:text-[var(--dash-text-primary)] hover:border-slate-300 transition-colors disabled:opacity-50"
+              >
+                {isTestingProxy ? '测试中...' : '测试连接'}
+              </button>
+              {proxyMessage && (
+                <p className="flex-1 text-xs text-[var(--dash-text-muted)] break-all">{proxyMessage}</p>
+              )}
+            </div>
+          </div>
+
+          <div className="pt-4 border-t border-slate-200 space-y-3">
+            <div className="flex items-center justify-between">
+              <div>
+                <p className="text-sm text-[var(--dash-text-primary)]">信任系统证书</p>
+                <p className="text-xs text-[var(--dash-text-muted)] mt-1">
+                  公司网络拦截 TLS 时，同时信任系统证书库中的根证书
+                </p>
+              </div>
+              <button
+                type="button"
+                onClick={() => setTlsUseSystemCerts(!tlsUseSystemCerts)}
+                className={`relative h-8 w-14 rounded-full transition-colors ${
+                  tlsUseSystemCerts ? 'bg-emerald-500' : 'bg-slate-200'
+                }`}
+              >
+                <span
+                  className={`absolute top-1 left-1 h-6 w-6 bg-white rounded-full shadow transition-transform ${
+                    tlsUseSystemCerts ? 'translate-x-6' : 'translate-x-0'
+                  }`}
+                />
+              </button>
+            </div>
+            <div>
+              <label className="block text-[var(--dash-text-secondary)] text-xs font-medium mb-1.5">
+                额外信任的 CA 证书
+              </label>
+              <textarea
+                value={tlsCaBundlePaths}
+                onChange={(e) => setTlsCaBundlePaths(e.target.value)}
+                placeholder="/etc/ssl/certs/corp-ca.pem"
+                rows={2}
+                className="w-full px-3 py-2 bg-white border border-[var(--dash-border)] rounded-xl text-sm text-[var(--dash-text-primary)] placeholder-[var(--dash-text-muted)] focus:border-blue-400 outline-none transition-colors resize-none"
+              />
+              <p className="text-xs text-[var(--dash-text-muted)] mt-1">PEM 文件路径，每行一个，修改文件后自动重新加载</p>
+            </div>
+          </div>
+
+          <div className="pt-4 border-t border-slate-200 space-y-3">
+            <div>
+              <p className="text-sm text-[var(--dash-text-primary)]">全局快捷键</p>
+              <p className="text-xs text-[var(--dash-text-muted)] mt-1">
+                留空表示不启用，保存后立即生效
+              </p>
+            </div>
+            {SHORTCUT_FIELDS.map((field) => (
+              <div key={field.key}>
+                <label className="block text-[var(--dash-text-secondary)] text-xs font-medium mb-1.5">
+                  {field.label}
+                </label>
+                <input
+                  type="text"
+                  value={globalShortcuts[field.key] ?? ''}
+                  onChange={(e) =>
+                    setGlobalShortcuts((current) => ({ ...current, [field.key]: e.target.value }))
+                  }
+                  placeholder={field.placeholder}
+                  className="w-full h-10 px-3 bg-white border border-[var(--dash-border)] rounded-xl text-sm text-[var(--dash-text-primary)] placeholder-[var(--dash-text-muted)] focus:border-blue-400 outline-none transition-colors"
+                />
+              </div>
+            ))}
+            {shortcutError && <p className="text-xs text-red-500 break-all">{shortcutError}</p>}
           </div>
 
           <div className="pt-4 border-t border-slate-200">
@@ -174,6 +526,16 @@ function SettingsModalContent({ config, onClose, onSave }: Omit<SettingsModalPro
               <p className="text-xs text-[var(--dash-text-muted)] mt-2">
                 所有数据存储在本地
               </p>
+              <button
+                type="button"
+                onClick={() => void handleExportDiagnostics()}
+                className="mt-2 text-xs text-[var(--dash-accent)] hover:underline"
+              >
+                导出诊断包（已脱敏）
+              </button>
+              {diagnosticsMessage && (
+                <p className="text-xs text-[var(--dash-text-muted)] break-all">{diagnosticsMessage}</p>
+              )}
             </div>
           </div>
         </div>
@@ -212,6 +574,11 @@ export const SettingsModal: React.FC<SettingsModalProps> = ({
     config.closeBehavior,
     config.proxyEnabled,
     config.proxyUrl,
+    config.proxyMode,
+    config.proxyNoProxy,
+    JSON.stringify(config.tlsCaBundlePaths ?? []),
+    config.tlsUseSystemCerts,
+    JSON.stringify(config.globalShortcuts ?? {}),
   ].join('|');
 
   return (
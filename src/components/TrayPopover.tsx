@@ -0,0 +1,139 @@
+import React, { useCallback, useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import type { AccountsStore, StoredAccount, UsageRefreshAccountEvent } from '../types';
+import UsageBar from './UsageBar';
+
+function resolveAccountLabel(account: StoredAccount): string {
+  const email = account.accountInfo.email?.trim();
+  const workspaceName = account.accountInfo.workspaceName?.trim();
+  const label = email || account.alias || '未命名账号';
+  return workspaceName && workspaceName !== label ? `${label} / ${workspaceName}` : label;
+}
+
+/**
+ * 托盘左键弹出的迷你面板：展示各账号剩余额度并支持一键切换
+ */
+export const TrayPopover: React.FC = () => {
+  const [accounts, setAccounts] = useState<StoredAccount[]>([]);
+  const [switchingId, setSwitchingId] = useState<string | null>(null);
+  const [error, setError] = useState<string | null>(null);
+
+  const loadAccounts = useCallback(async () => {
+    try {
+      const data = await invoke<string>('load_accounts_store');
+      const store = JSON.parse(data) as AccountsStore;
+      setAccounts(store.accounts);
+      setError(null);
+    } catch (currentError) {
+      setError(currentError instanceof Error ? currentError.message : String(currentError));
+    }
+  }, []);
+
+  useEffect(() => {
+    void loadAccounts();
+
+    let unlisten: (() => void) | null = null;
+    let unlistenAccountRefresh: (() => void) | null = null;
+    void listen('tray-popover-shown', () => {
+      void loadAccounts();
+    }).then((dispose) => {
+      unlisten = dispose;
+    });
+    void listen<UsageRefreshAccountEvent>('usage-refresh-account', (event) => {
+      if (event.payload?.phase !== 'started') {
+        void loadAccounts();
+      }
+    }).then((dispose) => {
+      unlistenAccountRefresh = dispose;
+    });
+
+    return () => {
+      unlisten?.();
+      unlistenAccountRefresh?.();
+    };
+  }, [loadAccounts]);
+
+  const handleSwitch = async (accountId: string) => {
+    setSwitchingId(accountId);
+    try {
+      await invoke('switch_account_from_popover', { accountId });
+      await loadAccounts();
+    } catch (currentError) {
+      setError(currentError instanceof Error ? currentError.message : String(currentError));
+    } finally {
+      setSwitchingId(null);
+    }
+  };
+
+  const handleOpenMain = async () => {
+    await invoke('hide_tray_popover');
+    await invoke('show_main_window');
+  };
+
+  return (
+    <div className="h-screen flex flex-col bg-[var(--dash-bg)] text-[var(--dash-text-primary)] select-none">
+      <div className="flex-1 overflow-y-auto p-3 space-y-2">
+        {accounts.length === 0 && (
+          <div className="text-sm text-center text-[var(--dash-text-muted)] py-8">
+            暂无账号，请先在主界面导入
+          </div>
+        )}
+        {accounts.map((account) => {
+          const usageInfo = account.usageInfo;
+          const hasError = !!usageInfo?.status && usageInfo.status !== 'ok';
+          return (
+            <div key={account.id} className="dash-card p-3 space-y-2">
+              <div className="flex items-center justify-between gap-2">
+                <span className="text-sm font-medium truncate" title={resolveAccountLabel(account)}>
+                  {resolveAccountLabel(account)}
+                </span>
+                {account.isActive ? (
+                  <span className="text-xs text-[var(--dash-accent)] shrink-0">当前</span>
+                ) : (
+                  <button
+                    className="text-xs px-2 py-1 rounded-md bg-[var(--dash-accent-soft)] text-[var(--dash-accent)] shrink-0 disabled:opacity-50"
+                    disabled={switchingId !== null}
+                    onClick={() => void handleSwitch(account.id)}
+                  >
+                    {switchingId === account.id ? '切换中…' : '切换'}
+                  </button>
+                )}
+              </div>
+              {hasError && (
+                <div className="text-xs text-[var(--dash-danger)] truncate">
+                  {usageInfo?.message ?? usageInfo?.status}
+                </div>
+              )}
+              {usageInfo?.fiveHourLimit && (
+                <UsageBar
+                  label="5小时"
+                  percentLeft={usageInfo.fiveHourLimit.percentLeft}
+                  resetTime={usageInfo.fiveHourLimit.resetTime}
+                />
+              )}
+              {usageInfo?.weeklyLimit && (
+                <UsageBar
+                  label="每周"
+                  percentLeft={usageInfo.weeklyLimit.percentLeft}
+                  resetTime={usageInfo.weeklyLimit.resetTime}
+                />
+              )}
+            </div>
+          );
+        })}
+      </div>
+      {error && <div className="px-3 pb-2 text-xs text-[var(--dash-danger)]">{error}</div>}
+      <div className="border-t border-[var(--dash-border)] p-2">
+        <button
+          className="w-full text-sm py-1.5 rounded-md text-[var(--dash-text-secondary)] hover:bg-[var(--dash-bg-strong)]"
+          onClick={() => void handleOpenMain()}
+        >
+          打开主界面
+        </button>
+      </div>
+    </div>
+  );
+};
+
+export default TrayPopover;
@@ -9,5 +9,6 @@ export { QuickLoginModal } from './QuickLoginModal';
 export { SettingsModal } from './SettingsModal';
 export { StatsSummary } from './StatsSummary';
 export { SwitchRestartDialog } from './SwitchRestartDialog';
+export { TrayPopover } from './TrayPopover';
 export { UsageBar } from './UsageBar';
 export { Toast } from './Toast';
@@ -1,7 +1,8 @@
 import { useEffect, useRef, useCallback, useState } from 'react';
 import { useAccountStore } from '../stores/useAccountStore';
 import { invoke } from '@tauri-apps/api/core';
-import type { UsageInfo } from '../types';
+import { listen } from '@tauri-apps/api/event';
+import type { RefreshJobStatus, UsageInfo, UsageWindow, WhamUsageDetails } from '../types';
 
 /**
  * Rust 后端返回的用量数据结构
@@ -13,7 +14,20 @@ interface RustUsageData {
   weekly_reset_time_ms?: number;
   code_review_percent_left?: number;
   code_review_reset_time_ms?: number;
+  five_hour_burn_rate_per_hour?: number;
+  five_hour_projected_exhaustion_ms?: number;
+  weekly_burn_rate_per_hour?: number;
+  weekly_projected_exhaustion_ms?: number;
   last_updated: string;
+  details?: WhamUsageDetails;
+  source?: 'wham' | 'session';
+  age_ms?: number;
+  windows?: UsageWindow[];
+  context_window?: {
+    percent_left: number;
+    used_tokens: number;
+    total_tokens: number;
+  };
 }
 
 interface RustUsageResult {
@@ -26,9 +40,13 @@ interface RustUsageResult {
     | 'expired'
     | 'stale_token'
     | 'forbidden'
+    | 'cooldown'
+    | 'rate_limited'
+    | 'tls_error'
     | 'error';
   message?: string;
   plan_type?: string;
+  next_retry_at_ms?: number;
   usage?: RustUsageData;
 }
 
@@ -53,10 +71,15 @@ const formatResetTime = (resetTimeMs: number, includeWeekday: boolean): string =
   return `${month}-${day} ${hours}:${minutes}`;
 };
 
+const finiteOrUndefined = (value: number | null | undefined) =>
+  Number.isFinite(value) ? (value as number) : undefined;
+
 const buildLimitInfo = (
   percentLeft: number | undefined,
   resetTimeMs: number | undefined,
-  includeWeekday: boolean
+  includeWeekday: boolean,
+  burnRatePerHour?: number,
+  projectedExhaustionMs?: number
 ) => {
   if (!Number.isFinite(percentLeft) || !Number.isFinite(resetTimeMs)) {
     return undefined;
@@ -65,21 +88,37 @@ const buildLimitInfo = (
   return {
     percentLeft: Math.round(percentLeft as number),
     resetTime: formatResetTime(resetTimeMs as number, includeWeekday),
+    resetTimeMs: resetTimeMs as number,
+    burnRatePerHour: finiteOrUndefined(burnRatePerHour),
+    projectedExhaustionMs: finiteOrUndefined(projectedExhaustionMs),
   };
 };
 
+// 紧凑的 token 数（如 850、45.2K、1M），与托盘显示一致
+const formatTokenCount = (tokens: number): string => {
+  if (tokens < 1000) {
+    return String(tokens);
+  }
+  const [value, unit] = tokens < 1_000_000 ? [tokens / 1000, 'K'] : [tokens / 1_000_000, 'M'];
+  return `${value.toFixed(1).replace(/\.0$/, '')}${unit}`;
+};
+
 const buildUsageInfo = (usageData: RustUsageData, planType?: string): UsageInfo => ({
   status: 'ok',
   planType,
   fiveHourLimit: buildLimitInfo(
     usageData.five_hour_percent_left,
     usageData.five_hour_reset_time_ms,
-    false
+    false,
+    usageData.five_hour_burn_rate_per_hour,
+    usageData.five_hour_projected_exhaustion_ms
   ),
   weeklyLimit: buildLimitInfo(
     usageData.weekly_percent_left,
     usageData.weekly_reset_time_ms,
-    true
+    true,
+    usageData.weekly_burn_rate_per_hour,
+    usageData.weekly_projected_exhaustion_ms
   ),
   codeReviewLimit: buildLimitInfo(
     usageData.code_review_percent_left,
@@ -87,20 +126,63 @@ const buildUsageInfo = (usageData: RustUsageData, planType?: string): UsageInfo
     false
   ),
   lastUpdated: usageData.last_updated,
+  details: usageData.details,
+  source: usageData.source,
+  sourceAgeMs: usageData.age_ms,
+  windows: usageData.windows,
+  contextWindow: usageData.context_window
+    ? {
+        percentLeft: Math.round(usageData.context_window.percent_left),
+        used: formatTokenCount(usageData.context_window.used_tokens),
+        total: formatTokenCount(usageData.context_window.total_tokens),
+      }
+    : undefined,
 });
 
 const buildStatusUsageInfo = (result: RustUsageResult): UsageInfo => ({
   status: result.status,
   message: result.message,
   planType: result.plan_type,
+  nextRetryAtMs: result.next_retry_at_ms,
   lastUpdated: new Date().toISOString(),
 });
 
+// 与后端 allows_session_fallback 一致：这些状态下上次的额度仍有参考价值
+const QUOTA_KEEPING_STATUSES: ReadonlyArray<UsageInfo['status']> = [
+  'error',
+  'cooldown',
+  'rate_limited',
+  'tls_error',
+  'expired',
+  'stale_token',
+  'missing_token',
+];
+
+/**
+ * 临时失败的结果不含额度时保留上次的额度与窗口，只更新状态、提示与下次重试时间
+ * （与后端 merge_usage_summary 保持一致）
+ */
+const mergeStatusUsageInfo = (previous: UsageInfo | undefined, next: UsageInfo): UsageInfo => {
+  const hasUsage = Boolean(
+    next.fiveHourLimit || next.weeklyLimit || next.codeReviewLimit || next.windows?.length
+  );
+  if (!previous || hasUsage || !QUOTA_KEEPING_STATUSES.includes(next.status)) {
+    return next;
+  }
+  return {
+    ...previous,
+    status: next.status,
+    message: next.message,
+    nextRetryAtMs: next.nextRetryAtMs,
+  };
+};
+
 /**
  * 自动刷新用量数据的 Hook
  */
 export function useAutoRefresh() {
-  const { accounts, config, updateUsage, activeAccountId, syncCurrentAccount } = useAccountStore();
+  const { accounts, config, updateUsage, activeAccountId, syncCurrentAccount, loadAccounts } =
+    useAccountStore();
   const authCheckIntervalRef = useRef<ReturnType<typeof setInterval> | null>(null);
   const isRefreshingRef = useRef(false);
   const autoRefreshAccountIdRef = useRef<string | null>(null);
@@ -115,17 +197,25 @@ export function useAutoRefresh() {
     | 'expired'
     | 'stale-token'
     | 'forbidden'
+    | 'cooldown'
+    | 'rate-limited'
+    | 'tls-error'
     | 'error'
     | 'skipped';
   type RefreshResult = { status: RefreshStatus; message?: string };
-  type RefreshAllResult = { updated: number; missing: number; skipped: boolean };
+  type RefreshAllResult = {
+    updated: number;
+    missing: number;
+    skipped: boolean;
+    cancelled?: boolean;
+  };
 
 
   /**
    * 获取单个账号的用量信息
-   * 通过 wham/usage API 获取 Codex quota
+   * 通过 wham/usage API 获取 Codex quota，force 为 true 时解除该账号的失败冷却
    */
-  const fetchAccountUsage = useCallback(async (accountId: string): Promise<{
+  const fetchAccountUsage = useCallback(async (accountId: string, force = false): Promise<{
     usage: UsageInfo | null;
     status: RefreshStatus;
   }> => {
@@ -135,15 +225,20 @@ export function useAutoRefresh() {
         await syncCurrentAccount();
       }
 
-      const usageResult = await invoke<RustUsageResult>('get_codex_wham_usage', {
+      // 在线获取失败时后端会回退到本地 session 数据
+      const usageResult = await invoke<RustUsageResult>('get_usage', {
         accountId,
         proxyEnabled: config.proxyEnabled,
         proxyUrl: config.proxyUrl,
+        force,
       });
 
       if (usageResult.status === 'ok' && usageResult.usage) {
         return {
-          usage: buildUsageInfo(usageResult.usage, usageResult.plan_type),
+          usage: {
+            ...buildUsageInfo(usageResult.usage, usageResult.plan_type),
+            message: usageResult.message,
+          },
           status: 'success',
         };
       }
@@ -155,10 +250,24 @@ export function useAutoRefresh() {
         expired: 'expired',
         stale_token: 'stale-token',
         forbidden: 'forbidden',
+        cooldown: 'cooldown',
+        rate_limited: 'rate-limited',
+        tls_error: 'tls-error',
         error: 'error',
       };
       const mappedStatus = statusMap[usageResult.status] ?? 'error';
-      return { usage: buildStatusUsageInfo(usageResult), status: mappedStatus };
+      // 失败时后端可能附带本地 session 用量，保留失败状态的同时展示这份数据
+      return {
+        usage: usageResult.usage
+          ? {
+              ...buildUsageInfo(usageResult.usage, usageResult.plan_type),
+              status: usageResult.status,
+              message: usageResult.message,
+              nextRetryAtMs: usageResult.next_retry_at_ms,
+            }
+          : buildStatusUsageInfo(usageResult),
+        status: mappedStatus,
+      };
     } catch (error) {
       console.error(`Failed to fetch usage for account ${accountId}:`, error);
       return {
@@ -174,6 +283,7 @@ export function useAutoRefresh() {
 
   /**
    * 刷新所有账号的用量
+   * 由后端刷新任务执行，与后台自动刷新去重；结果已由后端写回存储
    */
   const refreshAllUsage = useCallback(async (): Promise<RefreshAllResult> => {
     if (isRefreshingRef.current || accounts.length === 0) {
@@ -182,35 +292,60 @@ export function useAutoRefresh() {
 
     isRefreshingRef.current = true;
     setIsRefreshing(true);
-    let updated = 0;
-    let missing = 0;
+    let unlisten: (() => void) | null = null;
 
     try {
-      for (const account of accounts) {
-        const { usage, status } = await fetchAccountUsage(account.id);
-        // 无论成功还是失败都保存 usage 信息，让 UI 能展示错误状态
-        if (usage) {
-          await updateUsage(account.id, usage);
-        }
-        if (status === 'success') {
-          updated += 1;
-        } else {
-          missing += 1;
+      let targetJobId: number | null = null;
+      const finishedJobs: RefreshJobStatus[] = [];
+      let resolveFinished: (job: RefreshJobStatus) => void = () => undefined;
+      const finished = new Promise<RefreshJobStatus>((resolve) => {
+        resolveFinished = resolve;
+      });
+
+      unlisten = await listen<RefreshJobStatus>('refresh-job-finished', (event) => {
+        if (targetJobId === null) {
+          finishedJobs.push(event.payload);
+        } else if (event.payload.jobId === targetJobId) {
+          resolveFinished(event.payload);
         }
-        // 添加延迟避免过快请求
-        await new Promise(resolve => setTimeout(resolve, 500));
+      });
+
+      const job = await invoke<RefreshJobStatus>('start_refresh_job', { accountIds: null });
+      targetJobId = job.jobId;
+      const earlyFinished = finishedJobs.find((item) => item.jobId === job.jobId);
+      if (earlyFinished) {
+        resolveFinished(earlyFinished);
       }
-      return { updated, missing, skipped: false };
+
+      const result = await finished;
+      await loadAccounts();
+      return {
+        updated: result.succeeded,
+        missing: result.failed,
+        skipped: false,
+        cancelled: result.state === 'cancelled',
+      };
+    } catch (error) {
+      console.error('Failed to run refresh job:', error);
+      return { updated: 0, missing: 0, skipped: true };
     } finally {
+      unlisten?.();
       isRefreshingRef.current = false;
       setIsRefreshing(false);
     }
-  }, [accounts, fetchAccountUsage, updateUsage]);
+  }, [accounts.length, loadAccounts]);
+
+  /**
+   * 取消正在运行的刷新任务
+   */
+  const cancelRefreshAll = useCallback(async () => {
+    await invoke<boolean>('cancel_refresh_job');
+  }, []);
 
   /**
    * 刷新单个账号的用量
    */
-  const refreshSingleAccount = useCallback(async (accountId: string): Promise<RefreshResult> => {
+  const refreshSingleAccount = useCallback(async (accountId: string, force = false): Promise<RefreshResult> => {
     if (isRefreshingRef.current) {
       return { status: 'skipped' };
     }
@@ -221,13 +356,16 @@ export function useAutoRefresh() {
     let message: string | undefined;
 
     try {
-      const { usage, status: fetchStatus } = await fetchAccountUsage(accountId);
+      const { usage, status: fetchStatus } = await fetchAccountUsage(accountId, force);
       status = fetchStatus === 'success' ? 'success' : fetchStatus;
       message = usage?.message;
 
-      // 无论成功还是失败都保存 usage 信息
+      // 无论成功还是失败都保存 usage 信息；冷却、限流等临时失败保留上次的额度
       if (usage) {
-        await updateUsage(accountId, usage);
+        const previous = useAccountStore
+          .getState()
+          .accounts.find((account) => account.id === accountId)?.usageInfo;
+        await updateUsage(accountId, mergeStatusUsageInfo(previous, usage));
       }
 
       return { status, message };
@@ -285,6 +423,7 @@ export function useAutoRefresh() {
 
   return {
     refreshAllUsage,
+    cancelRefreshAll,
     refreshSingleAccount,
     isRefreshing,
   };
@@ -1,10 +1,15 @@
 import { StrictMode } from 'react'
 import { createRoot } from 'react-dom/client'
+import { getCurrentWindow } from '@tauri-apps/api/window'
 import './index.css'
 import App from './App.tsx'
+import { TrayPopover } from './components/TrayPopover.tsx'
+
+// 托盘迷你面板与主窗口共用同一入口，按窗口标签区分
+const isTrayPopover = getCurrentWindow().label === 'popover'
 
 createRoot(document.getElementById('root')!).render(
   <StrictMode>
-    <App />
+    {isTrayPopover ? <TrayPopover /> : <App />}
   </StrictMode>,
 )
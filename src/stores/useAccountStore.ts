@@ -7,6 +7,9 @@ import {
   addAccount as addAccountToStore,
   removeAccount as removeAccountFromStore,
   updateAccountUsage as updateUsage,
+  updateAccountProxy as updateProxy,
+  updateAccountTrayPlacement as updateTrayPlacement,
+  moveAccountInOrder,
   syncCurrentAccount as syncCurrent,
   isMissingIdentityError,
   refreshAccountsWorkspaceMetadata,
@@ -26,6 +29,13 @@ interface AccountState {
   removeAccount: (accountId: string) => Promise<void>;
   switchToAccount: (accountId: string) => Promise<void>;
   updateUsage: (accountId: string, usage: UsageInfo) => Promise<void>;
+  updateAccountProxy: (accountId: string, proxyUrl: string) => Promise<void>;
+  updateAccountPlacement: (
+    accountId: string,
+    placement: { pinned?: boolean; group?: string }
+  ) => Promise<void>;
+  moveAccount: (accountId: string, offset: -1 | 1) => Promise<void>;
+  applyContextWindow: (accountId: string, contextWindow?: UsageInfo['contextWindow']) => void;
   updateConfig: (config: Partial<AppConfig>) => Promise<void>;
   refreshAllUsage: () => Promise<void>;
   setError: (message: string) => void;
@@ -42,6 +52,16 @@ const DEFAULT_CONFIG: AppConfig = {
   proxyUrl: 'http://127.0.0.1:7890',
   autoRestartCodexOnSwitch: false,
   skipSwitchRestartConfirm: false,
+  traySortMode: 'manual',
+  trayHideUnavailable: false,
+  trayPopoverEnabled: false,
+  refreshConcurrency: 4,
+  requestConnectTimeoutSeconds: 10,
+  requestReadTimeoutSeconds: 30,
+  usageHistoryEnabled: true,
+  usageHistoryRetentionDays: 90,
+  proxyMode: 'env',
+  tlsUseSystemCerts: false,
 };
 
 function buildStateFromStore(store: AccountsStore) {
@@ -181,6 +201,34 @@ export const useAccountStore = create<AccountState>((set) => ({
     }
   },
 
+  updateAccountProxy: async (accountId: string, proxyUrl: string) => {
+    await updateProxy(accountId, proxyUrl);
+    const store = await loadAccountsStore();
+    set(buildStateFromStore(store));
+  },
+
+  updateAccountPlacement: async (accountId: string, placement: { pinned?: boolean; group?: string }) => {
+    await updateTrayPlacement(accountId, placement);
+    const store = await loadAccountsStore();
+    set(buildStateFromStore(store));
+  },
+
+  moveAccount: async (accountId: string, offset: -1 | 1) => {
+    await moveAccountInOrder(accountId, offset);
+    const store = await loadAccountsStore();
+    set(buildStateFromStore(store));
+  },
+
+  // 后端已写回存储，这里只同步内存中的状态，不重新加载账号
+  applyContextWindow: (accountId: string, contextWindow?: UsageInfo['contextWindow']) =>
+    set((state) => ({
+      accounts: state.accounts.map((account) =>
+        account.id === accountId && account.usageInfo
+          ? { ...account, usageInfo: { ...account.usageInfo, contextWindow } }
+          : account
+      ),
+    })),
+
   updateConfig: async (config: Partial<AppConfig>) => {
     const store = await loadAccountsStore();
     const nextStore: AccountsStore = {
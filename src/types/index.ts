@@ -40,10 +40,15 @@ export interface UsageInfo {
     | 'expired'
     | 'stale_token'
     | 'forbidden'
+    | 'cooldown'
+    | 'rate_limited'
+    | 'tls_error'
     | 'error';
   message?: string;
   planType?: string;
+  nextRetryAtMs?: number; // 冷却中或被限流的账号下次自动重试时间
   contextWindow?: {
+    // 当前会话的上下文窗口（仅当前账号，来自本地 session）
     percentLeft: number;
     used: string;
     total: string;
@@ -51,17 +56,82 @@ export interface UsageInfo {
   fiveHourLimit?: {
     percentLeft: number;
     resetTime: string;
+    resetTimeMs?: number;
+    burnRatePerHour?: number; // 近期消耗速率（百分比/小时）
+    projectedExhaustionMs?: number; // 预计在重置前耗尽的时间
   };
   weeklyLimit?: {
     percentLeft: number;
     resetTime: string;
+    resetTimeMs?: number;
+    burnRatePerHour?: number;
+    projectedExhaustionMs?: number;
   };
   codeReviewLimit?: {
     percentLeft: number;
     resetTime: string;
+    resetTimeMs?: number;
   };
   lastUpdated?: string;
   sourceFile?: string;
+  details?: WhamUsageDetails; // wham/usage 中额度窗口之外的账号状态
+  source?: 'wham' | 'session'; // 数据来源：在线接口或本地会话文件（离线回退）
+  sourceAgeMs?: number; // 获取时数据距今的毫秒数
+  windows?: UsageWindow[]; // 全部额度窗口，上面的 5h / 周 / 代码审查字段由其派生
+}
+
+// 通用额度窗口（与后端 UsageData 一致，保持 snake_case）
+export interface UsageWindow {
+  key: string; // 来源字段路径，如 rate_limit.primary_window
+  label: string;
+  window_minutes?: number;
+  percent_left: number;
+  reset_time_ms: number;
+}
+
+// wham/usage 额度窗口（字段与接口一致，保持 snake_case）
+export interface WhamRateLimitWindow {
+  used_percent?: number;
+  limit_window_seconds?: number;
+  reset_after_seconds?: number;
+  reset_at?: number;
+}
+
+export interface WhamRateLimitStatus {
+  allowed?: boolean;
+  limit_reached?: boolean;
+  primary_window?: WhamRateLimitWindow;
+  secondary_window?: WhamRateLimitWindow;
+}
+
+export interface WhamUsageDetails {
+  user_id?: string;
+  account_id?: string;
+  email?: string;
+  allowed?: boolean;
+  limit_reached?: boolean;
+  rate_limit_reached_type?: string;
+  code_review_allowed?: boolean;
+  code_review_limit_reached?: boolean;
+  credits?: {
+    has_credits?: boolean;
+    unlimited?: boolean;
+    overage_limit_reached?: boolean;
+    balance?: string;
+    approx_local_messages?: unknown;
+    approx_cloud_messages?: unknown;
+  };
+  spend_control_reached?: boolean;
+  additional_rate_limits: Array<{
+    limit_name?: string;
+    metered_feature?: string;
+    rate_limit?: WhamRateLimitStatus;
+  }>;
+  promo?: {
+    campaign_id?: string;
+    message?: string;
+  };
+  blocked: boolean; // 服务端明确拒绝请求（allowed=false、limit_reached 或消费上限），即使剩余百分比大于 0
 }
 
 // 存储的账号数据
@@ -73,6 +143,10 @@ export interface StoredAccount {
   isActive: boolean; // 是否是当前激活账号
   createdAt: string;
   updatedAt: string;
+  group?: string; // 托盘中的分组名称
+  pinned?: boolean; // 托盘中置顶显示
+  sortIndex?: number; // 手动排序序号
+  proxyUrl?: string; // 账号级代理，留空跟随全局设置，"direct" 表示直连
 }
 
 // 应用配置
@@ -86,6 +160,29 @@ export interface AppConfig {
   proxyUrl: string;
   autoRestartCodexOnSwitch: boolean;
   skipSwitchRestartConfirm: boolean;
+  globalShortcuts?: GlobalShortcutsConfig;
+  traySortMode: 'manual' | 'remaining' | 'reset' | 'alphabetical';
+  trayHideUnavailable: boolean; // 托盘中隐藏已失效或被封禁的账号
+  trayPopoverEnabled: boolean; // 托盘左键打开迷你面板
+  refreshConcurrency: number; // 后台刷新并发数
+  requestConnectTimeoutSeconds: number; // 请求连接超时（秒）
+  requestReadTimeoutSeconds: number; // 请求读取超时（秒）
+  usageHistoryEnabled: boolean; // 记录用量历史
+  usageHistoryRetentionDays: number; // 用量历史保留天数，0 表示永久保留
+  apiBaseUrl?: string; // 后端 API 根地址，留空使用官方地址（环境变量 CODEX_MANAGER_API_BASE_URL 优先）
+  proxyMode?: 'env' | 'direct'; // 未开启手动代理时：跟随环境变量代理或直连
+  proxyNoProxy?: string; // 不走代理的主机列表（逗号分隔）
+  tlsCaBundlePaths?: string[]; // 额外信任的 CA 证书（PEM 文件路径）
+  tlsUseSystemCerts?: boolean; // 同时信任系统证书库
+}
+
+// 全局快捷键配置（Tauri accelerator 格式，如 CommandOrControl+Alt+Right）
+export interface GlobalShortcutsConfig {
+  nextAccount?: string;
+  previousAccount?: string;
+  recommendedAccount?: string;
+  openWindow?: string;
+  refreshAll?: string;
 }
 
 // 账号存储文件结构
@@ -94,3 +191,91 @@ export interface AccountsStore {
   accounts: StoredAccount[];
   config: AppConfig;
 }
+
+// 账号的下次计划刷新（后台自适应调度）
+export interface AccountRefreshPlan {
+  accountId: string;
+  nextRefreshAtMs?: number;
+  // attention：登录失效、封禁等需要用户处理的失败，按最长冷却时间退避直到手动刷新或 auth 更新
+  reason: 'disabled' | 'initial' | 'active' | 'idle' | 'reset' | 'backoff' | 'attention';
+  status?: string; // reason 为 attention 时的失败状态
+}
+
+// 用量历史快照
+export interface UsageHistoryWindow {
+  window: 'fiveHour' | 'weekly' | 'codeReview';
+  percentLeft: number;
+  resetTimeMs?: number;
+}
+
+export interface UsageHistoryEntry {
+  accountId: string;
+  recordedAtMs: number;
+  status: string;
+  planType?: string;
+  windows: UsageHistoryWindow[];
+  source?: 'wham' | 'session'; // 采样来源：在线刷新或本地会话
+}
+
+// 单个额度窗口的时间序列点
+export interface UsageHistoryPoint {
+  recordedAtMs: number;
+  percentLeft: number;
+  resetTimeMs?: number;
+}
+
+// 后端刷新任务状态（start_refresh_job / get_refresh_job_status / refresh-job-finished）
+export interface RefreshJobStatus {
+  jobId: number;
+  trigger: 'manual' | 'background' | 'resume' | 'reconnect';
+  state: 'queued' | 'running' | 'completed' | 'cancelled' | 'failed';
+  total: number;
+  completed: number;
+  succeeded: number;
+  failed: number;
+  startedAtMs: number;
+  finishedAtMs?: number;
+  error?: string;
+}
+
+// 后台刷新逐账号事件（usage-refresh-account）
+export interface UsageRefreshAccountEvent {
+  accountId: string;
+  phase: 'started' | 'succeeded' | 'failed' | 'cancelled';
+  code?: string; // 失败时的状态码，如 expired / forbidden / cooldown / error
+  message?: string;
+  summary?: UsageInfo;
+}
+
+// 当前账号 session 的上下文窗口变化（session-context-window-changed），会话已结束时为空
+export interface SessionContextWindowEvent {
+  accountId: string;
+  contextWindow: UsageInfo['contextWindow'] | null;
+}
+
+// 后台刷新整体进度（usage-refresh-progress）
+export interface UsageRefreshProgressEvent {
+  total: number;
+  completed: number;
+  succeeded: number;
+  failed: number;
+  inFlight: number;
+  finished: boolean;
+}
+
+// 代理连通性测试结果（test_proxy）
+export interface ProxyTestResult {
+  ok: boolean;
+  mode: 'env' | 'direct' | 'manual';
+  proxy?: string;
+  statusCode?: number;
+  latencyMs?: number;
+  error?: string;
+  errorCode?: 'tls_error';
+}
+
+// 代理凭据状态（不包含密码）
+export interface ProxyCredentialsStatus {
+  username?: string;
+  hasPassword: boolean;
+}
@@ -1,4 +1,4 @@
-import type { StoredAccount } from '../types';
+import type { StoredAccount, UsageWindow } from '../types';
 
 const DAY_IN_MS = 24 * 60 * 60 * 1000;
 const HOUR_IN_MS = 60 * 60 * 1000;
@@ -162,3 +162,42 @@ export function getAccountExpiryBucket(account: StoredAccount): AccountExpiryBuc
   }
   return 'active';
 }
+
+/**
+ * 额度窗口是否已由 5h / 周 / 代码审查字段展示，与后端 UsageWindow::is_legacy_limit 保持一致
+ */
+export function isLegacyLimitWindow(window: UsageWindow): boolean {
+  if (window.key === 'code_review_rate_limit.primary_window' || window.key.startsWith('rate_limits.')) {
+    return true;
+  }
+  if (!window.key.startsWith('rate_limit.')) {
+    return false;
+  }
+  // 没有窗口长度时按位置归入 5h / 周
+  if (window.window_minutes == null) {
+    return window.key === 'rate_limit.primary_window' || window.key === 'rate_limit.secondary_window';
+  }
+  return window.window_minutes === 300 || window.window_minutes === 10080;
+}
+
+type LegacyUsageWindow = UsageWindow & {
+  windowMinutes?: number;
+  percentLeft?: number;
+  resetTimeMs?: number;
+};
+
+/**
+ * 旧版本保存的额度窗口使用 camelCase，读取时转换为 snake_case
+ */
+export function normalizeUsageWindow(window: LegacyUsageWindow): UsageWindow {
+  if (window.percent_left !== undefined) {
+    return window;
+  }
+  return {
+    key: window.key,
+    label: window.label,
+    window_minutes: window.windowMinutes,
+    percent_left: window.percentLeft ?? 0,
+    reset_time_ms: window.resetTimeMs ?? 0,
+  };
+}
@@ -1,6 +1,7 @@
 import { invoke } from '@tauri-apps/api/core';
 import type { AccountsStore, StoredAccount, CodexAuthConfig, AppConfig, AccountInfo } from '../types';
 import { parseAccountInfo, generateId } from './jwt';
+import { normalizeUsageWindow } from './accountStatus';
 
 const DEFAULT_CONFIG: AppConfig = {
   autoRefreshInterval: 30, // 30分钟
@@ -12,6 +13,16 @@ const DEFAULT_CONFIG: AppConfig = {
   proxyUrl: 'http://127.0.0.1:7890',
   autoRestartCodexOnSwitch: false,
   skipSwitchRestartConfirm: false,
+  traySortMode: 'manual',
+  trayHideUnavailable: false,
+  trayPopoverEnabled: false,
+  refreshConcurrency: 4,
+  requestConnectTimeoutSeconds: 10,
+  requestReadTimeoutSeconds: 30,
+  usageHistoryEnabled: true,
+  usageHistoryRetentionDays: 90,
+  proxyMode: 'env',
+  tlsUseSystemCerts: false,
 };
 
 const DEFAULT_STORE: AccountsStore = {
@@ -347,6 +358,12 @@ export async function loadAccountsStore(): Promise<AccountsStore> {
       }
       const normalizedAccount = { ...account } as StoredAccount & { authConfig?: CodexAuthConfig };
       delete normalizedAccount.authConfig;
+      if (normalizedAccount.usageInfo?.windows) {
+        normalizedAccount.usageInfo = {
+          ...normalizedAccount.usageInfo,
+          windows: normalizedAccount.usageInfo.windows.map(normalizeUsageWindow),
+        };
+      }
       normalizedAccounts.push(normalizedAccount);
     }
 
@@ -656,6 +673,91 @@ export async function updateAccountUsage(
   }
 }
 
+/**
+ * 更新账号级代理（留空跟随全局设置，"direct" 表示直连）
+ */
+export async function updateAccountProxy(accountId: string, proxyUrl: string): Promise<void> {
+  const store = await loadAccountsStore();
+  const account = store.accounts.find((acc) => acc.id === accountId);
+
+  if (account) {
+    account.proxyUrl = proxyUrl.trim() || undefined;
+    account.updatedAt = new Date().toISOString();
+    await saveAccountsStore(store);
+  }
+}
+
+/**
+ * 手动排序：置顶账号在前，其余按排序序号，未设置序号的按添加顺序排在后面（与托盘的手动排序一致）
+ */
+export function sortAccountsManually(accounts: StoredAccount[]): StoredAccount[] {
+  return accounts
+    .map((account, index) => ({ account, index }))
+    .sort((a, b) => {
+      const pinned = Number(!!b.account.pinned) - Number(!!a.account.pinned);
+      if (pinned !== 0) return pinned;
+      const aSortIndex = a.account.sortIndex;
+      const bSortIndex = b.account.sortIndex;
+      if (aSortIndex !== undefined && bSortIndex !== undefined && aSortIndex !== bSortIndex) {
+        return aSortIndex - bSortIndex;
+      }
+      if ((aSortIndex === undefined) !== (bSortIndex === undefined)) {
+        return aSortIndex === undefined ? 1 : -1;
+      }
+      return a.index - b.index;
+    })
+    .map(({ account }) => account);
+}
+
+/**
+ * 更新账号在托盘中的置顶状态与分组（分组留空表示不分组）
+ */
+export async function updateAccountTrayPlacement(
+  accountId: string,
+  placement: { pinned?: boolean; group?: string }
+): Promise<void> {
+  const store = await loadAccountsStore();
+  const account = store.accounts.find((acc) => acc.id === accountId);
+
+  if (account) {
+    if (placement.pinned !== undefined) {
+      account.pinned = placement.pinned || undefined;
+    }
+    if (placement.group !== undefined) {
+      account.group = placement.group.trim() || undefined;
+    }
+    account.updatedAt = new Date().toISOString();
+    await saveAccountsStore(store);
+  }
+}
+
+/**
+ * 账号在手动排序中能否向该方向移动：相邻账号存在且置顶状态相同
+ */
+export function canMoveAccount(ordered: StoredAccount[], account: StoredAccount, offset: -1 | 1): boolean {
+  const index = ordered.findIndex((acc) => acc.id === account.id);
+  const target = ordered[index + offset];
+  return index >= 0 && !!target && !!target.pinned === !!account.pinned;
+}
+
+/**
+ * 在手动排序中与相邻账号交换位置（只在置顶状态相同的账号之间移动），并按新顺序重新编号
+ */
+export async function moveAccountInOrder(accountId: string, offset: -1 | 1): Promise<void> {
+  const store = await loadAccountsStore();
+  const ordered = sortAccountsManually(store.accounts);
+  const index = ordered.findIndex((acc) => acc.id === accountId);
+  if (index < 0 || !canMoveAccount(ordered, ordered[index], offset)) {
+    return;
+  }
+
+  [ordered[index], ordered[index + offset]] = [ordered[index + offset], ordered[index]];
+  ordered.forEach((account, position) => {
+    account.sortIndex = position;
+  });
+  await saveAccountsStore(store);
+}
+
 /**
  * 设置活动账号
  */